@@ -2,25 +2,27 @@ use core::fmt;
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Debug;
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 use bitcoin::{secp256k1, Address, Transaction};
 use clap::{Parser, Subcommand};
 use fedimint_api::config::ClientConfig;
 use fedimint_api::task::TaskGroup;
-use fedimint_api::{Amount, NumPeers, OutPoint, TieredMulti, TransactionId};
+use fedimint_api::{Amount, NumPeers, OutPoint, PeerId, TieredMulti, TransactionId};
 use fedimint_core::config::load_from_file;
 use fedimint_core::modules::ln::contracts::ContractId;
 use fedimint_core::modules::wallet::txoproof::TxOutProof;
 use mint_client::api::{WsFederationApi, WsFederationConnect};
 use mint_client::mint::SpendableNote;
+use mint_client::operations::{OperationFilter, OperationKind, OperationLogEntry};
 use mint_client::query::{CurrentConsensus, EventuallyConsistent};
+use mint_client::token::{EcashToken, ReceivedEcash};
 use mint_client::utils::{
-    from_hex, parse_bitcoin_amount, parse_ecash, parse_fedimint_amount, parse_node_pub_key,
-    serialize_ecash,
+    from_hex, parse_bitcoin_amount, parse_fedimint_amount, parse_node_pub_key, parse_received_ecash,
 };
-use mint_client::{Client, UserClientConfig};
+use mint_client::{Client, FederationId, UserClientConfig};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tracing_subscriber::EnvFilter;
@@ -56,6 +58,8 @@ enum CliOutput {
 
     Spend {
         token: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        qr_frames: Option<Vec<String>>,
     },
 
     PegOut {
@@ -66,6 +70,10 @@ enum CliOutput {
         contract_id: ContractId,
     },
 
+    LnPayMpp {
+        contract_ids: Vec<ContractId>,
+    },
+
     Fetch {
         issuance: Vec<OutPoint>,
     },
@@ -76,6 +84,10 @@ enum CliOutput {
         details: BTreeMap<Amount, usize>,
     },
 
+    History {
+        operations: Vec<OperationLogEntry>,
+    },
+
     LnInvoice {
         invoice: lightning_invoice::Invoice,
     },
@@ -84,6 +96,14 @@ enum CliOutput {
         paid_in_tx: OutPoint,
     },
 
+    LnurlRegister {
+        username: String,
+    },
+
+    LnurlClaim {
+        claimed_in_txs: Vec<OutPoint>,
+    },
+
     WaitBlockHeight {
         reached: u64,
     },
@@ -206,6 +226,19 @@ enum Command {
         arg: String,
     },
 
+    /// Call an endpoint of a guardian's password protected admin API, e.g. `/admin/audit`
+    Admin {
+        /// Id of the guardian whose admin API is called, the password is only sent to it
+        peer: u16,
+        method: String,
+        /// JSON params that will be serialized and send with the password
+        #[clap(default_value = "null")]
+        arg: String,
+        /// Admin password of the guardian
+        #[clap(long, env = "FM_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+
     /// Issue tokens in exchange for a peg-in proof (not yet implemented, just creates notes)
     PegIn {
         #[clap(value_parser = from_hex::<TxOutProof>)]
@@ -214,22 +247,30 @@ enum Command {
         transaction: Transaction,
     },
 
-    /// Reissue tokens received from a third party to avoid double spends
+    /// Reissue tokens received from a third party to avoid double spends, joins the token's
+    /// federation if the workdir doesn't contain a client config yet. Notes in the legacy base64
+    /// encoding are still accepted.
     Reissue {
-        #[clap(value_parser = parse_ecash)]
-        coins: TieredMulti<SpendableNote>,
+        #[clap(value_parser = parse_received_ecash)]
+        token: ReceivedEcash,
     },
 
     /// Validate tokens without claiming them (only checks if signatures valid, does not check if nonce unspent)
     Validate {
-        #[clap(value_parser = parse_ecash)]
-        coins: TieredMulti<SpendableNote>,
+        #[clap(value_parser = parse_received_ecash)]
+        token: ReceivedEcash,
     },
 
     /// Prepare notes to send to a third party as a payment
     Spend {
         #[clap(value_parser = parse_fedimint_amount)]
         amount: Amount,
+        /// Message for the recipient included in the token
+        #[clap(long)]
+        memo: Option<String>,
+        /// Additionally split the token into frames of this many characters for an animated QR code
+        #[clap(long)]
+        qr_chunk_size: Option<NonZeroUsize>,
     },
 
     /// Withdraw funds from the federation
@@ -242,12 +283,36 @@ enum Command {
     /// Pay a lightning invoice via a gateway
     LnPay { bolt11: lightning_invoice::Invoice },
 
+    /// Pay a lightning invoice by splitting it into parts paid by different gateways
+    LnPayMpp {
+        bolt11: lightning_invoice::Invoice,
+        /// Maximum number of gateways the payment is split across
+        #[clap(long, default_value = "2")]
+        max_parts: usize,
+    },
+
     /// Fetch (re-)issued notes and finalize issuance process
     Fetch,
 
     /// Display wallet info (holdings, tiers)
     Info,
 
+    /// List past operations (spends, reissues, peg-ins/outs, lightning payments), most recent first
+    History {
+        /// Only list operations of this kind (e.g. `peg_in` or `ln_pay`), can be repeated
+        #[clap(long = "kind")]
+        kinds: Vec<OperationKind>,
+        /// Only list operations submitted at or after this unix timestamp
+        #[clap(long)]
+        since: Option<u64>,
+        /// Only list operations submitted before this unix timestamp
+        #[clap(long)]
+        until: Option<u64>,
+        /// Maximum number of operations to list
+        #[clap(long)]
+        limit: Option<usize>,
+    },
+
     /// Create a lightning invoice to receive payment via gateway
     LnInvoice {
         #[clap(value_parser = parse_fedimint_amount)]
@@ -259,6 +324,17 @@ enum Command {
     /// Wait for incoming invoice to be paid
     WaitInvoice { invoice: lightning_invoice::Invoice },
 
+    /// Register a lightning address username with the active gateway, or top up the offers of a
+    /// registered username. Every payment to the address uses up one offer.
+    LnurlRegister {
+        username: String,
+        #[clap(long, default_value_t = 20)]
+        offers: u64,
+    },
+
+    /// Claim the payments received through the lightning address `username`
+    LnurlClaim { username: String },
+
     /// Wait for the fed to reach a consensus block height
     WaitBlockHeight { height: u64 },
 
@@ -360,25 +436,7 @@ async fn main() {
         if let Command::JoinFederation { connect } = cli.command {
             let connect_obj: WsFederationConnect = serde_json::from_str(&connect)
                 .or_terminate(CliErrorKind::InvalidValue, "invalid connect info");
-            let api = WsFederationApi::new(connect_obj.members);
-            let cfg: ClientConfig = api
-                .request(
-                    "/config",
-                    (),
-                    CurrentConsensus::new(api.peers().one_honest()),
-                )
-                .await
-                .or_terminate(
-                    CliErrorKind::NetworkError,
-                    "couldn't download config from peer",
-                );
-            let cfg_path = cli.workdir.join("client.json");
-            std::fs::create_dir_all(&cli.workdir)
-                .or_terminate(CliErrorKind::IOError, "failed to create config directory");
-            let writer = std::fs::File::create(cfg_path)
-                .or_terminate(CliErrorKind::IOError, "couldn't create config.json");
-            serde_json::to_writer_pretty(writer, &cfg)
-                .or_terminate(CliErrorKind::IOError, "couldn't write config");
+            join_federation(&cli.workdir, connect_obj).await;
             println!(
                 "{}",
                 &CliOutput::JoinFederation { joined: (connect) }.to_string()
@@ -387,6 +445,16 @@ async fn main() {
         };
 
         let cfg_path = cli.workdir.join("client.json");
+        // Allows redeeming tokens of federations the user never joined
+        if let Command::Reissue {
+            token: ReceivedEcash::Token(token),
+        } = &cli.command
+        {
+            if !cfg_path.exists() {
+                join_federation(&cli.workdir, token.connect.clone()).await;
+            }
+        }
+
         let db_path = cli.workdir.join("client.db");
         let cfg: UserClientConfig = load_from_file(&cfg_path).expect("Failed to parse config");
         let db = fedimint_rocksdb::RocksDb::open(db_path)
@@ -411,6 +479,49 @@ async fn main() {
     }
 }
 
+/// Downloads the client config from the federation and stores it in `workdir`
+async fn join_federation(workdir: &Path, connect: WsFederationConnect) {
+    let api = WsFederationApi::new(connect.members);
+    let cfg: ClientConfig = api
+        .request(
+            "/config",
+            (),
+            CurrentConsensus::new(api.peers().one_honest()),
+        )
+        .await
+        .or_terminate(
+            CliErrorKind::NetworkError,
+            "couldn't download config from peer",
+        );
+    let cfg_path = workdir.join("client.json");
+    std::fs::create_dir_all(workdir)
+        .or_terminate(CliErrorKind::IOError, "failed to create config directory");
+    let writer = std::fs::File::create(cfg_path)
+        .or_terminate(CliErrorKind::IOError, "couldn't create config.json");
+    serde_json::to_writer_pretty(writer, &cfg)
+        .or_terminate(CliErrorKind::IOError, "couldn't write config");
+}
+
+/// Legacy notes don't name their federation, so they are assumed to be of the joined one
+fn verify_token_federation(
+    client: &Client<UserClientConfig>,
+    token: &ReceivedEcash,
+) -> Result<(), CliError> {
+    let federation_id = match token {
+        ReceivedEcash::Token(token) => &token.federation_id,
+        ReceivedEcash::Legacy(_) => return Ok(()),
+    };
+    if federation_id.0 == client.config().0.federation_name {
+        Ok(())
+    } else {
+        Err(CliError::from(
+            CliErrorKind::InvalidValue,
+            "token was issued by a different federation",
+            None,
+        ))
+    }
+}
+
 async fn handle_command(
     cli: Cli,
     client: Client<UserClientConfig>,
@@ -432,6 +543,24 @@ async fn handle_command(
 
             Ok(CliOutput::UntypedApiOutput { value: response })
         }
+        Command::Admin {
+            peer,
+            method,
+            arg,
+            password,
+        } => {
+            let arg: Value = serde_json::from_str(&arg)
+                .or_terminate(CliErrorKind::InvalidValue, "invalid JSON params");
+            let ws_api = WsFederationApi::from_config(client.config().as_ref());
+            ws_api
+                .admin_request(PeerId::from(peer), &method, &password, arg)
+                .await
+                .transform(
+                    |value| CliOutput::UntypedApiOutput { value },
+                    CliErrorKind::GeneralFederationError,
+                    "admin request failed",
+                )
+        }
         Command::VersionHash => Ok(CliOutput::VersionHash {
             hash: env!("GIT_HASH").to_string(),
         }),
@@ -453,15 +582,18 @@ async fn handle_command(
                 "peg-in failed (no further information)",
             ),
 
-        Command::Reissue { coins } => {
-            let id = client.reissue(coins, &mut rng).await;
+        Command::Reissue { token } => {
+            verify_token_federation(&client, &token)?;
+            let id = client.reissue(token.into_notes(), &mut rng).await;
             id.transform(
                 |v| CliOutput::Reissue { id: (v) },
                 CliErrorKind::GeneralFederationError,
                 "could not reissue notes (no further information)",
             )
         }
-        Command::Validate { coins } => {
+        Command::Validate { token } => {
+            verify_token_federation(&client, &token)?;
+            let coins = token.into_notes();
             let validate_result = client.validate_note_signatures(&coins).await;
             let details_vec = coins
                 .iter_tiers()
@@ -479,13 +611,30 @@ async fn handle_command(
                 }),
             }
         }
-        Command::Spend { amount } => client.spend_ecash(amount, rng).await.transform(
-            |v| CliOutput::Spend {
-                token: (serialize_ecash(&v)),
-            },
-            CliErrorKind::GeneralFederationError,
-            "failed to execute spend (no further information)",
-        ),
+        Command::Spend {
+            amount,
+            memo,
+            qr_chunk_size,
+        } => {
+            let config = client.config();
+            client.spend_ecash(amount, rng).await.transform(
+                |notes| {
+                    let token = EcashToken {
+                        federation_id: FederationId(config.0.federation_name.clone()),
+                        connect: WsFederationConnect::from(config.as_ref()),
+                        notes,
+                        memo: memo.clone(),
+                    };
+                    CliOutput::Spend {
+                        token: token.to_bech32m(),
+                        qr_frames: qr_chunk_size
+                            .map(|chunk_size| token.to_qr_frames(chunk_size.get())),
+                    }
+                },
+                CliErrorKind::GeneralFederationError,
+                "failed to execute spend (no further information)",
+            )
+        }
         Command::Fetch => {
             let mut result = Vec::<OutPoint>::new();
             let mut has_error = false;
@@ -519,6 +668,25 @@ async fn handle_command(
                 details: (details_vec),
             })
         }
+        Command::History {
+            kinds,
+            since,
+            until,
+            limit,
+        } => {
+            let filter = OperationFilter {
+                kinds,
+                since,
+                until,
+                limit,
+            };
+            // If the federation can't be reached the history is still listed, with operations
+            // whose outcome is unknown shown as pending
+            let _ = client.refresh_operation_statuses().await;
+            Ok(CliOutput::History {
+                operations: client.list_operations(&filter).await,
+            })
+        }
         Command::PegOut { address, satoshis } => {
             match client.new_peg_out_with_fees(satoshis, address).await {
                 Ok(peg_out) => match client.peg_out(peg_out, &mut rng).await {
@@ -545,11 +713,19 @@ async fn handle_command(
             }
         }
         Command::LnPay { bolt11 } => {
+            let amount = Amount::from_msats(bolt11.amount_milli_satoshis().unwrap_or_default());
+            if let Err(e) = client.ensure_live_gateway(amount).await {
+                return Err(CliError::from(
+                    CliErrorKind::NetworkError,
+                    "no lightning gateway is online",
+                    Some(Box::new(e)),
+                ));
+            }
             match client.fund_outgoing_ln_contract(bolt11, &mut rng).await {
                 Ok((contract_id, outpoint)) => {
                     match client.await_outgoing_contract_acceptance(outpoint).await {
                         Ok(_) => client
-                            .await_outgoing_contract_execution(contract_id, &mut rng)
+                            .await_outgoing_contract_execution(contract_id, outpoint.txid, &mut rng)
                             .await
                             .transform(
                                 |_| CliOutput::LnPay {
@@ -572,6 +748,48 @@ async fn handle_command(
                 )),
             }
         }
+        Command::LnPayMpp { bolt11, max_parts } => {
+            let amount = Amount::from_msats(bolt11.amount_milli_satoshis().unwrap_or_default());
+            if let Err(e) = client.ensure_live_gateway(amount).await {
+                return Err(CliError::from(
+                    CliErrorKind::NetworkError,
+                    "no lightning gateway is online",
+                    Some(Box::new(e)),
+                ));
+            }
+            match client
+                .fund_outgoing_mpp_ln_contracts(bolt11, max_parts, &mut rng)
+                .await
+            {
+                Ok((parts, txid)) => {
+                    match client.await_outgoing_mpp_acceptance(&parts, txid).await {
+                        Ok(_) => client
+                            .await_outgoing_mpp_execution(&parts, txid, &mut rng)
+                            .await
+                            .transform(
+                                |_| CliOutput::LnPayMpp {
+                                    contract_ids: parts
+                                        .iter()
+                                        .map(|part| part.contract_id)
+                                        .collect(),
+                                },
+                                CliErrorKind::GeneralFederationError,
+                                "gateways failed to execute contracts",
+                            ),
+                        Err(e) => Err(CliError::from(
+                            CliErrorKind::Timeout,
+                            "contracts weren't accepted in time",
+                            Some(Box::new(e)),
+                        )),
+                    }
+                }
+                Err(e) => Err(CliError::from(
+                    CliErrorKind::GeneralFederationError,
+                    "Failure creating outgoing LN contracts",
+                    Some(Box::new(e)),
+                )),
+            }
+        }
         Command::LnInvoice {
             amount,
             description,
@@ -599,6 +817,26 @@ async fn handle_command(
                     "invoice did not get paid in time",
                 )
         }
+        Command::LnurlRegister { username, offers } => client
+            .register_lnurl_username(username.clone(), offers)
+            .await
+            .transform(
+                |_| CliOutput::LnurlRegister {
+                    username: username.clone(),
+                },
+                CliErrorKind::NetworkError,
+                "couldn't register username with gateway",
+            ),
+        Command::LnurlClaim { username } => client
+            .claim_lnurl_payments(&username, &mut rng)
+            .await
+            .transform(
+                |outpoints| CliOutput::LnurlClaim {
+                    claimed_in_txs: outpoints,
+                },
+                CliErrorKind::GeneralFederationError,
+                "couldn't claim lightning address payments",
+            ),
         Command::WaitBlockHeight { height } => {
             client.await_consensus_block_height(height).await.transform(
                 |_| CliOutput::WaitBlockHeight { reached: (height) },
@@ -1,4 +1,5 @@
 use std::cmp::min;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
@@ -10,6 +11,7 @@ use fedimint_api::config::ClientConfig;
 use fedimint_api::module::registry::ModuleDecoderRegistry;
 use fedimint_api::task::{sleep, RwLock, RwLockWriteGuard};
 use fedimint_api::{dyn_newtype_define, NumPeers, OutPoint, PeerId, TransactionId};
+use fedimint_core::admin::AdminRequest;
 use fedimint_core::epoch::{SerdeEpochHistory, SignedEpochOutcome};
 use fedimint_core::modules::ln::contracts::incoming::IncomingContractOffer;
 use fedimint_core::modules::ln::contracts::ContractId;
@@ -22,10 +24,10 @@ use fedimint_core::transaction::legacy::Transaction as LegacyTransaction;
 use fedimint_core::transaction::SerdeTransaction;
 use fedimint_core::CoreError;
 use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 #[cfg(not(target_family = "wasm"))]
 use jsonrpsee_core::client::CertificateStore;
-use jsonrpsee_core::client::ClientT;
+use jsonrpsee_core::client::{ClientT, Subscription, SubscriptionClientT};
 use jsonrpsee_core::Error as JsonRpcError;
 use jsonrpsee_types::error::CallError as RpcCallError;
 #[cfg(target_family = "wasm")]
@@ -174,7 +176,7 @@ struct FederationMember<C> {
 }
 
 /// Information required for client to construct [`WsFederationApi`] instance
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WsFederationConnect {
     pub members: Vec<(PeerId, Url)>,
 }
@@ -217,6 +219,8 @@ pub enum ApiError {
     Timeout,
     #[error("Unable to determine a consistent API result from peers")]
     NoResult,
+    #[error("Peer {0} is not a member of the federation")]
+    UnknownPeer(PeerId),
 }
 
 impl ApiError {
@@ -551,6 +555,36 @@ impl<C: JsonRpcClient> FederationMember<C> {
     }
 }
 
+impl<C: JsonRpcClient + SubscriptionClientT> FederationMember<C> {
+    #[instrument(fields(peer = %self.peer_id), skip_all)]
+    pub async fn subscribe<N>(
+        &self,
+        subscribe_method: &str,
+        params: &[serde_json::Value],
+        unsubscribe_method: &str,
+    ) -> std::result::Result<Subscription<N>, JsonRpcError>
+    where
+        N: serde::de::DeserializeOwned,
+    {
+        let mut wclient = self.client.write().await;
+        if !matches!(&*wclient, Some(client) if client.is_connected()) {
+            debug!("web socket not connected, reconnecting");
+            *wclient = Some(C::connect(&self.url).await.map_err(|err| {
+                error!(%err, "unable to connect to server");
+                err
+            })?);
+        }
+
+        // drop the write lock before subscribing
+        let rclient = RwLockWriteGuard::downgrade(wclient);
+        rclient
+            .as_ref()
+            .expect("connected above")
+            .subscribe::<N, _>(subscribe_method, params, unsubscribe_method)
+            .await
+    }
+}
+
 /// `jsonrpsee` converts the `Url` to a `&str` internally and then parses it as an `Uri`.
 /// Unfortunately `Url` swallows ports that it considers default ports (e.g. 80 and 443 for HTTP(S))
 /// which makes the `Uri` parsing fail in these cases. This function works around this limitation in
@@ -569,6 +603,28 @@ fn url_to_string_with_default_port(url: &Url) -> String {
 }
 
 impl<C: JsonRpcClient> WsFederationApi<C> {
+    /// Calls `method` of the password protected admin API of the guardian `peer`, the password is
+    /// only sent to that guardian
+    pub async fn admin_request<P: serde::Serialize, R: serde::de::DeserializeOwned>(
+        &self,
+        peer: PeerId,
+        method: &str,
+        password: &str,
+        params: P,
+    ) -> Result<R> {
+        let member = self
+            .members
+            .iter()
+            .find(|member| member.peer_id == peer)
+            .ok_or(ApiError::UnknownPeer(peer))?;
+        let request = AdminRequest {
+            password: password.to_string(),
+            params,
+        };
+        let params = [serde_json::to_value(request).expect("encoding error")];
+        Ok(member.request(method, &params).await.result?)
+    }
+
     pub async fn request<P: serde::Serialize, R: serde::de::DeserializeOwned>(
         &self,
         method: &str,
@@ -620,6 +676,103 @@ impl<C: JsonRpcClient> WsFederationApi<C> {
     }
 }
 
+impl<C: JsonRpcClient + SubscriptionClientT> WsFederationApi<C> {
+    /// Subscribes to the signed epoch history of all members, starting at `from_epoch`
+    ///
+    /// The returned stream yields the epochs in order, each one as soon as it was validated using
+    /// [`ValidHistory`], so a single honest member with a valid signature is enough to make
+    /// progress. The stream ends once all members closed their subscription.
+    pub async fn subscribe_epoch_history(
+        &self,
+        from_epoch: u64,
+        epoch_pk: PublicKey,
+    ) -> Result<impl Stream<Item = Result<SignedEpochOutcome>> + '_> {
+        let params = [serde_json::to_value(from_epoch).expect("encoding error")];
+
+        let mut subscriptions = vec![];
+        let mut last_error = None;
+        for member in &self.members {
+            match member
+                .subscribe::<SerdeEpochHistory>(
+                    "/subscribe_epoch_history",
+                    &params,
+                    "/unsubscribe_epoch_history",
+                )
+                .await
+            {
+                Ok(subscription) => {
+                    let peer = member.peer_id;
+                    subscriptions.push(Box::pin(
+                        subscription.map(move |result| FedResponse { peer, result }),
+                    ));
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if subscriptions.is_empty() {
+            return Err(last_error
+                .map(ApiError::RpcError)
+                .unwrap_or(ApiError::NoResult));
+        }
+
+        let required = self.peers().one_honest();
+        Ok(futures::stream::unfold(
+            (
+                futures::stream::select_all(subscriptions),
+                from_epoch,
+                BTreeMap::<u64, ValidHistory>::new(),
+                BTreeMap::<u64, SignedEpochOutcome>::new(),
+            ),
+            move |(mut responses, next_epoch, mut strategies, mut validated)| async move {
+                loop {
+                    if let Some(epoch) = validated.remove(&next_epoch) {
+                        return Some((
+                            Ok(epoch),
+                            (responses, next_epoch + 1, strategies, validated),
+                        ));
+                    }
+
+                    let FedResponse { peer, result } = responses.next().await?;
+                    let epoch = match result.and_then(|history| {
+                        history
+                            .try_into_inner(&self.module_registry)
+                            .map_err(|e| JsonRpcError::Custom(e.to_string()))
+                    }) {
+                        Ok(epoch) => epoch,
+                        Err(err) => {
+                            warn!(%peer, %err, "Received invalid epoch history notification");
+                            continue;
+                        }
+                    };
+
+                    let epoch_number = epoch.outcome.epoch;
+                    if epoch_number < next_epoch || validated.contains_key(&epoch_number) {
+                        continue;
+                    }
+
+                    let strategy = strategies
+                        .entry(epoch_number)
+                        .or_insert_with(|| ValidHistory::new(epoch_pk, required));
+                    match strategy.process(FedResponse {
+                        peer,
+                        result: Ok(epoch),
+                    }) {
+                        QueryStep::Finished(Ok(epoch)) => {
+                            strategies.remove(&epoch_number);
+                            validated.insert(epoch_number, epoch);
+                        }
+                        QueryStep::Finished(Err(e)) => {
+                            strategies.remove(&epoch_number);
+                            return Some((Err(e), (responses, next_epoch, strategies, validated)));
+                        }
+                        QueryStep::Retry(_) | QueryStep::Continue => {}
+                    }
+                }
+            },
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
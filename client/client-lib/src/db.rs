@@ -1,16 +1,26 @@
-use fedimint_api::db::DatabaseKeyPrefixConst;
-use fedimint_api::encoding::{Decodable, Encodable};
+use std::io::{Read, Write};
+
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion};
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::TransactionId;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
+use crate::operations::OperationLogEntry;
 use crate::ClientSecret;
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
 pub enum DbKeyPrefix {
     ClientSecret = 0x29,
+    OperationLog = 0x2c,
+    OperationLogTx = 0x2f,
 }
 
+/// Current version of the client database schema, not including the client modules
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -25,3 +35,118 @@ impl DatabaseKeyPrefixConst for ClientSecretKey {
     type Key = ClientSecretKey;
     type Value = ClientSecret;
 }
+
+/// Operations are keyed by the time they were logged, so iterating the table lists them in
+/// chronological order. The random `nonce` keeps operations logged in the same microsecond apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct OperationLogKey {
+    pub timestamp_micros: u64,
+    pub nonce: u32,
+}
+
+/// Encoded big-endian (unlike the default integer encoding) so the order of the encoded keys is
+/// the order of the keys
+impl Encodable for OperationLogKey {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        writer.write_all(&self.timestamp_micros.to_be_bytes())?;
+        writer.write_all(&self.nonce.to_be_bytes())?;
+        Ok(12)
+    }
+}
+
+impl Decodable for OperationLogKey {
+    fn consensus_decode<R: Read>(
+        r: &mut R,
+        _modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let mut timestamp_micros = [0u8; 8];
+        r.read_exact(&mut timestamp_micros)
+            .map_err(DecodeError::from_err)?;
+        let mut nonce = [0u8; 4];
+        r.read_exact(&mut nonce).map_err(DecodeError::from_err)?;
+        Ok(OperationLogKey {
+            timestamp_micros: u64::from_be_bytes(timestamp_micros),
+            nonce: u32::from_be_bytes(nonce),
+        })
+    }
+}
+
+impl DatabaseKeyPrefixConst for OperationLogKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::OperationLog as u8;
+    type Key = Self;
+    type Value = OperationLogEntry;
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OperationLogKeyPrefix;
+
+impl DatabaseKeyPrefixConst for OperationLogKeyPrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::OperationLog as u8;
+    type Key = OperationLogKey;
+    type Value = OperationLogEntry;
+}
+
+/// Index of the operation log by transaction, to update the status of an operation
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct OperationLogTxKey(pub TransactionId);
+
+impl DatabaseKeyPrefixConst for OperationLogTxKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::OperationLogTx as u8;
+    type Key = Self;
+    type Value = OperationLogKey;
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OperationLogTxKeyPrefix;
+
+impl DatabaseKeyPrefixConst for OperationLogTxKeyPrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::OperationLogTx as u8;
+    type Key = OperationLogTxKey;
+    type Value = OperationLogKey;
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::db::{DatabaseKey, DatabaseKeyPrefix};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+    use super::OperationLogKey;
+
+    #[test]
+    fn operation_log_keys_sort_chronologically() {
+        let keys = [
+            OperationLogKey {
+                timestamp_micros: 1,
+                nonce: u32::MAX,
+            },
+            OperationLogKey {
+                timestamp_micros: 255,
+                nonce: 0,
+            },
+            OperationLogKey {
+                timestamp_micros: 256,
+                nonce: 0,
+            },
+            OperationLogKey {
+                timestamp_micros: 256,
+                nonce: 1,
+            },
+            OperationLogKey {
+                timestamp_micros: u64::MAX,
+                nonce: 0,
+            },
+        ];
+
+        let encoded = keys.iter().map(|key| key.to_bytes()).collect::<Vec<_>>();
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(encoded, sorted);
+
+        for (key, bytes) in keys.iter().zip(encoded) {
+            assert_eq!(bytes.len(), 13);
+            let decoded =
+                OperationLogKey::from_bytes(&bytes, &ModuleDecoderRegistry::default()).unwrap();
+            assert_eq!(&decoded, key);
+        }
+    }
+}
@@ -2,7 +2,9 @@ pub mod api;
 pub mod db;
 pub mod ln;
 pub mod mint;
+pub mod operations;
 pub mod query;
+pub mod token;
 pub mod transaction;
 pub mod utils;
 pub mod wallet;
@@ -19,8 +21,10 @@ use bitcoin::util::key::KeyPair;
 use bitcoin::{secp256k1, Address, Transaction as BitcoinTransaction};
 use bitcoin_hashes::{sha256, Hash};
 use fedimint_api::config::ClientConfig;
-use fedimint_api::core::{Decoder, MODULE_KEY_LN, MODULE_KEY_MINT, MODULE_KEY_WALLET};
-use fedimint_api::db::Database;
+use fedimint_api::core::{
+    Decoder, MODULE_KEY_GLOBAL, MODULE_KEY_LN, MODULE_KEY_MINT, MODULE_KEY_WALLET,
+};
+use fedimint_api::db::{apply_migrations, Database, DatabaseVersion, IterOrder, MigrationMap};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
 use fedimint_api::task::{self, sleep};
@@ -43,10 +47,10 @@ use fedimint_core::{
         ln::{
             contracts::{
                 incoming::{IncomingContract, IncomingContractOffer, OfferId},
-                Contract, ContractId, DecryptedPreimage, IdentifyableContract,
+                Contract, ContractId, DecryptedPreimage, EncryptedPreimage, IdentifyableContract,
                 OutgoingContractOutcome, Preimage,
             },
-            ContractOutput, LightningGateway, LightningOutput,
+            ContractOutput, GatewayFee, LightningGateway, LightningOutput,
         },
         mint::BlindNonce,
         wallet::txoproof::TxOutProof,
@@ -59,8 +63,10 @@ use futures::StreamExt;
 use lightning::ln::PaymentSecret;
 use lightning::routing::gossip::RoutingFees;
 use lightning::routing::router::{RouteHint, RouteHintHop};
-use lightning_invoice::{CreationError, Invoice, InvoiceBuilder, DEFAULT_EXPIRY_TIME};
-use ln::{db::LightningGatewayKey, PayInvoicePayload};
+use lightning_invoice::{
+    CreationError, Invoice, InvoiceBuilder, InvoiceDescription, DEFAULT_EXPIRY_TIME,
+};
+use ln::{db::LightningGatewayKey, PayInvoicePayload, PaymentPart};
 use mint::NoteIssuanceRequests;
 use rand::distributions::Standard;
 use rand::prelude::*;
@@ -69,18 +75,26 @@ use secp256k1_zkp::{All, Secp256k1};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use threshold_crypto::PublicKey;
-use tracing::debug;
+use tracing::{debug, warn};
 use url::Url;
 
-use crate::db::ClientSecretKey;
+use crate::db::{ClientSecretKey, OperationLogKey, OperationLogKeyPrefix, OperationLogTxKey};
 use crate::ln::db::{
-    OutgoingContractAccountKey, OutgoingContractAccountKeyPrefix, OutgoingPaymentClaimKey,
-    OutgoingPaymentClaimKeyPrefix, OutgoingPaymentKey,
+    LnurlInvoiceKey, LnurlInvoiceUsernamePrefix, LnurlRegistrationKey, OutgoingContractAccountKey,
+    OutgoingContractAccountKeyPrefix, OutgoingPaymentClaimKey, OutgoingPaymentClaimKeyPrefix,
+    OutgoingPaymentKey,
+};
+use crate::ln::lnurl::{
+    is_valid_lnurl_username, LnurlInvoice, LnurlInvoicesRequest, LnurlOffer, LnurlRegisterPayload,
+    LnurlRegistration, LNURL_MAX_OFFERS, LNURL_REQUEST_MAX_AGE,
 };
 use crate::ln::outgoing::OutgoingContractAccount;
 use crate::ln::LnClientError;
 use crate::mint::db::{CoinKey, PendingCoinsKeyPrefix};
 use crate::mint::MintClientError;
+use crate::operations::{
+    unix_time_now, OperationFilter, OperationKind, OperationLogEntry, OperationStatus,
+};
 use crate::transaction::TransactionBuilder;
 use crate::utils::{network_to_currency, ClientContext};
 use crate::wallet::WalletClientError;
@@ -95,6 +109,10 @@ use crate::{
 const OUTGOING_LN_CONTRACT_TIMELOCK: u64 = 500;
 /// Mint module's secret key derivation child id
 pub const MINT_SECRET_CHILD_ID: ChildId = ChildId(0);
+/// Child id of the key registered with gateways to receive LNURL-pay payments
+pub const LNURL_SECRET_CHILD_ID: ChildId = ChildId(1);
+/// Child id of the secret the payment keys of our LNURL-pay offers are derived from
+pub const LNURL_PAYMENT_SECRET_CHILD_ID: ChildId = ChildId(2);
 
 type Result<T> = std::result::Result<T, ClientError>;
 pub type GatewayClient = Client<GatewayClientConfig>;
@@ -104,9 +122,69 @@ pub type UserClient = Client<UserClientConfig>;
 pub struct PaymentParameters {
     pub max_delay: u64,
     pub invoice_amount: Amount,
+    /// Amount of the invoice we are supposed to pay, less than `invoice_amount` if the contract
+    /// only funds one part of a multi-part payment
+    pub payment_amount: Amount,
     pub max_send_amount: Amount,
     pub payment_hash: sha256::Hash,
     pub maybe_internal: bool,
+    /// Id of the part we pay if the contract only funds one part of a multi-part payment, 0
+    /// otherwise
+    pub part_id: u16,
+}
+
+/// One part of a multi-part payment, paid by `gateway` and funded by the outgoing contract
+/// `contract_id`
+#[derive(Debug, Clone)]
+pub struct OutgoingPaymentPart {
+    pub gateway: LightningGateway,
+    pub contract_id: ContractId,
+    /// Id distinguishing the part from the other parts of the payment, starting at 1
+    pub id: u16,
+    /// Amount of the invoice paid by this part, excluding fees
+    pub amount: Amount,
+    /// Index of the contract output in the transaction funding the payment
+    pub out_idx: u64,
+}
+
+/// What an invoice tells the payer about the payment
+enum InvoiceMemo {
+    Description(String),
+    /// Hash of a description too long for the invoice, e.g. LNURL-pay metadata
+    DescriptionHash(sha256::Hash),
+}
+
+/// Payment amount gateway fees are compared at when picking a default gateway before we know the
+/// amount of any payment, which gateway is cheapest depends on the amount as fees consist of a
+/// base and a proportional part
+const GATEWAY_FEE_REFERENCE_AMOUNT: Amount = Amount::from_sats(10_000);
+
+/// Time a gateway has to answer a health probe before we consider it offline
+const GATEWAY_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Picks the gateway charging the least for paying `amount` among those that can pay within the
+/// timelock of our outgoing contracts
+pub fn select_cheapest_gateway(
+    gateways: Vec<LightningGateway>,
+    amount: Amount,
+) -> Option<LightningGateway> {
+    suitable_gateways_by_fee(gateways, amount)
+        .into_iter()
+        .next()
+}
+
+/// Gateways that can pay within the timelock of our outgoing contracts, cheapest for paying
+/// `amount` first
+fn suitable_gateways_by_fee(
+    gateways: Vec<LightningGateway>,
+    amount: Amount,
+) -> Vec<LightningGateway> {
+    let mut suitable: Vec<_> = gateways
+        .into_iter()
+        .filter(|gateway| gateway.timelock_delta < OUTGOING_LN_CONTRACT_TIMELOCK)
+        .collect();
+    suitable.sort_by_key(|gateway| gateway.fees.fee(amount));
+    suitable
 }
 
 // Placeholder struct for identifying federations across clients
@@ -138,6 +216,9 @@ pub struct GatewayClientConfig {
     pub timelock_delta: u64,
     pub api: Url,
     pub node_pub_key: bitcoin::secp256k1::PublicKey,
+    /// Fee we charge for paying invoices, advertised to users when registering with the federation
+    #[serde(default)]
+    pub fees: GatewayFee,
 }
 
 impl From<GatewayClientConfig> for LightningGateway {
@@ -146,6 +227,8 @@ impl From<GatewayClientConfig> for LightningGateway {
             mint_pub_key: config.redeem_key.x_only_public_key().0,
             node_pub_key: config.node_pub_key,
             api: config.api,
+            fees: config.fees,
+            timelock_delta: config.timelock_delta,
         }
     }
 }
@@ -184,8 +267,13 @@ impl AsRef<ClientConfig> for UserClientConfig {
 impl PaymentParameters {
     // FIXME: change to absolute fee to avoid rounding errors
     pub fn max_fee_percent(&self) -> f64 {
-        let max_absolute_fee = self.max_send_amount - self.invoice_amount;
-        (max_absolute_fee.msats as f64) / (self.invoice_amount.msats as f64)
+        let max_absolute_fee = self.max_send_amount - self.payment_amount;
+        (max_absolute_fee.msats as f64) / (self.payment_amount.msats as f64)
+    }
+
+    /// Returns true if we only pay one part of a multi-part payment
+    pub fn is_partial(&self) -> bool {
+        self.payment_amount < self.invoice_amount
     }
 }
 
@@ -252,6 +340,9 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
         api: FederationApi,
         secp: Secp256k1<All>,
     ) -> Client<T> {
+        Self::apply_migrations(&db)
+            .await
+            .expect("Failed to migrate the client database");
         let root_secret = Self::get_secret(&db).await;
         Self {
             config,
@@ -260,6 +351,43 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
         }
     }
 
+    /// Upgrades the database schemas of the client and its modules to the versions expected by
+    /// the code, all in one database transaction
+    async fn apply_migrations(db: &Database) -> anyhow::Result<()> {
+        let mut ln_migrations = MigrationMap::new();
+        ln_migrations.insert(DatabaseVersion(0), crate::ln::db::migrate_to_v1);
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        let schemas = [
+            (
+                MODULE_KEY_GLOBAL,
+                crate::db::DATABASE_VERSION,
+                MigrationMap::new(),
+            ),
+            (
+                MODULE_KEY_MINT,
+                crate::mint::db::DATABASE_VERSION,
+                MigrationMap::new(),
+            ),
+            (
+                MODULE_KEY_LN,
+                crate::ln::db::DATABASE_VERSION,
+                ln_migrations,
+            ),
+            (
+                MODULE_KEY_WALLET,
+                crate::wallet::db::DATABASE_VERSION,
+                MigrationMap::new(),
+            ),
+        ];
+
+        for (module_key, version, migrations) in schemas {
+            apply_migrations(&mut dbtx, module_key, version, migrations).await?;
+        }
+
+        dbtx.commit_tx().await
+    }
+
     /// Fetches the client secret from the database or generates a new one if none is present
     async fn get_secret(db: &Database) -> DerivableSecret {
         let mut tx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
@@ -301,7 +429,9 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
             Input::Wallet(WalletInput(Box::new(peg_in_proof))),
         );
 
-        self.submit_tx_with_change(tx, &mut rng).await
+        let amount = tx.change_required(self);
+        self.submit_logged_tx_with_change(tx, OperationKind::PegIn, amount, Amount::ZERO, &mut rng)
+            .await
     }
 
     async fn submit_tx_with_change<R: RngCore + CryptoRng>(
@@ -313,6 +443,153 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
         Ok(self.context.api.submit_transaction(final_tx).await?)
     }
 
+    /// Submits the transaction like [`Client::submit_tx_with_change`] and records it in the
+    /// operation log, `extra_fees` are paid outside the federation (e.g. to a gateway)
+    async fn submit_logged_tx_with_change<R: RngCore + CryptoRng>(
+        &self,
+        tx: TransactionBuilder,
+        kind: OperationKind,
+        amount: Amount,
+        extra_fees: Amount,
+        rng: R,
+    ) -> Result<TransactionId> {
+        let fees = tx.fees_paid(self) + extra_fees;
+        let txid = self.submit_tx_with_change(tx, rng).await?;
+        self.log_operation(kind, amount, fees, Some(txid)).await;
+        Ok(txid)
+    }
+
+    /// Logs an operation, operations with a transaction start out
+    /// [`OperationStatus::Pending`] until their outcome is known
+    async fn log_operation(
+        &self,
+        kind: OperationKind,
+        amount: Amount,
+        fees: Amount,
+        txid: Option<TransactionId>,
+    ) {
+        let now = unix_time_now();
+        let key = OperationLogKey {
+            timestamp_micros: now.as_micros() as u64,
+            nonce: thread_rng().gen(),
+        };
+        let entry = OperationLogEntry {
+            timestamp: now.as_secs(),
+            kind,
+            amount,
+            fees,
+            txid,
+            status: match txid {
+                Some(_) => OperationStatus::Pending,
+                None => OperationStatus::Completed,
+            },
+        };
+        debug!(?entry, "Logging operation");
+
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        dbtx.insert_new_entry(&key, &entry).await.expect("DB Error");
+        if let Some(txid) = txid {
+            dbtx.insert_entry(&OperationLogTxKey(txid), &key)
+                .await
+                .expect("DB Error");
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Sets the status of the pending operation that submitted `txid`, operations that already
+    /// completed or failed keep their status
+    async fn update_operation_status(&self, txid: TransactionId, status: OperationStatus) {
+        self.update_operation_status_if(txid, status, |_| true)
+            .await
+    }
+
+    /// Marks the operation that submitted `txid` as completed if that only required the federation
+    /// to accept its transaction
+    async fn complete_accepted_operation(&self, txid: TransactionId) {
+        self.update_operation_status_if(txid, OperationStatus::Completed, |kind| {
+            kind.is_complete_when_accepted()
+        })
+        .await
+    }
+
+    async fn update_operation_status_if(
+        &self,
+        txid: TransactionId,
+        status: OperationStatus,
+        predicate: impl FnOnce(OperationKind) -> bool,
+    ) {
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        let key = match dbtx
+            .get_value(&OperationLogTxKey(txid))
+            .await
+            .expect("DB Error")
+        {
+            Some(key) => key,
+            None => return,
+        };
+        let mut entry = dbtx
+            .get_value(&key)
+            .await
+            .expect("DB Error")
+            .expect("Operation log index points to missing entry");
+        if entry.status != OperationStatus::Pending || !predicate(entry.kind) {
+            return;
+        }
+
+        debug!(%txid, ?status, "Updating operation status");
+        entry.status = status;
+        dbtx.insert_entry(&key, &entry).await.expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Asks the federation for the outcome of all pending operations' transactions and updates
+    /// their status
+    pub async fn refresh_operation_statuses(&self) -> Result<()> {
+        let pending = self
+            .list_operations(&OperationFilter::default())
+            .await
+            .into_iter()
+            .filter(|entry| entry.status == OperationStatus::Pending)
+            .filter_map(|entry| Some((entry.txid?, entry.kind)));
+
+        for (txid, kind) in pending {
+            match self.context.api.fetch_tx_outcome(txid).await? {
+                TransactionStatus::Rejected(_) => {
+                    self.update_operation_status(txid, OperationStatus::Failed)
+                        .await;
+                }
+                TransactionStatus::Accepted { .. } if kind.is_complete_when_accepted() => {
+                    self.update_operation_status(txid, OperationStatus::Completed)
+                        .await;
+                }
+                TransactionStatus::Accepted { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the logged operations matching `filter`, most recent first
+    pub async fn list_operations(&self, filter: &OperationFilter) -> Vec<OperationLogEntry> {
+        self.context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await
+            .find_by_prefix_ordered(&OperationLogKeyPrefix, IterOrder::Descending)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .filter(|entry| filter.matches(entry))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
     /// Spent some [`SpendableNote`]s to receive a freshly minted ones
     ///
     /// This is useful in scenarios where certain notes were handed over
@@ -345,7 +622,16 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
         let mut tx = TransactionBuilder::default();
         let (mut keys, input) = MintClient::ecash_input(notes)?;
         tx.input(&mut keys, input);
-        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+        let amount = tx.change_required(self);
+        let txid = self
+            .submit_logged_tx_with_change(
+                tx,
+                OperationKind::Reissue,
+                amount,
+                Amount::ZERO,
+                &mut rng,
+            )
+            .await?;
 
         Ok(OutPoint { txid, out_idx: 0 })
     }
@@ -439,19 +725,30 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
     ) -> Result<OutPoint> {
         let mut tx = TransactionBuilder::default();
 
+        let peg_out_amount = (peg_out.amount + peg_out.fees.amount()).into();
         let funding_amount = self
             .config
             .as_ref()
             .get_module::<WalletClientConfig>("wallet")
             .expect("missing wallet module config")
             .fee_consensus
-            .peg_out_abs
-            + (peg_out.amount + peg_out.fees.amount()).into();
+            .peg_out_fee(peg_out_amount)
+            + peg_out_amount;
         let (mut keys, input) = self.mint_client().select_input(funding_amount).await?;
         tx.input(&mut keys, input);
+        let amount: Amount = peg_out.amount.into();
+        let on_chain_fees: Amount = peg_out.fees.amount().into();
         let peg_out_idx = tx.output(Output::Wallet(WalletOutput(peg_out)));
 
-        let fedimint_tx_id = self.submit_tx_with_change(tx, &mut rng).await?;
+        let fedimint_tx_id = self
+            .submit_logged_tx_with_change(
+                tx,
+                OperationKind::PegOut,
+                amount,
+                on_chain_fees,
+                &mut rng,
+            )
+            .await?;
 
         Ok(OutPoint {
             txid: fedimint_tx_id,
@@ -492,6 +789,7 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
     ) -> Result<TieredMulti<SpendableNote>> {
         let coins = self.mint_client().select_coins(amount).await?;
 
+        let mut txid = None;
         let final_coins = if coins.total_amount() == amount {
             coins
         } else {
@@ -504,7 +802,7 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
                 .build_with_change(self.mint_client(), rng, change, &self.context.secp)
                 .await;
 
-            self.context.api.submit_transaction(tx).await?;
+            txid = Some(self.context.api.submit_transaction(tx).await?);
             self.fetch_all_coins().await;
             self.mint_client().select_coins(amount).await?
         };
@@ -529,6 +827,9 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
         }
         dbtx.commit_tx().await.expect("DB Error");
 
+        self.log_operation(OperationKind::SpendEcash, amount, Amount::ZERO, txid)
+            .await;
+
         Ok(final_coins)
     }
 
@@ -543,6 +844,7 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
             .await;
         self.mint_client().fetch_coins(&mut dbtx, outpoint).await?;
         dbtx.commit_tx().await.expect("DB Error");
+        self.complete_accepted_operation(outpoint.txid).await;
         Ok(())
     }
 
@@ -607,9 +909,11 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
     }
 
     pub async fn fetch_all_coins<'a>(&self) -> Vec<Result<OutPoint>> {
-        self.mint_client()
-            .fetch_all_coins()
-            .await
+        let results = self.mint_client().fetch_all_coins().await;
+        for outpoint in results.iter().flatten() {
+            self.complete_accepted_operation(outpoint.txid).await;
+        }
+        results
             .into_iter()
             .map(|res| res.map_err(|e| e.into()))
             .collect()
@@ -634,6 +938,103 @@ impl<T: AsRef<ClientConfig> + Clone> Client<T> {
             .await
             .map_err(|e| e.into())
     }
+
+    /// Creates an invoice for `payment_hash` that is routed through the gateway with node key
+    /// `gateway_node_pub_key`. We don't run a lightning node, so the invoice is signed by a
+    /// temporary node key.
+    #[allow(clippy::too_many_arguments)]
+    fn create_invoice_via_gateway<R: RngCore + CryptoRng>(
+        &self,
+        gateway_node_pub_key: secp256k1::PublicKey,
+        amount: Amount,
+        memo: InvoiceMemo,
+        payment_hash: sha256::Hash,
+        payment_secret: PaymentSecret,
+        expiry_time: Option<u64>,
+        mut rng: R,
+    ) -> Result<Invoice> {
+        // Temporary lightning node pubkey
+        let (node_secret_key, node_public_key) = self.context.secp.generate_keypair(&mut rng);
+
+        // Route hint instructing payer how to route to gateway
+        let gateway_route_hint = RouteHint(vec![RouteHintHop {
+            src_node_id: gateway_node_pub_key,
+            short_channel_id: 8,
+            fees: RoutingFees {
+                base_msat: 0,
+                proportional_millionths: 0,
+            },
+            cltv_expiry_delta: 30,
+            htlc_minimum_msat: None,
+            htlc_maximum_msat: None,
+        }]);
+
+        let builder = InvoiceBuilder::new(network_to_currency(
+            self.config
+                .as_ref()
+                .get_module::<WalletClientConfig>("wallet")
+                .expect("must have wallet config available")
+                .network,
+        ));
+        let builder = match memo {
+            InvoiceMemo::Description(description) => builder.description(description),
+            InvoiceMemo::DescriptionHash(hash) => builder.description_hash(hash),
+        };
+
+        Ok(builder
+            .amount_milli_satoshis(amount.msats)
+            .payment_hash(payment_hash)
+            .payment_secret(payment_secret)
+            .duration_since_epoch(duration_since_epoch())
+            .min_final_cltv_expiry(18)
+            .payee_pub_key(node_public_key)
+            .private_route(gateway_route_hint)
+            .expiry_time(Duration::from_secs(
+                expiry_time.unwrap_or(DEFAULT_EXPIRY_TIME),
+            ))
+            .build_signed(|hash| {
+                self.context
+                    .secp
+                    .sign_ecdsa_recoverable(hash, &node_secret_key)
+            })?)
+    }
+
+    /// Our registration of `username` with a gateway's LNURL-pay service, or the gateway's record
+    /// of it if we are the gateway
+    pub async fn get_lnurl_registration(&self, username: &str) -> Option<LnurlRegistration> {
+        self.context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await
+            .get_value(&LnurlRegistrationKey(username.to_string()))
+            .await
+            .expect("DB error")
+    }
+
+    /// Offers the federation's gateways to sell a preimage with the offer output `offer_output`
+    /// and waits until the federation accepted the offer
+    async fn submit_offer<R: RngCore + CryptoRng>(
+        &self,
+        offer_output: LightningOutput,
+        rng: R,
+    ) -> Result<()> {
+        let ln_output = Output::LN(offer_output);
+
+        // There is no input here because this is just an announcement
+        let mut tx = TransactionBuilder::default();
+        tx.output(ln_output);
+        let txid = self.submit_tx_with_change(tx, rng).await?;
+
+        // Await acceptance by the federation
+        let timeout = std::time::Duration::from_secs(15);
+        let outpoint = OutPoint { txid, out_idx: 0 };
+        self.context
+            .api
+            .await_output_outcome::<OfferId>(outpoint, timeout)
+            .await?;
+
+        Ok(())
+    }
 }
 
 impl Client<UserClientConfig> {
@@ -656,7 +1057,10 @@ impl Client<UserClientConfig> {
         }
     }
     /// Switches the clients active gateway to a registered gateway with the given node pubkey.
-    /// If no pubkey is given (node_pub_key == None) the first available registered gateway is activated.
+    /// If no pubkey is given (node_pub_key == None) the cheapest suitable registered gateway is
+    /// activated, see [`select_cheapest_gateway`]. Fees are compared at
+    /// [`GATEWAY_FEE_REFERENCE_AMOUNT`] since we don't know which payments will be made through
+    /// it, [`Client::ensure_live_gateway`] compares them at the actual payment amount.
     /// This behavior is useful for scenarios where we don't know any registered gateways in advance.
     pub async fn switch_active_gateway(
         &self,
@@ -676,22 +1080,104 @@ impl Client<UserClientConfig> {
                     debug!("Could not find gateway with public key {:?}", pub_key);
                     ClientError::GatewayNotFound
                 })?,
-            // Otherwise (no pubkey provided), select and activate the first registered gateway.
+            // Otherwise (no pubkey provided), select and activate the cheapest suitable gateway.
             None => {
-                debug!("No public key for gateway supplied, using first registered one");
-                gateways[0].clone()
+                debug!("No public key for gateway supplied, using cheapest registered one");
+                select_cheapest_gateway(gateways, GATEWAY_FEE_REFERENCE_AMOUNT)
+                    .ok_or(ClientError::NoSuitableGateway)?
             }
         };
+        self.save_active_gateway(&gateway).await;
+        Ok(gateway)
+    }
+
+    async fn save_active_gateway(&self, gateway: &LightningGateway) {
         let mut dbtx = self
             .context
             .db
             .begin_transaction(ModuleDecoderRegistry::default())
             .await;
-        dbtx.insert_entry(&LightningGatewayKey, &gateway)
+        dbtx.insert_entry(&LightningGatewayKey, gateway)
             .await
             .expect("DB error");
         dbtx.commit_tx().await.expect("DB Error");
-        Ok(gateway)
+    }
+
+    /// Returns the active gateway as it is currently registered with the federation. Gateways can
+    /// change their fees after we saved them as active, so contracts have to be funded with the
+    /// registered ones.
+    pub async fn refresh_active_gateway(&self) -> Result<LightningGateway> {
+        let active_gateway = self.fetch_active_gateway().await?;
+        let registered_gateway = self
+            .fetch_registered_gateways()
+            .await?
+            .into_iter()
+            .find(|gateway| gateway.node_pub_key == active_gateway.node_pub_key)
+            .ok_or(ClientError::GatewayNotFound)?;
+
+        if registered_gateway != active_gateway {
+            self.save_active_gateway(&registered_gateway).await;
+        }
+        Ok(registered_gateway)
+    }
+
+    /// Returns the active gateway if it is still registered and answers a health probe, otherwise
+    /// switches to the registered gateway charging the least for paying `payment_amount` among the
+    /// suitable ones that do. Call this before funding an outgoing contract to not lock funds for a
+    /// gateway that is offline until the timelock expires.
+    pub async fn ensure_live_gateway(&self, payment_amount: Amount) -> Result<LightningGateway> {
+        let active_gateway = self.fetch_active_gateway().await?;
+        let gateways = self.fetch_registered_gateways().await?;
+
+        if let Some(registered_gateway) = gateways
+            .iter()
+            .find(|gateway| gateway.node_pub_key == active_gateway.node_pub_key)
+        {
+            if self.probe_gateway(registered_gateway).await {
+                if *registered_gateway != active_gateway {
+                    self.save_active_gateway(registered_gateway).await;
+                }
+                return Ok(registered_gateway.clone());
+            }
+        }
+        warn!(
+            node_pub_key = %active_gateway.node_pub_key,
+            "Active gateway is offline or no longer registered"
+        );
+
+        for gateway in suitable_gateways_by_fee(gateways, payment_amount) {
+            if gateway.node_pub_key != active_gateway.node_pub_key
+                && self.probe_gateway(&gateway).await
+            {
+                return self.switch_active_gateway(Some(gateway.node_pub_key)).await;
+            }
+        }
+        Err(ClientError::NoLiveGateway)
+    }
+
+    /// Checks whether `gateway` answers requests
+    async fn probe_gateway(&self, gateway: &LightningGateway) -> bool {
+        let future = reqwest::Client::new()
+            .get(
+                gateway
+                    .api
+                    .join("health")
+                    .expect("'health' contains no invalid characters for a URL")
+                    .as_str(),
+            )
+            .send();
+
+        match fedimint_api::task::timeout(GATEWAY_PROBE_TIMEOUT, future).await {
+            Ok(Ok(response)) => response.status().is_success(),
+            Ok(Err(e)) => {
+                debug!(api = %gateway.api, error = %e, "Gateway health probe failed");
+                false
+            }
+            Err(_) => {
+                debug!(api = %gateway.api, "Gateway health probe timed out");
+                false
+            }
+        }
     }
 
     pub async fn fund_outgoing_ln_contract<R: RngCore + CryptoRng>(
@@ -699,7 +1185,9 @@ impl Client<UserClientConfig> {
         invoice: Invoice,
         mut rng: R,
     ) -> Result<(ContractId, OutPoint)> {
-        let gateway = self.fetch_active_gateway().await?;
+        let gateway = self.refresh_active_gateway().await?;
+        let invoice_amount =
+            Amount::from_msats(invoice.amount_milli_satoshis().unwrap_or_default());
         let mut dbtx = self
             .context
             .db
@@ -737,13 +1225,132 @@ impl Client<UserClientConfig> {
         let (mut keys, input) = self.mint_client().select_input(amount).await?;
         tx.input(&mut keys, input);
         tx.output(Output::LN(contract));
-        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+        let txid = self
+            .submit_logged_tx_with_change(
+                tx,
+                OperationKind::LnPay,
+                invoice_amount,
+                amount.saturating_sub(invoice_amount),
+                &mut rng,
+            )
+            .await?;
         let outpoint = OutPoint { txid, out_idx: 0 };
 
         debug!("Funded outgoing contract {} in {}", contract_id, outpoint);
         Ok((contract_id, outpoint))
     }
 
+    /// Splits the payment of a multi-part payment (MPP) invoice across up to `max_parts`
+    /// registered gateways, funding one outgoing contract per gateway in a single transaction.
+    ///
+    /// The active gateway is always used for the first part. Use
+    /// [`Client::await_outgoing_mpp_execution`] to let the gateways pay their parts.
+    pub async fn fund_outgoing_mpp_ln_contracts<R: RngCore + CryptoRng>(
+        &self,
+        invoice: Invoice,
+        max_parts: usize,
+        mut rng: R,
+    ) -> Result<(Vec<OutgoingPaymentPart>, TransactionId)> {
+        let invoice_amount = Amount::from_msats(
+            invoice
+                .amount_milli_satoshis()
+                .ok_or(ClientError::InvoiceMissingAmount)?,
+        );
+        if !invoice
+            .features()
+            .map_or(false, |features| features.supports_basic_mpp())
+        {
+            return Err(ClientError::InvoiceWithoutMpp);
+        }
+
+        let active_gateway = self.refresh_active_gateway().await?;
+        let max_parts = max_parts.max(1);
+        let other_gateways = self
+            .fetch_registered_gateways()
+            .await?
+            .into_iter()
+            .filter(|gateway| gateway.mint_pub_key != active_gateway.mint_pub_key)
+            .collect();
+        let mut gateways = vec![active_gateway];
+        gateways.extend(suitable_gateways_by_fee(
+            other_gateways,
+            Amount::from_msats(invoice_amount.msats / max_parts as u64),
+        ));
+        gateways.truncate(max_parts);
+
+        // The first part also pays the remainder that can't be split evenly
+        let num_parts = gateways.len() as u64;
+        let part_amount = Amount::from_msats(invoice_amount.msats / num_parts);
+        let first_part_amount = part_amount + Amount::from_msats(invoice_amount.msats % num_parts);
+
+        let consensus_height = self.context.api.fetch_consensus_block_height().await?;
+        let absolute_timelock = consensus_height + OUTGOING_LN_CONTRACT_TIMELOCK;
+
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        let mut tx = TransactionBuilder::default();
+        let mut parts = vec![];
+        let mut funding_amount = Amount::ZERO;
+        for (idx, gateway) in gateways.into_iter().enumerate() {
+            let amount = if idx == 0 {
+                first_part_amount
+            } else {
+                part_amount
+            };
+            let output = self
+                .ln_client()
+                .create_outgoing_part_output(
+                    &mut dbtx,
+                    invoice.clone(),
+                    amount,
+                    &gateway,
+                    absolute_timelock as u32,
+                    &mut rng,
+                )
+                .await?;
+            let contract_id = match &output {
+                LightningOutput::Contract(c) => {
+                    funding_amount += c.amount;
+                    c.contract.contract_id()
+                }
+                LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
+                    return Err(ClientError::WrongContractType);
+                }
+            };
+            let out_idx = tx.output(Output::LN(output));
+            parts.push(OutgoingPaymentPart {
+                gateway,
+                contract_id,
+                id: idx as u16 + 1,
+                amount,
+                out_idx,
+            });
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let (mut keys, input) = self.mint_client().select_input(funding_amount).await?;
+        tx.input(&mut keys, input);
+        let txid = self
+            .submit_logged_tx_with_change(
+                tx,
+                OperationKind::LnPay,
+                invoice_amount,
+                funding_amount.saturating_sub(invoice_amount),
+                &mut rng,
+            )
+            .await?;
+
+        debug!(
+            parts = parts.len(),
+            %txid,
+            "Funded outgoing multi-part payment"
+        );
+        Ok((parts, txid))
+    }
+
     /// Claims a refund for an expired or cancelled outgoing contract
     ///
     /// This can be necessary when the Lightning gateway cannot route the payment, is malicious or
@@ -768,8 +1375,10 @@ impl Client<UserClientConfig> {
             .ln_client()
             .create_refund_outgoing_contract_input(&contract_data);
         tx.input(&mut vec![*refund_key], Input::LN(refund_input));
-        let final_tx = tx.build(self, rng).await;
-        let txid = self.context.api.submit_transaction(final_tx).await?;
+        let amount = tx.change_required(self);
+        let txid = self
+            .submit_logged_tx_with_change(tx, OperationKind::LnRefund, amount, Amount::ZERO, rng)
+            .await?;
 
         let mut dbtx = self
             .context
@@ -794,6 +1403,23 @@ impl Client<UserClientConfig> {
         Ok(())
     }
 
+    /// Waits for the federation to accept the outgoing contracts of all `parts` of a multi-part
+    /// payment funded in `txid`, see [`Client::fund_outgoing_mpp_ln_contracts`]
+    pub async fn await_outgoing_mpp_acceptance(
+        &self,
+        parts: &[OutgoingPaymentPart],
+        txid: TransactionId,
+    ) -> Result<()> {
+        for part in parts {
+            self.await_outgoing_contract_acceptance(OutPoint {
+                txid,
+                out_idx: part.out_idx,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
     /// Waits for the federation to sign an ecash note.
     ///
     /// This function will poll until the returned result includes a SigResponse from the federation
@@ -834,83 +1460,197 @@ impl Client<UserClientConfig> {
         let payment_hash = bitcoin::secp256k1::hashes::sha256::Hash::hash(&raw_payment_secret);
         let payment_secret = PaymentSecret(raw_payment_secret);
 
-        // Temporary lightning node pubkey
-        let (node_secret_key, node_public_key) = self.context.secp.generate_keypair(&mut rng);
+        let invoice = self.create_invoice_via_gateway(
+            gateway.node_pub_key,
+            amount,
+            InvoiceMemo::Description(description),
+            payment_hash,
+            payment_secret,
+            expiry_time,
+            &mut rng,
+        )?;
+        let offer_output = self.ln_client().create_offer_output(
+            amount,
+            payment_hash,
+            Preimage(raw_payment_secret),
+            expiry_time,
+        );
+        self.submit_offer(offer_output, &mut rng).await?;
 
-        // Route hint instructing payer how to route to gateway
-        let gateway_route_hint = RouteHint(vec![RouteHintHop {
-            src_node_id: gateway.node_pub_key,
-            short_channel_id: 8,
-            fees: RoutingFees {
-                base_msat: 0,
-                proportional_millionths: 0,
-            },
-            cltv_expiry_delta: 30,
-            htlc_minimum_msat: None,
-            htlc_maximum_msat: None,
-        }]);
+        let confirmed = ConfirmedInvoice {
+            invoice,
+            keypair: payment_keypair,
+        };
+        self.ln_client().save_confirmed_invoice(&confirmed).await;
 
-        #[cfg(not(target_family = "wasm"))]
-        let duration_since_epoch = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
+        Ok(confirmed)
+    }
 
-        #[cfg(target_family = "wasm")]
-        let duration_since_epoch =
-            Duration::from_secs_f64(js_sys::Date::new_0().get_time() / 1000.);
+    /// Key pair whose public key we register with gateways to receive LNURL-pay payments, it
+    /// signs our requests to the gateway
+    pub fn lnurl_keypair(&self) -> KeyPair {
+        self.root_secret
+            .child_key(LNURL_SECRET_CHILD_ID)
+            .to_secp_key(&self.context.secp)
+    }
 
-        let invoice = InvoiceBuilder::new(network_to_currency(
-            self.config
-                .0
-                .get_module::<WalletClientConfig>("wallet")
-                .expect("must have wallet config available")
-                .network,
-        ))
-        .amount_milli_satoshis(amount.msats)
-        .description(description)
-        .payment_hash(payment_hash)
-        .payment_secret(payment_secret)
-        .duration_since_epoch(duration_since_epoch)
-        .min_final_cltv_expiry(18)
-        .payee_pub_key(node_public_key)
-        .private_route(gateway_route_hint)
-        .expiry_time(Duration::from_secs(
-            expiry_time.unwrap_or(DEFAULT_EXPIRY_TIME),
-        ))
-        .build_signed(|hash| {
-            self.context
-                .secp
-                .sign_ecdsa_recoverable(hash, &node_secret_key)
-        })?;
+    /// Key pair whose public key is the preimage of our LNURL-pay offer with index `index`
+    pub fn lnurl_payment_keypair(&self, index: u64) -> KeyPair {
+        self.root_secret
+            .child_key(LNURL_PAYMENT_SECRET_CHILD_ID)
+            .child_key(ChildId(index))
+            .to_secp_key(&self.context.secp)
+    }
 
-        let offer_output = self.ln_client().create_offer_output(
-            amount,
-            payment_hash,
-            Preimage(raw_payment_secret),
-            expiry_time,
-        );
-        let ln_output = Output::LN(offer_output);
+    /// Creates a request registering `username` together with `num_offers` new offers that
+    /// continue after the offers of our last registration of `username`
+    pub async fn create_lnurl_register_payload(
+        &self,
+        username: String,
+        num_offers: u64,
+    ) -> LnurlRegisterPayload {
+        let next_index = self
+            .get_lnurl_registration(&username)
+            .await
+            .map_or(0, |registration| registration.next_index);
+        let threshold_pub_key = self.ln_client().config.threshold_pub_key;
+        let offers = (next_index..next_index + num_offers)
+            .map(|index| {
+                let preimage = self
+                    .lnurl_payment_keypair(index)
+                    .x_only_public_key()
+                    .0
+                    .serialize();
+                LnurlOffer {
+                    index,
+                    payment_hash: sha256::Hash::hash(&preimage),
+                    encrypted_preimage: EncryptedPreimage::new(
+                        Preimage(preimage),
+                        &threshold_pub_key,
+                    ),
+                }
+            })
+            .collect();
+
+        LnurlRegisterPayload::new(
+            &self.context.secp,
+            &self.lnurl_keypair(),
+            FederationId(self.config().0.federation_name),
+            username,
+            offers,
+        )
+    }
 
-        // There is no input here because this is just an announcement
-        let mut tx = TransactionBuilder::default();
-        tx.output(ln_output);
-        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+    /// Registers `username` with our active gateway, or hands it more offers if we registered
+    /// `username` before. Every payment to the lightning address `username@<gateway domain>` uses
+    /// up one of the `num_offers` offers and can be claimed with
+    /// [`Client::claim_lnurl_payments`].
+    pub async fn register_lnurl_username(&self, username: String, num_offers: u64) -> Result<()> {
+        let gateway = self.fetch_active_gateway().await?;
+        let payload = self
+            .create_lnurl_register_payload(username.clone(), num_offers)
+            .await;
 
-        // Await acceptance by the federation
-        let timeout = std::time::Duration::from_secs(15);
-        let outpoint = OutPoint { txid, out_idx: 0 };
-        self.context
-            .api
-            .await_output_outcome::<OfferId>(outpoint, timeout)
+        let registration: LnurlRegistration = reqwest::Client::new()
+            .post(
+                gateway
+                    .api
+                    .join("lnurl/register")
+                    .expect("'lnurl/register' contains no invalid characters for a URL")
+                    .as_str(),
+            )
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
             .await?;
 
-        let confirmed = ConfirmedInvoice {
-            invoice,
-            keypair: payment_keypair,
-        };
-        self.ln_client().save_confirmed_invoice(&confirmed).await;
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        dbtx.insert_entry(&LnurlRegistrationKey(username), &registration)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(())
+    }
 
-        Ok(confirmed)
+    /// Fetches the invoices our active gateway created for payments to `username` and claims the
+    /// incoming contracts of the paid ones
+    pub async fn claim_lnurl_payments(
+        &self,
+        username: &str,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<Vec<OutPoint>> {
+        let gateway = self.fetch_active_gateway().await?;
+        let request = LnurlInvoicesRequest::new(
+            &self.context.secp,
+            &self.lnurl_keypair(),
+            username,
+            duration_since_epoch().as_secs(),
+        );
+        let invoices: Vec<LnurlInvoice> = reqwest::Client::new()
+            .post(
+                gateway
+                    .api
+                    .join(&format!("lnurl/{}/invoices", username))
+                    .map_err(|_| ClientError::InvalidLnurlUsername)?
+                    .as_str(),
+            )
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        self.claim_lnurl_invoices(invoices, rng).await
+    }
+
+    /// Claims the incoming contracts of the paid ones among the `invoices` a gateway created for
+    /// our username
+    pub async fn claim_lnurl_invoices(
+        &self,
+        invoices: Vec<LnurlInvoice>,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<Vec<OutPoint>> {
+        let mut outpoints = vec![];
+        for LnurlInvoice { index, invoice } in invoices {
+            let keypair = self.lnurl_payment_keypair(index);
+
+            // The gateway may hand us invoices we can't claim, there is no point in keeping those
+            let preimage = keypair.x_only_public_key().0.serialize();
+            if *invoice.payment_hash() != sha256::Hash::hash(&preimage) {
+                warn!(
+                    index,
+                    "Gateway returned LNURL invoice not matching our offer"
+                );
+                continue;
+            }
+
+            let confirmed = ConfirmedInvoice { invoice, keypair };
+            let contract_id = confirmed.contract_id();
+            self.ln_client().save_confirmed_invoice(&confirmed).await;
+
+            // Skip invoices that weren't paid yet and contracts we already claimed
+            match self.ln_client().get_incoming_contract(contract_id).await {
+                Ok(account)
+                    if account.amount != Amount::ZERO
+                        && matches!(
+                            account.contract.decrypted_preimage,
+                            DecryptedPreimage::Some(_)
+                        ) =>
+                {
+                    outpoints.push(self.claim_incoming_contract(contract_id, &mut rng).await?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(outpoints)
     }
 
     pub async fn claim_incoming_contract(
@@ -925,7 +1665,16 @@ impl Client<UserClientConfig> {
         // Input claims this contract
         let mut tx = TransactionBuilder::default();
         tx.input(&mut vec![ci.keypair], Input::LN(contract.claim()));
-        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+        let amount = tx.change_required(self);
+        let txid = self
+            .submit_logged_tx_with_change(
+                tx,
+                OperationKind::LnReceive,
+                amount,
+                Amount::ZERO,
+                &mut rng,
+            )
+            .await?;
 
         // TODO: Update database if invoice is paid or expired
 
@@ -934,9 +1683,13 @@ impl Client<UserClientConfig> {
 
     /// Notify gateway that we've escrowed tokens they can claim by routing our payment and wait
     /// for them to do so
+    ///
+    /// `txid` is the transaction that funded the contract, its logged operation gets updated
+    /// with the outcome of the payment.
     pub async fn await_outgoing_contract_execution(
         &self,
         contract_id: ContractId,
+        txid: TransactionId,
         rng: impl RngCore + CryptoRng,
     ) -> Result<()> {
         let gateway = self.fetch_active_gateway().await?;
@@ -944,6 +1697,90 @@ impl Client<UserClientConfig> {
         let federation_name = self.config().0.federation_name;
         let payload = PayInvoicePayload::new(FederationId(federation_name), contract_id);
 
+        if self.request_outgoing_payment(&gateway, &payload).await? {
+            self.update_operation_status(txid, OperationStatus::Completed)
+                .await;
+            return Ok(());
+        }
+
+        self.update_operation_status(txid, OperationStatus::Failed)
+            .await;
+        self.refund_failed_outgoing_payment(contract_id, rng)
+            .await?;
+        Err(ClientError::RefundedFailedPayment)
+    }
+
+    /// Notify all gateways of a multi-part payment that we've escrowed tokens they can claim by
+    /// routing their part and wait for them to do so.
+    ///
+    /// The recipient only releases the preimage once all parts arrived, so if any gateway fails
+    /// all other parts fail too and the contracts of all failed parts get refunded. `txid` is the
+    /// transaction that funded the parts, its logged operation gets updated with the outcome of
+    /// the payment.
+    pub async fn await_outgoing_mpp_execution(
+        &self,
+        parts: &[OutgoingPaymentPart],
+        txid: TransactionId,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<()> {
+        let federation_name = self.config().0.federation_name;
+        let results = futures::future::join_all(parts.iter().map(|part| {
+            let payload = PayInvoicePayload::new_part(
+                FederationId(federation_name.clone()),
+                part.contract_id,
+                PaymentPart {
+                    id: part.id,
+                    amount: part.amount,
+                },
+            );
+            async move { self.request_outgoing_payment(&part.gateway, &payload).await }
+        }))
+        .await;
+
+        if results.iter().all(|result| matches!(result, Ok(true))) {
+            self.update_operation_status(txid, OperationStatus::Completed)
+                .await;
+            return Ok(());
+        }
+        // Without an answer from every gateway we can't be sure the payment failed
+        if results.iter().all(|result| result.is_ok()) {
+            self.update_operation_status(txid, OperationStatus::Failed)
+                .await;
+        }
+
+        let mut refund_result = Ok(());
+        for (part, result) in parts.iter().zip(results) {
+            match result {
+                Ok(true) => warn!(
+                    contract_id = %part.contract_id,
+                    "Part of failed multi-part payment was claimed by its gateway"
+                ),
+                Ok(false) => {
+                    if let Err(e) = self
+                        .refund_failed_outgoing_payment(part.contract_id, &mut rng)
+                        .await
+                    {
+                        warn!(contract_id = %part.contract_id, error = %e, "Failed to refund part");
+                        refund_result = Err(e);
+                    }
+                }
+                Err(e) => {
+                    warn!(contract_id = %part.contract_id, error = %e, "Failed to reach gateway");
+                    refund_result = Err(e);
+                }
+            }
+        }
+
+        refund_result.and(Err(ClientError::RefundedFailedPayment))
+    }
+
+    /// Asks `gateway` to pay the invoice of an outgoing contract, returns `false` if the gateway
+    /// failed to do so
+    async fn request_outgoing_payment(
+        &self,
+        gateway: &LightningGateway,
+        payload: &PayInvoicePayload,
+    ) -> Result<bool> {
         let future = reqwest::Client::new()
             .post(
                 gateway
@@ -952,31 +1789,31 @@ impl Client<UserClientConfig> {
                     .expect("'pay_invoice' contains no invalid characters for a URL")
                     .as_str(),
             )
-            .json(&payload)
+            .json(payload)
             .send();
-        let result = fedimint_api::task::timeout(Duration::from_secs(120), future)
+        let response = fedimint_api::task::timeout(Duration::from_secs(120), future)
             .await
             .map_err(|_| ClientError::OutgoingPaymentTimeout)?
-            .map_err(ClientError::HttpError);
+            .map_err(ClientError::HttpError)?;
 
-        match result {
-            Ok(response) => {
-                if response.status().is_success() {
-                    return Ok(());
-                }
+        Ok(response.status().is_success())
+    }
 
-                fedimint_api::task::timeout(
-                    Duration::from_secs(10),
-                    self.ln_client().await_outgoing_refundable(contract_id),
-                )
-                .await
-                .map_err(|_| ClientError::FailedPaymentNoRefund)??;
+    /// Waits for the gateway to cancel the contract of a failed outgoing payment and claims the
+    /// refund
+    async fn refund_failed_outgoing_payment(
+        &self,
+        contract_id: ContractId,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<OutPoint> {
+        fedimint_api::task::timeout(
+            Duration::from_secs(10),
+            self.ln_client().await_outgoing_refundable(contract_id),
+        )
+        .await
+        .map_err(|_| ClientError::FailedPaymentNoRefund)??;
 
-                self.try_refund_outgoing_contract(contract_id, rng).await?;
-                Err(ClientError::RefundedFailedPayment)
-            }
-            Err(e) => Err(e),
-        }
+        self.try_refund_outgoing_contract(contract_id, rng).await
     }
 }
 
@@ -994,9 +1831,13 @@ impl Client<GatewayClientConfig> {
 
     /// Check if we can claim the contract account and returns the max delay in blocks for how long
     /// other nodes on the route are allowed to delay the payment.
+    ///
+    /// If the contract only funds one part of a multi-part payment `part` tells us which part of
+    /// the invoice we are supposed to pay.
     pub async fn validate_outgoing_account(
         &self,
         account: &OutgoingContractAccount,
+        part: Option<PaymentPart>,
     ) -> Result<PaymentParameters> {
         let our_pub_key = secp256k1_zkp::XOnlyPublicKey::from_keypair(&self.config.redeem_key).0;
 
@@ -1011,8 +1852,21 @@ impl Client<GatewayClientConfig> {
                 .ok_or(ClientError::InvoiceMissingAmount)?,
         );
 
-        if account.amount < invoice_amount {
-            return Err(ClientError::Underfunded(invoice_amount, account.amount));
+        let (part_id, payment_amount) = match part {
+            Some(part) if part.amount > invoice_amount => {
+                return Err(ClientError::InvalidPartAmount(part.amount, invoice_amount));
+            }
+            Some(part) => (part.id, part.amount),
+            None => (0, invoice_amount),
+        };
+
+        if account.amount < payment_amount {
+            return Err(ClientError::Underfunded(payment_amount, account.amount));
+        }
+
+        // The contract has to pay our fee on top of the payment
+        if account.amount < payment_amount + self.config.fees.fee(payment_amount) {
+            return Err(ClientError::ViolatedFeePolicy);
         }
 
         let consensus_block_height = self.context.api.fetch_consensus_block_height().await?;
@@ -1026,9 +1880,11 @@ impl Client<GatewayClientConfig> {
         Ok(PaymentParameters {
             max_delay,
             invoice_amount,
+            payment_amount,
             max_send_amount: account.amount,
             payment_hash: *invoice.payment_hash(),
             maybe_internal: self.is_maybe_internal_payment(&invoice),
+            part_id,
         })
     }
 
@@ -1217,6 +2073,210 @@ impl Client<GatewayClientConfig> {
         Ok(mint_tx_id)
     }
 
+    /// Binds the username of `payload` to its key to receive LNURL-pay payments and adds its
+    /// offers. A username can't be taken over by another key once registered.
+    pub async fn register_lnurl_user(
+        &self,
+        payload: LnurlRegisterPayload,
+    ) -> Result<LnurlRegistration> {
+        if !is_valid_lnurl_username(&payload.username) {
+            return Err(ClientError::InvalidLnurlUsername);
+        }
+        if !payload.verify_signature(&self.context.secp) {
+            return Err(ClientError::InvalidLnurlSignature);
+        }
+
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        let key = LnurlRegistrationKey(payload.username);
+        let mut registration = match dbtx.get_value(&key).await.expect("DB error") {
+            Some(registration) if registration.pubkey != payload.pubkey => {
+                return Err(ClientError::LnurlUsernameTaken)
+            }
+            Some(registration) => registration,
+            None => LnurlRegistration {
+                pubkey: payload.pubkey,
+                offers: vec![],
+                next_index: 0,
+            },
+        };
+
+        // Offers have to continue where the registered ones end, which also rejects replayed
+        // requests
+        let continues_registered_offers = payload
+            .offers
+            .iter()
+            .zip(registration.next_index..)
+            .all(|(offer, index)| offer.index == index);
+        if !continues_registered_offers
+            || LNURL_MAX_OFFERS < registration.offers.len() + payload.offers.len()
+        {
+            return Err(ClientError::InvalidLnurlOffers);
+        }
+        registration.next_index += payload.offers.len() as u64;
+        registration.offers.extend(payload.offers);
+
+        dbtx.insert_entry(&key, &registration)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(registration)
+    }
+
+    /// Creates an invoice paying `amount` to the registered `username` from the next offer of the
+    /// user. We only learn the preimage once the federation decrypts it for the contract we fund
+    /// when the invoice gets paid.
+    ///
+    /// Anyone can request invoices, so an unexpired invoice for the same amount is handed out
+    /// again as long as it wasn't paid instead of using up another offer and submitting another
+    /// transaction for it.
+    pub async fn create_lnurl_invoice(
+        &self,
+        username: &str,
+        amount: Amount,
+        description_hash: sha256::Hash,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<LnurlInvoice> {
+        if let Some(lnurl_invoice) = self
+            .unpaid_lnurl_invoice(username, amount, description_hash)
+            .await?
+        {
+            return Ok(lnurl_invoice);
+        }
+
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        let key = LnurlRegistrationKey(username.to_string());
+        let mut registration = dbtx
+            .get_value(&key)
+            .await
+            .expect("DB error")
+            .ok_or(ClientError::UnknownLnurlUsername)?;
+        if registration.offers.is_empty() {
+            return Err(ClientError::LnurlOffersExhausted);
+        }
+        // Offers are never reused, even if creating the invoice fails
+        let offer = registration.offers.remove(0);
+        dbtx.insert_entry(&key, &registration)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let invoice = self.create_invoice_via_gateway(
+            self.config.node_pub_key,
+            amount,
+            InvoiceMemo::DescriptionHash(description_hash),
+            offer.payment_hash,
+            PaymentSecret(rng.gen()),
+            None,
+            &mut rng,
+        )?;
+        let offer_output = LightningOutput::Offer(IncomingContractOffer {
+            amount,
+            hash: offer.payment_hash,
+            encrypted_preimage: offer.encrypted_preimage,
+            expiry_time: None,
+        });
+        self.submit_offer(offer_output, &mut rng).await?;
+
+        let lnurl_invoice = LnurlInvoice {
+            index: offer.index,
+            invoice,
+        };
+        let mut dbtx = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        dbtx.insert_new_entry(
+            &LnurlInvoiceKey {
+                username: username.to_string(),
+                index: offer.index,
+            },
+            &lnurl_invoice,
+        )
+        .await
+        .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        Ok(lnurl_invoice)
+    }
+
+    /// Returns an unexpired invoice created for paying `amount` to `username` that wasn't paid yet
+    async fn unpaid_lnurl_invoice(
+        &self,
+        username: &str,
+        amount: Amount,
+        description_hash: sha256::Hash,
+    ) -> Result<Option<LnurlInvoice>> {
+        let invoices = self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await
+            .find_by_prefix(&LnurlInvoiceUsernamePrefix(username.to_string()))
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect::<Vec<_>>();
+
+        for lnurl_invoice in invoices {
+            let invoice = &lnurl_invoice.invoice;
+            let is_reusable = invoice.amount_milli_satoshis() == Some(amount.msats)
+                && matches!(
+                    invoice.description(),
+                    InvoiceDescription::Hash(hash) if hash.0 == description_hash
+                )
+                && !invoice.is_expired();
+            // The federation removes the offer once the contract paying the invoice is funded
+            if is_reusable
+                && self
+                    .ln_client()
+                    .offer_exists(*invoice.payment_hash())
+                    .await?
+            {
+                return Ok(Some(lnurl_invoice));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Lists the invoices created for payments to `username` if `request` was recently signed by
+    /// the registered key
+    pub async fn list_lnurl_invoices(
+        &self,
+        username: &str,
+        request: &LnurlInvoicesRequest,
+    ) -> Result<Vec<LnurlInvoice>> {
+        let registration = self
+            .get_lnurl_registration(username)
+            .await
+            .ok_or(ClientError::UnknownLnurlUsername)?;
+
+        // Limits for how long an intercepted request can be replayed to learn the invoices
+        let now = duration_since_epoch().as_secs();
+        if LNURL_REQUEST_MAX_AGE < now.abs_diff(request.timestamp)
+            || !request.verify_signature(&self.context.secp, &registration.pubkey, username)
+        {
+            return Err(ClientError::InvalidLnurlSignature);
+        }
+
+        Ok(self
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await
+            .find_by_prefix(&LnurlInvoiceUsernamePrefix(username.to_string()))
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect())
+    }
+
     /// Lists all claim transactions for outgoing contracts that we have submitted but were not part
     /// of the consensus yet.
     pub async fn list_pending_claimed_outgoing(&self) -> Vec<ContractId> {
@@ -1310,6 +2370,18 @@ impl Debug for ClientSecret {
 
 /// Builds a fake module registry which is only usable for decoding messages since the client isn't
 /// modularized yet but we need the decoding functionality.
+#[cfg(not(target_family = "wasm"))]
+fn duration_since_epoch() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+}
+
+#[cfg(target_family = "wasm")]
+fn duration_since_epoch() -> Duration {
+    Duration::from_secs_f64(js_sys::Date::new_0().get_time() / 1000.)
+}
+
 fn module_decode_stubs() -> ModuleDecoderRegistry {
     ModuleDecoderRegistry::new([
         (MODULE_KEY_LN, Decoder::from_typed(&LightningModuleDecoder)),
@@ -1372,8 +2444,12 @@ pub enum ClientError {
     InvalidInvoice(lightning_invoice::ParseOrSemanticError),
     #[error("Invoice is missing amount")]
     InvoiceMissingAmount,
+    #[error("Invoice does not support multi-part payments")]
+    InvoiceWithoutMpp,
     #[error("Outgoing contract is underfunded, wants us to pay {0}, but only contains {1}")]
     Underfunded(Amount, Amount),
+    #[error("Part of multi-part payment {0} exceeds the invoice amount {1}")]
+    InvalidPartAmount(Amount, Amount),
     #[error("The contract's timeout is in the past or does not allow for a safety margin")]
     TimeoutTooClose,
     #[error("No offer")]
@@ -1392,6 +2468,10 @@ pub enum ClientError {
     NoGateways,
     #[error("Federation has no registered lightning gateway with the given node public key")]
     GatewayNotFound,
+    #[error("None of the federation's lightning gateways can pay invoices within our timelock")]
+    NoSuitableGateway,
+    #[error("None of the federation's suitable lightning gateways is online")]
+    NoLiveGateway,
     #[error("HTTP Error {0}")]
     HttpError(#[from] reqwest::Error),
     #[error("Outgoing payment timeout")]
@@ -1416,6 +2496,18 @@ pub enum ClientError {
     Timeout,
     #[error("Failed to spend ecash, all spend attempts re-used an ecash note")]
     SpendReusedNote,
+    #[error("Invalid LNURL username")]
+    InvalidLnurlUsername,
+    #[error("LNURL username is already registered with another key")]
+    LnurlUsernameTaken,
+    #[error("Unknown LNURL username")]
+    UnknownLnurlUsername,
+    #[error("LNURL request is not signed by the registered key or expired")]
+    InvalidLnurlSignature,
+    #[error("LNURL offers don't continue the registered offers or exceed the limit")]
+    InvalidLnurlOffers,
+    #[error("No LNURL offers left to create an invoice from")]
+    LnurlOffersExhausted,
 }
 
 impl From<InvalidAmountTierError> for ClientError {
@@ -1423,3 +2515,108 @@ impl From<InvalidAmountTierError> for ClientError {
         ClientError::InvalidAmountTier(e.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::{KeyPair, Secp256k1};
+    use fedimint_core::modules::ln::{GatewayFee, LightningGateway};
+
+    use fedimint_api::Amount;
+
+    use crate::{select_cheapest_gateway, OUTGOING_LN_CONTRACT_TIMELOCK};
+
+    fn gateway(seed: u8, base_msat: u32, proportional_millionths: u32) -> LightningGateway {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[seed; 32]).unwrap();
+        LightningGateway {
+            mint_pub_key: keypair.x_only_public_key().0,
+            node_pub_key: keypair.public_key(),
+            api: format!("http://gateway-{}.example.com", seed)
+                .parse()
+                .unwrap(),
+            fees: GatewayFee {
+                base_msat,
+                proportional_millionths,
+            },
+            timelock_delta: 10,
+        }
+    }
+
+    #[test]
+    fn selects_gateway_with_lowest_fee() {
+        // Fees for paying 10k sats: 10k msat, 5k msat and 21k msat
+        let amount = Amount::from_sats(10_000);
+        let proportional_fee_only = gateway(1, 0, 1_000);
+        let base_fee_only = gateway(2, 5_000, 0);
+        let expensive = gateway(3, 1_000, 2_000);
+
+        assert_eq!(
+            select_cheapest_gateway(
+                vec![
+                    expensive.clone(),
+                    proportional_fee_only.clone(),
+                    base_fee_only.clone()
+                ],
+                amount
+            ),
+            Some(base_fee_only.clone())
+        );
+        assert_eq!(
+            select_cheapest_gateway(
+                vec![expensive.clone(), proportional_fee_only.clone()],
+                amount
+            ),
+            Some(proportional_fee_only.clone())
+        );
+        assert_eq!(
+            select_cheapest_gateway(vec![expensive.clone()], amount),
+            Some(expensive.clone())
+        );
+        assert_eq!(select_cheapest_gateway(vec![], amount), None);
+
+        // For paying 1k sats the proportional fee of 1k msat is cheaper than the base fee
+        assert_eq!(
+            select_cheapest_gateway(
+                vec![expensive, base_fee_only, proportional_fee_only.clone()],
+                Amount::from_sats(1_000)
+            ),
+            Some(proportional_fee_only)
+        );
+    }
+
+    #[test]
+    fn selects_first_of_equally_cheap_gateways() {
+        // Both charge 10k msat for paying 10k sats
+        let amount = Amount::from_sats(10_000);
+        let first = gateway(1, 0, 1_000);
+        let second = gateway(2, 10_000, 0);
+        let expensive = gateway(3, 20_000, 0);
+
+        assert_eq!(
+            select_cheapest_gateway(
+                vec![expensive.clone(), first.clone(), second.clone()],
+                amount
+            ),
+            Some(first.clone())
+        );
+        assert_eq!(
+            select_cheapest_gateway(vec![second.clone(), first, expensive], amount),
+            Some(second)
+        );
+    }
+
+    #[test]
+    fn ignores_gateways_too_slow_for_our_timelock() {
+        let mut slow = gateway(1, 0, 0);
+        slow.timelock_delta = OUTGOING_LN_CONTRACT_TIMELOCK;
+        let expensive = gateway(2, 1_000, 1_000);
+
+        let amount = Amount::from_sats(10_000);
+
+        assert_eq!(
+            select_cheapest_gateway(vec![slow.clone(), expensive.clone()], amount),
+            Some(expensive)
+        );
+        assert_eq!(select_cheapest_gateway(vec![slow], amount), None);
+    }
+}
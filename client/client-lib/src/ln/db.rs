@@ -1,11 +1,13 @@
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseTransaction, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_core::modules::ln::contracts::ContractId;
-use fedimint_core::modules::ln::LightningGateway;
+use fedimint_core::modules::ln::{LightningGateway, LightningGatewayV0};
+use futures::future::BoxFuture;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
 use super::incoming::ConfirmedInvoice;
+use super::lnurl::{LnurlInvoice, LnurlRegistration};
 use super::outgoing::OutgoingContractAccount;
 use crate::ln::outgoing::OutgoingContractData;
 
@@ -17,8 +19,13 @@ pub enum DbKeyPrefix {
     OutgoingContractAccount = 0x25,
     ConfirmedInvoice = 0x26,
     LightningGateway = 0x28,
+    LnurlRegistration = 0x2d,
+    LnurlInvoice = 0x2e,
 }
 
+/// Current version of the lightning client database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(1);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -114,3 +121,168 @@ impl DatabaseKeyPrefixConst for LightningGatewayKeyPrefix {
     type Key = LightningGatewayKey;
     type Value = LightningGateway;
 }
+
+/// The active gateway in the layout of database version 0
+#[derive(Debug, Encodable, Decodable)]
+pub struct LightningGatewayKeyV0;
+
+impl DatabaseKeyPrefixConst for LightningGatewayKeyV0 {
+    const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
+    type Key = Self;
+    type Value = LightningGatewayV0;
+}
+
+/// Adds the default fee and timelock delta to the active gateway if it was stored before gateways
+/// advertised them
+pub fn migrate_to_v1<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        if let Some(gateway) = dbtx.remove_entry(&LightningGatewayKeyV0).await? {
+            dbtx.insert_new_entry(&LightningGatewayKey, &LightningGateway::from(gateway))
+                .await?;
+        }
+        Ok(())
+    })
+}
+
+/// Username registered at a gateway to receive LNURL-pay payments, kept by the gateway and the user
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct LnurlRegistrationKey(pub String);
+
+impl DatabaseKeyPrefixConst for LnurlRegistrationKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::LnurlRegistration as u8;
+    type Key = Self;
+    type Value = LnurlRegistration;
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LnurlRegistrationKeyPrefix;
+
+impl DatabaseKeyPrefixConst for LnurlRegistrationKeyPrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::LnurlRegistration as u8;
+    type Key = LnurlRegistrationKey;
+    type Value = LnurlRegistration;
+}
+
+/// Invoice the gateway created for an LNURL-pay payment to `username`
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct LnurlInvoiceKey {
+    pub username: String,
+    pub index: u64,
+}
+
+impl DatabaseKeyPrefixConst for LnurlInvoiceKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::LnurlInvoice as u8;
+    type Key = Self;
+    type Value = LnurlInvoice;
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LnurlInvoiceKeyPrefix;
+
+impl DatabaseKeyPrefixConst for LnurlInvoiceKeyPrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::LnurlInvoice as u8;
+    type Key = LnurlInvoiceKey;
+    type Value = LnurlInvoice;
+}
+
+/// Invoices of a single username, the encoding of [`LnurlInvoiceKey`] starts with the username
+#[derive(Debug, Encodable, Decodable)]
+pub struct LnurlInvoiceUsernamePrefix(pub String);
+
+impl DatabaseKeyPrefixConst for LnurlInvoiceUsernamePrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::LnurlInvoice as u8;
+    type Key = LnurlInvoiceKey;
+    type Value = LnurlInvoice;
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::core::MODULE_KEY_LN;
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::{
+        apply_migrations, Database, DatabaseVersion, DatabaseVersionKey, MigrationMap,
+    };
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::modules::ln::{
+        GatewayFee, LightningGateway, LightningGatewayV0, DEFAULT_GATEWAY_TIMELOCK_DELTA,
+    };
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use super::{migrate_to_v1, LightningGatewayKey, LightningGatewayKeyV0};
+
+    async fn migrate(db: &Database) {
+        let mut migrations = MigrationMap::new();
+        migrations.insert(DatabaseVersion(0), migrate_to_v1);
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        apply_migrations(&mut dbtx, MODULE_KEY_LN, DatabaseVersion(1), migrations)
+            .await
+            .expect("Migration failed");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn migrate_to_v1_adds_default_fee_and_timelock_delta() {
+        let db: Database = MemDatabase::new().into();
+
+        // Write a database fixture using the version 0 layout
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[42; 32]).unwrap();
+        let gateway_v0 = LightningGatewayV0 {
+            mint_pub_key: keypair.x_only_public_key().0,
+            node_pub_key: keypair.public_key(),
+            api: "http://gateway.example.com".parse().unwrap(),
+        };
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_new_entry(&LightningGatewayKeyV0, &gateway_v0)
+            .await
+            .unwrap();
+        dbtx.commit_tx().await.expect("DB Error");
+
+        migrate(&db).await;
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey(MODULE_KEY_LN))
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(1))
+        );
+        assert_eq!(
+            dbtx.get_value(&LightningGatewayKey).await.unwrap(),
+            Some(LightningGateway {
+                mint_pub_key: gateway_v0.mint_pub_key,
+                node_pub_key: gateway_v0.node_pub_key,
+                api: gateway_v0.api,
+                fees: GatewayFee {
+                    base_msat: 0,
+                    proportional_millionths: 10_000,
+                },
+                timelock_delta: DEFAULT_GATEWAY_TIMELOCK_DELTA,
+            })
+        );
+
+        // Commit to surpress the warning message
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn migrate_to_v1_without_active_gateway() {
+        let db: Database = MemDatabase::new().into();
+
+        migrate(&db).await;
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey(MODULE_KEY_LN))
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(1))
+        );
+        assert_eq!(dbtx.get_value(&LightningGatewayKey).await.unwrap(), None);
+
+        // Commit to surpress the warning message
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+}
@@ -0,0 +1,284 @@
+//! LNURL-pay ("lightning address") payments received by a gateway on behalf of a user.
+//!
+//! A user registers a username together with a public key at the gateway and hands it a batch of
+//! [`LnurlOffer`]s. Every offer contains the payment hash of a payment key the user derived from
+//! their secret and the payment key itself, encrypted to the federation. When someone pays
+//! `username@gateway` the gateway uses up the next offer to create an incoming contract offer,
+//! just like [`Client::generate_invoice`](crate::Client::generate_invoice) does with a random
+//! payment key. The gateway only learns a payment key once the federation decrypted it for a
+//! funded contract, and only the user knows its secret key, so only they can claim the contract.
+//!
+//! All requests of the user are signed with the registered key.
+use bitcoin::XOnlyPublicKey;
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::Amount;
+use fedimint_core::modules::ln::contracts::EncryptedPreimage;
+use lightning_invoice::Invoice;
+use secp256k1::schnorr::Signature;
+use secp256k1::{KeyPair, Message, Secp256k1, Signing, Verification};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::FederationId;
+
+/// Smallest amount a payer may send to a lightning address
+pub const LNURL_MIN_SENDABLE: Amount = Amount::from_sats(1);
+
+/// Largest amount a payer may send to a lightning address
+pub const LNURL_MAX_SENDABLE: Amount = Amount::from_sats(10_000_000);
+
+/// Maximum length of a lightning address username
+pub const LNURL_MAX_USERNAME_LEN: usize = 64;
+
+/// Maximum number of unused offers the gateway keeps for a username
+pub const LNURL_MAX_OFFERS: usize = 100;
+
+/// Seconds for which a signed request to list invoices is accepted, limits replaying it
+pub const LNURL_REQUEST_MAX_AGE: u64 = 300;
+
+/// Tags making sure signatures of one request type can't be used for another one
+const LNURL_REGISTER_TAG: &[u8] = b"fedimint-lnurl-register";
+const LNURL_INVOICES_TAG: &[u8] = b"fedimint-lnurl-invoices";
+
+/// Payment hash and encrypted preimage the user generated for a future payment
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LnurlOffer {
+    /// Index the payment key was derived with
+    pub index: u64,
+    pub payment_hash: sha256::Hash,
+    /// Payment key encrypted to the federation's threshold key
+    pub encrypted_preimage: EncryptedPreimage,
+}
+
+/// A username registered at the gateway
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LnurlRegistration {
+    /// Key the user signs their requests with
+    pub pubkey: XOnlyPublicKey,
+    /// Offers not used for an invoice yet, in the order they will be used
+    pub offers: Vec<LnurlOffer>,
+    /// Index the next registered offer must have, so an offer can't be registered twice
+    pub next_index: u64,
+}
+
+/// Invoice the gateway created for a payment to a registered username
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LnurlInvoice {
+    /// Index of the offer the invoice was created from
+    pub index: u64,
+    #[serde(serialize_with = "serialize_invoice")]
+    #[serde(deserialize_with = "deserialize_invoice")]
+    pub invoice: Invoice,
+}
+
+/// Request to bind a username of the gateway's lightning address domain to a public key and to
+/// add offers to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LnurlRegisterPayload {
+    pub federation_id: FederationId,
+    pub username: String,
+    pub pubkey: XOnlyPublicKey,
+    pub offers: Vec<LnurlOffer>,
+    /// Signature of `pubkey` over all other fields
+    pub signature: Signature,
+}
+
+impl LnurlRegisterPayload {
+    pub fn new<C: Signing>(
+        secp: &Secp256k1<C>,
+        keypair: &KeyPair,
+        federation_id: FederationId,
+        username: String,
+        offers: Vec<LnurlOffer>,
+    ) -> Self {
+        let pubkey = keypair.x_only_public_key().0;
+        let message = Self::message(&federation_id, &username, &pubkey, &offers);
+        LnurlRegisterPayload {
+            federation_id,
+            username,
+            pubkey,
+            offers,
+            signature: secp.sign_schnorr(&message, keypair),
+        }
+    }
+
+    /// Returns `true` if the request was signed by `pubkey`
+    pub fn verify_signature<C: Verification>(&self, secp: &Secp256k1<C>) -> bool {
+        let message = Self::message(
+            &self.federation_id,
+            &self.username,
+            &self.pubkey,
+            &self.offers,
+        );
+        secp.verify_schnorr(&self.signature, &message, &self.pubkey)
+            .is_ok()
+    }
+
+    fn message(
+        federation_id: &FederationId,
+        username: &str,
+        pubkey: &XOnlyPublicKey,
+        offers: &[LnurlOffer],
+    ) -> Message {
+        let mut engine = sha256::Hash::engine();
+        engine.input(LNURL_REGISTER_TAG);
+        federation_id
+            .0
+            .as_bytes()
+            .consensus_encode(&mut engine)
+            .expect("can't fail");
+        username
+            .as_bytes()
+            .consensus_encode(&mut engine)
+            .expect("can't fail");
+        pubkey.consensus_encode(&mut engine).expect("can't fail");
+        offers.consensus_encode(&mut engine).expect("can't fail");
+        Message::from_slice(&sha256::Hash::from_engine(engine)[..])
+            .expect("hashes are valid messages")
+    }
+}
+
+/// Request to list the invoices of a username, signed by its registered key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LnurlInvoicesRequest {
+    /// Seconds since the unix epoch at which the request was signed
+    pub timestamp: u64,
+    pub signature: Signature,
+}
+
+impl LnurlInvoicesRequest {
+    pub fn new<C: Signing>(
+        secp: &Secp256k1<C>,
+        keypair: &KeyPair,
+        username: &str,
+        timestamp: u64,
+    ) -> Self {
+        LnurlInvoicesRequest {
+            timestamp,
+            signature: secp.sign_schnorr(&Self::message(username, timestamp), keypair),
+        }
+    }
+
+    /// Returns `true` if the request for the invoices of `username` was signed by `pubkey`
+    pub fn verify_signature<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        pubkey: &XOnlyPublicKey,
+        username: &str,
+    ) -> bool {
+        let message = Self::message(username, self.timestamp);
+        secp.verify_schnorr(&self.signature, &message, pubkey)
+            .is_ok()
+    }
+
+    fn message(username: &str, timestamp: u64) -> Message {
+        let mut engine = sha256::Hash::engine();
+        engine.input(LNURL_INVOICES_TAG);
+        username
+            .as_bytes()
+            .consensus_encode(&mut engine)
+            .expect("can't fail");
+        timestamp.consensus_encode(&mut engine).expect("can't fail");
+        Message::from_slice(&sha256::Hash::from_engine(engine)[..])
+            .expect("hashes are valid messages")
+    }
+}
+
+/// Usernames are restricted to the characters allowed by LUD-16
+pub fn is_valid_lnurl_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= LNURL_MAX_USERNAME_LEN
+        && username
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '-' | '_' | '.'))
+}
+
+fn serialize_invoice<S: Serializer>(invoice: &Invoice, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&invoice.to_string())
+}
+
+fn deserialize_invoice<'de, D: Deserializer<'de>>(d: D) -> Result<Invoice, D::Error> {
+    String::deserialize(d)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::{sha256, Hash};
+    use fedimint_core::modules::ln::contracts::{EncryptedPreimage, Preimage};
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use super::{is_valid_lnurl_username, LnurlInvoicesRequest, LnurlOffer, LnurlRegisterPayload};
+    use crate::FederationId;
+
+    #[test]
+    fn register_signature_covers_all_fields() {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let other_keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let threshold_key = threshold_crypto::SecretKey::random().public_key();
+        let offers = (0..2)
+            .map(|index| LnurlOffer {
+                index,
+                payment_hash: sha256::Hash::hash(&[index as u8; 32]),
+                encrypted_preimage: EncryptedPreimage::new(
+                    Preimage([index as u8; 32]),
+                    &threshold_key,
+                ),
+            })
+            .collect();
+        let payload = LnurlRegisterPayload::new(
+            &secp,
+            &keypair,
+            FederationId("fed".into()),
+            "satoshi".into(),
+            offers,
+        );
+        assert!(payload.verify_signature(&secp));
+
+        let mut tampered = payload.clone();
+        tampered.username = "hal".into();
+        assert!(!tampered.verify_signature(&secp));
+
+        let mut tampered = payload.clone();
+        tampered.federation_id = FederationId("other fed".into());
+        assert!(!tampered.verify_signature(&secp));
+
+        let mut tampered = payload.clone();
+        tampered.offers.pop();
+        assert!(!tampered.verify_signature(&secp));
+
+        let mut tampered = payload;
+        tampered.pubkey = other_keypair.x_only_public_key().0;
+        assert!(!tampered.verify_signature(&secp));
+    }
+
+    #[test]
+    fn invoices_request_signature_covers_username_and_timestamp() {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let pubkey = keypair.x_only_public_key().0;
+        let request = LnurlInvoicesRequest::new(&secp, &keypair, "satoshi", 1_000);
+        assert!(request.verify_signature(&secp, &pubkey, "satoshi"));
+
+        assert!(!request.verify_signature(&secp, &pubkey, "hal"));
+        let other_keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let other_pubkey = other_keypair.x_only_public_key().0;
+        assert!(!request.verify_signature(&secp, &other_pubkey, "satoshi"));
+
+        let mut replayed = request;
+        replayed.timestamp += 1;
+        assert!(!replayed.verify_signature(&secp, &pubkey, "satoshi"));
+    }
+
+    #[test]
+    fn username_validation() {
+        assert!(is_valid_lnurl_username("satoshi"));
+        assert!(is_valid_lnurl_username("hal.finney_2-0"));
+        assert!(!is_valid_lnurl_username(""));
+        assert!(!is_valid_lnurl_username("Satoshi"));
+        assert!(!is_valid_lnurl_username("sat@shi"));
+        assert!(!is_valid_lnurl_username(&"a".repeat(65)));
+    }
+}
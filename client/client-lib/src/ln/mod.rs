@@ -1,6 +1,7 @@
 // TODO: once user and mint client are merged, make this private again
 pub mod db;
 pub mod incoming;
+pub mod lnurl;
 pub mod outgoing;
 
 use std::sync::Arc;
@@ -60,7 +61,7 @@ impl ClientModulePlugin for LnClient {
     ) -> TransactionItemAmount {
         TransactionItemAmount {
             amount: input.amount,
-            fee: self.config.fee_consensus.contract_input,
+            fee: self.config.fee_consensus.contract_input_fee(input.amount),
         }
     }
 
@@ -71,7 +72,10 @@ impl ClientModulePlugin for LnClient {
         match output {
             LightningOutput::Contract(account_output) => TransactionItemAmount {
                 amount: account_output.amount,
-                fee: self.config.fee_consensus.contract_output,
+                fee: self
+                    .config
+                    .fee_consensus
+                    .contract_output_fee(account_output.amount),
             },
             LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
                 TransactionItemAmount {
@@ -93,17 +97,29 @@ impl LnClient {
         invoice: Invoice,
         gateway: &LightningGateway,
         timelock: u32,
-        mut rng: impl RngCore + CryptoRng + 'a,
+        rng: impl RngCore + CryptoRng + 'a,
     ) -> Result<LightningOutput> {
-        let contract_amount = {
-            let invoice_amount_msat = invoice
+        let invoice_amount = Amount::from_msats(
+            invoice
                 .amount_milli_satoshis()
-                .ok_or(LnClientError::MissingInvoiceAmount)?;
-            // TODO: better define fee handling
-            // Add 1% fee margin
-            let contract_amount_msat = invoice_amount_msat + (invoice_amount_msat / 100);
-            Amount::from_msats(contract_amount_msat)
-        };
+                .ok_or(LnClientError::MissingInvoiceAmount)?,
+        );
+        self.create_outgoing_part_output(dbtx, invoice, invoice_amount, gateway, timelock, rng)
+            .await
+    }
+
+    /// Creates an outgoing contract that funds the gateway for paying `part_amount` of the
+    /// invoice, which allows splitting a multi-part payment across several gateways
+    pub async fn create_outgoing_part_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut DatabaseTransaction<'b>,
+        invoice: Invoice,
+        part_amount: Amount,
+        gateway: &LightningGateway,
+        timelock: u32,
+        mut rng: impl RngCore + CryptoRng + 'a,
+    ) -> Result<LightningOutput> {
+        let contract_amount = part_amount + gateway.fees.fee(part_amount);
 
         let user_sk = bitcoin::KeyPair::new(&self.context.secp, &mut rng);
 
@@ -305,10 +321,25 @@ impl LnClient {
     }
 }
 
+/// One part of a multi-part payment that a gateway is asked to pay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentPart {
+    /// Distinguishes the part from the other parts of the payment, which is required by lightning
+    /// nodes sending multiple parts of the same invoice. Starts at 1, since 0 denotes a payment
+    /// that isn't split.
+    pub id: u16,
+    /// Amount of the invoice paid by this part
+    pub amount: Amount,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PayInvoicePayload {
     pub federation_id: FederationId,
     pub contract_id: ContractId,
+    /// Set if the contract only funds one part of a multi-part payment, in which case the gateway
+    /// only pays this part of the invoice
+    #[serde(default)]
+    pub part: Option<PaymentPart>,
 }
 
 impl PayInvoicePayload {
@@ -316,6 +347,19 @@ impl PayInvoicePayload {
         Self {
             contract_id,
             federation_id,
+            part: None,
+        }
+    }
+
+    pub fn new_part(
+        federation_id: FederationId,
+        contract_id: ContractId,
+        part: PaymentPart,
+    ) -> Self {
+        Self {
+            contract_id,
+            federation_id,
+            part: Some(part),
         }
     }
 }
@@ -342,16 +386,21 @@ mod tests {
     use bitcoin::hashes::{sha256, Hash};
     use bitcoin::Address;
     use fedimint_api::config::ConfigGenParams;
+    use fedimint_api::core::client::ClientModulePlugin;
     use fedimint_api::core::OutputOutcome;
     use fedimint_api::db::mem_impl::MemDatabase;
     use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::module::TransactionItemAmount;
     use fedimint_api::{Amount, OutPoint, TransactionId};
     use fedimint_core::epoch::SignedEpochOutcome;
-    use fedimint_core::modules::ln::config::LightningModuleClientConfig;
+    use fedimint_core::modules::ln::config::{FeeConsensus, LightningModuleClientConfig};
+    use fedimint_core::modules::ln::contracts::account::AccountContract;
     use fedimint_core::modules::ln::contracts::incoming::IncomingContractOffer;
-    use fedimint_core::modules::ln::contracts::{ContractId, IdentifyableContract};
+    use fedimint_core::modules::ln::contracts::{Contract, ContractId, IdentifyableContract};
     use fedimint_core::modules::ln::{ContractAccount, LightningModule, LightningModuleConfigGen};
-    use fedimint_core::modules::ln::{LightningGateway, LightningOutput};
+    use fedimint_core::modules::ln::{
+        ContractOutput, GatewayFee, LightningGateway, LightningInput, LightningOutput,
+    };
     use fedimint_core::modules::mint::db::ECashUserBackupSnapshot;
     use fedimint_core::modules::wallet::PegOutFees;
     use fedimint_core::outcome::{SerdeOutputOutcome, TransactionStatus};
@@ -505,6 +554,49 @@ mod tests {
         (fed, client_config.cast().unwrap(), client_context)
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_fee_accounting() {
+        let (_fed, mut client_config, client_context) = new_mint_and_client().await;
+        client_config.fee_consensus = FeeConsensus {
+            contract_input: Amount::from_msats(100),
+            contract_input_ppm: 1_000,
+            contract_output: Amount::from_msats(200),
+            contract_output_ppm: 2_500,
+        };
+        let client = LnClient {
+            config: client_config,
+            context: Arc::new(client_context),
+        };
+
+        let contract = Contract::Account(AccountContract {
+            key: secp256k1_zkp::XOnlyPublicKey::from_slice(&[42; 32][..]).unwrap(),
+        });
+        let input = LightningInput {
+            contract_id: contract.contract_id(),
+            amount: Amount::from_sats(1_000),
+            witness: None,
+        };
+        assert_eq!(
+            client.input_amount(&input),
+            TransactionItemAmount {
+                amount: Amount::from_sats(1_000),
+                fee: Amount::from_msats(100 + 1_000),
+            }
+        );
+
+        let output = LightningOutput::Contract(ContractOutput {
+            amount: Amount::from_sats(1_000),
+            contract,
+        });
+        assert_eq!(
+            client.output_amount(&output),
+            TransactionItemAmount {
+                amount: Amount::from_sats(1_000),
+                fee: Amount::from_msats(200 + 2_500),
+            }
+        );
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_outgoing() {
         let mut rng = rand::thread_rng();
@@ -538,6 +630,11 @@ mod tests {
                 node_pub_key,
                 api: Url::parse("http://example.com")
                     .expect("Could not parse URL to generate GatewayClientConfig API endpoint"),
+                fees: GatewayFee {
+                    base_msat: 1_000,
+                    proportional_millionths: 5_000,
+                },
+                timelock_delta: 10,
             }
         };
         let timelock = 42;
@@ -576,7 +673,9 @@ mod tests {
         assert_eq!(contract_acc.contract.gateway_key, gateway.mint_pub_key);
         // TODO: test that the client has its key
 
-        let expected_amount_msat = invoice_amt_msat + (invoice_amt_msat / 100);
+        // The contract pays the gateway's advertised fee on top of the invoice
+        let expected_amount_msat =
+            invoice_amt_msat + 1_000 + (invoice_amt_msat * 5_000 / 1_000_000);
         let expected_amount = Amount::from_msats(expected_amount_msat);
         assert_eq!(contract_acc.amount, expected_amount);
 
@@ -612,4 +711,90 @@ mod tests {
             .unwrap();
         assert_eq!(account.amount, Amount::ZERO);
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_outgoing_part() {
+        let mut rng = rand::thread_rng();
+        let (fed, client_config, client_context) = new_mint_and_client().await;
+
+        let client = LnClient {
+            config: client_config,
+            context: Arc::new(client_context),
+        };
+
+        let out_point = OutPoint {
+            txid: sha256::Hash::hash(b"txid").into(),
+            out_idx: 0,
+        };
+
+        let invoice: Invoice =
+            "lnbcrt1u1pslya9jpp58005t06rezrqx2g6e84j44gs0aalcxfc47nzu97040fjzfrl\
+        cmasdq8w3jhxaqxqyjw5qcqp2sp5huz0lzk5v47kfdd58d0k96gm06kr2rkedgr5j8488jaqk44puz6s9qyyssqexyz\
+        s9rzrhu73625ag4ndtw4fqmstrnuaukh3z427la6mn2m2u25zy7j2jfk36pcsz5hl4m07ehcmhvh729424tjagv4lx2\
+        vgdsgy3sqphsc92"
+                .parse()
+                .unwrap();
+        let part_amount = Amount::from_msats(invoice.amount_milli_satoshis().unwrap() / 2);
+        let gateway = LightningGateway {
+            mint_pub_key: secp256k1_zkp::XOnlyPublicKey::from_slice(&[42; 32][..]).unwrap(),
+            node_pub_key: secp256k1_zkp::PublicKey::from_slice(&[2; 33][..]).unwrap(),
+            api: Url::parse("http://example.com")
+                .expect("Could not parse URL to generate GatewayClientConfig API endpoint"),
+            fees: GatewayFee {
+                base_msat: 1_000,
+                proportional_millionths: 5_000,
+            },
+            timelock_delta: 10,
+        };
+        let timelock = 42;
+
+        let mut dbtx = client
+            .context
+            .db
+            .begin_transaction(ModuleDecoderRegistry::default())
+            .await;
+        let output = client
+            .create_outgoing_part_output(
+                &mut dbtx,
+                invoice.clone(),
+                part_amount,
+                &gateway,
+                timelock,
+                &mut rng,
+            )
+            .await
+            .unwrap();
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let contract = match &output {
+            LightningOutput::Contract(c) => &c.contract,
+            _ => unreachable!(),
+        };
+
+        fed.lock()
+            .await
+            .consensus_round(&[], &[(out_point, output.clone())])
+            .await;
+
+        let contract_acc = client
+            .get_outgoing_contract(contract.contract_id())
+            .await
+            .unwrap();
+
+        // The part's contract keeps the whole invoice but only funds the part plus the fee
+        assert_eq!(contract_acc.contract.invoice, invoice);
+        assert_eq!(contract_acc.contract.hash, *invoice.payment_hash());
+        assert_eq!(
+            contract_acc.amount,
+            part_amount + Amount::from_msats(1_000 + part_amount.msats * 5_000 / 1_000_000)
+        );
+
+        // The part can be refunded on its own like any other outgoing contract
+        let refund_inputs = client.refundable_outgoing_contracts(timelock as u64).await;
+        assert_eq!(refund_inputs.len(), 1);
+        assert_eq!(
+            refund_inputs[0].contract_account.contract.contract_id(),
+            contract.contract_id()
+        );
+    }
 }
@@ -784,6 +784,7 @@ impl EcashRecoveryTracker {
     ) {
         match item {
             ConsensusItem::EpochOutcomeSignatureShare(_) => {}
+            ConsensusItem::SnapshotSignatureShare(_) => {}
             ConsensusItem::Transaction(tx) => {
                 let txid = tx.tx_hash();
 
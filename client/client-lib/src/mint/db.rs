@@ -1,4 +1,4 @@
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::{Amount, OutPoint, TieredMulti, TransactionId};
 use fedimint_core::modules::mint::Nonce;
@@ -17,6 +17,9 @@ pub enum DbKeyPrefix {
     NotesPerDenomination = 0x2b,
 }
 
+/// Current version of the mint client database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -164,7 +164,10 @@ impl ClientModulePlugin for MintClient {
     ) -> TransactionItemAmount {
         TransactionItemAmount {
             amount: input.total_amount(),
-            fee: self.config.fee_consensus.coin_spend_abs * (input.item_count() as u64),
+            fee: self
+                .config
+                .fee_consensus
+                .coin_spend_fee(input.total_amount(), input.item_count()),
         }
     }
 
@@ -174,12 +177,51 @@ impl ClientModulePlugin for MintClient {
     ) -> TransactionItemAmount {
         TransactionItemAmount {
             amount: output.total_amount(),
-            fee: self.config.fee_consensus.coin_issuance_abs * (output.item_count() as u64),
+            fee: self
+                .config
+                .fee_consensus
+                .coin_issuance_fee(output.total_amount(), output.item_count()),
         }
     }
 }
 
 impl MintClient {
+    /// Change outputs that spend `surplus` exactly once the proportional issuance fee of every
+    /// change output has been deducted
+    ///
+    /// Since the fee is rounded down not every surplus can be spent by a single output, the msat
+    /// left over is then issued as an additional change output that costs no fee.
+    pub fn change_amounts(&self, surplus: Amount) -> Vec<Amount> {
+        let ppm = self.config.fee_consensus.coin_issuance_ppm;
+        let mut change = vec![];
+        let mut remaining = surplus;
+        loop {
+            let amount = self.change_amount(remaining);
+            change.push(amount);
+            remaining -= amount + amount.mul_ppm(ppm);
+            if remaining == Amount::ZERO || amount == Amount::ZERO {
+                return change;
+            }
+        }
+    }
+
+    /// Largest change amount that can be issued from `surplus` once the proportional issuance
+    /// fee of the change output itself has been deducted
+    fn change_amount(&self, surplus: Amount) -> Amount {
+        let ppm = self.config.fee_consensus.coin_issuance_ppm;
+        let change = Amount::from_msats(
+            ((surplus.msats as u128 * 1_000_000) / (1_000_000 + ppm as u128)) as u64,
+        );
+        // rounding down may leave one msat unaccounted for, in which case a slightly bigger change
+        // output can still be afforded
+        let bigger_change = change + Amount::from_msats(1);
+        if bigger_change + bigger_change.mul_ppm(ppm) == surplus {
+            bigger_change
+        } else {
+            change
+        }
+    }
+
     pub async fn start_dbtx(&self) -> DatabaseTransaction<'_> {
         self.context
             .db
@@ -678,7 +720,7 @@ mod tests {
     use fedimint_core::modules::ln::contracts::incoming::IncomingContractOffer;
     use fedimint_core::modules::ln::contracts::ContractId;
     use fedimint_core::modules::ln::{ContractAccount, LightningGateway};
-    use fedimint_core::modules::mint::config::MintClientConfig;
+    use fedimint_core::modules::mint::config::{FeeConsensus, MintClientConfig};
     use fedimint_core::modules::mint::db::ECashUserBackupSnapshot;
     use fedimint_core::modules::mint::{
         Mint, MintConfigGenParams, MintConfigGenerator, MintOutput,
@@ -975,6 +1017,60 @@ mod tests {
         }
     }
 
+    fn client_with_issuance_ppm(coin_issuance_ppm: u64) -> MintClient {
+        MintClient {
+            epoch_pk: threshold_crypto::SecretKey::random().public_key(),
+            config: MintClientConfig {
+                tbs_pks: Tiered::from_iter([]),
+                fee_consensus: FeeConsensus {
+                    coin_issuance_ppm,
+                    ..Default::default()
+                },
+                peer_tbs_pks: BTreeMap::default(),
+                max_notes_per_denomination: 0,
+            },
+            context: Arc::new(ClientContext {
+                db: MemDatabase::new().into(),
+                api: WsFederationApi::new(vec![]).into(),
+                secp: Default::default(),
+            }),
+            secret: DerivableSecret::new_root(&[], &[]).child_key(MINT_SECRET_CHILD_ID),
+        }
+    }
+
+    #[test]
+    fn change_amounts_balance_exactly() {
+        for ppm in [0, 1, 1000, 2_500, 100_000, 999_999] {
+            let client = client_with_issuance_ppm(ppm);
+            for surplus in (0..5_000).chain([1_000_000, 123_456_789, 21_000_000_000_000]) {
+                let surplus = Amount::from_msats(surplus);
+                let change = client.change_amounts(surplus);
+                let spent: Amount = change.iter().map(|c| *c + c.mul_ppm(ppm)).sum();
+                assert_eq!(spent, surplus, "ppm {ppm}, surplus {surplus}");
+                assert!(change.len() <= 2, "ppm {ppm}, surplus {surplus}");
+
+                // no bigger change is affordable
+                let bigger = change[0] + Amount::from_msats(1);
+                assert!(bigger + bigger.mul_ppm(ppm) > surplus);
+            }
+        }
+    }
+
+    #[test]
+    fn change_amounts_with_unbalanceable_surplus() {
+        // 999 msat change costs no fee, 1000 msat change costs 1 msat fee
+        let client = client_with_issuance_ppm(1000);
+        assert_eq!(
+            client.change_amounts(Amount::from_msats(1000)),
+            vec![Amount::from_msats(999), Amount::from_msats(1)]
+        );
+        assert_eq!(
+            client.change_amounts(Amount::from_msats(1001)),
+            vec![Amount::from_msats(1000)]
+        );
+        assert_eq!(client.change_amounts(Amount::ZERO), vec![Amount::ZERO]);
+    }
+
     #[allow(clippy::needless_collect)]
     #[tokio::test]
     async fn test_parallel_issuance() {
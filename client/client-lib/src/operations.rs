@@ -0,0 +1,189 @@
+//! Persistent log of client operations, e.g. to show users their transaction history
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::{Amount, TransactionId};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    /// Notes were taken out of the wallet to be handed to someone else
+    SpendEcash,
+    /// Notes (usually received from someone else) were exchanged for fresh ones
+    Reissue,
+    PegIn,
+    PegOut,
+    /// A lightning invoice was paid through a gateway
+    LnPay,
+    /// A lightning payment to one of our invoices was claimed
+    LnReceive,
+    /// An outgoing lightning contract was refunded after the payment failed
+    LnRefund,
+}
+
+impl OperationKind {
+    pub const ALL: [OperationKind; 7] = [
+        OperationKind::SpendEcash,
+        OperationKind::Reissue,
+        OperationKind::PegIn,
+        OperationKind::PegOut,
+        OperationKind::LnPay,
+        OperationKind::LnReceive,
+        OperationKind::LnRefund,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            OperationKind::SpendEcash => "spend_ecash",
+            OperationKind::Reissue => "reissue",
+            OperationKind::PegIn => "peg_in",
+            OperationKind::PegOut => "peg_out",
+            OperationKind::LnPay => "ln_pay",
+            OperationKind::LnReceive => "ln_receive",
+            OperationKind::LnRefund => "ln_refund",
+        }
+    }
+
+    /// Whether the operation is done once the federation accepted its transaction, lightning
+    /// payments additionally need the gateway to pay the invoice
+    pub fn is_complete_when_accepted(&self) -> bool {
+        !matches!(self, OperationKind::LnPay)
+    }
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown operation kind {0}")]
+pub struct UnknownOperationKind(String);
+
+impl FromStr for OperationKind {
+    type Err = UnknownOperationKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OperationKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| UnknownOperationKind(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    /// The outcome of the operation isn't known yet
+    Pending,
+    /// The federation accepted the transaction and, for lightning payments, the gateway paid the
+    /// invoice
+    Completed,
+    /// The federation rejected the transaction or the lightning payment failed and was refunded
+    Failed,
+}
+
+/// Record of an operation in the [`OperationLogKey`](crate::db::OperationLogKey) table
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct OperationLogEntry {
+    /// Seconds since the unix epoch when the operation was submitted
+    pub timestamp: u64,
+    pub kind: OperationKind,
+    /// Amount sent or received, excluding fees
+    pub amount: Amount,
+    /// Fees paid to the federation and, if applicable, gateways and bitcoin miners
+    pub fees: Amount,
+    /// Federation transaction of the operation, `None` if none was necessary
+    pub txid: Option<TransactionId>,
+    pub status: OperationStatus,
+}
+
+/// Selects which entries [`Client::list_operations`](crate::Client::list_operations) returns
+#[derive(Debug, Clone, Default)]
+pub struct OperationFilter {
+    /// Only return operations of these kinds, all kinds if empty
+    pub kinds: Vec<OperationKind>,
+    /// Only return operations submitted at or after this unix timestamp
+    pub since: Option<u64>,
+    /// Only return operations submitted before this unix timestamp
+    pub until: Option<u64>,
+    /// Maximum number of operations to return, starting with the most recent one
+    pub limit: Option<usize>,
+}
+
+impl OperationFilter {
+    pub fn matches(&self, entry: &OperationLogEntry) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&entry.kind))
+            && self.since.map_or(true, |since| since <= entry.timestamp)
+            && self.until.map_or(true, |until| entry.timestamp < until)
+    }
+}
+
+/// Current time since the unix epoch
+pub(crate) fn unix_time_now() -> Duration {
+    #[cfg(not(target_family = "wasm"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap()
+    }
+
+    #[cfg(target_family = "wasm")]
+    {
+        Duration::from_secs_f64(js_sys::Date::new_0().get_time() / 1000.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use fedimint_api::Amount;
+
+    use super::{OperationFilter, OperationKind, OperationLogEntry, OperationStatus};
+
+    fn entry(kind: OperationKind, timestamp: u64) -> OperationLogEntry {
+        OperationLogEntry {
+            timestamp,
+            kind,
+            amount: Amount::from_sats(1),
+            fees: Amount::ZERO,
+            txid: None,
+            status: OperationStatus::Completed,
+        }
+    }
+
+    #[test]
+    fn operation_kind_string_roundtrip() {
+        for kind in OperationKind::ALL {
+            assert_eq!(OperationKind::from_str(&kind.to_string()).unwrap(), kind);
+            assert_eq!(
+                serde_json::to_string(&kind).unwrap(),
+                format!("\"{}\"", kind)
+            );
+        }
+        assert!(OperationKind::from_str("peg-in").is_err());
+    }
+
+    #[test]
+    fn operation_filter_matches() {
+        assert!(OperationFilter::default().matches(&entry(OperationKind::PegIn, 10)));
+
+        let filter = OperationFilter {
+            kinds: vec![OperationKind::LnPay, OperationKind::LnReceive],
+            since: Some(10),
+            until: Some(20),
+            limit: None,
+        };
+        assert!(filter.matches(&entry(OperationKind::LnPay, 10)));
+        assert!(filter.matches(&entry(OperationKind::LnReceive, 19)));
+        assert!(!filter.matches(&entry(OperationKind::LnPay, 9)));
+        assert!(!filter.matches(&entry(OperationKind::LnPay, 20)));
+        assert!(!filter.matches(&entry(OperationKind::PegOut, 15)));
+    }
+}
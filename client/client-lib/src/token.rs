@@ -0,0 +1,378 @@
+//! Self-contained ecash tokens that can be transferred out-of-band, e.g. as (animated) QR codes.
+//!
+//! A token contains everything a recipient needs to redeem the notes, including the
+//! information required to connect to the federation in case they never joined it.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use bech32::{FromBase32, ToBase32, Variant};
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::TieredMulti;
+use thiserror::Error;
+
+use crate::api::WsFederationConnect;
+use crate::mint::SpendableNote;
+use crate::utils::parse_ecash;
+use crate::FederationId;
+
+/// Version of the binary [`EcashToken`] encoding, bumped on incompatible changes
+pub const ECASH_TOKEN_VERSION: u8 = 1;
+
+/// Human readable part of bech32m encoded [`EcashToken`]s
+pub const ECASH_TOKEN_HRP: &str = "fedimint";
+
+/// Prefix of every animated QR code frame, only uses characters of the QR alphanumeric mode
+pub const ECASH_QR_FRAME_PREFIX: &str = "FMQR";
+
+/// Number of bytes of the token's hash identifying the token in its QR code frames
+const ECASH_QR_TOKEN_ID_LEN: usize = 4;
+
+/// Notes of a federation packaged together with the federation's connection info
+#[derive(Debug, Clone)]
+pub struct EcashToken {
+    pub federation_id: FederationId,
+    pub connect: WsFederationConnect,
+    pub notes: TieredMulti<SpendableNote>,
+    /// Optional message from the sender to the recipient
+    pub memo: Option<String>,
+}
+
+/// Notes received from another user, either as an [`EcashToken`] or in the legacy base64 encoding
+/// of bare notes used before tokens were introduced
+#[derive(Debug, Clone)]
+pub enum ReceivedEcash {
+    Token(EcashToken),
+    /// Notes without the federation's connection info, which can only be redeemed in a federation
+    /// the client already joined
+    Legacy(TieredMulti<SpendableNote>),
+}
+
+impl ReceivedEcash {
+    /// Parses an [`EcashToken`], falling back to the legacy encoding of bare notes
+    pub fn parse(s: &str) -> Result<Self, EcashTokenError> {
+        match EcashToken::from_bech32m(s) {
+            Ok(token) => Ok(ReceivedEcash::Token(token)),
+            // report why the string isn't a token, the legacy format is deprecated
+            Err(e) => parse_ecash(s).map(ReceivedEcash::Legacy).map_err(|_| e),
+        }
+    }
+
+    pub fn into_notes(self) -> TieredMulti<SpendableNote> {
+        match self {
+            ReceivedEcash::Token(token) => token.notes,
+            ReceivedEcash::Legacy(notes) => notes,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EcashTokenError {
+    #[error("Invalid bech32m encoding: {0}")]
+    Bech32(#[from] bech32::Error),
+    #[error("Token has to be encoded as bech32m")]
+    WrongVariant,
+    #[error("Token has prefix {0}, expected {}", ECASH_TOKEN_HRP)]
+    WrongPrefix(String),
+    #[error("Token could not be decoded: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("Invalid QR frame: {0}")]
+    InvalidQrFrame(&'static str),
+}
+
+impl EcashToken {
+    /// Encodes the token as a checksummed bech32m string
+    pub fn to_bech32m(&self) -> String {
+        let mut bytes = Vec::new();
+        self.consensus_encode(&mut bytes)
+            .expect("Writing to vec can't fail");
+        bech32::encode(ECASH_TOKEN_HRP, bytes.to_base32(), Variant::Bech32m).expect("HRP is valid")
+    }
+
+    /// Decodes a token previously encoded by [`EcashToken::to_bech32m`]
+    pub fn from_bech32m(s: &str) -> Result<Self, EcashTokenError> {
+        let (hrp, data, variant) = bech32::decode(s)?;
+        if hrp != ECASH_TOKEN_HRP {
+            return Err(EcashTokenError::WrongPrefix(hrp));
+        }
+        if variant != Variant::Bech32m {
+            return Err(EcashTokenError::WrongVariant);
+        }
+
+        let bytes = Vec::<u8>::from_base32(&data)?;
+        Ok(Self::consensus_decode(
+            &mut std::io::Cursor::new(bytes),
+            &ModuleDecoderRegistry::default(),
+        )?)
+    }
+
+    /// Splits the token into frames of at most `max_chunk_len` token characters to be displayed
+    /// as an animated QR code, see [`EcashQrDecoder`] for reassembling them. Every frame carries
+    /// an id derived from the token, so frames of different tokens can't be mixed up.
+    ///
+    /// Frames are uppercase so that QR codes can use the more compact alphanumeric mode.
+    pub fn to_qr_frames(&self, max_chunk_len: usize) -> Vec<String> {
+        assert!(max_chunk_len > 0, "QR frames can't be empty");
+
+        let token = self.to_bech32m().to_uppercase();
+        let token_id = qr_token_id(&token);
+        let chunks = token
+            .as_bytes()
+            .chunks(max_chunk_len)
+            .map(|chunk| std::str::from_utf8(chunk).expect("bech32 is ascii"))
+            .collect::<Vec<_>>();
+
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(idx, chunk)| {
+                format!(
+                    "{}{}:{}/{}:{}",
+                    ECASH_QR_FRAME_PREFIX,
+                    token_id,
+                    idx,
+                    chunks.len(),
+                    chunk
+                )
+            })
+            .collect()
+    }
+}
+
+/// Identifies the token in QR code frames, uppercase hex to fit the QR alphanumeric mode
+fn qr_token_id(uppercase_token: &str) -> String {
+    let hash = sha256::Hash::hash(uppercase_token.as_bytes());
+    hex::encode_upper(&hash[..ECASH_QR_TOKEN_ID_LEN])
+}
+
+impl fmt::Display for EcashToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_bech32m())
+    }
+}
+
+impl FromStr for EcashToken {
+    type Err = EcashTokenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bech32m(s)
+    }
+}
+
+impl Encodable for EcashToken {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += ECASH_TOKEN_VERSION.consensus_encode(writer)?;
+        len += self.federation_id.0.consensus_encode(writer)?;
+        len += self.connect.members.consensus_encode(writer)?;
+        len += self.notes.consensus_encode(writer)?;
+        len += self.memo.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for EcashToken {
+    fn consensus_decode<R: Read>(
+        r: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let version = u8::consensus_decode(r, modules)?;
+        if version != ECASH_TOKEN_VERSION {
+            return Err(DecodeError::from_str("Unsupported ecash token version"));
+        }
+
+        Ok(EcashToken {
+            federation_id: FederationId(String::consensus_decode(r, modules)?),
+            connect: WsFederationConnect {
+                members: Decodable::consensus_decode(r, modules)?,
+            },
+            notes: Decodable::consensus_decode(r, modules)?,
+            memo: Decodable::consensus_decode(r, modules)?,
+        })
+    }
+}
+
+/// Reassembles an [`EcashToken`] from animated QR code frames scanned in any order
+#[derive(Debug, Default)]
+pub struct EcashQrDecoder {
+    /// Id and frame count of the token, taken from the first frame
+    token: Option<(String, usize)>,
+    chunks: BTreeMap<usize, String>,
+}
+
+impl EcashQrDecoder {
+    /// Adds a scanned frame, returns the token once all frames were received. Scanning the same
+    /// frame multiple times is fine.
+    pub fn add_frame(&mut self, frame: &str) -> Result<Option<EcashToken>, EcashTokenError> {
+        let frame = frame
+            .strip_prefix(ECASH_QR_FRAME_PREFIX)
+            .ok_or(EcashTokenError::InvalidQrFrame("missing prefix"))?;
+        let (token_id, frame) = frame
+            .split_once(':')
+            .ok_or(EcashTokenError::InvalidQrFrame("missing token id"))?;
+        let (position, chunk) = frame
+            .split_once(':')
+            .ok_or(EcashTokenError::InvalidQrFrame("missing chunk"))?;
+        let (idx, total) = position
+            .split_once('/')
+            .ok_or(EcashTokenError::InvalidQrFrame("missing frame count"))?;
+        let idx: usize = idx
+            .parse()
+            .map_err(|_| EcashTokenError::InvalidQrFrame("invalid frame index"))?;
+        let total: usize = total
+            .parse()
+            .map_err(|_| EcashTokenError::InvalidQrFrame("invalid frame count"))?;
+
+        if idx >= total {
+            return Err(EcashTokenError::InvalidQrFrame("frame index out of range"));
+        }
+        let (expected_id, expected_total) = self
+            .token
+            .get_or_insert_with(|| (token_id.to_string(), total));
+        if expected_id != token_id || *expected_total != total {
+            return Err(EcashTokenError::InvalidQrFrame(
+                "frame belongs to a different token",
+            ));
+        }
+        self.chunks.insert(idx, chunk.to_string());
+
+        if self.chunks.len() < total {
+            return Ok(None);
+        }
+
+        let token = self.chunks.values().cloned().collect::<String>();
+        if qr_token_id(&token.to_uppercase()) != token_id {
+            return Err(EcashTokenError::InvalidQrFrame(
+                "frames don't match the token id",
+            ));
+        }
+        EcashToken::from_bech32m(&token).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::{PeerId, TieredMulti};
+    use url::Url;
+
+    use super::{EcashQrDecoder, EcashToken, EcashTokenError, ReceivedEcash};
+    use crate::api::WsFederationConnect;
+    use crate::utils::serialize_ecash;
+    use crate::FederationId;
+
+    fn token() -> EcashToken {
+        EcashToken {
+            federation_id: FederationId("test-federation".into()),
+            connect: WsFederationConnect {
+                members: vec![
+                    (PeerId::from(0), Url::parse("ws://127.0.0.1:5000").unwrap()),
+                    (PeerId::from(1), Url::parse("ws://127.0.0.1:5001").unwrap()),
+                ],
+            },
+            notes: TieredMulti::default(),
+            memo: Some("coffee".into()),
+        }
+    }
+
+    fn assert_same_token(a: &EcashToken, b: &EcashToken) {
+        assert_eq!(a.federation_id, b.federation_id);
+        assert_eq!(a.connect.members, b.connect.members);
+        assert_eq!(a.notes, b.notes);
+        assert_eq!(a.memo, b.memo);
+    }
+
+    #[test]
+    fn token_bech32m_roundtrip() {
+        let token = token();
+        let encoded = token.to_bech32m();
+        assert!(encoded.starts_with("fedimint1"));
+
+        assert_same_token(&token, &EcashToken::from_bech32m(&encoded).unwrap());
+        assert_same_token(
+            &token,
+            &EcashToken::from_bech32m(&encoded.to_uppercase()).unwrap(),
+        );
+
+        // The checksum catches typos
+        let mut corrupted = encoded.into_bytes();
+        let last = corrupted.len() - 1;
+        corrupted[last] = if corrupted[last] == b'q' { b'p' } else { b'q' };
+        assert!(matches!(
+            EcashToken::from_bech32m(&String::from_utf8(corrupted).unwrap()),
+            Err(EcashTokenError::Bech32(_))
+        ));
+    }
+
+    #[test]
+    fn received_ecash_falls_back_to_legacy_notes() {
+        let token = token();
+        assert!(matches!(
+            ReceivedEcash::parse(&token.to_bech32m()),
+            Ok(ReceivedEcash::Token(_))
+        ));
+
+        let legacy = serialize_ecash(&token.notes);
+        match ReceivedEcash::parse(&legacy).unwrap() {
+            ReceivedEcash::Legacy(notes) => assert_eq!(notes, token.notes),
+            ReceivedEcash::Token(_) => panic!("legacy notes parsed as token"),
+        }
+
+        assert!(ReceivedEcash::parse("garbage").is_err());
+    }
+
+    #[test]
+    fn token_qr_frames_roundtrip() {
+        let token = token();
+        let frames = token.to_qr_frames(20);
+        assert!(frames.len() > 1);
+
+        let mut decoder = EcashQrDecoder::default();
+        let mut decoded = None;
+        // Frames can be scanned in any order and more than once
+        for frame in frames.iter().rev().chain(frames.iter()) {
+            if let Some(token) = decoder.add_frame(frame).unwrap() {
+                decoded = Some(token);
+                break;
+            }
+        }
+        assert_same_token(&token, &decoded.expect("all frames were scanned"));
+
+        assert!(EcashQrDecoder::default().add_frame("garbage").is_err());
+    }
+
+    #[test]
+    fn token_qr_frames_of_different_tokens_are_rejected() {
+        let token = token();
+        let other_token = EcashToken {
+            memo: Some("teacup".into()),
+            ..token.clone()
+        };
+        let frames = token.to_qr_frames(20);
+        let other_frames = other_token.to_qr_frames(20);
+        assert_eq!(frames.len(), other_frames.len());
+
+        // Frames of another token with the same number of frames are caught by the token id
+        let mut decoder = EcashQrDecoder::default();
+        assert!(decoder.add_frame(&frames[0]).unwrap().is_none());
+        assert!(matches!(
+            decoder.add_frame(&other_frames[1]),
+            Err(EcashTokenError::InvalidQrFrame(_))
+        ));
+
+        // Chunks that don't belong to the token id are caught once the token is complete, the
+        // tokens differ in their last chunk containing the checksum
+        let (token_id, _) = frames[0].split_once(':').unwrap();
+        let (_, other_last_frame) = other_frames.last().unwrap().split_once(':').unwrap();
+        let mut decoder = EcashQrDecoder::default();
+        for frame in &frames[..frames.len() - 1] {
+            assert!(decoder.add_frame(frame).unwrap().is_none());
+        }
+        assert!(matches!(
+            decoder.add_frame(&format!("{token_id}:{other_last_frame}")),
+            Err(EcashTokenError::InvalidQrFrame(_))
+        ));
+    }
+}
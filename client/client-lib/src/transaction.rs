@@ -85,7 +85,25 @@ impl TransactionBuilder {
     where
         C: AsRef<ClientConfig> + Clone,
     {
-        self.input_amount(client) - self.output_amount(client) - self.fee_amount(client)
+        self.change_outputs(client).into_iter().sum()
+    }
+
+    /// Amounts of the change outputs that balance the transaction exactly
+    fn change_outputs<C>(&self, client: &Client<C>) -> Vec<Amount>
+    where
+        C: AsRef<ClientConfig> + Clone,
+    {
+        let surplus =
+            self.input_amount(client) - self.output_amount(client) - self.fee_amount(client);
+        client.mint_client().change_amounts(surplus)
+    }
+
+    /// Fees paid to the federation by the transaction once built using [`TransactionBuilder::build`]
+    pub fn fees_paid<C>(&self, client: &Client<C>) -> Amount
+    where
+        C: AsRef<ClientConfig> + Clone,
+    {
+        self.input_amount(client) - self.output_amount(client) - self.change_required(client)
     }
 
     /// Builds and signs the final transaction with correct change
@@ -94,15 +112,9 @@ impl TransactionBuilder {
         client: &Client<C>,
         rng: R,
     ) -> Transaction {
-        let change =
-            self.input_amount(client) - self.output_amount(client) - self.fee_amount(client);
-        self.build_with_change(
-            client.mint_client(),
-            rng,
-            vec![change],
-            &client.context.secp,
-        )
-        .await
+        let change = self.change_outputs(client);
+        self.build_with_change(client.mint_client(), rng, change, &client.context.secp)
+            .await
     }
 
     /// Builds and signs the final transaction with exact change amounts
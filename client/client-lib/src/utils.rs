@@ -9,6 +9,7 @@ use lightning_invoice::Currency;
 
 use crate::api::FederationApi;
 use crate::mint::SpendableNote;
+use crate::token::{EcashToken, EcashTokenError, ReceivedEcash};
 
 pub fn parse_ecash(s: &str) -> anyhow::Result<TieredMulti<SpendableNote>> {
     let bytes = base64::decode(s)?;
@@ -24,6 +25,14 @@ pub fn serialize_ecash(c: &TieredMulti<SpendableNote>) -> String {
     base64::encode(&bytes)
 }
 
+pub fn parse_ecash_token(s: &str) -> Result<EcashToken, EcashTokenError> {
+    EcashToken::from_bech32m(s)
+}
+
+pub fn parse_received_ecash(s: &str) -> Result<ReceivedEcash, EcashTokenError> {
+    ReceivedEcash::parse(s)
+}
+
 pub fn from_hex<D: Decodable>(s: &str) -> Result<D, anyhow::Error> {
     let bytes = hex::decode(s)?;
     Ok(D::consensus_decode(
@@ -1,5 +1,5 @@
 use bitcoin::Script;
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use serde::Serialize;
 use strum_macros::EnumIter;
@@ -10,6 +10,9 @@ pub enum DbKeyPrefix {
     PegIn = 0x22,
 }
 
+/// Current version of the wallet client database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
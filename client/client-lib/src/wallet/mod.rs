@@ -44,9 +44,10 @@ impl ClientModulePlugin for WalletClient {
         &self,
         input: &<Self::Module as ServerModulePlugin>::Input,
     ) -> TransactionItemAmount {
+        let amount = Amount::from_sats(input.tx_output().value);
         TransactionItemAmount {
-            amount: Amount::from_sats(input.tx_output().value),
-            fee: self.config.fee_consensus.peg_in_abs,
+            amount,
+            fee: self.config.fee_consensus.peg_in_fee(amount),
         }
     }
 
@@ -54,9 +55,10 @@ impl ClientModulePlugin for WalletClient {
         &self,
         output: &<Self::Module as ServerModulePlugin>::Output,
     ) -> TransactionItemAmount {
+        let amount = (output.amount + output.fees.amount()).into();
         TransactionItemAmount {
-            amount: (output.amount + output.fees.amount()).into(),
-            fee: self.config.fee_consensus.peg_out_abs,
+            amount,
+            fee: self.config.fee_consensus.peg_out_fee(amount),
         }
     }
 }
@@ -148,8 +150,9 @@ impl WalletClient {
             .verify(&self.context.secp, &self.config.peg_in_descriptor)
             .map_err(WalletClientError::PegInProofError)?;
 
-        let amount = Amount::from_sats(peg_in_proof.tx_output().value)
-            .saturating_sub(self.config.fee_consensus.peg_in_abs);
+        let peg_in_amount = Amount::from_sats(peg_in_proof.tx_output().value);
+        let amount =
+            peg_in_amount.saturating_sub(self.config.fee_consensus.peg_in_fee(peg_in_amount));
         if amount == Amount::ZERO {
             return Err(WalletClientError::PegInAmountTooSmall);
         }
@@ -199,9 +202,11 @@ mod tests {
     use bitcoin::{Address, Txid};
     use bitcoin_hashes::Hash;
     use fedimint_api::config::{BitcoindRpcCfg, ConfigGenParams};
+    use fedimint_api::core::client::ClientModulePlugin;
     use fedimint_api::core::{Decoder, MODULE_KEY_WALLET};
     use fedimint_api::db::mem_impl::MemDatabase;
     use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::module::TransactionItemAmount;
     use fedimint_api::task::TaskGroup;
     use fedimint_api::{Feerate, OutPoint, TransactionId};
     use fedimint_core::epoch::SignedEpochOutcome;
@@ -210,7 +215,7 @@ mod tests {
     use fedimint_core::modules::ln::{ContractAccount, LightningGateway};
     use fedimint_core::modules::mint::db::ECashUserBackupSnapshot;
     use fedimint_core::modules::wallet::common::WalletModuleDecoder;
-    use fedimint_core::modules::wallet::config::WalletClientConfig;
+    use fedimint_core::modules::wallet::config::{FeeConsensus, WalletClientConfig};
     use fedimint_core::modules::wallet::{
         PegOut, PegOutFees, Wallet, WalletConfigGenParams, WalletConfigGenerator, WalletOutput,
         WalletOutputOutcome,
@@ -357,6 +362,7 @@ mod tests {
                 &ConfigGenParams::new().attach(WalletConfigGenParams {
                     network: bitcoin::network::constants::Network::Regtest,
                     bitcoin_rpc: BitcoindRpcCfg {
+                        kind: Default::default(),
                         btc_rpc_address: "localhst".to_string(),
                         btc_rpc_user: "bitcoin".to_string(),
                         btc_rpc_pass: "bitcoin".to_string(),
@@ -386,6 +392,43 @@ mod tests {
         )
     }
 
+    #[test_log::test(tokio::test)]
+    async fn peg_out_fee_accounting() {
+        let mut task_group = TaskGroup::new();
+        let (_fed, mut client_config, client_context, _btc_rpc) =
+            new_mint_and_client(&mut task_group).await;
+        client_config.fee_consensus = FeeConsensus {
+            peg_out_abs: fedimint_api::Amount::from_msats(1_000),
+            peg_out_ppm: 1_000,
+            ..Default::default()
+        };
+        let client = WalletClient {
+            config: client_config,
+            context: Arc::new(client_context),
+        };
+
+        // the peg-out amount includes the bitcoin fees of 2000 sats/kvb * 500 weight units
+        let output = WalletOutput(PegOut {
+            recipient: Address::from_str("msFGPqHVk8rbARMd69FfGYxwcboZLemdBi").unwrap(),
+            amount: bitcoin::Amount::from_sat(42_000),
+            fees: PegOutFees {
+                fee_rate: Feerate {
+                    sats_per_kvb: 2_000,
+                },
+                total_weight: 500,
+            },
+        });
+        assert_eq!(
+            client.output_amount(&output),
+            TransactionItemAmount {
+                amount: fedimint_api::Amount::from_sats(43_000),
+                fee: fedimint_api::Amount::from_msats(1_000 + 43_000),
+            }
+        );
+
+        task_group.shutdown().await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn create_output() {
         let mut task_group = TaskGroup::new();
@@ -5,6 +5,7 @@ use std::ops::Mul;
 
 use anyhow::bail;
 use anyhow::format_err;
+use bincode::Options;
 use bitcoin::secp256k1;
 use bitcoin_hashes::sha256::Hash as Sha256;
 use bitcoin_hashes::sha256::HashEngine;
@@ -12,6 +13,7 @@ use fedimint_api::BitcoinHash;
 use hbbft::crypto::group::Curve;
 use hbbft::crypto::group::GroupEncoding;
 use hbbft::crypto::poly::Commitment;
+use hbbft::crypto::serde_impl::SerdeSecret;
 use hbbft::crypto::{G1Projective, G2Projective, PublicKeySet, SecretKeyShare};
 use hbbft::pairing::group::Group;
 use rand::{CryptoRng, RngCore};
@@ -197,6 +199,8 @@ pub trait TypedClientModuleConfig: DeserializeOwned + Serialize {
 pub enum DkgPeerMsg {
     PublicKey(secp256k1::PublicKey),
     DistributedGen((String, SupportedDkgMessage)),
+    /// Hash of the consensus config before resharing, which all peers have to agree on
+    OldConsensus(Sha256),
 }
 
 /// Supported (by Fedimint's code) `DkgMessage<T>` types
@@ -414,6 +418,9 @@ impl<G: DkgGroup> Dkg<G> {
                     });
                 }
             }
+            DkgMessage::ReshareCommit(_) | DkgMessage::ReshareShare(_) => {
+                panic!("{} sent us a resharing message during DKG", peer)
+            }
         }
 
         DkgStep::Messages(vec![])
@@ -440,6 +447,270 @@ impl<G: DkgGroup> Dkg<G> {
     }
 }
 
+impl<G: DkgGroup> DkgProtocol<G> for Dkg<G> {
+    fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> DkgStep<G> {
+        Dkg::step(self, peer, msg)
+    }
+}
+
+/// Protocols that [`DkgRunner`] can drive over the peer connections
+trait DkgProtocol<G: DkgGroup> {
+    /// Processes a `msg` from `peer`
+    fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> DkgStep<G>;
+}
+
+/// Shares of a key before resharing it, see [`DkgRunner::run_reshare`]
+#[derive(Debug, Clone)]
+pub struct PreviousKeys<G> {
+    /// Public key shares of (at least) all dealers
+    pub public_key_shares: BTreeMap<PeerId, G>,
+    /// Our secret key share, `None` if we are joining the federation
+    pub secret_key_share: Option<Scalar>,
+}
+
+impl PreviousKeys<G1Projective> {
+    /// Converts the `threshold_crypto` keys of `peers`
+    pub fn from_threshold_crypto(
+        pk_set: &PublicKeySet,
+        peers: &[PeerId],
+        sks: Option<&SecretKeyShare>,
+    ) -> anyhow::Result<Self> {
+        let public_key_shares = peers
+            .iter()
+            .map(|peer| {
+                let bytes = pk_set.public_key_share(peer.to_usize()).to_bytes();
+                let mut repr = <G1Projective as GroupEncoding>::Repr::default();
+                repr.as_mut().copy_from_slice(&bytes);
+                let point = Option::<G1Projective>::from(G1Projective::from_bytes(&repr))
+                    .ok_or_else(|| format_err!("Invalid public key share of {}", peer))?;
+                Ok((*peer, point))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(PreviousKeys {
+            public_key_shares,
+            secret_key_share: sks.map(threshold_crypto_scalar).transpose()?,
+        })
+    }
+}
+
+impl PreviousKeys<G2Projective> {
+    /// Converts the `tbs` keys of all peers
+    pub fn from_tbs(
+        pk_shares: BTreeMap<PeerId, tbs::PublicKeyShare>,
+        sks: Option<tbs::SecretKeyShare>,
+    ) -> Self {
+        PreviousKeys {
+            public_key_shares: pk_shares
+                .into_iter()
+                .map(|(peer, pk)| (peer, G2Projective::from(pk.0)))
+                .collect(),
+            secret_key_share: sks.map(|sks| sks.0),
+        }
+    }
+}
+
+/// `threshold_crypto` doesn't expose the scalar of a secret key share, so we recover it from the
+/// serialized key and check that it results in the same public key share
+///
+/// The bincode options are pinned to fixed-size little endian integers, so the 32 bytes of the
+/// scalar are always the tail of the encoding.
+fn threshold_crypto_scalar(sks: &SecretKeyShare) -> anyhow::Result<Scalar> {
+    let bytes = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .serialize(&SerdeSecret(sks.clone()))?;
+    let serialized: [u8; 32] = bytes
+        .get(bytes.len().saturating_sub(32)..)
+        .and_then(|tail| tail.try_into().ok())
+        .ok_or_else(|| format_err!("Secret key share too short"))?;
+    let mut reversed = serialized;
+    reversed.reverse();
+
+    // depending on the version the scalar is serialized as little or big endian
+    [serialized, reversed]
+        .iter()
+        .filter_map(|bytes| Option::<Scalar>::from(Scalar::from_bytes(bytes)))
+        .find(|candidate| {
+            SecretKeyShare::from_mut(&mut candidate.clone()).public_key_share()
+                == sks.public_key_share()
+        })
+        .ok_or_else(|| format_err!("Unsupported secret key share encoding"))
+}
+
+struct Reshare<G> {
+    gen_g: G,
+    dealers: Vec<PeerId>,
+    our_id: PeerId,
+    threshold: usize,
+    previous_pk_shares: BTreeMap<PeerId, G>,
+    commitments: BTreeMap<PeerId, Vec<G>>,
+    sub_shares: BTreeMap<PeerId, Scalar>,
+}
+
+/// Proactive resharing of an existing threshold key among a new set of peers, see "Verifiable
+/// Secret Redistribution for Threshold Sharing Schemes" by Theodore M. Wong, Chenxi Wang and
+/// Jeannette M. Wing
+///
+/// Every dealer shares its old key share with a Feldman-VSS of the new `threshold`. Since the
+/// old shares are points on the old polynomial, interpolating the sub-shares yields shares of a
+/// fresh polynomial with the same secret, so the public key doesn't change. Old shares are
+/// useless in combination with new ones, so removed peers don't retain any power.
+impl<G: DkgGroup> Reshare<G> {
+    /// Creates the resharing, dealers need to know their old secret key share
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        group: G,
+        our_id: PeerId,
+        peers: Vec<PeerId>,
+        dealers: Vec<PeerId>,
+        threshold: usize,
+        previous: PreviousKeys<G>,
+        rng: &mut impl rand::RngCore,
+    ) -> (Self, Vec<DkgStep<G>>) {
+        let mut reshare = Reshare {
+            gen_g: group,
+            dealers,
+            our_id,
+            threshold,
+            previous_pk_shares: previous.public_key_shares,
+            commitments: Default::default(),
+            sub_shares: Default::default(),
+        };
+
+        if !reshare.dealers.contains(&our_id) {
+            return (reshare, vec![]);
+        }
+
+        // the secret of our polynomial is our old key share
+        let secret = previous
+            .secret_key_share
+            .expect("dealers know their old key share");
+        let random: Poly<Scalar, Scalar> = Poly::random(threshold - 1, rng);
+        let poly: Poly<Scalar, Scalar> = Poly::from(
+            std::iter::once(secret)
+                .chain(random.coefficients().skip(1).copied())
+                .collect(),
+        );
+
+        let commit: Vec<G> = poly.coefficients().map(|c| reshare.gen_g * *c).collect();
+        let mut messages = vec![];
+        for peer in peers.iter().filter(|peer| **peer != our_id) {
+            messages.push((*peer, DkgMessage::ReshareCommit(commit.clone())));
+            messages.push((*peer, DkgMessage::ReshareShare(poly.evaluate(scalar(peer)))));
+        }
+        reshare.commitments.insert(our_id, commit);
+        reshare
+            .sub_shares
+            .insert(our_id, poly.evaluate(scalar(&our_id)));
+
+        let mut steps = vec![DkgStep::Messages(messages)];
+        steps.extend(reshare.result().map(DkgStep::Result));
+        (reshare, steps)
+    }
+
+    /// Runs a single step of the resharing, processing a `msg` from `peer`
+    pub fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> DkgStep<G> {
+        assert!(self.dealers.contains(&peer), "{} is not a dealer", peer);
+
+        match msg {
+            DkgMessage::ReshareCommit(commit) => {
+                assert_eq!(self.threshold, commit.len(), "wrong degree from {}", peer);
+                assert_eq!(
+                    Some(&commit[0]),
+                    self.previous_pk_shares.get(&peer),
+                    "{} did not commit to its old key share",
+                    peer
+                );
+
+                match self.commitments.get(&peer) {
+                    Some(old) if *old != commit => panic!("{} sent us two commitments!", peer),
+                    _ => self.commitments.insert(peer, commit),
+                };
+            }
+            DkgMessage::ReshareShare(share) => {
+                match self.sub_shares.get(&peer) {
+                    Some(old) if *old != share => panic!("{} sent us two shares!", peer),
+                    _ => self.sub_shares.insert(peer, share),
+                };
+            }
+            _ => panic!("{} sent us a DKG message during resharing", peer),
+        }
+
+        match self.result() {
+            Some(keys) => DkgStep::Result(keys),
+            None => DkgStep::Messages(vec![]),
+        }
+    }
+
+    /// Combines the sub-shares once we received them from all dealers
+    fn result(&self) -> Option<DkgKeys<G>> {
+        if self.commitments.len() < self.dealers.len() || self.sub_shares.len() < self.dealers.len()
+        {
+            return None;
+        }
+
+        // Feldman-VSS verifies the sub-shares match the commitments
+        for (dealer, share) in &self.sub_shares {
+            let commit_product: G = self.commitments[dealer]
+                .iter()
+                .enumerate()
+                .map(|(idx, commit)| *commit * scalar(&self.our_id).pow(&[idx as u64, 0, 0, 0]))
+                .reduce(|a, b| a + b)
+                .expect("sums");
+            assert_eq!(
+                self.gen_g * *share,
+                commit_product,
+                "bad share from {}",
+                dealer
+            );
+        }
+
+        let lagrange = lagrange_coefficients(&self.dealers);
+        let sks = self
+            .dealers
+            .iter()
+            .zip(&lagrange)
+            .map(|(dealer, coefficient)| self.sub_shares[dealer] * coefficient)
+            .sum();
+        let pks: Vec<G> = (0..self.threshold)
+            .map(|idx| {
+                self.dealers
+                    .iter()
+                    .zip(&lagrange)
+                    .map(|(dealer, coefficient)| self.commitments[dealer][idx] * *coefficient)
+                    .reduce(|a, b| a + b)
+                    .expect("sums")
+            })
+            .collect();
+
+        Some(DkgKeys {
+            public_key_set: pks,
+            secret_key_share: sks,
+        })
+    }
+}
+
+impl<G: DkgGroup> DkgProtocol<G> for Reshare<G> {
+    fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> DkgStep<G> {
+        Reshare::step(self, peer, msg)
+    }
+}
+
+/// Coefficients for interpolating the value at 0 of a polynomial from its values at `peers`
+fn lagrange_coefficients(peers: &[PeerId]) -> Vec<Scalar> {
+    peers
+        .iter()
+        .map(|peer| {
+            peers
+                .iter()
+                .filter(|other| *other != peer)
+                .map(|other| scalar(other) * (scalar(other) - scalar(peer)).invert().unwrap())
+                .product()
+        })
+        .collect()
+}
+
 /// PeerIds are offset by 1, since evaluating a poly at 0 reveals the secret
 pub fn scalar(peer: &PeerId) -> Scalar {
     Scalar::from(peer.to_usize() as u64 + 1)
@@ -513,33 +784,117 @@ where
         DkgMessage<G>: ISupportedDkgMessage,
     {
         let mut dkgs: HashMap<T, Dkg<G>> = HashMap::new();
-        let mut results: HashMap<T, DkgKeys<G>> = HashMap::new();
+        let mut steps = vec![];
 
-        // create the dkgs and send our initial messages
+        // create the dkgs and our initial messages
         for (key, threshold) in self.dkg_config.iter() {
             let our_id = self.our_id;
             let peers = self.peers.clone();
             let (dkg, step) = Dkg::new(group, our_id, peers, *threshold, rng);
-            if let DkgStep::Messages(messages) = step {
-                for (peer, msg) in messages {
-                    connections
-                        .send(
-                            &[peer],
-                            module_id,
-                            DkgPeerMsg::DistributedGen((
-                                serde_json::to_string(key).expect("serialization can't fail"),
-                                msg.to_msg(),
-                            )),
-                        )
-                        .await?;
-                }
-            }
+            steps.push((key.clone(), step));
             dkgs.insert(key.clone(), dkg);
         }
 
+        Self::run_protocols(module_id, dkgs, steps, connections).await
+    }
+
+    /// Reshares G2 keys used in `tbs`, see [`DkgRunner::run_reshare`]
+    pub async fn run_reshare_g2(
+        &mut self,
+        module_id: ModuleKey,
+        dealers: &[PeerId],
+        previous: HashMap<T, PreviousKeys<G2Projective>>,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Cancellable<HashMap<T, DkgKeys<G2Projective>>> {
+        self.run_reshare(
+            module_id,
+            G2Projective::generator(),
+            dealers,
+            previous,
+            connections,
+            rng,
+        )
+        .await
+    }
+
+    /// Reshares G1 keys used in `threshold_crypto`, see [`DkgRunner::run_reshare`]
+    pub async fn run_reshare_g1(
+        &mut self,
+        module_id: ModuleKey,
+        dealers: &[PeerId],
+        previous: HashMap<T, PreviousKeys<G1Projective>>,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Cancellable<HashMap<T, DkgKeys<G1Projective>>> {
+        self.run_reshare(
+            module_id,
+            G1Projective::generator(),
+            dealers,
+            previous,
+            connections,
+            rng,
+        )
+        .await
+    }
+
+    /// Reshares the `previous` keys among our peers, producing new shares with the configured
+    /// thresholds for the same public keys
+    ///
+    /// `dealers` hold the old key shares, there need to be at least as many as the old
+    /// threshold and they all need to be part of the new peers.
+    pub async fn run_reshare<G: DkgGroup>(
+        &mut self,
+        module_id: ModuleKey,
+        group: G,
+        dealers: &[PeerId],
+        mut previous: HashMap<T, PreviousKeys<G>>,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Cancellable<HashMap<T, DkgKeys<G>>>
+    where
+        DkgMessage<G>: ISupportedDkgMessage,
+    {
+        let mut reshares: HashMap<T, Reshare<G>> = HashMap::new();
+        let mut steps = vec![];
+
+        for (key, threshold) in self.dkg_config.iter() {
+            let previous = previous.remove(key).expect("previous keys exist");
+            let (reshare, key_steps) = Reshare::new(
+                group,
+                self.our_id,
+                self.peers.clone(),
+                dealers.to_vec(),
+                *threshold,
+                previous,
+                rng,
+            );
+            steps.extend(key_steps.into_iter().map(|step| (key.clone(), step)));
+            reshares.insert(key.clone(), reshare);
+        }
+
+        Self::run_protocols(module_id, reshares, steps, connections).await
+    }
+
+    /// Sends the `initial_steps` and runs the protocols until all produced keys
+    async fn run_protocols<G: DkgGroup, P: DkgProtocol<G>>(
+        module_id: ModuleKey,
+        mut protocols: HashMap<T, P>,
+        initial_steps: Vec<(T, DkgStep<G>)>,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+    ) -> Cancellable<HashMap<T, DkgKeys<G>>>
+    where
+        DkgMessage<G>: ISupportedDkgMessage,
+    {
+        let mut results: HashMap<T, DkgKeys<G>> = HashMap::new();
+
+        for (key, step) in initial_steps {
+            Self::process_step(module_id, key, step, &mut results, connections).await?;
+        }
+
         // process steps for each key
         // TODO: fix error handling here; what do we do on a malfunctining peer when building the federation?
-        loop {
+        while results.len() < protocols.len() {
             let (peer, msg) = connections.receive(module_id).await?;
 
             let (key, message) = if let DkgPeerMsg::DistributedGen(v) = msg {
@@ -550,33 +905,44 @@ where
 
             let key = serde_json::from_str(&key).expect("invalid key");
             let message = ISupportedDkgMessage::from_msg(message).expect("invalid message");
-            let step = dkgs.get_mut(&key).expect("exists").step(peer, message);
-
-            match step {
-                DkgStep::Messages(messages) => {
-                    for (peer, msg) in messages {
-                        connections
-                            .send(
-                                &[peer],
-                                module_id,
-                                DkgPeerMsg::DistributedGen((
-                                    serde_json::to_string(&key)
-                                        .expect("FIXME - handle errors here"),
-                                    msg.to_msg(),
-                                )),
-                            )
-                            .await?;
-                    }
-                }
-                DkgStep::Result(result) => {
-                    results.insert(key, result);
+            let step = protocols.get_mut(&key).expect("exists").step(peer, message);
+
+            Self::process_step(module_id, key, step, &mut results, connections).await?;
+        }
+
+        Ok(results)
+    }
+
+    async fn process_step<G: DkgGroup>(
+        module_id: ModuleKey,
+        key: T,
+        step: DkgStep<G>,
+        results: &mut HashMap<T, DkgKeys<G>>,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+    ) -> Cancellable<()>
+    where
+        DkgMessage<G>: ISupportedDkgMessage,
+    {
+        match step {
+            DkgStep::Messages(messages) => {
+                for (peer, msg) in messages {
+                    connections
+                        .send(
+                            &[peer],
+                            module_id,
+                            DkgPeerMsg::DistributedGen((
+                                serde_json::to_string(&key).expect("serialization can't fail"),
+                                msg.to_msg(),
+                            )),
+                        )
+                        .await?;
                 }
             }
-
-            if results.len() == dkgs.len() {
-                return Ok(results);
+            DkgStep::Result(result) => {
+                results.insert(key, result);
             }
         }
+        Ok(())
     }
 }
 
@@ -619,6 +985,10 @@ pub enum DkgMessage<G: DkgGroup> {
         #[serde(with = "serde_impl::scalar")] Scalar,
     ),
     Extract(#[serde(with = "serde_commit")] Vec<G>),
+    /// Feldman commitment of a dealer to its resharing polynomial, see [`Reshare`]
+    ReshareCommit(#[serde(with = "serde_commit")] Vec<G>),
+    /// A dealer's evaluation of its resharing polynomial for the receiving peer
+    ReshareShare(#[serde(with = "serde_impl::scalar")] Scalar),
 }
 
 /// Defines a group (e.g. G1 or G2) that we can generate keys for
@@ -694,7 +1064,11 @@ mod tests {
     use hbbft::crypto::{G1Projective, G2Projective};
     use rand::rngs::OsRng;
 
-    use crate::config::{scalar, Dkg, DkgGroup, DkgKeys};
+    use tbs::Scalar;
+
+    use crate::config::{
+        scalar, threshold_crypto_scalar, Dkg, DkgGroup, DkgKeys, PreviousKeys, Reshare,
+    };
     use crate::PeerId;
 
     #[test_log::test]
@@ -715,6 +1089,106 @@ mod tests {
         }
     }
 
+    #[test_log::test]
+    fn test_reshare() {
+        let keys = run(G1Projective::generator());
+        let (old_pk, _) = keys[&PeerId::from(0)].threshold_crypto();
+        for (peer, keys) in reshare(G1Projective::generator(), keys) {
+            let (pk, sk) = keys.threshold_crypto();
+            assert_eq!(pk.threshold(), 3);
+            assert_eq!(pk.public_key(), old_pk.public_key());
+            assert_eq!(pk.public_key_share(peer.to_usize()), sk.public_key_share());
+        }
+
+        let keys = run(G2Projective::generator());
+        let old_pk = keys[&PeerId::from(0)].public_key_set[0];
+        for (peer, keys) in reshare(G2Projective::generator(), keys) {
+            assert_eq!(keys.public_key_set[0], old_pk);
+            let (pk, sk) = keys.tbs();
+            assert_eq!(pk.coefficients().len(), 4);
+            assert_eq!(
+                pk.evaluate(scalar(&peer)).to_affine(),
+                sk.to_pub_key_share().0
+            );
+        }
+    }
+
+    #[test]
+    fn test_threshold_crypto_scalar() {
+        // The byte-reversed encodings of some vectors (e.g. 1 and -1) are valid scalars too, so
+        // only checking the public key share tells the byte orders apart
+        let vectors = [
+            Scalar::from(1),
+            Scalar::from(42),
+            Scalar::from(u64::MAX),
+            Scalar::from_raw([0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210, 0x42, 0x0]),
+            -Scalar::from(1),
+            Scalar::from_raw([0x0, 0x0, 0x0, 0x1234_5678_9abc_def0]),
+        ];
+        for expected in vectors {
+            let mut scalar = expected;
+            let sks = threshold_crypto::SecretKeyShare::from_mut(&mut scalar);
+            assert_eq!(threshold_crypto_scalar(&sks).unwrap(), expected);
+        }
+    }
+
+    /// Replaces peer 3 and adds peer 4, increasing the threshold to 4
+    fn reshare<G: DkgGroup>(
+        group: G,
+        old_keys: HashMap<PeerId, DkgKeys<G>>,
+    ) -> HashMap<PeerId, DkgKeys<G>> {
+        let mut rng = OsRng::default();
+        let threshold = 4;
+        let peers = (0..5u16).map(PeerId::from).collect::<Vec<_>>();
+        let dealers = (0..3u16).map(PeerId::from).collect::<Vec<_>>();
+
+        let mut steps: VecDeque<(PeerId, DkgStep<G>)> = VecDeque::new();
+        let mut reshares: HashMap<PeerId, Reshare<G>> = HashMap::new();
+        let mut keys: HashMap<PeerId, DkgKeys<G>> = HashMap::new();
+
+        for peer in &peers {
+            let previous = PreviousKeys {
+                public_key_shares: dealers
+                    .iter()
+                    .map(|dealer| (*dealer, group * old_keys[dealer].secret_key_share))
+                    .collect(),
+                secret_key_share: old_keys
+                    .get(peer)
+                    .filter(|_| dealers.contains(peer))
+                    .map(|keys| keys.secret_key_share),
+            };
+            let (reshare, peer_steps) = Reshare::new(
+                group,
+                *peer,
+                peers.clone(),
+                dealers.clone(),
+                threshold,
+                previous,
+                &mut rng,
+            );
+            reshares.insert(*peer, reshare);
+            steps.extend(peer_steps.into_iter().map(|step| (*peer, step)));
+        }
+
+        while keys.len() < peers.len() {
+            match steps.pop_front() {
+                Some((peer, DkgStep::Messages(messages))) => {
+                    for (receive_peer, msg) in messages {
+                        let receive_reshare = reshares.get_mut(&receive_peer).unwrap();
+                        let step = receive_reshare.step(peer, msg);
+                        steps.push_back((receive_peer, step));
+                    }
+                }
+                Some((peer, DkgStep::Result(step_keys))) => {
+                    keys.insert(peer, step_keys);
+                }
+                None => panic!("Resharing got stuck"),
+            }
+        }
+
+        keys
+    }
+
     fn run<G: DkgGroup>(group: G) -> HashMap<PeerId, DkgKeys<G>> {
         let mut rng = OsRng::default();
         let num_peers = 4;
@@ -753,7 +1227,51 @@ mod tests {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BitcoindRpcCfg {
+    /// Backend to query, `btc_rpc_address` has to be in the format it expects
+    #[serde(default)]
+    pub kind: BitcoinRpcKind,
     pub btc_rpc_address: String,
+    /// Only used by [`BitcoinRpcKind::Bitcoind`]
     pub btc_rpc_user: String,
+    /// Only used by [`BitcoinRpcKind::Bitcoind`]
     pub btc_rpc_pass: String,
 }
+
+/// Source of blockchain data for the wallet module
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BitcoinRpcKind {
+    /// Bitcoin Core's JSON RPC, address in the format `host:port`
+    #[default]
+    Bitcoind,
+    /// Esplora HTTP API, address is the base URL, e.g. `https://blockstream.info/api`
+    Esplora,
+    /// Electrum server, address in the format `tcp://host:port` or `ssl://host:port`
+    ///
+    /// Electrum can't serve whole blocks, so it is only supported on regtest and signet where
+    /// blocks are small enough to be fetched one transaction at a time.
+    Electrum,
+}
+
+impl std::fmt::Display for BitcoinRpcKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BitcoinRpcKind::Bitcoind => "bitcoind",
+            BitcoinRpcKind::Esplora => "esplora",
+            BitcoinRpcKind::Electrum => "electrum",
+        })
+    }
+}
+
+impl std::str::FromStr for BitcoinRpcKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bitcoind" => Ok(BitcoinRpcKind::Bitcoind),
+            "esplora" => Ok(BitcoinRpcKind::Esplora),
+            "electrum" => Ok(BitcoinRpcKind::Electrum),
+            _ => Err(format_err!("Unknown bitcoin rpc kind {}", s)),
+        }
+    }
+}
@@ -62,7 +62,7 @@ macro_rules! module_dyn_newtype_impl_encode_decode {
                 modules: &$crate::module::registry::ModuleDecoderRegistry,
             ) -> Result<Self, DecodeError> {
                 $crate::core::encode::module_decode_key_prefixed_decodable(r, modules, |r, m| {
-                    m.$decode_fn(r)
+                    m.$decode_fn(r, modules)
                 })
             }
         }
@@ -214,30 +214,58 @@ macro_rules! newtype_impl_display_passthrough {
 /// at least until we start to support modules with overriden [`ModuleKey`]s
 pub trait PluginDecode: Debug {
     /// Decode `Input` compatible with this module, after the module key prefix was already decoded
-    fn decode_input(r: &mut dyn io::Read) -> Result<Input, DecodeError>;
+    fn decode_input(
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError>;
 
     /// Decode `Output` compatible with this module, after the module key prefix was already decoded
-    fn decode_output(r: &mut dyn io::Read) -> Result<Output, DecodeError>;
+    fn decode_output(
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError>;
 
     /// Decode `OutputOutcome` compatible with this module, after the module key prefix was already decoded
-    fn decode_output_outcome(r: &mut dyn io::Read) -> Result<OutputOutcome, DecodeError>;
+    fn decode_output_outcome(
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError>;
 
     /// Decode `ConsensusItem` compatible with this module, after the module key prefix was already decoded
-    fn decode_consensus_item(r: &mut dyn io::Read) -> Result<ConsensusItem, DecodeError>;
+    fn decode_consensus_item(
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<ConsensusItem, DecodeError>;
 }
 
 pub trait ModuleDecode: Debug {
     /// Decode `Input` compatible with this module, after the module key prefix was already decoded
-    fn decode_input(&self, r: &mut dyn io::Read) -> Result<Input, DecodeError>;
+    fn decode_input(
+        &self,
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError>;
 
     /// Decode `Output` compatible with this module, after the module key prefix was already decoded
-    fn decode_output(&self, r: &mut dyn io::Read) -> Result<Output, DecodeError>;
+    fn decode_output(
+        &self,
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError>;
 
     /// Decode `OutputOutcome` compatible with this module, after the module key prefix was already decoded
-    fn decode_output_outcome(&self, r: &mut dyn io::Read) -> Result<OutputOutcome, DecodeError>;
+    fn decode_output_outcome(
+        &self,
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError>;
 
     /// Decode `ConsensusItem` compatible with this module, after the module key prefix was already decoded
-    fn decode_consensus_item(&self, r: &mut dyn io::Read) -> Result<ConsensusItem, DecodeError>;
+    fn decode_consensus_item(
+        &self,
+        r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<ConsensusItem, DecodeError>;
 }
 
 // TODO: use macro again
@@ -263,38 +291,70 @@ impl<T> ModuleDecode for T
 where
     T: PluginDecode + 'static,
 {
-    fn decode_input(&self, r: &mut dyn Read) -> Result<Input, DecodeError> {
-        <Self as PluginDecode>::decode_input(r)
+    fn decode_input(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError> {
+        <Self as PluginDecode>::decode_input(r, modules)
     }
 
-    fn decode_output(&self, r: &mut dyn Read) -> Result<Output, DecodeError> {
-        <Self as PluginDecode>::decode_output(r)
+    fn decode_output(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError> {
+        <Self as PluginDecode>::decode_output(r, modules)
     }
 
-    fn decode_output_outcome(&self, r: &mut dyn Read) -> Result<OutputOutcome, DecodeError> {
-        <Self as PluginDecode>::decode_output_outcome(r)
+    fn decode_output_outcome(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError> {
+        <Self as PluginDecode>::decode_output_outcome(r, modules)
     }
 
-    fn decode_consensus_item(&self, r: &mut dyn Read) -> Result<ConsensusItem, DecodeError> {
-        <Self as PluginDecode>::decode_consensus_item(r)
+    fn decode_consensus_item(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<ConsensusItem, DecodeError> {
+        <Self as PluginDecode>::decode_consensus_item(r, modules)
     }
 }
 
 impl ModuleDecode for Decoder {
-    fn decode_input(&self, r: &mut dyn Read) -> Result<Input, DecodeError> {
-        self.0.decode_input(r)
+    fn decode_input(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError> {
+        self.0.decode_input(r, modules)
     }
 
-    fn decode_output(&self, r: &mut dyn Read) -> Result<Output, DecodeError> {
-        self.0.decode_output(r)
+    fn decode_output(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError> {
+        self.0.decode_output(r, modules)
     }
 
-    fn decode_output_outcome(&self, r: &mut dyn Read) -> Result<OutputOutcome, DecodeError> {
-        self.0.decode_output_outcome(r)
+    fn decode_output_outcome(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError> {
+        self.0.decode_output_outcome(r, modules)
     }
 
-    fn decode_consensus_item(&self, r: &mut dyn Read) -> Result<ConsensusItem, DecodeError> {
-        self.0.decode_consensus_item(r)
+    fn decode_consensus_item(
+        &self,
+        r: &mut dyn Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<ConsensusItem, DecodeError> {
+        self.0.decode_consensus_item(r, modules)
     }
 }
 
@@ -15,6 +15,10 @@ where
     F: FnOnce(&mut R, &Decoder) -> Result<T, DecodeError>,
 {
     let key = ModuleKey::consensus_decode(&mut d, modules)?;
+    let decoder = modules
+        .get(key)
+        .ok_or_else(|| DecodeError::from_str("Unknown module key"))?;
 
-    decode_fn(d, modules.decoder(key))
+    let _guard = modules.enter()?;
+    decode_fn(d, decoder)
 }
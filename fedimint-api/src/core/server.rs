@@ -8,14 +8,14 @@ use std::{collections::HashSet, sync::Arc};
 
 use async_trait::async_trait;
 use fedimint_api::{
-    db::DatabaseTransaction,
+    db::{DatabaseTransaction, DatabaseVersion, MigrationMap},
     module::{audit::Audit, interconnect::ModuleInterconect},
     OutPoint, PeerId,
 };
 
 use super::*;
 use crate::module::{
-    ApiEndpoint, InputMeta, ModuleError, ServerModulePlugin, TransactionItemAmount,
+    ApiEndpoint, ApiHandler, InputMeta, ModuleError, ServerModulePlugin, TransactionItemAmount,
 };
 
 pub trait ModuleVerificationCache: Debug {
@@ -61,6 +61,15 @@ pub trait IServerModule: Debug {
 
     fn as_any(&self) -> &(dyn Any + 'static);
 
+    /// Version of the database schema the module's code expects
+    fn database_version(&self) -> DatabaseVersion;
+
+    /// Migrations bringing an older module database up to [`Self::database_version`]
+    fn database_migrations(&self) -> MigrationMap;
+
+    /// Database key prefixes holding the module's consensus state
+    fn consensus_state_prefixes(&self) -> Vec<u8>;
+
     /// Blocks until a new `consensus_proposal` is available.
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>);
 
@@ -110,6 +119,17 @@ pub trait IServerModule: Debug {
         verification_cache: &VerificationCache,
     ) -> Result<InputMeta, ModuleError>;
 
+    /// Like [`Self::apply_input`] for an input that [`Self::validate_input`] returned `meta` for at
+    /// the start of the epoch, allows modules to skip checks that depend on the input alone.
+    async fn apply_validated_input<'a, 'b, 'c>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b Input,
+        verification_cache: &VerificationCache,
+        meta: InputMeta,
+    ) -> Result<InputMeta, ModuleError>;
+
     /// Validate a transaction output before submitting it to the unconfirmed transaction pool. This
     /// function has no side effects and may be called at any time. False positives due to outdated
     /// database state are ok since they get filtered out after consensus has been reached on them
@@ -198,6 +218,18 @@ where
         self
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        <Self as ServerModulePlugin>::database_version(self)
+    }
+
+    fn database_migrations(&self) -> MigrationMap {
+        <Self as ServerModulePlugin>::database_migrations(self)
+    }
+
+    fn consensus_state_prefixes(&self) -> Vec<u8> {
+        <Self as ServerModulePlugin>::consensus_state_prefixes(self)
+    }
+
     /// Blocks until a new `consensus_proposal` is available.
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>) {
         <Self as ServerModulePlugin>::await_consensus_proposal(self, dbtx).await
@@ -316,6 +348,34 @@ where
         .map(Into::into)
     }
 
+    /// Like [`Self::apply_input`] for an input that [`Self::validate_input`] returned `meta` for at
+    /// the start of the epoch, allows modules to skip checks that depend on the input alone.
+    async fn apply_validated_input<'a, 'b, 'c>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b Input,
+        verification_cache: &VerificationCache,
+        meta: InputMeta,
+    ) -> Result<InputMeta, ModuleError> {
+        <Self as ServerModulePlugin>::apply_validated_input(
+            self,
+            interconnect,
+            dbtx,
+            input
+                .as_any()
+                .downcast_ref::<<Self as ServerModulePlugin>::Input>()
+                .expect("incorrect input type passed to module plugin"),
+            verification_cache
+                .as_any()
+                .downcast_ref::<<Self as ServerModulePlugin>::VerificationCache>()
+                .expect("incorrect verification cache type passed to module plugin"),
+            meta,
+        )
+        .await
+        .map(Into::into)
+    }
+
     /// Validate a transaction output before submitting it to the unconfirmed transaction pool. This
     /// function has no side effects and may be called at any time. False positives due to outdated
     /// database state are ok since they get filtered out after consensus has been reached on them
@@ -407,18 +467,30 @@ where
             .into_iter()
             .map(|ApiEndpoint { path, handler }| ApiEndpoint {
                 path,
-                handler: Box::new(
-                    move |module: &ServerModule,
-                          dbtx: fedimint_api::db::DatabaseTransaction<'_>,
-                          value: serde_json::Value| {
-                        let typed_module = module
-                            .as_any()
-                            .downcast_ref::<T>()
-                            .expect("the dispatcher should always call with the right module");
-                        Box::pin(handler(typed_module, dbtx, value))
-                    },
-                ),
+                handler: match handler {
+                    ApiHandler::Read(handler) => ApiHandler::Read(Box::new(
+                        move |module: &ServerModule,
+                              dbtx: fedimint_api::db::ReadDatabaseTransaction<'_>,
+                              value: serde_json::Value| {
+                            Box::pin(handler(downcast_module::<T>(module), dbtx, value))
+                        },
+                    )),
+                    ApiHandler::Write(handler) => ApiHandler::Write(Box::new(
+                        move |module: &ServerModule,
+                              dbtx: fedimint_api::db::DatabaseTransaction<'_>,
+                              value: serde_json::Value| {
+                            Box::pin(handler(downcast_module::<T>(module), dbtx, value))
+                        },
+                    )),
+                },
             })
             .collect()
     }
 }
+
+fn downcast_module<T: 'static>(module: &ServerModule) -> &T {
+    module
+        .as_any()
+        .downcast_ref::<T>()
+        .expect("the dispatcher should always call with the right module")
+}
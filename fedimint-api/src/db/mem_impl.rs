@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::ops::Bound;
 use std::sync::Mutex;
 
 use anyhow::Result;
@@ -7,9 +8,9 @@ use async_trait::async_trait;
 
 use super::{
     DatabaseDeleteOperation, DatabaseInsertOperation, DatabaseOperation, DatabaseTransaction,
-    IDatabase, IDatabaseTransaction,
+    IDatabase, IDatabaseTransaction, ReadDatabaseTransaction,
 };
-use crate::db::PrefixIter;
+use crate::db::{IterOrder, PrefixIter};
 use crate::ModuleDecoderRegistry;
 
 #[derive(Debug, Default)]
@@ -61,6 +62,25 @@ impl IDatabase for MemDatabase {
         tx.set_tx_savepoint().await;
         tx
     }
+
+    async fn begin_read_transaction(
+        &self,
+        decoders: ModuleDecoderRegistry,
+    ) -> ReadDatabaseTransaction {
+        // a single copy of the data, there is nothing to roll back to
+        let memtx = MemTransaction {
+            operations: Vec::new(),
+            tx_data: self.data.lock().unwrap().clone(),
+            db: self,
+            savepoint: BTreeMap::new(),
+            num_pending_operations: 0,
+            num_savepoint_operations: 0,
+        };
+        ReadDatabaseTransaction::new(DatabaseTransaction::new(
+            MemReadTransaction(memtx),
+            decoders,
+        ))
+    }
 }
 
 // In-memory database transaction should only be used for test code and never for production
@@ -84,6 +104,13 @@ impl<'a> IDatabaseTransaction<'a> for MemTransaction<'a> {
         Ok(self.tx_data.get(key).cloned())
     }
 
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        Ok(keys
+            .iter()
+            .map(|key| self.tx_data.get(key).cloned())
+            .collect())
+    }
+
     async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         // Remove data from copy so we can read our own writes
         let ret = self.tx_data.remove(&key.to_vec());
@@ -107,6 +134,33 @@ impl<'a> IDatabaseTransaction<'a> for MemTransaction<'a> {
         Box::new(MemDbIter { data })
     }
 
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        // `BTreeMap::range` panics on ranges ending before they start
+        if end.map_or(false, |end| end <= start) {
+            return Box::new(MemDbIter { data: vec![] });
+        }
+
+        let range = self
+            .tx_data
+            .range::<[u8], _>((
+                Bound::Included(start),
+                end.map_or(Bound::Unbounded, Bound::Excluded),
+            ))
+            .map(|(key, value)| (key.clone(), value.clone()));
+        // `MemDbIter` pops from the back
+        let data = match order {
+            IterOrder::Ascending => range.rev().collect(),
+            IterOrder::Descending => range.collect(),
+        };
+
+        Box::new(MemDbIter { data })
+    }
+
     async fn commit_tx(self: Box<Self>) -> Result<()> {
         for op in self.operations {
             match op {
@@ -142,6 +196,54 @@ impl<'a> IDatabaseTransaction<'a> for MemTransaction<'a> {
     }
 }
 
+/// Read-only transaction on a copy of the data taken when it started
+#[derive(Debug)]
+pub struct MemReadTransaction<'a>(MemTransaction<'a>);
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for MemReadTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, _key: &[u8], _value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot insert into a read only transaction");
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.raw_get_bytes(key).await
+    }
+
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0.raw_get_bytes_batch(keys).await
+    }
+
+    async fn raw_remove_entry(&mut self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot remove from a read only transaction");
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixIter<'_> {
+        self.0.raw_find_by_prefix(key_prefix).await
+    }
+
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        self.0.raw_find_by_range(start, end, order).await
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        panic!("Cannot commit a read only transaction");
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        panic!("Cannot rollback a read only transaction");
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        panic!("Cannot set a savepoint in a read only transaction");
+    }
+}
+
 struct MemDbIter {
     data: Vec<(Vec<u8>, Vec<u8>)>,
 }
@@ -178,6 +280,11 @@ mod tests {
         fedimint_api::db::verify_read_own_writes(MemDatabase::new().into()).await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_get_values() {
+        fedimint_api::db::verify_get_values(MemDatabase::new().into()).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_prevent_dirty_reads() {
         fedimint_api::db::verify_prevent_dirty_reads(MemDatabase::new().into()).await;
@@ -188,6 +295,21 @@ mod tests {
         fedimint_api::db::verify_find_by_prefix(MemDatabase::new().into()).await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_range() {
+        fedimint_api::db::verify_find_by_range(MemDatabase::new().into()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix_ordered() {
+        fedimint_api::db::verify_find_by_prefix_ordered(MemDatabase::new().into()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_read_transaction() {
+        fedimint_api::db::verify_read_transaction(MemDatabase::new().into()).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_commit() {
         fedimint_api::db::verify_commit(MemDatabase::new().into()).await;
@@ -212,4 +334,9 @@ mod tests {
     async fn test_dbtx_remove_by_prefix() {
         fedimint_api::db::verify_remove_by_prefix(MemDatabase::new().into()).await;
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_apply_migrations() {
+        fedimint_api::db::verify_apply_migrations(MemDatabase::new().into()).await;
+    }
 }
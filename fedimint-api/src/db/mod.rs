@@ -1,12 +1,16 @@
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::{trace, warn};
+use tracing::{info, trace, warn};
 
+use crate::core::ModuleKey;
 use crate::dyn_newtype_define;
 use crate::encoding::{Decodable, Encodable};
 
@@ -57,9 +61,40 @@ pub trait DatabaseValue: Sized + SerializableDatabaseValue {
 
 pub type PrefixIter<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + Send + 'a>;
 
+/// Order in which range queries return their entries, based on the encoded keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IterOrder {
+    Ascending,
+    Descending,
+}
+
+/// The smallest key that is greater than all keys starting with `prefix`, `None` if there is no
+/// such key because the prefix only consists of `0xff` bytes
+pub fn prefix_range_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last != u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
 #[async_trait]
 pub trait IDatabase: Debug + Send + Sync {
     async fn begin_transaction(&self, decoders: ModuleDecoderRegistry) -> DatabaseTransaction;
+
+    /// Starts a transaction that can only read, e.g. to answer API requests
+    ///
+    /// Default implementation wraps [`Self::begin_transaction`], backends with a cheaper way to
+    /// read from a snapshot should override it.
+    async fn begin_read_transaction(
+        &self,
+        decoders: ModuleDecoderRegistry,
+    ) -> ReadDatabaseTransaction {
+        ReadDatabaseTransaction::new(self.begin_transaction(decoders).await)
+    }
 }
 
 dyn_newtype_define! {
@@ -101,10 +136,32 @@ pub trait IDatabaseTransaction<'a>: 'a + Send {
 
     async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
+    /// Looks up the values of all `keys` at once, the result has the same order as `keys`
+    ///
+    /// Default implementation is a loop over [`Self::raw_get_bytes`], backends that can batch
+    /// lookups should override it.
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.raw_get_bytes(key).await?);
+        }
+        Ok(values)
+    }
+
     async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
+    /// All entries with keys starting with `key_prefix`, not all backends return them in key order
     async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixIter<'_>;
 
+    /// All entries with keys from `start` (inclusive) to `end` (exclusive, unbounded if `None`)
+    /// sorted by key in the given `order`
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_>;
+
     /// Default implementation is a combination of [`Self::raw_find_by_prefix`] + loop over [`Self::raw_remove_entry`]
     async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
         let mut keys = vec![];
@@ -210,6 +267,25 @@ impl<'a> DatabaseTransaction<'a> {
         Ok(Some(K::Value::from_bytes(&value_bytes, &self.decoders)?))
     }
 
+    /// Like [`Self::get_value`] for all `keys` at once, the result has the same order as `keys`
+    pub async fn get_values<K>(&mut self, keys: &[K]) -> Result<Vec<Option<K::Value>>>
+    where
+        K: DatabaseKey + DatabaseKeyPrefixConst,
+    {
+        let keys_bytes = keys.iter().map(|key| key.to_bytes()).collect::<Vec<_>>();
+        self.tx
+            .raw_get_bytes_batch(&keys_bytes)
+            .await?
+            .into_iter()
+            .map(|value_bytes| {
+                value_bytes
+                    .map(|value_bytes| K::Value::from_bytes(&value_bytes, &self.decoders))
+                    .transpose()
+                    .map_err(Into::into)
+            })
+            .collect()
+    }
+
     pub async fn find_by_prefix<KP>(
         &mut self,
         key_prefix: &KP,
@@ -236,6 +312,57 @@ impl<'a> DatabaseTransaction<'a> {
             })
     }
 
+    /// Like [`Self::find_by_prefix`], but sorted by the encoded keys in the given `order`
+    pub async fn find_by_prefix_ordered<KP>(
+        &mut self,
+        key_prefix: &KP,
+        order: IterOrder,
+    ) -> impl Iterator<Item = Result<(KP::Key, KP::Value)>> + '_
+    where
+        KP: DatabaseKeyPrefix + DatabaseKeyPrefixConst,
+    {
+        let decoders = self.decoders.clone();
+        let start = key_prefix.to_bytes();
+        let end = prefix_range_end(&start);
+        let entries = self
+            .tx
+            .raw_find_by_range(&start, end.as_deref(), order)
+            .await;
+        decode_entries::<KP::Key, KP::Value>(entries, decoders)
+    }
+
+    /// Entries with keys from `start` (inclusive) to `end` (exclusive) sorted by the encoded keys
+    /// in the given `order`
+    ///
+    /// Integers are encoded little-endian, so for keys containing them the order of the encoding
+    /// is not their numeric order.
+    pub async fn find_by_range<K>(
+        &mut self,
+        start: &K,
+        end: &K,
+        order: IterOrder,
+    ) -> impl Iterator<Item = Result<(K, K::Value)>> + '_
+    where
+        K: DatabaseKey + DatabaseKeyPrefixConst<Key = K>,
+    {
+        let decoders = self.decoders.clone();
+        let entries = self
+            .tx
+            .raw_find_by_range(&start.to_bytes(), Some(&end.to_bytes()), order)
+            .await;
+        decode_entries::<K, K::Value>(entries, decoders)
+    }
+
+    /// Inserts an entry without a typed key, e.g. when copying entries between databases
+    pub async fn raw_insert_bytes(
+        &mut self,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>> {
+        self.commit_tracker.has_writes = true;
+        self.tx.raw_insert_bytes(key, value).await
+    }
+
     pub async fn insert_entry<K>(&mut self, key: &K, value: &K::Value) -> Result<Option<K::Value>>
     where
         K: DatabaseKey + DatabaseKeyPrefixConst,
@@ -304,6 +431,107 @@ impl<'a> DatabaseTransaction<'a> {
     }
 }
 
+fn decode_entries<K, V>(
+    entries: PrefixIter<'_>,
+    decoders: ModuleDecoderRegistry,
+) -> impl Iterator<Item = Result<(K, V)>> + '_
+where
+    K: DatabaseKey,
+    V: DatabaseValue,
+{
+    entries.map(move |res| {
+        res.and_then(|(key_bytes, value_bytes)| {
+            let key = K::from_bytes(&key_bytes, &decoders)?;
+            trace!(
+                "find by range: Decoding {} from bytes {:?}",
+                std::any::type_name::<V>(),
+                value_bytes
+            );
+            let value = V::from_bytes(&value_bytes, &decoders)?;
+            Ok((key, value))
+        })
+    })
+}
+
+/// A transaction that can only read from the database, e.g. to answer API requests
+///
+/// It reads from the same consistent snapshot as a [`DatabaseTransaction`], but since it can't
+/// write there is nothing to commit and it is simply dropped when done.
+pub struct ReadDatabaseTransaction<'a>(DatabaseTransaction<'a>);
+
+impl<'a> ReadDatabaseTransaction<'a> {
+    pub fn new(dbtx: DatabaseTransaction<'a>) -> ReadDatabaseTransaction<'a> {
+        ReadDatabaseTransaction(dbtx)
+    }
+
+    pub async fn get_value<K>(&mut self, key: &K) -> Result<Option<K::Value>>
+    where
+        K: DatabaseKey + DatabaseKeyPrefixConst,
+    {
+        self.0.get_value(key).await
+    }
+
+    /// Like [`Self::get_value`] for all `keys` at once, the result has the same order as `keys`
+    pub async fn get_values<K>(&mut self, keys: &[K]) -> Result<Vec<Option<K::Value>>>
+    where
+        K: DatabaseKey + DatabaseKeyPrefixConst,
+    {
+        self.0.get_values(keys).await
+    }
+
+    pub async fn find_by_prefix<KP>(
+        &mut self,
+        key_prefix: &KP,
+    ) -> impl Iterator<Item = Result<(KP::Key, KP::Value)>> + '_
+    where
+        KP: DatabaseKeyPrefix + DatabaseKeyPrefixConst,
+    {
+        self.0.find_by_prefix(key_prefix).await
+    }
+
+    /// See [`DatabaseTransaction::find_by_prefix_ordered`]
+    pub async fn find_by_prefix_ordered<KP>(
+        &mut self,
+        key_prefix: &KP,
+        order: IterOrder,
+    ) -> impl Iterator<Item = Result<(KP::Key, KP::Value)>> + '_
+    where
+        KP: DatabaseKeyPrefix + DatabaseKeyPrefixConst,
+    {
+        self.0.find_by_prefix_ordered(key_prefix, order).await
+    }
+
+    /// See [`DatabaseTransaction::find_by_range`]
+    pub async fn find_by_range<K>(
+        &mut self,
+        start: &K,
+        end: &K,
+        order: IterOrder,
+    ) -> impl Iterator<Item = Result<(K, K::Value)>> + '_
+    where
+        K: DatabaseKey + DatabaseKeyPrefixConst<Key = K>,
+    {
+        self.0.find_by_range(start, end, order).await
+    }
+
+    pub async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.raw_get_bytes(key).await
+    }
+
+    pub async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixIter<'_> {
+        self.0.raw_find_by_prefix(key_prefix).await
+    }
+
+    pub async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        self.0.raw_find_by_range(start, end, order).await
+    }
+}
+
 impl<T> DatabaseKeyPrefix for T
 where
     T: DatabaseKeyPrefixConst + crate::encoding::Encodable + Debug,
@@ -361,6 +589,122 @@ where
     }
 }
 
+/// Key prefix reserved for the database schema version records, must not be used by any module
+pub const DATABASE_VERSION_KEY_PREFIX: u8 = 0x50;
+
+/// Version of the database schema used by a module (or the core server/client)
+///
+/// Has to be incremented whenever the layout of a stored key or value changes, together with a
+/// migration in the corresponding [`MigrationMap`] upgrading the data from the previous version.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Encodable,
+    Decodable,
+    Serialize,
+    Deserialize,
+)]
+pub struct DatabaseVersion(pub u64);
+
+impl DatabaseVersion {
+    pub fn increment(&self) -> DatabaseVersion {
+        DatabaseVersion(self.0 + 1)
+    }
+}
+
+impl std::fmt::Display for DatabaseVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Schema version record of the module identified by the contained [`ModuleKey`]
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct DatabaseVersionKey(pub ModuleKey);
+
+impl DatabaseKeyPrefixConst for DatabaseVersionKey {
+    const DB_PREFIX: u8 = DATABASE_VERSION_KEY_PREFIX;
+    type Key = Self;
+    type Value = DatabaseVersion;
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct DatabaseVersionKeyPrefix;
+
+impl DatabaseKeyPrefixConst for DatabaseVersionKeyPrefix {
+    const DB_PREFIX: u8 = DATABASE_VERSION_KEY_PREFIX;
+    type Key = DatabaseVersionKey;
+    type Value = DatabaseVersion;
+}
+
+/// Function upgrading the database from the version it is registered under to the next one
+pub type DatabaseMigrationFn =
+    for<'r, 'tx> fn(&'r mut DatabaseTransaction<'tx>) -> BoxFuture<'r, Result<()>>;
+
+/// Migrations of a module, keyed by the version they upgrade *from*
+pub type MigrationMap = BTreeMap<DatabaseVersion, DatabaseMigrationFn>;
+
+/// Brings the data of the module identified by `module_key` up to `target_version`
+///
+/// Databases without a version record are treated as version 0, which is the layout all data had
+/// before versioning was introduced. Every migration from the stored version up to
+/// `target_version` is applied in order and the new version is recorded, all within `dbtx`, so
+/// that a failed migration leaves the database untouched once the transaction is dropped.
+///
+/// Returns an error if the database was written by a newer version of the code or a migration is
+/// missing.
+pub async fn apply_migrations(
+    dbtx: &mut DatabaseTransaction<'_>,
+    module_key: ModuleKey,
+    target_version: DatabaseVersion,
+    migrations: MigrationMap,
+) -> Result<()> {
+    let version_key = DatabaseVersionKey(module_key);
+    let stored_version = dbtx.get_value(&version_key).await?;
+    let mut current_version = stored_version.unwrap_or(DatabaseVersion(0));
+
+    if current_version > target_version {
+        bail!(
+            "Database version {} of module {} is newer than the supported version {}",
+            current_version,
+            module_key,
+            target_version
+        );
+    }
+
+    while current_version < target_version {
+        let migration = match migrations.get(&current_version) {
+            Some(migration) => migration,
+            None => bail!(
+                "Missing migration from database version {} of module {}",
+                current_version,
+                module_key
+            ),
+        };
+
+        info!(
+            module_key,
+            from = %current_version,
+            "Migrating database to version {}",
+            current_version.increment()
+        );
+        migration(dbtx).await?;
+        current_version = current_version.increment();
+    }
+
+    if stored_version != Some(current_version) {
+        dbtx.insert_entry(&version_key, &current_version).await?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 pub enum DecodingError {
     #[error("Key had a wrong prefix, expected {expected} but got {found}")]
@@ -386,11 +730,19 @@ impl DecodingError {
 }
 
 mod tests {
-    use super::Database;
+    use futures::future::BoxFuture;
+
+    use super::{
+        apply_migrations, Database, DatabaseTransaction, DatabaseVersion, DatabaseVersionKey,
+        IterOrder, MigrationMap,
+    };
+    use crate::core::ModuleKey;
     use crate::db::DatabaseKeyPrefixConst;
     use crate::encoding::{Decodable, Encodable};
     use crate::module::registry::ModuleDecoderRegistry;
 
+    const TEST_MODULE_KEY: ModuleKey = 42;
+
     #[repr(u8)]
     #[derive(Clone)]
     pub enum TestDbKeyPrefix {
@@ -518,6 +870,35 @@ mod tests {
         dbtx.commit_tx().await.expect("DB Error");
     }
 
+    pub async fn verify_get_values(db: Database) {
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(2))
+            .await
+            .expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_entry(&TestKey(3), &TestVal(4))
+            .await
+            .expect("DB Error");
+
+        assert_eq!(
+            dbtx.get_values(&[TestKey(3), TestKey(2), TestKey(1)])
+                .await
+                .unwrap(),
+            vec![Some(TestVal(4)), None, Some(TestVal(2))]
+        );
+        assert_eq!(dbtx.get_values::<TestKey>(&[]).await.unwrap(), vec![]);
+
+        dbtx.remove_entry(&TestKey(1)).await.expect("DB Error");
+        assert_eq!(
+            dbtx.get_values(&[TestKey(1), TestKey(3)]).await.unwrap(),
+            vec![None, Some(TestVal(4))]
+        );
+
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
     pub async fn verify_prevent_dirty_reads(db: Database) {
         let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
 
@@ -599,6 +980,126 @@ mod tests {
         assert_eq!(returned_keys, expected_keys);
     }
 
+    pub async fn verify_find_by_range(db: Database) {
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        for key in [3, 1, 5, 2, 4] {
+            dbtx.insert_entry(&TestKey(key), &TestVal(key * 10))
+                .await
+                .expect("DB Error");
+        }
+        dbtx.insert_entry(&AltTestKey(3), &TestVal(0))
+            .await
+            .expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        // the range has to include our own uncommitted writes
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_entry(&TestKey(6), &TestVal(60))
+            .await
+            .expect("DB Error");
+        dbtx.remove_entry(&TestKey(2)).await.expect("DB Error");
+
+        let ascending = dbtx
+            .find_by_range(&TestKey(1), &TestKey(7), IterOrder::Ascending)
+            .await
+            .map(|res| res.expect("DB Error"))
+            .map(|(key, value)| (key.0, value.0))
+            .collect::<Vec<_>>();
+        assert_eq!(ascending, vec![(1, 10), (3, 30), (4, 40), (5, 50), (6, 60)]);
+
+        let descending = dbtx
+            .find_by_range(&TestKey(2), &TestKey(5), IterOrder::Descending)
+            .await
+            .map(|res| res.expect("DB Error").0 .0)
+            .collect::<Vec<_>>();
+        assert_eq!(descending, vec![4, 3]);
+
+        assert_eq!(
+            dbtx.find_by_range(&TestKey(4), &TestKey(4), IterOrder::Ascending)
+                .await
+                .count(),
+            0
+        );
+        assert_eq!(
+            dbtx.find_by_range(&TestKey(5), &TestKey(1), IterOrder::Descending)
+                .await
+                .count(),
+            0
+        );
+
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    pub async fn verify_find_by_prefix_ordered(db: Database) {
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        for key in [2, 3, 1] {
+            dbtx.insert_entry(&TestKey(key), &TestVal(key))
+                .await
+                .expect("DB Error");
+            dbtx.insert_entry(&AltTestKey(key), &TestVal(key))
+                .await
+                .expect("DB Error");
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_entry(&TestKey(0), &TestVal(0))
+            .await
+            .expect("DB Error");
+
+        let ascending = dbtx
+            .find_by_prefix_ordered(&DbPrefixTestPrefix, IterOrder::Ascending)
+            .await
+            .map(|res| res.expect("DB Error").0 .0)
+            .collect::<Vec<_>>();
+        assert_eq!(ascending, vec![0, 1, 2, 3]);
+
+        let descending = dbtx
+            .find_by_prefix_ordered(&AltDbPrefixTestPrefix, IterOrder::Descending)
+            .await
+            .map(|res| res.expect("DB Error").0 .0)
+            .collect::<Vec<_>>();
+        assert_eq!(descending, vec![3, 2, 1]);
+
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    pub async fn verify_read_transaction(db: Database) {
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(2))
+            .await
+            .expect("DB Error");
+        dbtx.insert_entry(&TestKey(2), &TestVal(3))
+            .await
+            .expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        // uncommitted writes of other transactions are not visible
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_entry(&TestKey(3), &TestVal(4))
+            .await
+            .expect("DB Error");
+
+        let mut read_dbtx = db
+            .begin_read_transaction(ModuleDecoderRegistry::default())
+            .await;
+        assert_eq!(
+            read_dbtx.get_value(&TestKey(1)).await.unwrap(),
+            Some(TestVal(2))
+        );
+        assert_eq!(read_dbtx.get_value(&TestKey(3)).await.unwrap(), None);
+        assert_eq!(
+            read_dbtx
+                .find_by_range(&TestKey(0), &TestKey(4), IterOrder::Descending)
+                .await
+                .map(|res| res.expect("DB Error").0 .0)
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
     pub async fn verify_commit(db: Database) {
         let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
 
@@ -879,4 +1380,118 @@ mod tests {
 
         assert_eq!(returned_keys, expected_keys);
     }
+
+    /// Test migration moving all `TestKey` entries to `AltTestKey` and doubling their values
+    fn migrate_test_db_version_0<'r, 'tx>(
+        dbtx: &'r mut DatabaseTransaction<'tx>,
+    ) -> BoxFuture<'r, anyhow::Result<()>> {
+        Box::pin(async move {
+            let entries_v0 = dbtx
+                .find_by_prefix(&DbPrefixTestPrefix)
+                .await
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            dbtx.remove_by_prefix(&DbPrefixTestPrefix).await?;
+            for (TestKey(key), TestVal(val)) in entries_v0 {
+                dbtx.insert_new_entry(&AltTestKey(key), &TestVal(val * 2))
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    pub async fn verify_apply_migrations(db: Database) {
+        // Write a database fixture using the version 0 layout
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        assert!(dbtx
+            .insert_entry(&TestKey(1), &TestVal(2))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(dbtx
+            .insert_entry(&TestKey(2), &TestVal(3))
+            .await
+            .unwrap()
+            .is_none());
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut migrations = MigrationMap::new();
+        migrations.insert(DatabaseVersion(0), migrate_test_db_version_0);
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        apply_migrations(
+            &mut dbtx,
+            TEST_MODULE_KEY,
+            DatabaseVersion(1),
+            migrations.clone(),
+        )
+        .await
+        .expect("Migration failed");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey(TEST_MODULE_KEY))
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(1))
+        );
+        assert_eq!(dbtx.get_value(&TestKey(1)).await.unwrap(), None);
+        assert_eq!(dbtx.get_value(&TestKey(2)).await.unwrap(), None);
+        assert_eq!(
+            dbtx.get_value(&AltTestKey(1)).await.unwrap(),
+            Some(TestVal(4))
+        );
+        assert_eq!(
+            dbtx.get_value(&AltTestKey(2)).await.unwrap(),
+            Some(TestVal(6))
+        );
+
+        // Running the migrations again must be a no-op
+        apply_migrations(
+            &mut dbtx,
+            TEST_MODULE_KEY,
+            DatabaseVersion(1),
+            migrations.clone(),
+        )
+        .await
+        .expect("Migration failed");
+        assert_eq!(
+            dbtx.get_value(&AltTestKey(1)).await.unwrap(),
+            Some(TestVal(4))
+        );
+
+        // Downgrades and missing migrations are refused
+        assert!(apply_migrations(
+            &mut dbtx,
+            TEST_MODULE_KEY,
+            DatabaseVersion(0),
+            migrations.clone(),
+        )
+        .await
+        .is_err());
+        assert!(
+            apply_migrations(&mut dbtx, TEST_MODULE_KEY, DatabaseVersion(2), migrations)
+                .await
+                .is_err()
+        );
+
+        // Other modules are versioned independently
+        apply_migrations(
+            &mut dbtx,
+            TEST_MODULE_KEY + 1,
+            DatabaseVersion(0),
+            MigrationMap::new(),
+        )
+        .await
+        .expect("Migration failed");
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey(TEST_MODULE_KEY + 1))
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(0))
+        );
+
+        // Commit to surpress the warning message
+        dbtx.commit_tx().await.expect("DB Error");
+    }
 }
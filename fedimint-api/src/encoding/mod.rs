@@ -9,6 +9,7 @@ mod tbs;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Formatter};
 use std::io::{self, Error, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::format_err;
@@ -104,6 +105,98 @@ impl DecodeError {
     }
 }
 
+/// Limits on the resources decoding a single untrusted item may use
+///
+/// Length prefixes are chosen by whoever encoded an item, without limits a few bytes could make
+/// the decoder allocate gigabytes or nest items until the stack overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Bytes that collections and boxes created while decoding may take up in total
+    pub max_bytes: usize,
+    /// How deeply collections, boxes and module items may be nested
+    pub max_depth: usize,
+}
+
+impl DecodeLimits {
+    /// Limits for items received from peers and API clients
+    pub const UNTRUSTED: DecodeLimits = DecodeLimits {
+        max_bytes: 16 * 1024 * 1024,
+        max_depth: 32,
+    };
+}
+
+/// Resources left while decoding an item, shared by all decoders of its nested items
+///
+/// Decoders reach it through [`ModuleDecoderRegistry::allocate`] and
+/// [`ModuleDecoderRegistry::enter`], decoding with a registry that has no context is unlimited.
+#[derive(Debug)]
+pub struct DecodeContext {
+    max_depth: usize,
+    remaining_bytes: AtomicUsize,
+    depth: AtomicUsize,
+}
+
+impl DecodeContext {
+    pub fn new(limits: DecodeLimits) -> DecodeContext {
+        DecodeContext {
+            max_depth: limits.max_depth,
+            remaining_bytes: AtomicUsize::new(limits.max_bytes),
+            depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Accounts for `count` items of `item_size` bytes (at least one byte each, so zero-sized
+    /// items can't be used to loop forever) before they are decoded, so a hostile length prefix
+    /// fails before anything is allocated
+    pub fn allocate(&self, count: u64, item_size: usize) -> Result<(), DecodeError> {
+        let bytes = usize::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_mul(item_size.max(1)))
+            .ok_or_else(|| DecodeError::from_str("Decoding budget exceeded"))?;
+
+        self.remaining_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                remaining.checked_sub(bytes)
+            })
+            .map(|_| ())
+            .map_err(|_| DecodeError::from_str("Decoding budget exceeded"))
+    }
+
+    /// Bytes left of the budget, every successful [`DecodeContext::allocate`] reduces it
+    pub fn remaining_bytes(&self) -> usize {
+        self.remaining_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Enters a nested item, the depth is restored once the returned guard is dropped
+    pub fn enter(&self) -> Result<DepthGuard<'_>, DecodeError> {
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        let guard = DepthGuard(Some(self));
+        if depth > self.max_depth {
+            return Err(DecodeError::from_str("Maximum decoding depth exceeded"));
+        }
+        Ok(guard)
+    }
+}
+
+/// Keeps track of being inside a nested item, see [`DecodeContext::enter`]
+#[derive(Debug)]
+pub struct DepthGuard<'a>(Option<&'a DecodeContext>);
+
+impl<'a> DepthGuard<'a> {
+    /// Guard for decoding without a [`DecodeContext`]
+    pub fn unlimited() -> DepthGuard<'a> {
+        DepthGuard(None)
+    }
+}
+
+impl<'a> Drop for DepthGuard<'a> {
+    fn drop(&mut self) {
+        if let Some(context) = self.0 {
+            context.depth.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
 macro_rules! impl_encode_decode_num {
     ($num_type:ty) => {
         impl Encodable for $num_type {
@@ -189,6 +282,8 @@ where
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let len = u64::consensus_decode(d, modules)?;
+        let _guard = modules.enter()?;
+        modules.allocate(len, std::mem::size_of::<T>())?;
         (0..len).map(|_| T::consensus_decode(d, modules)).collect()
     }
 }
@@ -275,6 +370,8 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _guard = modules.enter()?;
+        modules.allocate(1, std::mem::size_of::<T>())?;
         Ok(Box::new(T::consensus_decode(d, modules)?))
     }
 }
@@ -330,8 +427,15 @@ impl Decodable for SystemTime {
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let secs = Decodable::consensus_decode(d, modules)?;
-        let nsecs = Decodable::consensus_decode(d, modules)?;
-        Ok(UNIX_EPOCH + Duration::new(secs, nsecs))
+        let nsecs: u32 = Decodable::consensus_decode(d, modules)?;
+        if nsecs >= 1_000_000_000 {
+            return Err(DecodeError::from_str(
+                "Out of range, expected less than 1s of nanos",
+            ));
+        }
+        UNIX_EPOCH
+            .checked_add(Duration::new(secs, nsecs))
+            .ok_or_else(|| DecodeError::from_str("Out of range time"))
     }
 }
 
@@ -432,12 +536,17 @@ where
     ) -> Result<Self, DecodeError> {
         let mut res = BTreeMap::new();
         let len = u64::consensus_decode(d, modules)?;
+        let _guard = modules.enter()?;
+        modules.allocate(len, std::mem::size_of::<(K, V)>())?;
         for _ in 0..len {
-            let amt = K::consensus_decode(d, modules)?;
-            let v = V::consensus_decode(d, modules)?;
-            if res.insert(amt, v).is_some() {
-                return Err(DecodeError(format_err!("Duplicate key")));
+            let k = K::consensus_decode(d, modules)?;
+            // keys are encoded in order, accepting any other order would make the encoding
+            // ambiguous
+            if res.keys().next_back().map_or(false, |last| last >= &k) {
+                return Err(DecodeError(format_err!("Duplicate or unordered key")));
             }
+            let v = V::consensus_decode(d, modules)?;
+            res.insert(k, v);
         }
         Ok(res)
     }
@@ -467,11 +576,15 @@ where
     ) -> Result<Self, DecodeError> {
         let mut res = BTreeSet::new();
         let len = u64::consensus_decode(d, modules)?;
+        let _guard = modules.enter()?;
+        modules.allocate(len, std::mem::size_of::<K>())?;
         for _ in 0..len {
             let k = K::consensus_decode(d, modules)?;
-            if !res.insert(k) {
-                return Err(DecodeError(format_err!("Duplicate key")));
+            // see `BTreeMap`
+            if res.iter().next_back().map_or(false, |last| last >= &k) {
+                return Err(DecodeError(format_err!("Duplicate or unordered key")));
             }
+            res.insert(k);
         }
         Ok(res)
     }
@@ -538,6 +651,8 @@ mod tests {
     use std::fmt::Debug;
     use std::io::Cursor;
 
+    use proptest::prelude::*;
+
     use super::*;
     use crate::encoding::{Decodable, Encodable};
     use crate::ModuleDecoderRegistry;
@@ -571,6 +686,234 @@ mod tests {
         assert_eq!(cursor.position(), len as u64);
     }
 
+    /// Decodes `bytes` and, if they decode, checks that they start with the canonical encoding of
+    /// the decoded value, so no two encodings decode to the same value
+    pub(crate) fn test_canonical_decoding<T>(bytes: &[u8])
+    where
+        T: Encodable + Decodable + Debug,
+    {
+        let mut cursor = Cursor::new(bytes);
+        if let Ok(decoded) = T::consensus_decode(&mut cursor, &ModuleDecoderRegistry::default()) {
+            let consumed = &bytes[..cursor.position() as usize];
+            assert_eq!(decoded.consensus_encode_to_vec().unwrap(), consumed);
+        }
+    }
+
+    #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq)]
+    struct DerivedStruct {
+        num: u64,
+        flag: bool,
+        text: String,
+        tuple: DerivedTupleStruct,
+        variants: Vec<DerivedEnum>,
+    }
+
+    #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq)]
+    struct DerivedTupleStruct(u16, [u8; 4], BTreeMap<u32, Vec<u8>>);
+
+    #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq)]
+    enum DerivedEnum {
+        Unit,
+        Tuple(u8, Option<u64>),
+        Struct {
+            set: BTreeSet<u16>,
+            time: SystemTime,
+        },
+    }
+
+    fn system_time() -> impl Strategy<Value = SystemTime> {
+        (0..u64::from(u32::MAX), 0..1_000_000_000u32)
+            .prop_map(|(secs, nanos)| UNIX_EPOCH + Duration::new(secs, nanos))
+    }
+
+    fn derived_enum() -> impl Strategy<Value = DerivedEnum> {
+        prop_oneof![
+            Just(DerivedEnum::Unit),
+            (any::<u8>(), any::<Option<u64>>()).prop_map(|(a, b)| DerivedEnum::Tuple(a, b)),
+            (any::<BTreeSet<u16>>(), system_time())
+                .prop_map(|(set, time)| DerivedEnum::Struct { set, time }),
+        ]
+    }
+
+    fn derived_struct() -> impl Strategy<Value = DerivedStruct> {
+        (
+            any::<u64>(),
+            any::<bool>(),
+            ".*",
+            (
+                any::<u16>(),
+                any::<[u8; 4]>(),
+                any::<BTreeMap<u32, Vec<u8>>>(),
+            ),
+            prop::collection::vec(derived_enum(), 0..8),
+        )
+            .prop_map(|(num, flag, text, (a, b, c), variants)| DerivedStruct {
+                num,
+                flag,
+                text,
+                tuple: DerivedTupleStruct(a, b, c),
+                variants,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_roundtrip_primitives(
+            num in any::<(u8, u16, u32, u64)>(),
+            flag in any::<bool>(),
+            text in ".*",
+            bytes in any::<Vec<u8>>(),
+            time in system_time(),
+        ) {
+            test_roundtrip(num);
+            test_roundtrip(flag);
+            test_roundtrip(text);
+            test_roundtrip(bytes);
+            test_roundtrip(time);
+        }
+
+        #[test]
+        fn proptest_roundtrip_derived(value in derived_struct()) {
+            test_roundtrip(value);
+        }
+
+        #[test]
+        fn proptest_canonical_decoding(bytes in any::<Vec<u8>>()) {
+            test_canonical_decoding::<DerivedStruct>(&bytes);
+            test_canonical_decoding::<DerivedEnum>(&bytes);
+            test_canonical_decoding::<BTreeMap<u8, bool>>(&bytes);
+            test_canonical_decoding::<BTreeSet<u16>>(&bytes);
+            test_canonical_decoding::<Option<String>>(&bytes);
+            test_canonical_decoding::<SystemTime>(&bytes);
+        }
+
+        #[test]
+        fn proptest_canonical_decoding_of_mutated_encodings(
+            value in derived_struct(),
+            idx in any::<prop::sample::Index>(),
+            byte in any::<u8>(),
+        ) {
+            let mut bytes = value.consensus_encode_to_vec().unwrap();
+            let idx = idx.index(bytes.len());
+            bytes[idx] = byte;
+            test_canonical_decoding::<DerivedStruct>(&bytes);
+        }
+
+        /// Length prefixes are attacker controlled, claiming huge lengths must fail on the missing
+        /// data instead of allocating memory for them up front
+        #[test]
+        fn proptest_hostile_lengths_fail(len in (1u64 << 32).., payload in any::<Vec<u8>>()) {
+            let mut bytes = len.consensus_encode_to_vec().unwrap();
+            bytes.extend(payload);
+            let modules = ModuleDecoderRegistry::default();
+
+            prop_assert!(Vec::<u8>::consensus_decode(&mut Cursor::new(&bytes), &modules).is_err());
+            prop_assert!(String::consensus_decode(&mut Cursor::new(&bytes), &modules).is_err());
+            prop_assert!(
+                Vec::<Vec<u64>>::consensus_decode(&mut Cursor::new(&bytes), &modules).is_err()
+            );
+            prop_assert!(
+                BTreeMap::<u8, String>::consensus_decode(&mut Cursor::new(&bytes), &modules)
+                    .is_err()
+            );
+
+            // with limits the length is rejected by the budget, which is only spent once
+            // allocating is allowed, before a single item is decoded
+            let limited = modules.with_limits(DecodeLimits::UNTRUSTED);
+            let rejected_before_allocating = |result: Result<(), DecodeError>| {
+                result.map_err(|e| e.to_string()) == Err("Decoding budget exceeded".to_string())
+                    && limited.remaining_bytes() == Some(DecodeLimits::UNTRUSTED.max_bytes)
+            };
+            prop_assert!(rejected_before_allocating(
+                Vec::<u8>::consensus_decode(&mut Cursor::new(&bytes), &limited).map(drop)
+            ));
+            prop_assert!(rejected_before_allocating(
+                String::consensus_decode(&mut Cursor::new(&bytes), &limited).map(drop)
+            ));
+            prop_assert!(rejected_before_allocating(
+                Vec::<Vec<u64>>::consensus_decode(&mut Cursor::new(&bytes), &limited).map(drop)
+            ));
+            prop_assert!(rejected_before_allocating(
+                BTreeMap::<u8, String>::consensus_decode(&mut Cursor::new(&bytes), &limited)
+                    .map(drop)
+            ));
+        }
+    }
+
+    #[test_log::test]
+    fn test_non_canonical_encodings_are_rejected() {
+        let modules = ModuleDecoderRegistry::default();
+
+        let unordered_map = [2, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 0];
+        assert!(BTreeMap::<u8, bool>::consensus_decode(&mut &unordered_map[..], &modules).is_err());
+
+        let unordered_set = [2, 0, 0, 0, 0, 0, 0, 0, 2, 1];
+        assert!(BTreeSet::<u8>::consensus_decode(&mut &unordered_set[..], &modules).is_err());
+
+        let mut excess_nanos = 0u64.consensus_encode_to_vec().unwrap();
+        excess_nanos.extend(1_000_000_000u32.to_le_bytes());
+        assert!(SystemTime::consensus_decode(&mut &excess_nanos[..], &modules).is_err());
+
+        let mut overflowing_time = u64::MAX.consensus_encode_to_vec().unwrap();
+        overflowing_time.extend(999_999_999u32.to_le_bytes());
+        assert!(SystemTime::consensus_decode(&mut &overflowing_time[..], &modules).is_err());
+
+        let truncated_variant = (1u64 << 32).consensus_encode_to_vec().unwrap();
+        assert!(DerivedEnum::consensus_decode(&mut &truncated_variant[..], &modules).is_err());
+    }
+
+    #[test_log::test]
+    fn test_decode_limits() {
+        let limits = DecodeLimits {
+            max_bytes: 64,
+            max_depth: 2,
+        };
+        let modules = ModuleDecoderRegistry::default();
+
+        let within_budget = vec![0u8; 64].consensus_encode_to_vec().unwrap();
+        assert!(
+            Vec::<u8>::consensus_decode(&mut &within_budget[..], &modules.with_limits(limits))
+                .is_ok()
+        );
+
+        let over_budget = vec![0u8; 65].consensus_encode_to_vec().unwrap();
+        assert!(
+            Vec::<u8>::consensus_decode(&mut &over_budget[..], &modules.with_limits(limits))
+                .is_err()
+        );
+
+        // zero-sized items don't read any data, so only the budget stops the decoder
+        let endless_units = u64::MAX.consensus_encode_to_vec().unwrap();
+        assert!(
+            Vec::<()>::consensus_decode(&mut &endless_units[..], &modules.with_limits(limits))
+                .is_err()
+        );
+
+        // the budget is shared by all items decoded with the same registry
+        let limited = modules.with_limits(limits);
+        let half_budget = vec![0u8; 40].consensus_encode_to_vec().unwrap();
+        assert!(Vec::<u8>::consensus_decode(&mut &half_budget[..], &limited).is_ok());
+        assert!(Vec::<u8>::consensus_decode(&mut &half_budget[..], &limited).is_err());
+
+        let nested = vec![vec![0u8]].consensus_encode_to_vec().unwrap();
+        assert!(
+            Vec::<Vec<u8>>::consensus_decode(&mut &nested[..], &modules.with_limits(limits))
+                .is_ok()
+        );
+
+        let too_deep = vec![vec![vec![0u8]]].consensus_encode_to_vec().unwrap();
+        assert!(Vec::<Vec<Vec<u8>>>::consensus_decode(
+            &mut &too_deep[..],
+            &modules.with_limits(limits)
+        )
+        .is_err());
+        assert!(Box::<Vec<Vec<u8>>>::consensus_decode(
+            &mut &too_deep[..],
+            &modules.with_limits(limits)
+        )
+        .is_err());
+    }
+
     #[test_log::test]
     fn test_derive_struct() {
         #[derive(Debug, Encodable, Decodable, Eq, PartialEq)]
@@ -100,6 +100,14 @@ impl Amount {
             msats: self.msats.saturating_sub(other.msats),
         }
     }
+
+    /// Returns `ppm` parts-per-million of this amount, rounded down to the nearest msat
+    pub fn mul_ppm(self, ppm: u64) -> Self {
+        let msats = (self.msats as u128 * ppm as u128) / 1_000_000;
+        Amount {
+            msats: msats.try_into().unwrap_or(u64::MAX),
+        }
+    }
 }
 
 /// Shorthand for [`Amount::from_msats`]
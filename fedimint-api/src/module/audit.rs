@@ -1,8 +1,10 @@
 use std::fmt::{Display, Formatter};
 
+use serde::{Deserialize, Serialize};
+
 use crate::db::{DatabaseKeyPrefix, DatabaseKeyPrefixConst, DatabaseTransaction};
 
-#[derive(Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Audit {
     items: Vec<AuditItem>,
 }
@@ -53,6 +55,7 @@ impl Display for Audit {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditItem {
     pub name: String,
     pub milli_sat: i64,
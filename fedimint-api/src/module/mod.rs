@@ -15,7 +15,7 @@ use crate::config::{ClientModuleConfig, ConfigGenParams, DkgPeerMsg, ServerModul
 use crate::core::{
     PluginConsensusItem, PluginDecode, PluginInput, PluginOutput, PluginOutputOutcome,
 };
-use crate::db::DatabaseTransaction;
+use crate::db::{Database, DatabaseTransaction, DatabaseVersion, MigrationMap};
 use crate::module::audit::Audit;
 use crate::module::interconnect::ModuleInterconect;
 use crate::net::peers::MuxPeerConnections;
@@ -23,6 +23,7 @@ use crate::server::PluginVerificationCache;
 use crate::task::TaskGroup;
 use crate::{Amount, OutPoint, PeerId};
 
+#[derive(Debug, Clone)]
 pub struct InputMeta {
     pub amount: TransactionItemAmount,
     pub puk_keys: Vec<XOnlyPublicKey>,
@@ -75,6 +76,24 @@ pub trait TypedApiEndpoint {
     type Param: serde::de::DeserializeOwned + Send;
     type Response: serde::Serialize;
 
+    async fn handle<'a, 'b>(
+        state: &'a Self::State,
+        dbtx: fedimint_api::db::ReadDatabaseTransaction<'b>,
+        params: Self::Param,
+    ) -> Result<Self::Response, ApiError>;
+}
+
+/// Like [`TypedApiEndpoint`] for the few endpoints that have to write to the database
+#[async_trait]
+pub trait TypedWriteApiEndpoint {
+    type State: Sync;
+
+    /// example: /transaction
+    const PATH: &'static str;
+
+    type Param: serde::de::DeserializeOwned + Send;
+    type Response: serde::Serialize;
+
     async fn handle<'a, 'b>(
         state: &'a Self::State,
         dbtx: fedimint_api::db::DatabaseTransaction<'b>,
@@ -87,6 +106,9 @@ pub mod __reexports {
     pub use serde_json;
 }
 
+/// Handlers get a [`ReadDatabaseTransaction`](crate::db::ReadDatabaseTransaction), unless the
+/// transaction is annotated with `DatabaseTransaction` because the endpoint has to write.
+///
 /// # Example
 ///
 /// ```rust
@@ -99,9 +121,55 @@ pub mod __reexports {
 ///         Ok(0)
 ///     }
 /// };
+///
+/// let _: ApiEndpoint<State> = api_endpoint! {
+///     "/store",
+///     async |state: &State, dbtx: DatabaseTransaction, params: ()| -> () {
+///         dbtx.commit_tx().await.expect("DB error");
+///         Ok(())
+///     }
+/// };
 /// ```
 #[macro_export]
 macro_rules! __api_endpoint {
+    (
+        $path:expr,
+        async |$state:ident: &$state_ty:ty, $dbtx:ident: DatabaseTransaction, $param:ident: $param_ty:ty| -> $resp_ty:ty $body:block
+    ) => {{
+        struct Endpoint;
+
+        #[async_trait::async_trait]
+        impl $crate::module::TypedWriteApiEndpoint for Endpoint {
+            const PATH: &'static str = $path;
+            type State = $state_ty;
+            type Param = $param_ty;
+            type Response = $resp_ty;
+
+            async fn handle<'a, 'b>(
+                $state: &'a Self::State,
+                mut $dbtx: fedimint_api::db::DatabaseTransaction<'b>,
+                $param: Self::Param,
+            ) -> ::std::result::Result<Self::Response, $crate::module::ApiError> {
+                $body
+            }
+        }
+
+        ApiEndpoint {
+            path: <Endpoint as $crate::module::TypedWriteApiEndpoint>::PATH,
+            handler: $crate::module::ApiHandler::Write(Box::new(|m, dbtx, param| {
+                Box::pin(async move {
+                    let params = $crate::module::__reexports::serde_json::from_value(param)
+                        .map_err(|e| $crate::module::ApiError::bad_request(e.to_string()))?;
+
+                    let ret =
+                        <Endpoint as $crate::module::TypedWriteApiEndpoint>::handle(m, dbtx, params)
+                            .await?;
+                    Ok($crate::module::__reexports::serde_json::to_value(ret)
+                        .expect("encoding error"))
+                })
+            })),
+        }
+    }};
     (
         $path:expr,
         async |$state:ident: &$state_ty:ty, $dbtx:ident, $param:ident: $param_ty:ty| -> $resp_ty:ty $body:block
@@ -117,7 +185,7 @@ macro_rules! __api_endpoint {
 
             async fn handle<'a, 'b>(
                 $state: &'a Self::State,
-                mut $dbtx: fedimint_api::db::DatabaseTransaction<'b>,
+                mut $dbtx: fedimint_api::db::ReadDatabaseTransaction<'b>,
                 $param: Self::Param,
             ) -> ::std::result::Result<Self::Response, $crate::module::ApiError> {
                 $body
@@ -126,7 +194,7 @@ macro_rules! __api_endpoint {
 
         ApiEndpoint {
             path: <Endpoint as $crate::module::TypedApiEndpoint>::PATH,
-            handler: Box::new(|m, dbtx, param| {
+            handler: $crate::module::ApiHandler::Read(Box::new(|m, dbtx, param| {
                 Box::pin(async move {
                     let params = $crate::module::__reexports::serde_json::from_value(param)
                         .map_err(|e| $crate::module::ApiError::bad_request(e.to_string()))?;
@@ -137,17 +205,26 @@ macro_rules! __api_endpoint {
                     Ok($crate::module::__reexports::serde_json::to_value(ret)
                         .expect("encoding error"))
                 })
-            }),
+            })),
         }
     }};
 }
 
 pub use __api_endpoint as api_endpoint;
 
-use crate::module::registry::ModuleKey;
+use crate::module::registry::{ModuleDecoderRegistry, ModuleKey};
 
 type HandlerFnReturn<'a> = BoxFuture<'a, Result<serde_json::Value, ApiError>>;
-type HandlerFn<M> = Box<
+pub type ReadHandlerFn<M> = Box<
+    dyn for<'a> Fn(
+            &'a M,
+            fedimint_api::db::ReadDatabaseTransaction<'a>,
+            serde_json::Value,
+        ) -> HandlerFnReturn<'a>
+        + Send
+        + Sync,
+>;
+pub type WriteHandlerFn<M> = Box<
     dyn for<'a> Fn(
             &'a M,
             fedimint_api::db::DatabaseTransaction<'a>,
@@ -157,6 +234,34 @@ type HandlerFn<M> = Box<
         + Sync,
 >;
 
+/// Handler of an [`ApiEndpoint`], only endpoints that have to write get a transaction that can
+pub enum ApiHandler<M> {
+    Read(ReadHandlerFn<M>),
+    Write(WriteHandlerFn<M>),
+}
+
+impl<M> ApiHandler<M> {
+    /// Calls the handler with a transaction on `db` of the kind it needs
+    pub async fn call(
+        &self,
+        state: &M,
+        db: &Database,
+        decoders: ModuleDecoderRegistry,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        match self {
+            ApiHandler::Read(handler) => {
+                let dbtx = db.begin_read_transaction(decoders).await;
+                handler(state, dbtx, params).await
+            }
+            ApiHandler::Write(handler) => {
+                let dbtx = db.begin_transaction(decoders).await;
+                handler(state, dbtx, params).await
+            }
+        }
+    }
+}
+
 /// Definition of an API endpoint defined by a module `M`.
 pub struct ApiEndpoint<M> {
     /// Path under which the API endpoint can be reached. It should start with a `/`
@@ -165,8 +270,9 @@ pub struct ApiEndpoint<M> {
     pub path: &'static str,
     /// Handler for the API call that takes the following arguments:
     ///   * Reference to the module which defined it
+    ///   * A database transaction, read-only unless the endpoint has to write
     ///   * Request parameters parsed into JSON `[Value](serde_json::Value)`
-    pub handler: HandlerFn<M>,
+    pub handler: ApiHandler<M>,
 }
 
 #[derive(Error, Debug)]
@@ -198,6 +304,18 @@ where
     }
 }
 
+/// Module config before resharing the federation's keys, see
+/// [`FederationModuleConfigGen::distributed_reshare`]
+#[derive(Debug, Clone)]
+pub struct ModuleReshareParams {
+    /// Guardians holding shares of the old keys, all of them continue in the new federation
+    pub dealers: Vec<PeerId>,
+    /// Consensus config of the module before resharing
+    pub old_consensus: serde_json::Value,
+    /// Our module config before resharing, `None` if we are joining the federation
+    pub old_config: Option<ServerModuleConfig>,
+}
+
 #[async_trait]
 pub trait FederationModuleConfigGen {
     fn trusted_dealer_gen(
@@ -215,6 +333,18 @@ pub trait FederationModuleConfigGen {
         task_group: &mut TaskGroup,
     ) -> anyhow::Result<Cancellable<ServerModuleConfig>>;
 
+    /// Hands the module's keys over to a new set of `peers`, keeping the public keys clients
+    /// know unchanged wherever possible, see [`DkgRunner::run_reshare`](crate::config::DkgRunner::run_reshare)
+    async fn distributed_reshare(
+        &self,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        our_id: &PeerId,
+        peers: &[PeerId],
+        reshare: &ModuleReshareParams,
+        params: &ConfigGenParams,
+        task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>>;
+
     fn to_client_config(&self, config: ServerModuleConfig) -> anyhow::Result<ClientModuleConfig>;
 
     // TODO: There's a bit of confusion here between whole config as one value, `ServerModuleConfig`, and just consensus part
@@ -239,6 +369,19 @@ pub trait ServerModulePlugin: Debug + Sized {
 
     fn decoder(&self) -> &'static Self::Decoder;
 
+    /// Version of the database schema the module's code expects
+    fn database_version(&self) -> DatabaseVersion;
+
+    /// Migrations bringing an older module database up to [`Self::database_version`], keyed by
+    /// the version each of them upgrades from
+    fn database_migrations(&self) -> MigrationMap;
+
+    /// Database key prefixes holding the module's consensus state, which is the same on all
+    /// guardians that processed the same epochs. Snapshots of these prefixes let a new guardian
+    /// start without replaying the whole epoch history, so data a guardian only keeps for itself
+    /// (e.g. its own signature shares) must not be included.
+    fn consensus_state_prefixes(&self) -> Vec<u8>;
+
     /// Blocks until a new `consensus_proposal` is available.
     async fn await_consensus_proposal<'a>(&'a self, dbtx: &mut DatabaseTransaction<'_>);
 
@@ -294,6 +437,23 @@ pub trait ServerModulePlugin: Debug + Sized {
         verification_cache: &Self::VerificationCache,
     ) -> Result<InputMeta, ModuleError>;
 
+    /// Like [`Self::apply_input`] for an input that [`Self::validate_input`] returned `meta` for at
+    /// the start of the epoch. Earlier inputs of the epoch may have changed the state the input
+    /// depends on, so only checks that depend on the input alone may be skipped.
+    ///
+    /// The default implementation validates the input again.
+    async fn apply_validated_input<'a, 'b, 'c>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b Self::Input,
+        verification_cache: &Self::VerificationCache,
+        _meta: InputMeta,
+    ) -> Result<InputMeta, ModuleError> {
+        self.apply_input(interconnect, dbtx, input, verification_cache)
+            .await
+    }
+
     /// Validate a transaction output before submitting it to the unconfirmed transaction pool. This
     /// function has no side effects and may be called at any time. False positives due to outdated
     /// database state are ok since they get filtered out after consensus has been reached on them
@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use crate::core::Decoder;
+use crate::encoding::{DecodeContext, DecodeError, DecodeLimits, DepthGuard};
 use crate::server::ServerModule;
 
 // TODO: unify and/or make a newtype?
@@ -54,13 +56,33 @@ impl ServerModuleRegistry {
 }
 
 /// Collection of decoders belonging to modules, typically obtained from a `ModuleRegistry`
+///
+/// Since every decoder receives the registry it also carries the [`DecodeContext`] that limits
+/// the resources decoding untrusted data may use, see [`ModuleDecoderRegistry::with_limits`].
 #[derive(Debug, Default, Clone)]
-pub struct ModuleDecoderRegistry(BTreeMap<ModuleKey, Decoder>);
+pub struct ModuleDecoderRegistry {
+    decoders: BTreeMap<ModuleKey, Decoder>,
+    context: Option<Arc<DecodeContext>>,
+}
 
 impl ModuleDecoderRegistry {
     /// Create a `ModuleDecoderRegistry` from decoders
     pub fn new(decoders: impl IntoIterator<Item = (ModuleKey, Decoder)>) -> ModuleDecoderRegistry {
-        ModuleDecoderRegistry(decoders.into_iter().collect())
+        ModuleDecoderRegistry {
+            decoders: decoders.into_iter().collect(),
+            context: None,
+        }
+    }
+
+    /// Return a copy of this registry that limits the resources decoding may use to `limits`
+    ///
+    /// The budget is shared by everything decoded with the returned registry (and its clones), so
+    /// a fresh one should be created for every untrusted item.
+    pub fn with_limits(&self, limits: DecodeLimits) -> ModuleDecoderRegistry {
+        ModuleDecoderRegistry {
+            decoders: self.decoders.clone(),
+            context: Some(Arc::new(DecodeContext::new(limits))),
+        }
     }
 
     /// Return the decoder belonging to the module identified by the supplied `module_key`
@@ -68,6 +90,37 @@ impl ModuleDecoderRegistry {
     /// # Panics
     /// If the decoder isn't in the registry
     pub fn decoder(&self, module_key: ModuleKey) -> &Decoder {
-        self.0.get(&module_key).expect("Module not found")
+        self.decoders.get(&module_key).expect("Module not found")
+    }
+
+    /// Return the decoder belonging to the module identified by the supplied `module_key`, if
+    /// there is one
+    pub fn get(&self, module_key: ModuleKey) -> Option<&Decoder> {
+        self.decoders.get(&module_key)
+    }
+
+    /// Account for `count` items of `item_size` bytes a decoder is about to allocate, see
+    /// [`DecodeContext::allocate`]
+    pub fn allocate(&self, count: u64, item_size: usize) -> Result<(), DecodeError> {
+        match &self.context {
+            Some(context) => context.allocate(count, item_size),
+            None => Ok(()),
+        }
+    }
+
+    /// Bytes left of the decoding budget, `None` if decoding is unlimited, see
+    /// [`DecodeContext::remaining_bytes`]
+    pub fn remaining_bytes(&self) -> Option<usize> {
+        self.context
+            .as_ref()
+            .map(|context| context.remaining_bytes())
+    }
+
+    /// Enter a nested item, see [`DecodeContext::enter`]
+    pub fn enter(&self) -> Result<DepthGuard<'_>, DecodeError> {
+        match &self.context {
+            Some(context) => context.enter(),
+            None => Ok(DepthGuard::unlimited()),
+        }
     }
 }
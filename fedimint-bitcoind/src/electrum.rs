@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, format_err};
+use electrum_client::{ElectrumApi, Error as ElectrumError};
+use fedimint_api::config::BitcoindRpcCfg;
+use tracing::warn;
+
+use super::*;
+
+/// Error message electrum servers forward from bitcoind if a transaction was already confirmed,
+/// see `RPC_VERIFY_ALREADY_IN_CHAIN` in the bitcoind backend
+const ALREADY_IN_CHAIN_MESSAGE: &str = "already in block chain";
+
+/// Networks whose blocks are small enough to be fetched one transaction at a time, see
+/// [`ElectrumClient`]
+const SUPPORTED_NETWORKS: [Network; 2] = [Network::Regtest, Network::Signet];
+
+pub fn make_electrum_rpc(cfg: &BitcoindRpcCfg, task_handle: TaskHandle) -> Result<BitcoindRpc> {
+    let client = ElectrumClient::new(&cfg.btc_rpc_address)?;
+    let retry_client = RetryClient::new(client, task_handle);
+
+    Ok(retry_client.into())
+}
+
+/// [`IBitcoindRpc`] implementation querying an Electrum server
+///
+/// The Electrum protocol has no way to fetch whole blocks, so [`IBitcoindRpc::get_block`] looks
+/// up the block's transactions one by one, which is only viable for small blocks. Connecting to a
+/// server of any network but regtest or signet is refused for that reason. Looking up a block
+/// also requires its height, so it only works for hashes previously returned by
+/// [`IBitcoindRpc::get_block_hash`], which is how the wallet fetches blocks.
+pub struct ElectrumClient {
+    client: electrum_client::Client,
+    block_heights: Mutex<HashMap<BlockHash, u64>>,
+}
+
+impl Debug for ElectrumClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ElectrumClient").finish_non_exhaustive()
+    }
+}
+
+impl ElectrumClient {
+    /// Connects to the Electrum server at `url`
+    ///
+    /// # Errors
+    /// If the server can't be reached or serves a network other than regtest or signet
+    pub fn new(url: &str) -> Result<Self> {
+        let client = Self {
+            client: electrum_client::Client::new(url).map_err(anyhow::Error::from)?,
+            block_heights: Mutex::new(HashMap::new()),
+        };
+
+        let network = client.network()?;
+        if !SUPPORTED_NETWORKS.contains(&network) {
+            return Err(anyhow!(
+                "Electrum can only be used on regtest and signet, the server is on {}",
+                network
+            )
+            .into());
+        }
+        Ok(client)
+    }
+
+    /// Electrum doesn't tell us its network, so we compare its genesis block to the known ones
+    fn network(&self) -> Result<Network> {
+        let genesis_hash = self.call(|client| client.block_header(0))?.block_hash();
+
+        [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .into_iter()
+        .find(|network| {
+            bitcoin::blockdata::constants::genesis_block(*network).block_hash() == genesis_hash
+        })
+        .ok_or_else(|| anyhow!("Unknown genesis block {}", genesis_hash).into())
+    }
+
+    fn call<T>(
+        &self,
+        call_fn: impl FnOnce(&electrum_client::Client) -> electrum_client::Result<T>,
+    ) -> Result<T> {
+        fedimint_api::task::block_in_place(|| {
+            call_fn(&self.client).map_err(|e| {
+                warn!("electrum returned error: {}", e);
+                anyhow::Error::from(e).into()
+            })
+        })
+    }
+}
+
+#[async_trait]
+impl IBitcoindRpc for ElectrumClient {
+    async fn get_network(&self) -> Result<Network> {
+        self.network()
+    }
+
+    async fn get_block_height(&self) -> Result<u64> {
+        Ok(self.call(|client| client.block_headers_subscribe())?.height as u64)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        let hash = self
+            .call(|client| client.block_header(height as usize))?
+            .block_hash();
+        self.block_heights
+            .lock()
+            .expect("poisoned")
+            .insert(hash, height);
+        Ok(hash)
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        let height = *self
+            .block_heights
+            .lock()
+            .expect("poisoned")
+            .get(hash)
+            .ok_or_else(|| anyhow!("Height of block {} unknown", hash))?
+            as usize;
+        let header = self.call(|client| client.block_header(height))?;
+
+        let txids = fedimint_api::task::block_in_place(|| {
+            let mut txids = vec![];
+            loop {
+                // The server returns an error once we are past the last transaction of the block
+                match self.client.txid_from_pos(height, txids.len()) {
+                    Ok(txid) => txids.push(txid),
+                    Err(ElectrumError::Protocol(_)) => return Ok(txids),
+                    Err(e) => return Err(anyhow::Error::from(e)),
+                }
+            }
+        })?;
+        let txdata = self.call(|client| client.batch_transaction_get(&txids))?;
+
+        let block = Block { header, txdata };
+        if block.block_hash() != *hash || !block.check_merkle_root() {
+            return Err(format_err!("Electrum returned inconsistent block {}", hash).into());
+        }
+        Ok(block)
+    }
+
+    async fn get_fee_rate(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        let btc_per_kvb = self.call(|client| client.estimate_fee(confirmation_target as usize))?;
+
+        // Electrum servers return -1 if bitcoind has no estimate yet
+        if btc_per_kvb <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(Feerate {
+            sats_per_kvb: bitcoin::Amount::from_btc(btc_per_kvb)
+                .map_err(anyhow::Error::from)?
+                .to_sat(),
+        }))
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
+        fedimint_api::task::block_in_place(|| {
+            match self.client.transaction_broadcast(&transaction) {
+                // for our purposes, this is not an error
+                Err(ElectrumError::Protocol(e))
+                    if e.to_string().contains(ALREADY_IN_CHAIN_MESSAGE) =>
+                {
+                    Ok(())
+                }
+                Err(e) => Err(anyhow::Error::from(e).into()),
+                Ok(_) => Ok(()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::encode::{deserialize, serialize_hex};
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::{Block, Network, Transaction, Txid};
+    use fedimint_api::Feerate;
+    use serde_json::{json, Value};
+
+    use super::ElectrumClient;
+    use crate::IBitcoindRpc;
+
+    /// Stand-in for an electrum server that serves a fixed chain over the line based JSON-RPC
+    /// protocol
+    struct MockElectrum {
+        blocks: Vec<Block>,
+        submitted: Mutex<Vec<Transaction>>,
+    }
+
+    impl MockElectrum {
+        fn new(blocks: Vec<Block>) -> Arc<Self> {
+            Arc::new(MockElectrum {
+                blocks,
+                submitted: Mutex::new(vec![]),
+            })
+        }
+
+        fn serve(self: &Arc<Self>) -> crate::Result<ElectrumClient> {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+
+            let mock = self.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mock = mock.clone();
+                    std::thread::spawn(move || mock.handle(stream.unwrap()));
+                }
+            });
+            ElectrumClient::new(&format!("tcp://{}", address))
+        }
+
+        fn handle(&self, stream: TcpStream) {
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                let request: Value = serde_json::from_str(&line).unwrap();
+                let method = request["method"].as_str().unwrap();
+                let response = match self.call(method, &request["params"]) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                    Err(message) => json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "error": {"code": 1, "message": message}
+                    }),
+                };
+                if writeln!(writer, "{}", response).is_err() {
+                    return;
+                }
+            }
+        }
+
+        fn call(&self, method: &str, params: &Value) -> Result<Value, String> {
+            let block = |idx: usize| {
+                let height = params[idx].as_u64().unwrap() as usize;
+                self.blocks.get(height).ok_or("unknown block height")
+            };
+
+            match method {
+                "blockchain.block.header" => Ok(json!(serialize_hex(&block(0)?.header))),
+                "blockchain.headers.subscribe" => Ok(json!({
+                    "height": self.blocks.len() - 1,
+                    "hex": serialize_hex(&self.blocks.last().unwrap().header),
+                })),
+                "blockchain.transaction.id_from_pos" => {
+                    let pos = params[1].as_u64().unwrap() as usize;
+                    let tx = block(0)?
+                        .txdata
+                        .get(pos)
+                        .ok_or("no transaction at position")?;
+                    Ok(json!(tx.txid().to_string()))
+                }
+                "blockchain.transaction.get" => {
+                    let txid = Txid::from_str(params[0].as_str().unwrap()).unwrap();
+                    self.blocks
+                        .iter()
+                        .flat_map(|block| block.txdata.iter())
+                        .find(|tx| tx.txid() == txid)
+                        .map(|tx| json!(serialize_hex(tx)))
+                        .ok_or_else(|| "unknown transaction".to_string())
+                }
+                // 1 sat/vB for any target above 1 block, no estimate otherwise
+                "blockchain.estimatefee" => match params[0].as_u64().unwrap() {
+                    0 | 1 => Ok(json!(-1)),
+                    _ => Ok(json!(0.00001)),
+                },
+                "blockchain.transaction.broadcast" => {
+                    let bytes = Vec::<u8>::from_hex(params[0].as_str().unwrap()).unwrap();
+                    let tx: Transaction = deserialize(&bytes).map_err(|e| e.to_string())?;
+                    let mut submitted = self.submitted.lock().unwrap();
+                    if submitted.contains(&tx) {
+                        return Err("Transaction already in block chain".to_string());
+                    }
+                    submitted.push(tx.clone());
+                    Ok(json!(tx.txid().to_string()))
+                }
+                _ => Err(format!("unknown method {}", method)),
+            }
+        }
+    }
+
+    fn regtest_chain() -> Vec<Block> {
+        let genesis = genesis_block(Network::Regtest);
+        let mut next = genesis.clone();
+        next.header.prev_blockhash = genesis.block_hash();
+        next.header.nonce += 1;
+        vec![genesis, next]
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn electrum_serves_canned_chain() {
+        let mock = MockElectrum::new(regtest_chain());
+        let client = mock.serve().unwrap();
+
+        assert_eq!(client.get_network().await.unwrap(), Network::Regtest);
+        assert_eq!(client.get_block_height().await.unwrap(), 1);
+
+        // Blocks can only be fetched once their height is known
+        let next = &mock.blocks[1];
+        assert!(client.get_block(&next.block_hash()).await.is_err());
+
+        for (height, block) in mock.blocks.iter().enumerate() {
+            let hash = client.get_block_hash(height as u64).await.unwrap();
+            assert_eq!(hash, block.block_hash());
+            assert_eq!(&client.get_block(&hash).await.unwrap(), block);
+        }
+        assert!(client.get_block_hash(2).await.is_err());
+
+        assert_eq!(
+            client.get_fee_rate(6).await.unwrap(),
+            Some(Feerate { sats_per_kvb: 1000 })
+        );
+        assert_eq!(client.get_fee_rate(1).await.unwrap(), None);
+
+        // Resubmitting an already known transaction is not an error
+        let tx = mock.blocks[0].txdata[0].clone();
+        client.submit_transaction(tx.clone()).await.unwrap();
+        client.submit_transaction(tx.clone()).await.unwrap();
+        assert_eq!(*mock.submitted.lock().unwrap(), vec![tx]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn electrum_is_refused_on_networks_with_large_blocks() {
+        for network in [Network::Bitcoin, Network::Testnet] {
+            let mock = MockElectrum::new(vec![genesis_block(network)]);
+            assert!(mock.serve().is_err());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn electrum_blocks_are_verified() {
+        // The header's merkle root doesn't commit to the additional transaction
+        let mut chain = regtest_chain();
+        let coinbase = chain[0].txdata[0].clone();
+        let mut spend = coinbase.clone();
+        spend.lock_time = bitcoin::PackedLockTime(1);
+        chain[1].txdata.push(spend);
+
+        let mock = MockElectrum::new(chain);
+        let client = mock.serve().unwrap();
+
+        let hash = client.get_block_hash(1).await.unwrap();
+        assert!(client.get_block(&hash).await.is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, format_err};
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::hashes::hex::FromHex;
+use fedimint_api::config::BitcoindRpcCfg;
+use reqwest::{Response, StatusCode};
+use tracing::warn;
+
+use super::*;
+
+/// Error message esplora forwards from bitcoind if a transaction was already confirmed, see
+/// `RPC_VERIFY_ALREADY_IN_CHAIN` in the bitcoind backend
+const ALREADY_IN_CHAIN_MESSAGE: &str = "already in block chain";
+
+pub fn make_esplora_rpc(cfg: &BitcoindRpcCfg, task_handle: TaskHandle) -> Result<BitcoindRpc> {
+    let client = EsploraClient::new(&cfg.btc_rpc_address)?;
+    let retry_client = RetryClient::new(client, task_handle);
+
+    Ok(retry_client.into())
+}
+
+/// [`IBitcoindRpc`] implementation querying an Esplora HTTP API, e.g. the one of
+/// <https://blockstream.info/api>, so that guardians don't need to run a full node
+#[derive(Debug)]
+pub struct EsploraClient {
+    client: reqwest::Client,
+    /// Base URL without trailing slash
+    url: String,
+}
+
+impl EsploraClient {
+    pub fn new(url: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(anyhow::Error::from)?;
+
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    async fn get(&self, path: &str) -> Result<Response> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .send()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        if !response.status().is_success() {
+            warn!(
+                "esplora returned status {} on GET {}",
+                response.status(),
+                path
+            );
+            return Err(format_err!("Esplora returned status {}", response.status()).into());
+        }
+        Ok(response)
+    }
+
+    async fn get_text(&self, path: &str) -> Result<String> {
+        Ok(self
+            .get(path)
+            .await?
+            .text()
+            .await
+            .map_err(anyhow::Error::from)?)
+    }
+}
+
+#[async_trait]
+impl IBitcoindRpc for EsploraClient {
+    /// Esplora doesn't tell us its network, so we compare its genesis block to the known ones
+    async fn get_network(&self) -> Result<Network> {
+        let genesis_hash = self.get_block_hash(0).await?;
+
+        [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .into_iter()
+        .find(|network| {
+            bitcoin::blockdata::constants::genesis_block(*network).block_hash() == genesis_hash
+        })
+        .ok_or_else(|| anyhow!("Unknown genesis block {}", genesis_hash).into())
+    }
+
+    async fn get_block_height(&self) -> Result<u64> {
+        Ok(self
+            .get_text("/blocks/tip/height")
+            .await?
+            .trim()
+            .parse()
+            .map_err(anyhow::Error::from)?)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        let hash = self.get_text(&format!("/block-height/{}", height)).await?;
+        Ok(BlockHash::from_hex(hash.trim()).map_err(anyhow::Error::from)?)
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        let bytes = self
+            .get(&format!("/block/{}/raw", hash))
+            .await?
+            .bytes()
+            .await
+            .map_err(anyhow::Error::from)?;
+        let block: Block = deserialize(&bytes).map_err(anyhow::Error::from)?;
+
+        if block.block_hash() != *hash || !block.check_merkle_root() {
+            return Err(format_err!("Esplora returned inconsistent block {}", hash).into());
+        }
+        Ok(block)
+    }
+
+    /// Esplora returns estimates in sat/vB for a fixed set of confirmation targets, we use the
+    /// highest target not exceeding `confirmation_target` to stay on the conservative side
+    async fn get_fee_rate(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        let estimates: BTreeMap<String, f64> = self
+            .get("/fee-estimates")
+            .await?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let sats_per_vbyte = estimates
+            .into_iter()
+            .filter_map(|(target, rate)| Some((target.parse::<u16>().ok()?, rate)))
+            .filter(|(target, _)| *target <= confirmation_target)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, rate)| rate);
+
+        Ok(sats_per_vbyte.map(|per_vbyte| Feerate {
+            sats_per_kvb: (per_vbyte * 1000.0).ceil() as u64,
+        }))
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
+        let response = self
+            .client
+            .post(format!("{}/tx", self.url))
+            .body(serialize_hex(&transaction))
+            .send()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(anyhow::Error::from)?;
+        match status {
+            status if status.is_success() => Ok(()),
+            // for our purposes, this is not an error
+            StatusCode::BAD_REQUEST if body.contains(ALREADY_IN_CHAIN_MESSAGE) => Ok(()),
+            status => {
+                warn!(
+                    "esplora returned status {} on tx submission: {}",
+                    status, body
+                );
+                Err(format_err!("Esplora rejected transaction: {}", body).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::{Extension, Path};
+    use axum::http::StatusCode;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::encode::{deserialize, serialize};
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::{Block, BlockHash, Network, Transaction};
+    use fedimint_api::Feerate;
+
+    use super::EsploraClient;
+    use crate::IBitcoindRpc;
+
+    /// Stand-in for an esplora server that serves a fixed chain
+    #[derive(Clone)]
+    struct MockEsplora {
+        blocks: Arc<Vec<Block>>,
+        /// Raw blocks served instead of the ones in `blocks`, to simulate a malicious server
+        raw_overrides: Arc<Mutex<HashMap<BlockHash, Block>>>,
+        submitted: Arc<Mutex<Vec<Transaction>>>,
+    }
+
+    impl MockEsplora {
+        fn new() -> Self {
+            let genesis = genesis_block(Network::Regtest);
+            let mut next = genesis.clone();
+            next.header.prev_blockhash = genesis.block_hash();
+            next.header.nonce += 1;
+
+            MockEsplora {
+                blocks: Arc::new(vec![genesis, next]),
+                raw_overrides: Arc::new(Mutex::new(HashMap::new())),
+                submitted: Arc::new(Mutex::new(vec![])),
+            }
+        }
+
+        async fn serve(&self) -> EsploraClient {
+            let port = portpicker::pick_unused_port().expect("Failed to pick port");
+            let address = SocketAddr::from(([127, 0, 0, 1], port));
+
+            let app = Router::new()
+                .route("/blocks/tip/height", get(tip_height))
+                .route("/block-height/:height", get(block_hash))
+                .route("/block/:hash/raw", get(raw_block))
+                .route("/fee-estimates", get(fee_estimates))
+                .route("/tx", post(submit_tx))
+                .layer(Extension(self.clone()));
+
+            tokio::spawn(axum::Server::bind(&address).serve(app.into_make_service()));
+            EsploraClient::new(&format!("http://{}/", address)).unwrap()
+        }
+    }
+
+    async fn tip_height(Extension(mock): Extension<MockEsplora>) -> String {
+        (mock.blocks.len() - 1).to_string()
+    }
+
+    async fn block_hash(
+        Extension(mock): Extension<MockEsplora>,
+        Path(height): Path<usize>,
+    ) -> Result<String, StatusCode> {
+        mock.blocks
+            .get(height)
+            .map(|block| block.block_hash().to_string())
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+
+    async fn raw_block(
+        Extension(mock): Extension<MockEsplora>,
+        Path(hash): Path<String>,
+    ) -> Result<Vec<u8>, StatusCode> {
+        let hash = BlockHash::from_hex(&hash).map_err(|_| StatusCode::BAD_REQUEST)?;
+        if let Some(block) = mock.raw_overrides.lock().unwrap().get(&hash) {
+            return Ok(serialize(block));
+        }
+        mock.blocks
+            .iter()
+            .find(|block| block.block_hash() == hash)
+            .map(serialize)
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+
+    async fn fee_estimates() -> Json<HashMap<&'static str, f64>> {
+        Json(HashMap::from([("1", 20.5), ("6", 10.0), ("144", 1.0)]))
+    }
+
+    async fn submit_tx(
+        Extension(mock): Extension<MockEsplora>,
+        body: String,
+    ) -> Result<String, (StatusCode, &'static str)> {
+        let tx: Transaction = Vec::<u8>::from_hex(&body)
+            .ok()
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or((StatusCode::BAD_REQUEST, "invalid transaction"))?;
+
+        let mut submitted = mock.submitted.lock().unwrap();
+        if submitted.contains(&tx) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "sendrawtransaction RPC error: {\"code\":-27,\"message\":\"Transaction already in block chain\"}",
+            ));
+        }
+        submitted.push(tx.clone());
+        Ok(tx.txid().to_string())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn esplora_serves_canned_chain() {
+        let mock = MockEsplora::new();
+        let client = mock.serve().await;
+
+        assert_eq!(client.get_network().await.unwrap(), Network::Regtest);
+        assert_eq!(client.get_block_height().await.unwrap(), 1);
+
+        for (height, block) in mock.blocks.iter().enumerate() {
+            let hash = client.get_block_hash(height as u64).await.unwrap();
+            assert_eq!(hash, block.block_hash());
+            assert_eq!(&client.get_block(&hash).await.unwrap(), block);
+        }
+        assert!(client.get_block_hash(2).await.is_err());
+
+        assert_eq!(
+            client.get_fee_rate(10).await.unwrap(),
+            Some(Feerate {
+                sats_per_kvb: 10_000
+            })
+        );
+        assert_eq!(
+            client.get_fee_rate(1).await.unwrap(),
+            Some(Feerate {
+                sats_per_kvb: 20_500
+            })
+        );
+        assert_eq!(client.get_fee_rate(0).await.unwrap(), None);
+
+        // Resubmitting an already known transaction is not an error
+        let tx = mock.blocks[0].txdata[0].clone();
+        client.submit_transaction(tx.clone()).await.unwrap();
+        client.submit_transaction(tx.clone()).await.unwrap();
+        assert_eq!(*mock.submitted.lock().unwrap(), vec![tx]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn esplora_blocks_are_verified() {
+        let mock = MockEsplora::new();
+        let client = mock.serve().await;
+        let genesis = mock.blocks[0].clone();
+        let next = mock.blocks[1].clone();
+
+        // A different block than the one requested
+        mock.raw_overrides
+            .lock()
+            .unwrap()
+            .insert(genesis.block_hash(), next.clone());
+        assert!(client.get_block(&genesis.block_hash()).await.is_err());
+
+        // The requested header with transactions that don't match its merkle root
+        let mut tampered = next.clone();
+        tampered.txdata.push(genesis.txdata[0].clone());
+        mock.raw_overrides
+            .lock()
+            .unwrap()
+            .insert(next.block_hash(), tampered);
+        assert!(client.get_block(&next.block_hash()).await.is_err());
+
+        mock.raw_overrides.lock().unwrap().clear();
+        assert_eq!(client.get_block(&next.block_hash()).await.unwrap(), next);
+    }
+}
@@ -5,12 +5,17 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use bitcoin::{Block, BlockHash, Network, Transaction};
+use fedimint_api::config::{BitcoinRpcKind, BitcoindRpcCfg};
 use fedimint_api::{dyn_newtype_define, task::TaskHandle, Feerate};
 use thiserror::Error;
 use tracing::info;
 
 #[cfg(feature = "bitcoincore-rpc")]
 pub mod bitcoincore_rpc;
+#[cfg(feature = "electrum")]
+pub mod electrum;
+#[cfg(feature = "esplora")]
+pub mod esplora;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -62,6 +67,21 @@ dyn_newtype_define! {
     pub BitcoindRpc(Arc<IBitcoindRpc>)
 }
 
+/// Connects to the backend selected by [`BitcoindRpcCfg::kind`], which has to be enabled through
+/// the crate feature of the same name
+pub fn make_bitcoin_rpc(cfg: &BitcoindRpcCfg, task_handle: TaskHandle) -> Result<BitcoindRpc> {
+    match cfg.kind {
+        #[cfg(feature = "bitcoincore-rpc")]
+        BitcoinRpcKind::Bitcoind => bitcoincore_rpc::make_bitcoind_rpc(cfg, task_handle),
+        #[cfg(feature = "esplora")]
+        BitcoinRpcKind::Esplora => esplora::make_esplora_rpc(cfg, task_handle),
+        #[cfg(feature = "electrum")]
+        BitcoinRpcKind::Electrum => electrum::make_electrum_rpc(cfg, task_handle),
+        #[allow(unreachable_patterns)]
+        kind => Err(anyhow::format_err!("Support for {} was not compiled in", kind).into()),
+    }
+}
+
 /// Wrapper around [`IBitcoindRpc`] that will retry failed calls
 #[derive(Debug)]
 pub struct RetryClient<C> {
@@ -0,0 +1,21 @@
+//! Types of the authenticated admin API through which guardians inspect and control their own
+//! `fedimintd`
+
+use serde::{Deserialize, Serialize};
+
+/// Parameters of every admin API request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminRequest<T> {
+    /// Password whose hash is configured as `admin_password_hash` in the guardian's local config
+    pub password: String,
+    pub params: T,
+}
+
+/// State of our connection to another guardian
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerConnectionStatus {
+    Connected,
+    /// Not connected, we will retry after a back-off depending on `failed_reconnects`
+    Disconnected { failed_reconnects: u64 },
+}
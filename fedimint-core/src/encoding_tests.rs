@@ -0,0 +1,322 @@
+//! Property tests of the consensus encoding of the items guardians exchange and agree on
+
+use bitcoin::hashes::sha256;
+use bitcoin_hashes::Hash;
+use fedimint_api::core::{ConsensusItem as ModuleConsensusItem, Input, Output};
+use fedimint_api::encoding::{Decodable, DecodeLimits, Encodable};
+use fedimint_api::{Amount, Feerate, OutPoint, PeerId, TieredMulti, TransactionId};
+use fedimint_ln::contracts::{ContractId, Preimage};
+use fedimint_ln::{LightningInput, LightningOutput};
+use fedimint_mint::{
+    BlindNonce, MintInput, MintOutput, MintOutputConfirmation, Nonce, Note,
+    OutputConfirmationSignatures,
+};
+use fedimint_testing::encoding::{assert_canonical_decoding, assert_roundtrip};
+use fedimint_wallet::{PegOut, PegOutFees, RoundConsensusItem, WalletConsensusItem, WalletOutput};
+use proptest::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use secp256k1_zkp::{schnorr, XOnlyPublicKey};
+use threshold_crypto::{SecretKey, SecretKeySet};
+
+use crate::all_decoders;
+use crate::epoch::{
+    ConsensusItem, EpochOutcome, EpochOutcomeSignature, EpochOutcomeSignatureShare,
+    SignedEpochOutcome, SnapshotSignatureShare,
+};
+use crate::transaction::Transaction;
+
+fn amount() -> impl Strategy<Value = Amount> {
+    any::<u64>().prop_map(Amount::from_msats)
+}
+
+fn hash() -> impl Strategy<Value = sha256::Hash> {
+    any::<[u8; 32]>().prop_map(sha256::Hash::from_inner)
+}
+
+fn transaction_id() -> impl Strategy<Value = TransactionId> {
+    any::<[u8; 32]>().prop_map(TransactionId::from_inner)
+}
+
+fn g1_point() -> impl Strategy<Value = tbs::MessagePoint> {
+    any::<[u8; 32]>().prop_map(|bytes| tbs::Message::from_bytes(&bytes).0)
+}
+
+fn x_only_public_key() -> impl Strategy<Value = XOnlyPublicKey> {
+    any::<[u8; 32]>().prop_filter_map("not a curve point", |bytes| {
+        XOnlyPublicKey::from_slice(&bytes).ok()
+    })
+}
+
+fn schnorr_signature() -> impl Strategy<Value = schnorr::Signature> {
+    any::<([u8; 32], [u8; 32])>().prop_map(|(r, s)| {
+        schnorr::Signature::from_slice(&[r, s].concat()).expect("has the right length")
+    })
+}
+
+fn tiered_multi<T: Strategy>(item: T) -> impl Strategy<Value = TieredMulti<T::Value>> {
+    prop::collection::vec((amount(), item), 0..4)
+        .prop_map(|items| items.into_iter().collect::<TieredMulti<_>>())
+}
+
+fn input() -> impl Strategy<Value = Input> {
+    prop_oneof![
+        (
+            any::<[u8; 32]>(),
+            amount(),
+            prop::option::of(any::<[u8; 32]>())
+        )
+            .prop_map(|(contract_id, amount, witness)| {
+                Input::from(LightningInput {
+                    contract_id: ContractId::from_inner(contract_id),
+                    amount,
+                    witness: witness.map(Preimage),
+                })
+            }),
+        tiered_multi(
+            (x_only_public_key(), g1_point())
+                .prop_map(|(nonce, sig)| Note(Nonce(nonce), tbs::Signature(sig)))
+        )
+        .prop_map(|notes| Input::from(MintInput(notes))),
+    ]
+}
+
+fn output() -> impl Strategy<Value = Output> {
+    prop_oneof![
+        tiered_multi(g1_point().prop_map(|point| BlindNonce(tbs::BlindedMessage(point))))
+            .prop_map(|nonces| Output::from(MintOutput(nonces))),
+        (any::<Vec<u8>>(), any::<u64>(), any::<u64>(), any::<u64>()).prop_map(
+            |(script, amount, sats_per_kvb, total_weight)| {
+                Output::from(WalletOutput(PegOut {
+                    recipient: bitcoin::Address::p2wsh(
+                        &bitcoin::Script::from(script),
+                        bitcoin::Network::Regtest,
+                    ),
+                    amount: bitcoin::Amount::from_sat(amount),
+                    fees: PegOutFees {
+                        fee_rate: Feerate { sats_per_kvb },
+                        total_weight,
+                    },
+                }))
+            }
+        ),
+        (any::<[u8; 32]>(), schnorr_signature()).prop_map(|(contract, gateway_signature)| {
+            Output::from(LightningOutput::CancelOutgoing {
+                contract: ContractId::from_inner(contract),
+                gateway_signature,
+            })
+        }),
+    ]
+}
+
+fn module_consensus_item() -> impl Strategy<Value = ModuleConsensusItem> {
+    prop_oneof![
+        (any::<u32>(), any::<u64>(), any::<[u8; 32]>()).prop_map(
+            |(block_height, sats_per_kvb, randomness)| {
+                ModuleConsensusItem::from(WalletConsensusItem::RoundConsensus(RoundConsensusItem {
+                    block_height,
+                    fee_rate: Feerate { sats_per_kvb },
+                    randomness,
+                }))
+            }
+        ),
+        (
+            transaction_id(),
+            any::<u64>(),
+            tiered_multi((g1_point(), g1_point()))
+        )
+            .prop_map(|(txid, out_idx, signatures)| {
+                let signatures = signatures
+                    .into_iter()
+                    .map(|(amount, (msg, share))| {
+                        (
+                            amount,
+                            (tbs::BlindedMessage(msg), tbs::BlindedSignatureShare(share)),
+                        )
+                    })
+                    .collect();
+                ModuleConsensusItem::from(MintOutputConfirmation {
+                    out_point: OutPoint { txid, out_idx },
+                    signatures: OutputConfirmationSignatures(signatures),
+                })
+            }),
+    ]
+}
+
+fn transaction() -> impl Strategy<Value = Transaction> {
+    (
+        prop::collection::vec(input(), 0..3),
+        prop::collection::vec(output(), 0..3),
+        prop::option::of(schnorr_signature()),
+    )
+        .prop_map(|(inputs, outputs, signature)| Transaction {
+            inputs,
+            outputs,
+            signature,
+        })
+}
+
+fn consensus_item() -> impl Strategy<Value = ConsensusItem> {
+    prop_oneof![
+        (any::<u64>(), any::<[u8; 32]>()).prop_map(|(seed, msg)| {
+            let sk_set = SecretKeySet::random(0, &mut StdRng::seed_from_u64(seed));
+            let share = sk_set.secret_key_share(0).sign(msg);
+            ConsensusItem::EpochOutcomeSignatureShare(EpochOutcomeSignatureShare(share))
+        }),
+        (any::<u64>(), any::<u64>(), any::<[u8; 32]>()).prop_map(|(seed, epoch, msg)| {
+            let sk_set = SecretKeySet::random(0, &mut StdRng::seed_from_u64(seed));
+            let share = sk_set.secret_key_share(0).sign(msg);
+            ConsensusItem::SnapshotSignatureShare(SnapshotSignatureShare {
+                epoch,
+                share: EpochOutcomeSignatureShare(share),
+            })
+        }),
+        transaction().prop_map(ConsensusItem::Transaction),
+        module_consensus_item().prop_map(ConsensusItem::Module),
+    ]
+}
+
+fn signed_epoch_outcome() -> impl Strategy<Value = SignedEpochOutcome> {
+    (
+        any::<u64>(),
+        prop::option::of(hash()),
+        prop::collection::vec(
+            (
+                any::<u16>().prop_map(PeerId::from),
+                prop::collection::vec(consensus_item(), 0..3),
+            ),
+            0..3,
+        ),
+        prop::collection::btree_set(transaction_id(), 0..3),
+        any::<bool>(),
+    )
+        .prop_map(|(epoch, last_hash, items, rejected_txs, signed)| {
+            let outcome = EpochOutcome {
+                epoch,
+                last_hash,
+                items,
+                rejected_txs,
+            };
+            let hash = outcome.hash();
+            let signature = signed.then(|| EpochOutcomeSignature(SecretKey::random().sign(hash)));
+            SignedEpochOutcome {
+                outcome,
+                hash,
+                signature,
+            }
+        })
+}
+
+/// Overwrites the byte at `idx` of the encoding of `value`
+fn mutated_encoding(value: &impl Encodable, idx: prop::sample::Index, byte: u8) -> Vec<u8> {
+    let mut bytes = value.consensus_encode_to_vec().expect("can't fail");
+    let idx = idx.index(bytes.len());
+    bytes[idx] = byte;
+    bytes
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn proptest_roundtrip_module_items(
+        input in input(),
+        output in output(),
+        item in module_consensus_item(),
+    ) {
+        assert_roundtrip(&input, &all_decoders());
+        assert_roundtrip(&output, &all_decoders());
+        assert_roundtrip(&item, &all_decoders());
+    }
+
+    #[test]
+    fn proptest_roundtrip_transaction(tx in transaction()) {
+        assert_roundtrip(&tx, &all_decoders());
+    }
+
+    /// The limits applied to untrusted items must not reject honest ones
+    #[test]
+    fn proptest_roundtrip_with_untrusted_limits(tx in transaction(), item in consensus_item()) {
+        let limited = || all_decoders().with_limits(DecodeLimits::UNTRUSTED);
+        let tx_bytes = tx.consensus_encode_to_vec().expect("can't fail");
+        let item_bytes = item.consensus_encode_to_vec().expect("can't fail");
+
+        prop_assert_eq!(Transaction::consensus_decode(&mut &tx_bytes[..], &limited()).unwrap(), tx);
+        prop_assert_eq!(
+            ConsensusItem::consensus_decode(&mut &item_bytes[..], &limited()).unwrap(),
+            item
+        );
+    }
+
+    #[test]
+    fn proptest_roundtrip_consensus_item(item in consensus_item()) {
+        assert_roundtrip(&item, &all_decoders());
+    }
+
+    #[test]
+    fn proptest_roundtrip_signed_epoch_outcome(outcome in signed_epoch_outcome()) {
+        assert_roundtrip(&outcome, &all_decoders());
+    }
+
+    #[test]
+    fn proptest_canonical_decoding(bytes in any::<Vec<u8>>()) {
+        assert_canonical_decoding::<Input>(&bytes, &all_decoders());
+        assert_canonical_decoding::<Output>(&bytes, &all_decoders());
+        assert_canonical_decoding::<ModuleConsensusItem>(&bytes, &all_decoders());
+        assert_canonical_decoding::<Transaction>(&bytes, &all_decoders());
+        assert_canonical_decoding::<ConsensusItem>(&bytes, &all_decoders());
+        assert_canonical_decoding::<SignedEpochOutcome>(&bytes, &all_decoders());
+    }
+
+    /// Random bytes rarely get past the first length prefix or enum variant, mutating valid
+    /// encodings reaches the decoders of nested items
+    #[test]
+    fn proptest_canonical_decoding_of_mutated_encodings(
+        tx in transaction(),
+        outcome in signed_epoch_outcome(),
+        idx in any::<prop::sample::Index>(),
+        byte in any::<u8>(),
+    ) {
+        assert_canonical_decoding::<Transaction>(
+            &mutated_encoding(&tx, idx.clone(), byte),
+            &all_decoders(),
+        );
+        assert_canonical_decoding::<SignedEpochOutcome>(
+            &mutated_encoding(&outcome, idx, byte),
+            &all_decoders(),
+        );
+    }
+
+    #[test]
+    fn proptest_hostile_lengths_fail(tx in transaction(), len in (1u64 << 32)..) {
+        // the encoding starts with the number of inputs
+        let mut bytes = tx.consensus_encode_to_vec().expect("can't fail");
+        bytes[..8].copy_from_slice(&len.to_le_bytes());
+
+        prop_assert!(Transaction::consensus_decode(&mut &bytes[..], &all_decoders()).is_err());
+
+        // the budget rejects the length before anything is allocated for the inputs
+        let limited = all_decoders().with_limits(DecodeLimits::UNTRUSTED);
+        let error = Transaction::consensus_decode(&mut &bytes[..], &limited).unwrap_err();
+        prop_assert_eq!(error.to_string(), "Decoding budget exceeded");
+        prop_assert_eq!(limited.remaining_bytes(), Some(DecodeLimits::UNTRUSTED.max_bytes));
+    }
+}
+
+#[test]
+fn unknown_module_keys_are_rejected() {
+    let mut bytes = u16::MAX.consensus_encode_to_vec().unwrap();
+    bytes.extend([0; 64]);
+
+    assert!(Input::consensus_decode(&mut &bytes[..], &all_decoders()).is_err());
+    assert!(Output::consensus_decode(&mut &bytes[..], &all_decoders()).is_err());
+    assert!(ModuleConsensusItem::consensus_decode(&mut &bytes[..], &all_decoders()).is_err());
+}
+
+#[test]
+fn invalid_signature_shares_are_rejected() {
+    let mut bytes = 0u64.consensus_encode_to_vec().unwrap();
+    bytes.extend([0xff; 96]);
+
+    assert!(ConsensusItem::consensus_decode(&mut &bytes[..], &all_decoders()).is_err());
+}
@@ -18,6 +18,7 @@ pub enum ConsensusItem {
     EpochOutcomeSignatureShare(EpochOutcomeSignatureShare),
     Transaction(Transaction),
     Module(ModuleConsensusItem),
+    SnapshotSignatureShare(SnapshotSignatureShare),
 }
 
 serde_module_encoding_wrapper!(SerdeConsensusItem, ConsensusItem);
@@ -37,6 +38,135 @@ pub struct SignedEpochOutcome {
 
 serde_module_encoding_wrapper!(SerdeEpochHistory, SignedEpochOutcome);
 
+/// Tag making sure snapshot hashes can't collide with hashes signed for other purposes
+const CONSENSUS_SNAPSHOT_TAG: &[u8] = b"fedimint-consensus-snapshot";
+
+/// Share of the signature over the [`ConsensusSnapshot`] taken after `epoch`
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct SnapshotSignatureShare {
+    pub epoch: u64,
+    pub share: EpochOutcomeSignatureShare,
+}
+
+/// Consensus state of all modules after processing `epoch`, lets a guardian start from it instead
+/// of replaying the whole epoch history
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConsensusSnapshot {
+    pub epoch: u64,
+    /// Raw database entries of the consensus state, ordered by key
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ConsensusSnapshot {
+    pub fn hash(&self) -> Sha256 {
+        let mut hasher = ConsensusSnapshotHasher::new(self.epoch);
+        for (key, value) in &self.entries {
+            hasher.add_entry(key, value);
+        }
+        hasher.finalize().1
+    }
+}
+
+/// Hashes the entries of a [`ConsensusSnapshot`] one at a time, so guardians never need to hold
+/// the whole snapshot in memory to take it
+pub struct ConsensusSnapshotHasher {
+    engine: HashEngine,
+    num_entries: u64,
+}
+
+impl ConsensusSnapshotHasher {
+    pub fn new(epoch: u64) -> ConsensusSnapshotHasher {
+        let mut engine = HashEngine::default();
+        CONSENSUS_SNAPSHOT_TAG
+            .consensus_encode(&mut engine)
+            .unwrap();
+        epoch.consensus_encode(&mut engine).unwrap();
+        ConsensusSnapshotHasher {
+            engine,
+            num_entries: 0,
+        }
+    }
+
+    pub fn add_entry(&mut self, key: &[u8], value: &[u8]) {
+        key.consensus_encode(&mut self.engine).unwrap();
+        value.consensus_encode(&mut self.engine).unwrap();
+        self.num_entries += 1;
+    }
+
+    /// Returns the number of entries added and the hash of the snapshot
+    pub fn finalize(mut self) -> (u64, Sha256) {
+        self.num_entries.consensus_encode(&mut self.engine).unwrap();
+        (self.num_entries, Sha256::from_engine(self.engine))
+    }
+}
+
+/// Identifies a [`ConsensusSnapshot`] without its entries, signed by the federation once a
+/// threshold of guardians contributed their [`SnapshotSignatureShare`]s
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ConsensusSnapshotHeader {
+    pub epoch: u64,
+    /// Only covered by the signature through the `hash`, so it can't be trusted before all
+    /// entries were received
+    pub num_entries: u64,
+    pub hash: Sha256,
+    pub signature: Option<EpochOutcomeSignature>,
+}
+
+impl ConsensusSnapshotHeader {
+    /// Combines the valid `shares` for this snapshot into the federation's signature
+    pub fn add_sig<'a>(
+        &mut self,
+        pks: &PublicKeySet,
+        shares: impl Iterator<Item = (PeerId, &'a SnapshotSignatureShare)>,
+    ) -> Result<(), EpochVerifyError> {
+        let mut contributing_peers = HashSet::new();
+        let sigs: BTreeMap<_, _> = shares
+            .filter(|(peer, share)| {
+                share.epoch == self.epoch
+                    && pks
+                        .public_key_share(peer.to_usize())
+                        .verify(&share.share.0, self.hash)
+            })
+            .map(|(peer, share)| {
+                contributing_peers.insert(peer);
+                (peer.to_usize(), &share.share.0)
+            })
+            .collect();
+
+        let signature = pks
+            .combine_signatures(sigs)
+            .map_err(|_| EpochVerifyError::NotEnoughValidSigShares(contributing_peers))?;
+        self.signature = Some(EpochOutcomeSignature(signature));
+        Ok(())
+    }
+
+    /// Checks that `snapshot` is the one signed by the federation
+    pub fn verify(
+        &self,
+        pk: &PublicKey,
+        snapshot: &ConsensusSnapshot,
+    ) -> Result<(), EpochVerifyError> {
+        if self.epoch != snapshot.epoch
+            || self.num_entries != snapshot.entries.len() as u64
+            || self.hash != snapshot.hash()
+        {
+            return Err(EpochVerifyError::InvalidEpochHash);
+        }
+
+        self.verify_sig(pk)
+    }
+
+    /// Checks that the federation signed the `hash`, which doesn't say anything about entries
+    /// claiming to belong to the snapshot yet
+    pub fn verify_sig(&self, pk: &PublicKey) -> Result<(), EpochVerifyError> {
+        match &self.signature {
+            Some(sig) if pk.verify(&sig.0, self.hash) => Ok(()),
+            Some(_) => Err(EpochVerifyError::InvalidSignature),
+            None => Err(EpochVerifyError::MissingSignature),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
 pub struct EpochOutcome {
     pub epoch: u64,
@@ -184,7 +314,9 @@ impl Decodable for EpochOutcomeSignature {
     ) -> Result<Self, DecodeError> {
         let mut bytes = [0u8; 96];
         d.read_exact(&mut bytes).map_err(DecodeError::from_err)?;
-        Ok(EpochOutcomeSignature(Signature::from_bytes(bytes).unwrap()))
+        Signature::from_bytes(bytes)
+            .map(EpochOutcomeSignature)
+            .map_err(|_| DecodeError::from_str("Invalid epoch outcome signature"))
     }
 }
 
@@ -201,9 +333,9 @@ impl Decodable for EpochOutcomeSignatureShare {
     ) -> Result<Self, DecodeError> {
         let mut bytes = [0u8; 96];
         d.read_exact(&mut bytes).map_err(DecodeError::from_err)?;
-        Ok(EpochOutcomeSignatureShare(
-            SignatureShare::from_bytes(bytes).unwrap(),
-        ))
+        SignatureShare::from_bytes(bytes)
+            .map(EpochOutcomeSignatureShare)
+            .map_err(|_| DecodeError::from_str("Invalid epoch outcome signature share"))
     }
 }
 
@@ -217,6 +349,7 @@ mod tests {
     use threshold_crypto::{SecretKey, SecretKeySet};
 
     use crate::epoch::{ConsensusItem, EpochOutcomeSignatureShare, Sha256};
+    use crate::epoch::{ConsensusSnapshot, ConsensusSnapshotHeader, SnapshotSignatureShare};
     use crate::epoch::{EpochOutcome, EpochOutcomeSignature, EpochVerifyError, SignedEpochOutcome};
 
     fn signed_history(
@@ -362,4 +495,80 @@ mod tests {
             Err(EpochVerifyError::InvalidSignature)
         );
     }
+
+    #[test]
+    fn signs_snapshot() {
+        let mut rng = OsRng;
+        let sk_set = SecretKeySet::random(1, &mut rng);
+        let pk_set = sk_set.public_keys();
+
+        let snapshot = ConsensusSnapshot {
+            epoch: 10,
+            entries: vec![(vec![0x10, 1], vec![]), (vec![0x13, 2], vec![3, 4])],
+        };
+        let mut header = ConsensusSnapshotHeader {
+            epoch: snapshot.epoch,
+            num_entries: 2,
+            hash: snapshot.hash(),
+            signature: None,
+        };
+        let shares = (0usize..3)
+            .map(|peer| {
+                let share = SnapshotSignatureShare {
+                    epoch: snapshot.epoch,
+                    share: EpochOutcomeSignatureShare(
+                        sk_set.secret_key_share(peer).sign(header.hash),
+                    ),
+                };
+                (PeerId::from(peer as u16), share)
+            })
+            .collect::<Vec<_>>();
+        let stale_share = SnapshotSignatureShare {
+            epoch: 0,
+            ..shares[1].1.clone()
+        };
+
+        assert_eq!(
+            header.verify(&pk_set.public_key(), &snapshot),
+            Err(EpochVerifyError::MissingSignature)
+        );
+        assert_eq!(
+            header.add_sig(
+                &pk_set,
+                [(shares[0].0, &shares[0].1), (shares[1].0, &stale_share)].into_iter()
+            ),
+            Err(EpochVerifyError::NotEnoughValidSigShares(HashSet::from([
+                PeerId::from(0)
+            ])))
+        );
+
+        header
+            .add_sig(&pk_set, shares.iter().map(|(peer, share)| (*peer, share)))
+            .unwrap();
+        assert_eq!(header.verify(&pk_set.public_key(), &snapshot), Ok(()));
+        assert_eq!(header.verify_sig(&pk_set.public_key()), Ok(()));
+
+        let forged_header = ConsensusSnapshotHeader {
+            num_entries: 3,
+            ..header.clone()
+        };
+        assert_eq!(
+            forged_header.verify(&pk_set.public_key(), &snapshot),
+            Err(EpochVerifyError::InvalidEpochHash)
+        );
+        let other_key = SecretKeySet::random(1, &mut rng).public_keys().public_key();
+        assert_eq!(
+            header.verify_sig(&other_key),
+            Err(EpochVerifyError::InvalidSignature)
+        );
+
+        let tampered = ConsensusSnapshot {
+            entries: vec![(vec![0x10, 1], vec![])],
+            ..snapshot
+        };
+        assert_eq!(
+            header.verify(&pk_set.public_key(), &tampered),
+            Err(EpochVerifyError::InvalidEpochHash)
+        );
+    }
 }
@@ -11,8 +11,11 @@ pub mod modules {
     pub use fedimint_wallet as wallet;
 }
 
+pub mod admin;
 /// Fedimint toplevel config
 pub mod config;
+#[cfg(test)]
+mod encoding_tests;
 pub mod epoch;
 pub mod outcome;
 pub mod transaction;
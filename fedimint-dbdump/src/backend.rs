@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use fedimint_api::db::Database;
+use fedimint_rocksdb::RocksDb;
+use fedimint_sled::SledDb;
+use fedimint_sqlite::SqliteDb;
+
+/// First bytes of every SQLite database file
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// The database implementations a dump can be read from and imported into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    RocksDb,
+    Sled,
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rocksdb" => Ok(Backend::RocksDb),
+            "sled" => Ok(Backend::Sled),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(format!("Unknown database backend '{}'", s)),
+        }
+    }
+}
+
+impl Backend {
+    /// Guesses the backend of the existing database at `path` from the files it consists of
+    pub fn detect(path: &Path) -> Option<Backend> {
+        if path.is_file() {
+            let mut header = [0; SQLITE_HEADER.len()];
+            File::open(path).ok()?.read_exact(&mut header).ok()?;
+            return (&header == SQLITE_HEADER).then_some(Backend::Sqlite);
+        }
+
+        if path.join("CURRENT").is_file() {
+            Some(Backend::RocksDb)
+        } else if path.join("conf").is_file() && path.join("db").is_file() {
+            Some(Backend::Sled)
+        } else {
+            None
+        }
+    }
+
+    /// Opens the database at `path` for reading and writing, creating it if it doesn't exist
+    pub async fn open(self, path: &Path, sled_tree: &str) -> anyhow::Result<Database> {
+        Ok(match self {
+            Backend::RocksDb => RocksDb::open(path)?.into(),
+            Backend::Sled => SledDb::open(path, sled_tree)?.into(),
+            Backend::Sqlite => SqliteDb::open(&format!("sqlite://{}", path.display()))
+                .await?
+                .into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Backend;
+
+    #[test_log::test(tokio::test)]
+    async fn detects_backend_of_existing_databases() {
+        let dir = tempfile::tempdir().unwrap();
+        let backends = [
+            (Backend::RocksDb, "rocksdb"),
+            (Backend::Sled, "sled"),
+            (Backend::Sqlite, "db.sqlite"),
+        ];
+        for (backend, name) in backends {
+            let path = dir.path().join(name);
+            assert_eq!(Backend::detect(&path), None);
+
+            drop(backend.open(&path, "mint").await.unwrap());
+            assert_eq!(Backend::detect(&path), Some(backend));
+        }
+
+        let empty_dir = dir.path().join("empty");
+        fs::create_dir(&empty_dir).unwrap();
+        assert_eq!(Backend::detect(&empty_dir), None);
+
+        let other_file = dir.path().join("notes.txt");
+        fs::write(&other_file, "SQLite is not in the header").unwrap();
+        assert_eq!(Backend::detect(&other_file), None);
+    }
+
+    #[test]
+    fn parses_backend_names() {
+        assert_eq!("RocksDB".parse(), Ok(Backend::RocksDb));
+        assert_eq!("sled".parse(), Ok(Backend::Sled));
+        assert_eq!("sqlite".parse(), Ok(Backend::Sqlite));
+        assert!("postgres".parse::<Backend>().is_err());
+    }
+}
@@ -1,8 +1,14 @@
 use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::exit;
 
+use anyhow::{bail, Context};
+use backend::Backend;
 use docopt::Docopt;
 use erased_serde::Serialize;
-use fedimint_api::db::DatabaseTransaction;
+use fedimint_api::db::{Database, DatabaseTransaction, IterOrder};
 use fedimint_api::encoding::Encodable;
 use fedimint_core::all_decoders;
 use fedimint_ln::db as LightningRange;
@@ -17,6 +23,8 @@ use mint_client::wallet::db as ClientWalletRange;
 use serde::Deserialize;
 use strum::IntoEnumIterator;
 
+mod backend;
+
 macro_rules! filter_prefixes {
     ($table:ident, $self:ident) => {
         if !$self.include_all_prefixes
@@ -50,6 +58,41 @@ impl SerdeWrapper {
     }
 }
 
+/// A single database entry of a JSON lines dump, the range and prefix are informational and
+/// ignored when importing
+#[derive(Debug, serde::Serialize, Deserialize)]
+struct DumpEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(with = "hex::serde")]
+    key: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    value: Vec<u8>,
+}
+
+/// Looks up the range and the name of a key prefix as used by `--range` and `--prefix`
+fn prefix_name(prefix: u8) -> Option<(&'static str, String)> {
+    macro_rules! find_prefix {
+        ($range:literal, $prefixes:ty) => {
+            if let Some(table) = <$prefixes>::iter().find(|table| table.clone() as u8 == prefix) {
+                return Some(($range, table.to_string().to_lowercase()));
+            }
+        };
+    }
+
+    find_prefix!("consensus", ConsensusRange::DbKeyPrefix);
+    find_prefix!("mint", MintRange::DbKeyPrefix);
+    find_prefix!("wallet", WalletRange::DbKeyPrefix);
+    find_prefix!("lightning", LightningRange::DbKeyPrefix);
+    find_prefix!("mintclient", ClientMintRange::DbKeyPrefix);
+    find_prefix!("lightningclient", ClientLightningRange::DbKeyPrefix);
+    find_prefix!("walletclient", ClientWalletRange::DbKeyPrefix);
+    find_prefix!("client", ClientRange::DbKeyPrefix);
+    None
+}
+
 macro_rules! push_db_pair_items_no_serde {
     ($self:ident, $prefix_type:expr, $key_type:ty, $value_type:ty, $map:ident, $key_literal:literal) => {
         let db_items = $self.read_only.find_by_prefix(&$prefix_type).await;
@@ -80,6 +123,7 @@ struct DatabaseDump<'a> {
     read_only: DatabaseTransaction<'a>,
     ranges: Vec<String>,
     prefixes: Vec<String>,
+    include_all_ranges: bool,
     include_all_prefixes: bool,
 }
 
@@ -126,6 +170,46 @@ impl<'a> DatabaseDump<'a> {
         self.print_database();
     }
 
+    /// Writes every entry within the specified ranges and prefixes as a JSON object on its own
+    /// line to `out`, entries with prefixes of no range are only written when dumping everything
+    pub async fn dump_json_lines(&mut self, mut out: impl Write) -> std::io::Result<()> {
+        let entries = self
+            .read_only
+            .raw_find_by_range(&[], None, IterOrder::Ascending)
+            .await
+            .collect::<Result<Vec<_>, _>>()
+            .expect("DB error");
+
+        for (key, value) in entries {
+            let name = key.first().and_then(|prefix| prefix_name(*prefix));
+            let selected = match &name {
+                Some((range, prefix)) => {
+                    self.ranges.iter().any(|r| r.as_str() == *range)
+                        && (self.include_all_prefixes || self.prefixes.contains(prefix))
+                }
+                None => self.include_all_ranges && self.include_all_prefixes,
+            };
+            if !selected {
+                continue;
+            }
+
+            let (range, prefix) = match name {
+                Some((range, prefix)) => (Some(range.to_string()), Some(prefix)),
+                None => (None, None),
+            };
+            let entry = DumpEntry {
+                range,
+                prefix,
+                key,
+                value,
+            };
+            serde_json::to_writer(&mut out, &entry)?;
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
     /// Iterates through each of the prefixes within the consensus range and retrieves
     /// the corresponding data.
     async fn get_consensus_data(&mut self) {
@@ -194,6 +278,33 @@ impl<'a> DatabaseDump<'a> {
                         consensus.insert("LastEpoch".to_string(), Box::new(last_epoch));
                     }
                 }
+                ConsensusRange::DbKeyPrefix::ConsensusSnapshotEntry => {
+                    // the entries are dumped with their own prefixes already
+                    let num_entries = self
+                        .read_only
+                        .find_by_prefix(&ConsensusRange::ConsensusSnapshotEntryKeyPrefix)
+                        .await
+                        .count();
+                    if num_entries > 0 {
+                        consensus.insert(
+                            "Consensus Snapshot".to_string(),
+                            Box::new(format!("{} entries", num_entries)),
+                        );
+                    }
+                }
+                ConsensusRange::DbKeyPrefix::ConsensusSnapshotHeader => {
+                    let header = self
+                        .read_only
+                        .get_value(&ConsensusRange::ConsensusSnapshotHeaderKey)
+                        .await
+                        .unwrap();
+                    if let Some(header) = header {
+                        consensus.insert(
+                            "Consensus Snapshot Header".to_string(),
+                            Box::new(SerdeWrapper::from_encodable(header)),
+                        );
+                    }
+                }
             }
         }
 
@@ -351,6 +462,45 @@ impl<'a> DatabaseDump<'a> {
                         "UTXOs"
                     );
                 }
+                WalletRange::DbKeyPrefix::LegacyUtxo => {
+                    push_db_pair_items!(
+                        self,
+                        WalletRange::LegacyUTXOPrefixKey,
+                        WalletRange::LegacyUTXOKey,
+                        fedimint_wallet::SpendableUTXO,
+                        wallet,
+                        "Legacy UTXOs"
+                    );
+                }
+                WalletRange::DbKeyPrefix::PegInHandover => {
+                    let handover = self
+                        .read_only
+                        .get_value(&WalletRange::PegInHandoverKey)
+                        .await
+                        .unwrap();
+                    if let Some(handover) = handover {
+                        wallet.insert("Peg-In Handover".to_string(), Box::new(handover));
+                    }
+                }
+                WalletRange::DbKeyPrefix::LegacyTransaction => {
+                    push_db_key_items!(
+                        self,
+                        WalletRange::LegacyTransactionPrefixKey,
+                        WalletRange::LegacyTransactionKey,
+                        wallet,
+                        "Legacy Transactions"
+                    );
+                }
+                WalletRange::DbKeyPrefix::ExternalSignerWait => {
+                    push_db_pair_items!(
+                        self,
+                        WalletRange::ExternalSignerWaitPrefixKey,
+                        WalletRange::ExternalSignerWaitKey,
+                        u32,
+                        wallet,
+                        "External Signer Wait"
+                    );
+                }
             }
         }
 
@@ -401,7 +551,7 @@ impl<'a> DatabaseDump<'a> {
                         self,
                         LightningRange::LightningGatewayKeyPrefix,
                         LightningRange::LightningGatewayKey,
-                        fedimint_ln::LightningGateway,
+                        fedimint_ln::LightningGatewayRegistration,
                         lightning,
                         "Lightning Gateways"
                     );
@@ -490,6 +640,26 @@ impl<'a> DatabaseDump<'a> {
                         "Outgoing Payment Claims"
                     );
                 }
+                ClientLightningRange::DbKeyPrefix::LnurlRegistration => {
+                    push_db_pair_items!(
+                        self,
+                        ClientLightningRange::LnurlRegistrationKeyPrefix,
+                        ClientLightningRange::LnurlRegistrationKey,
+                        mint_client::ln::lnurl::LnurlRegistration,
+                        ln_client,
+                        "LNURL Registrations"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::LnurlInvoice => {
+                    push_db_pair_items!(
+                        self,
+                        ClientLightningRange::LnurlInvoiceKeyPrefix,
+                        ClientLightningRange::LnurlInvoiceKey,
+                        mint_client::ln::lnurl::LnurlInvoice,
+                        ln_client,
+                        "LNURL Invoices"
+                    );
+                }
             }
         }
 
@@ -604,6 +774,26 @@ impl<'a> DatabaseDump<'a> {
                         client.insert("Client Secret".to_string(), Box::new(secret));
                     }
                 }
+                ClientRange::DbKeyPrefix::OperationLog => {
+                    push_db_pair_items!(
+                        self,
+                        ClientRange::OperationLogKeyPrefix,
+                        ClientRange::OperationLogKey,
+                        mint_client::operations::OperationLogEntry,
+                        client,
+                        "Operation Log"
+                    );
+                }
+                ClientRange::DbKeyPrefix::OperationLogTx => {
+                    push_db_pair_items!(
+                        self,
+                        ClientRange::OperationLogTxKeyPrefix,
+                        ClientRange::OperationLogTxKey,
+                        ClientRange::OperationLogKey,
+                        client,
+                        "Operation Log Transactions"
+                    );
+                }
             }
         }
 
@@ -614,11 +804,19 @@ impl<'a> DatabaseDump<'a> {
 
 const USAGE: &str = "
 Usage:
-    fedimint-dbdump <path> [--range=<range>] [--prefix=<prefix>]
+    fedimint-dbdump <path> [--backend=<backend>] [--sled-tree=<tree>] [--range=<range>]
+                           [--prefix=<prefix>] [--json-lines]
+    fedimint-dbdump <path> --import=<dump> [--backend=<backend>] [--sled-tree=<tree>]
     
 Options:
-    --range=<range>    A CSV list of the ranges of the database to dump [default: All].
-    --prefix=<prefix>  A CSV list of he prefixes within the range of the database to dump [default: All].
+    --backend=<backend>  The database backend, one of rocksdb, sled or sqlite. Only the backend of
+                         existing databases is detected [default: auto].
+    --sled-tree=<tree>   The tree of a sled database [default: mint].
+    --range=<range>      A CSV list of the ranges of the database to dump [default: All].
+    --prefix=<prefix>    A CSV list of he prefixes within the range of the database to dump [default: All].
+    --json-lines         Print every entry with its raw key and value as JSON on its own line.
+    --import=<dump>      Import a dump printed with --json-lines ('-' for stdin) into the empty
+                         database at <path>.
 
     RANGES=consensus,mint,wallet,lightning,mintclient,lightningclient,walletclient,client
 ";
@@ -637,8 +835,35 @@ const RANGES: [&str; 8] = [
 #[derive(Debug, Deserialize)]
 struct Args {
     arg_path: String,
+    flag_backend: String,
+    flag_sled_tree: String,
     flag_range: String,
     flag_prefix: String,
+    flag_json_lines: bool,
+    flag_import: Option<String>,
+}
+
+/// Inserts the entries of a JSON lines dump into `db`, which has to be empty
+async fn import_json_lines(db: &Database, dump: impl BufRead) -> anyhow::Result<usize> {
+    let mut dbtx = db.begin_transaction(all_decoders()).await;
+    if dbtx.raw_find_by_prefix(&[]).await.next().is_some() {
+        bail!("The database is not empty");
+    }
+
+    let mut imported = 0;
+    for (idx, line) in dump.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: DumpEntry = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid entry on line {}", idx + 1))?;
+        dbtx.raw_insert_bytes(&entry.key, entry.value).await?;
+        imported += 1;
+    }
+
+    dbtx.commit_tx().await?;
+    Ok(imported)
 }
 
 #[tokio::main]
@@ -647,7 +872,54 @@ async fn main() {
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
 
-    let db_path = args.arg_path;
+    let db_path = Path::new(&args.arg_path);
+    let backend = if args.flag_backend == "auto" {
+        match Backend::detect(db_path) {
+            Some(backend) => backend,
+            None => {
+                eprintln!(
+                    "Could not detect the database backend, please pass --backend. Quitting..."
+                );
+                exit(1);
+            }
+        }
+    } else {
+        match args.flag_backend.parse::<Backend>() {
+            Ok(backend) => backend,
+            Err(e) => {
+                eprintln!("{}. Quitting...", e);
+                exit(1);
+            }
+        }
+    };
+
+    if let Some(dump_path) = args.flag_import {
+        let dump: Box<dyn BufRead> = if dump_path == "-" {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            match File::open(&dump_path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(e) => {
+                    eprintln!("Error opening dump {}: {}. Quitting...", dump_path, e);
+                    exit(1);
+                }
+            }
+        };
+
+        let result = match backend.open(db_path, &args.flag_sled_tree).await {
+            Ok(db) => import_json_lines(&db, dump).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(imported) => eprintln!("Imported {} entries into {:?}", imported, backend),
+            Err(e) => {
+                eprintln!("Error importing dump: {:#}. Quitting...", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
     let csv_range = args.flag_range;
     let csv_prefix = args.flag_prefix;
 
@@ -665,22 +937,142 @@ async fn main() {
         .map(|s| s.to_string().to_lowercase())
         .collect::<Vec<String>>();
 
-    let read_only = match RocksDbReadOnly::open_read_only(db_path) {
-        Ok(db) => db,
-        Err(_) => {
-            eprintln!("Error reading RocksDB database. Quitting...");
-            return;
+    // sled and sqlite databases have no read only mode, but we never commit to them
+    let db: Database;
+    let read_only = match backend {
+        Backend::RocksDb => match RocksDbReadOnly::open_read_only(db_path) {
+            Ok(read_only) => DatabaseTransaction::new(read_only, all_decoders()),
+            Err(_) => {
+                eprintln!("Error reading RocksDB database. Quitting...");
+                exit(1);
+            }
+        },
+        Backend::Sled | Backend::Sqlite => {
+            match backend.open(db_path, &args.flag_sled_tree).await {
+                Ok(opened) => {
+                    db = opened;
+                    db.begin_transaction(all_decoders()).await
+                }
+                Err(e) => {
+                    eprintln!("Error reading {:?} database: {}. Quitting...", backend, e);
+                    exit(1);
+                }
+            }
         }
     };
 
     let serialized: BTreeMap<String, Box<dyn Serialize>> = BTreeMap::new();
     let mut dbdump = DatabaseDump {
         serialized,
-        read_only: DatabaseTransaction::new(read_only, all_decoders()),
+        read_only,
         ranges,
         prefixes,
+        include_all_ranges: csv_range == "All",
         include_all_prefixes: csv_prefix == "All",
     };
 
-    dbdump.dump_database().await;
+    if args.flag_json_lines {
+        if let Err(e) = dbdump.dump_json_lines(std::io::stdout().lock()).await {
+            eprintln!("Error writing dump: {}. Quitting...", e);
+            exit(1);
+        }
+    } else {
+        dbdump.dump_database().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_api::db::{Database, IterOrder};
+    use fedimint_core::all_decoders;
+
+    use crate::backend::Backend;
+    use crate::{import_json_lines, DatabaseDump, RANGES};
+
+    /// Entries of several ranges and one with a prefix no range knows about
+    fn entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![
+            (vec![0x02, 1, 2, 3], vec![4, 5]),
+            (vec![0x10, 0xff], vec![]),
+            (vec![0x30, 7], vec![8; 100]),
+            (vec![0xf0, 1], vec![2]),
+        ]
+    }
+
+    async fn all_entries(db: &Database) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut dbtx = db.begin_transaction(all_decoders()).await;
+        let entries = dbtx
+            .raw_find_by_range(&[], None, IterOrder::Ascending)
+            .await
+            .collect::<Result<_, _>>()
+            .unwrap();
+        entries
+    }
+
+    async fn dump_json_lines(db: &Database) -> Vec<u8> {
+        let mut dbdump = DatabaseDump {
+            serialized: BTreeMap::new(),
+            read_only: db.begin_transaction(all_decoders()).await,
+            ranges: RANGES.map(String::from).to_vec(),
+            prefixes: vec![],
+            include_all_ranges: true,
+            include_all_prefixes: true,
+        };
+        let mut dump = vec![];
+        dbdump.dump_json_lines(&mut dump).await.unwrap();
+        dump
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn json_lines_round_trip_across_backends() {
+        let dir = tempfile::tempdir().unwrap();
+        let backends = [Backend::RocksDb, Backend::Sled, Backend::Sqlite];
+
+        for source_backend in backends {
+            let source_path = dir.path().join(format!("{:?}-source", source_backend));
+            let source = source_backend.open(&source_path, "mint").await.unwrap();
+            let mut dbtx = source.begin_transaction(all_decoders()).await;
+            for (key, value) in entries() {
+                dbtx.raw_insert_bytes(&key, value).await.unwrap();
+            }
+            dbtx.commit_tx().await.unwrap();
+
+            let dump = dump_json_lines(&source).await;
+            assert_eq!(
+                String::from_utf8(dump.clone()).unwrap().lines().count(),
+                entries().len()
+            );
+
+            for target_backend in backends {
+                let target_path = dir
+                    .path()
+                    .join(format!("{:?}-from-{:?}", target_backend, source_backend));
+                let target = target_backend.open(&target_path, "mint").await.unwrap();
+
+                let imported = import_json_lines(&target, &dump[..]).await.unwrap();
+                assert_eq!(imported, entries().len());
+                assert_eq!(all_entries(&target).await, entries());
+                assert_eq!(dump_json_lines(&target).await, dump);
+
+                // imports never merge into existing data
+                assert!(import_json_lines(&target, &dump[..]).await.is_err());
+            }
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn import_rejects_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Backend::RocksDb
+            .open(&dir.path().join("db"), "mint")
+            .await
+            .unwrap();
+
+        let dump = "{\"key\":\"0201\",\"value\":\"\"}\n\n{\"key\":\"zz\",\"value\":\"\"}\n";
+        let error = import_json_lines(&db, dump.as_bytes()).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("line 3"));
+        assert!(all_entries(&db).await.is_empty());
+    }
 }
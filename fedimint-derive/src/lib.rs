@@ -226,6 +226,8 @@ pub fn derive_decodable(input: TokenStream) -> TokenStream {
         }
         syn::Data::Enum(DataEnum { variants, .. }) => {
             let match_arms = variants.iter().enumerate().map(|(variant_idx, variant)| {
+                // matched as `u64`, a `usize` would truncate the encoded index on 32 bit targets
+                let variant_idx = variant_idx as u64;
                 let variant_ident = variant.ident.clone();
 
                 if variant.fields.iter().any(|field| field.ident.is_none()) {
@@ -262,7 +264,7 @@ pub fn derive_decodable(input: TokenStream) -> TokenStream {
                 impl ::fedimint_api::encoding::Decodable for #ident {
                     fn consensus_decode<D: std::io::Read>(d: &mut D, modules: &::fedimint_api::module::registry::ModuleDecoderRegistry) -> std::result::Result<Self, ::fedimint_api::encoding::DecodeError>
                     {
-                        let variant = <u64 as ::fedimint_api::encoding::Decodable>::consensus_decode(d, modules)? as usize;
+                        let variant = <u64 as ::fedimint_api::encoding::Decodable>::consensus_decode(d, modules)?;
                         let decoded = match variant {
                             #(#match_arms)*
                             _ => {
@@ -2,7 +2,9 @@ use std::path::Path;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use fedimint_api::db::{DatabaseTransaction, PrefixIter};
+use fedimint_api::db::{
+    prefix_range_end, DatabaseTransaction, IterOrder, PrefixIter, ReadDatabaseTransaction,
+};
 use fedimint_api::db::{IDatabase, IDatabaseTransaction};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
 pub use rocksdb;
@@ -16,6 +18,12 @@ pub struct RocksDbReadOnly(rocksdb::DB);
 
 pub struct RocksDbTransaction<'a>(rocksdb::Transaction<'a, rocksdb::OptimisticTransactionDB>);
 
+/// Read-only transaction on a snapshot of the database, unlike [`RocksDbTransaction`] there is no
+/// transaction tracking what was read or written
+pub struct RocksDbReadTransaction<'a>(
+    rocksdb::SnapshotWithThreadMode<'a, rocksdb::OptimisticTransactionDB>,
+);
+
 impl RocksDb {
     pub fn open(db_path: impl AsRef<Path>) -> Result<RocksDb, rocksdb::Error> {
         let db: rocksdb::OptimisticTransactionDB =
@@ -36,6 +44,35 @@ impl RocksDbReadOnly {
     }
 }
 
+/// Read options and iterator mode to iterate from `start` (inclusive) to `end` (exclusive)
+fn range_iterator(
+    start: &[u8],
+    end: Option<&[u8]>,
+    order: IterOrder,
+) -> (rocksdb::ReadOptions, rocksdb::IteratorMode<'_>) {
+    let mut options = rocksdb::ReadOptions::default();
+    options.set_iterate_lower_bound(start.to_vec());
+    if let Some(end) = end {
+        options.set_iterate_upper_bound(end.to_vec());
+    }
+
+    // with the upper bound set iterating from the end starts at the last key before it
+    let mode = match order {
+        IterOrder::Ascending => rocksdb::IteratorMode::From(start, rocksdb::Direction::Forward),
+        IterOrder::Descending => rocksdb::IteratorMode::End,
+    };
+    (options, mode)
+}
+
+fn range_entries<'a>(
+    iter: impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>> + Send + 'a,
+) -> PrefixIter<'a> {
+    Box::new(iter.map(|res| {
+        res.map(|(key_bytes, value_bytes)| (key_bytes.to_vec(), value_bytes.to_vec()))
+            .map_err(anyhow::Error::from)
+    }))
+}
+
 impl From<rocksdb::OptimisticTransactionDB> for RocksDb {
     fn from(db: OptimisticTransactionDB) -> Self {
         RocksDb(db)
@@ -61,6 +98,16 @@ impl IDatabase for RocksDb {
         tx.set_tx_savepoint().await;
         tx
     }
+
+    async fn begin_read_transaction(
+        &self,
+        decoders: ModuleDecoderRegistry,
+    ) -> ReadDatabaseTransaction {
+        ReadDatabaseTransaction::new(DatabaseTransaction::new(
+            RocksDbReadTransaction(self.0.snapshot()),
+            decoders,
+        ))
+    }
 }
 
 #[async_trait]
@@ -75,6 +122,15 @@ impl<'a> IDatabaseTransaction<'a> for RocksDbTransaction<'a> {
         Ok(self.0.snapshot().get(key)?)
     }
 
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0
+            .snapshot()
+            .multi_get(keys)
+            .into_iter()
+            .map(|value| value.map_err(anyhow::Error::from))
+            .collect()
+    }
+
     async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let val = self.0.get(key).unwrap();
         self.0.delete(key)?;
@@ -101,6 +157,16 @@ impl<'a> IDatabaseTransaction<'a> for RocksDbTransaction<'a> {
         )
     }
 
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        let (options, mode) = range_iterator(start, end, order);
+        range_entries(self.0.snapshot().iterator_opt(mode, options))
+    }
+
     async fn commit_tx(self: Box<Self>) -> Result<()> {
         self.0.commit()?;
         Ok(())
@@ -120,6 +186,57 @@ impl<'a> IDatabaseTransaction<'a> for RocksDbTransaction<'a> {
     }
 }
 
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for RocksDbReadTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, _key: &[u8], _value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot insert into a read only transaction");
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.get(key)?)
+    }
+
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0
+            .multi_get(keys)
+            .into_iter()
+            .map(|value| value.map_err(anyhow::Error::from))
+            .collect()
+    }
+
+    async fn raw_remove_entry(&mut self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot remove from a read only transaction");
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixIter<'_> {
+        let end = prefix_range_end(key_prefix);
+        let (options, mode) = range_iterator(key_prefix, end.as_deref(), IterOrder::Ascending);
+        range_entries(self.0.iterator_opt(mode, options))
+    }
+
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        let (options, mode) = range_iterator(start, end, order);
+        range_entries(self.0.iterator_opt(mode, options))
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        panic!("Cannot commit a read only transaction");
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        panic!("Cannot rollback a read only transaction");
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        panic!("Cannot set a savepoint in a read only transaction");
+    }
+}
+
 #[async_trait]
 impl IDatabaseTransaction<'_> for RocksDbReadOnly {
     async fn raw_insert_bytes(&mut self, _key: &[u8], _value: Vec<u8>) -> Result<Option<Vec<u8>>> {
@@ -130,6 +247,14 @@ impl IDatabaseTransaction<'_> for RocksDbReadOnly {
         Ok(self.0.get(key)?)
     }
 
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0
+            .multi_get(keys)
+            .into_iter()
+            .map(|value| value.map_err(anyhow::Error::from))
+            .collect()
+    }
+
     async fn raw_remove_entry(&mut self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
         panic!("Cannot remove from a read only transaction");
     }
@@ -150,6 +275,16 @@ impl IDatabaseTransaction<'_> for RocksDbReadOnly {
         )
     }
 
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        let (options, mode) = range_iterator(start, end, order);
+        range_entries(self.0.iterator_opt(mode, options))
+    }
+
     async fn commit_tx(self: Box<Self>) -> Result<()> {
         panic!("Cannot commit a read only transaction");
     }
@@ -208,6 +343,12 @@ mod fedimint_rocksdb_tests {
         .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_get_values() {
+        fedimint_api::db::verify_get_values(open_temp_db("fcb-rocksdb-test-get-values").into())
+            .await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_prevent_dirty_reads() {
         fedimint_api::db::verify_prevent_dirty_reads(
@@ -224,6 +365,30 @@ mod fedimint_rocksdb_tests {
         .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_range() {
+        fedimint_api::db::verify_find_by_range(
+            open_temp_db("fcb-rocksdb-test-find-by-range").into(),
+        )
+        .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix_ordered() {
+        fedimint_api::db::verify_find_by_prefix_ordered(
+            open_temp_db("fcb-rocksdb-test-find-by-prefix-ordered").into(),
+        )
+        .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_read_transaction() {
+        fedimint_api::db::verify_read_transaction(
+            open_temp_db("fcb-rocksdb-test-read-transaction").into(),
+        )
+        .await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_commit() {
         fedimint_api::db::verify_commit(open_temp_db("fcb-rocksdb-test-commit").into()).await;
@@ -268,4 +433,12 @@ mod fedimint_rocksdb_tests {
         )
         .await;
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_apply_migrations() {
+        fedimint_api::db::verify_apply_migrations(
+            open_temp_db("fcb-rocksdb-test-apply-migrations").into(),
+        )
+        .await;
+    }
 }
@@ -0,0 +1,166 @@
+//! Benchmarks of processing epochs full of mint reissuances
+//!
+//! Run with `cargo bench -p fedimint-server`, every iteration processes one epoch on a fresh
+//! guardian so the same notes can be spent again.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use fedimint_api::db::mem_impl::MemDatabase;
+use fedimint_api::module::FederationModuleConfigGen;
+use fedimint_api::{sats, Amount, PeerId, TieredMulti};
+use fedimint_core::epoch::ConsensusItem;
+use fedimint_core::transaction::{agg_sign, Transaction};
+use fedimint_mint::{
+    BlindNonce, Mint, MintConfig, MintConfigGenerator, MintInput, MintOutput, Nonce, Note,
+};
+use fedimint_server::config::{ModuleConfigGens, ServerConfig, ServerConfigParams};
+use fedimint_server::consensus::{FedimintConsensus, HbbftConsensusOutcome};
+use hbbft::honey_badger::Batch;
+use rand::rngs::OsRng;
+use rand::Rng;
+use secp256k1_zkp::{KeyPair, SECP256K1};
+
+const NOTE_AMOUNT: Amount = Amount::from_msats(1024);
+
+/// Federation of a single guardian running only the mint
+struct Federation {
+    cfg: ServerConfig,
+    module_config_gens: ModuleConfigGens,
+    mint_cfg: MintConfig,
+}
+
+impl Federation {
+    fn new() -> Federation {
+        let peers = [PeerId::from(0)];
+        let params =
+            ServerConfigParams::gen_local(&peers, sats(100_000), 4000, "bench", "127.0.0.1:18443");
+        let module_config_gens: ModuleConfigGens = BTreeMap::from([(
+            "mint",
+            Arc::new(MintConfigGenerator) as Arc<dyn FederationModuleConfigGen + Send + Sync>,
+        )]);
+        let cfg = ServerConfig::trusted_dealer_gen(
+            "",
+            &peers,
+            &params,
+            module_config_gens.clone(),
+            OsRng,
+        )
+        .remove(&peers[0])
+        .expect("config for our peer");
+        let mint_cfg = cfg.get_module_config_typed("mint").expect("mint config");
+
+        Federation {
+            cfg,
+            module_config_gens,
+            mint_cfg,
+        }
+    }
+
+    /// Guardian with an empty database
+    fn guardian(&self) -> FedimintConsensus {
+        // runs inside the benchmark's runtime, so it can't block on it
+        futures::executor::block_on(FedimintConsensus::new(
+            self.cfg.clone(),
+            MemDatabase::new().into(),
+            self.module_config_gens.clone(),
+            vec![Mint::new(self.mint_cfg.clone()).into()],
+        ))
+        .expect("Failed to migrate the database")
+    }
+
+    /// Signs a note without going through issuance, with a single guardian its key share is the
+    /// whole mint key
+    fn note(&self, keypair: &KeyPair) -> Note {
+        let nonce = Nonce(keypair.x_only_public_key().0);
+        let secret_key = self
+            .mint_cfg
+            .private
+            .tbs_sks
+            .tier(&NOTE_AMOUNT)
+            .expect("note amount is a mint tier");
+        let signature =
+            tbs::sign_blinded_msg(tbs::BlindedMessage(nonce.to_message().0), *secret_key);
+        Note(nonce, tbs::Signature(signature.0))
+    }
+
+    /// Transaction spending `notes` notes and issuing as many new ones
+    fn reissuance(&self, notes: usize) -> Transaction {
+        let keypairs = (0..notes)
+            .map(|_| {
+                KeyPair::from_seckey_slice(SECP256K1, &OsRng.gen::<[u8; 32]>())
+                    .expect("random key is valid")
+            })
+            .collect::<Vec<_>>();
+        let input = MintInput(tiered(
+            keypairs.iter().map(|keypair| self.note(keypair)).collect(),
+        ));
+        let output = MintOutput(tiered(
+            (0..notes)
+                .map(|_| {
+                    let message = tbs::Message::from_bytes(&OsRng.gen::<[u8; 32]>());
+                    BlindNonce(tbs::blind_message(message, tbs::BlindingKey::random()))
+                })
+                .collect(),
+        ));
+
+        let mut transaction = Transaction {
+            inputs: vec![input.into()],
+            outputs: vec![output.into()],
+            signature: None,
+        };
+        transaction.signature = Some(agg_sign(
+            &keypairs,
+            transaction.tx_hash().as_hash(),
+            SECP256K1,
+            OsRng,
+        ));
+        transaction
+    }
+
+    fn epoch(&self, transactions: usize, notes_per_tx: usize) -> HbbftConsensusOutcome {
+        let items = (0..transactions)
+            .map(|_| ConsensusItem::Transaction(self.reissuance(notes_per_tx)))
+            .collect();
+
+        Batch {
+            epoch: 0,
+            contributions: BTreeMap::from([(PeerId::from(0), items)]),
+        }
+    }
+}
+
+fn tiered<T>(items: Vec<T>) -> TieredMulti<T> {
+    TieredMulti::new(BTreeMap::from([(NOTE_AMOUNT, items)]))
+}
+
+fn process_epoch(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let federation = Federation::new();
+
+    let mut group = c.benchmark_group("process_epoch");
+    group.sample_size(10);
+    for (transactions, notes_per_tx) in [(1_000, 1), (4_000, 1), (1_000, 8)] {
+        let epoch = federation.epoch(transactions, notes_per_tx);
+        group.throughput(Throughput::Elements(transactions as u64));
+        group.bench_with_input(
+            BenchmarkId::new(format!("{notes_per_tx}_notes_per_tx"), transactions),
+            &epoch,
+            |b, epoch| {
+                b.to_async(&runtime).iter_batched(
+                    || (federation.guardian(), epoch.clone()),
+                    |(guardian, epoch)| async move {
+                        let outcome = guardian.process_consensus_outcome(epoch, &None).await;
+                        assert!(outcome.outcome.rejected_txs.is_empty());
+                    },
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, process_epoch);
+criterion_main!(benches);
@@ -3,13 +3,15 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use anyhow::{bail, format_err};
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
 use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::config::{
-    BitcoindRpcCfg, ClientConfig, ConfigGenParams, DkgPeerMsg, DkgRunner, Node, ServerModuleConfig,
-    TypedServerModuleConfig,
+    BitcoinRpcKind, BitcoindRpcCfg, ClientConfig, ConfigGenParams, DkgPeerMsg, DkgRunner, Node,
+    PreviousKeys, ServerModuleConfig, TypedServerModuleConfig,
 };
 use fedimint_api::core::{ModuleKey, MODULE_KEY_GLOBAL};
-use fedimint_api::module::FederationModuleConfigGen;
+use fedimint_api::module::{FederationModuleConfigGen, ModuleReshareParams};
 use fedimint_api::net::peers::{IPeerConnections, MuxPeerConnections, PeerConnections};
 use fedimint_api::task::TaskGroup;
 use fedimint_api::{Amount, PeerId};
@@ -25,6 +27,7 @@ use tracing::info;
 use url::Url;
 
 use crate::fedimint_api::NumPeers;
+use crate::net::admin::AdminPasswordHash;
 use crate::net::connect::TlsConfig;
 use crate::net::connect::{parse_host_port, Connector};
 use crate::net::peers::NetworkConfig;
@@ -39,6 +42,15 @@ pub const DEFAULT_API_PORT: u16 = 8174;
 /// The maximum open connections the API can handle
 const DEFAULT_MAX_CLIENT_CONNECTIONS: u32 = 1000;
 
+/// The maximum number of submitted transactions waiting to be proposed to consensus
+const DEFAULT_MAX_PENDING_TRANSACTIONS: u32 = 10_000;
+
+/// The maximum number of API requests per second a single connection may make
+const DEFAULT_MAX_REQUESTS_PER_SECOND: u32 = 100;
+
+/// How many epochs pass between two snapshots of the consensus state in new federations
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 10_000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// All the serializable configuration for the fedimint server
 pub struct ServerConfig {
@@ -81,6 +93,14 @@ pub struct ServerConfigConsensus {
     pub epoch_pk_set: hbbft::crypto::PublicKeySet,
     /// All configuration that needs to be the same for modules
     pub modules: BTreeMap<String, serde_json::Value>,
+    /// Maximum amount that may leave the federation through peg-outs in a single epoch,
+    /// unlimited if not set
+    #[serde(default)]
+    pub max_peg_out_per_epoch: Option<Amount>,
+    /// Number of epochs between two snapshots of the consensus state, which guardians use to
+    /// bootstrap without the full epoch history, no snapshots are taken if not set
+    #[serde(default)]
+    pub snapshot_interval: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -96,10 +116,35 @@ pub struct ServerConfigLocal {
     pub tls_cert: rustls::Certificate,
     /// How many API connections we will accept
     pub max_connections: u32,
+    /// How many submitted transactions may wait to be proposed to consensus
+    #[serde(default = "default_max_pending_transactions")]
+    pub max_pending_transactions: u32,
+    /// How many API requests per second a single connection may make, admin API requests are
+    /// limited separately
+    #[serde(default = "default_max_requests_per_second")]
+    pub max_requests_per_second: u32,
+    /// Salted hash of the password authenticating requests to the admin API (e.g. the output of
+    /// `distributedgen hash-admin-password`), the admin API is disabled if not set
+    #[serde(default)]
+    pub admin_password_hash: Option<AdminPasswordHash>,
+    /// How many epochs before the latest signed snapshot to keep, older epochs are deleted. Clients
+    /// restoring ecash from a backup download the epochs since the backup was made, so this should
+    /// cover the age of the backups users are expected to restore from. All epochs are kept if not
+    /// set.
+    #[serde(default)]
+    pub epoch_history_retention: Option<u64>,
     /// Non-consensus, non-private configuration from modules
     pub modules: BTreeMap<String, serde_json::Value>,
 }
 
+fn default_max_pending_transactions() -> u32 {
+    DEFAULT_MAX_PENDING_TRANSACTIONS
+}
+
+fn default_max_requests_per_second() -> u32 {
+    DEFAULT_MAX_REQUESTS_PER_SECOND
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Peer {
     /// Certs for TLS communication, required for peer authentication
@@ -193,6 +238,10 @@ impl ServerConfig {
             api_bind: params.api_network.bind_addr,
             tls_cert: params.tls.our_certificate.clone(),
             max_connections: DEFAULT_MAX_CLIENT_CONNECTIONS,
+            max_pending_transactions: DEFAULT_MAX_PENDING_TRANSACTIONS,
+            max_requests_per_second: DEFAULT_MAX_REQUESTS_PER_SECOND,
+            admin_password_hash: None,
+            epoch_history_retention: None,
             modules: Default::default(),
         };
         let consensus = ServerConfigConsensus {
@@ -202,6 +251,8 @@ impl ServerConfig {
             hbbft_pk_set,
             epoch_pk_set,
             modules: Default::default(),
+            max_peg_out_per_epoch: None,
+            snapshot_interval: Some(DEFAULT_SNAPSHOT_INTERVAL),
         };
         let mut cfg = Self {
             consensus,
@@ -409,6 +460,170 @@ impl ServerConfig {
 
         Ok(Ok(server))
     }
+
+    /// Hands the keys of the federation described by `old_consensus` over to the `peers`,
+    /// keeping the federation's public keys wherever possible, see
+    /// [`FederationModuleConfigGen::distributed_reshare`]
+    ///
+    /// `dealers` are the guardians continuing from the old federation, they keep their peer ids
+    /// and need to pass their `old_config`. There need to be at least as many as the old
+    /// threshold.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn distributed_reshare(
+        code_version: &str,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        our_id: &PeerId,
+        peers: &[PeerId],
+        dealers: &[PeerId],
+        old_consensus: &ServerConfigConsensus,
+        old_config: Option<&ServerConfig>,
+        params: &ServerConfigParams,
+        module_config_gens: ModuleConfigGens,
+        mut rng: impl RngCore + CryptoRng,
+        task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<Self>> {
+        let old_peers = old_consensus.peers.keys().copied().collect::<Vec<_>>();
+        if let Some(dealer) = dealers
+            .iter()
+            .find(|dealer| !peers.contains(dealer) || !old_peers.contains(dealer))
+        {
+            bail!(
+                "Dealer {} has to be part of the old and new federation",
+                dealer
+            );
+        }
+        if dealers.len() < old_peers.threshold() {
+            bail!(
+                "Resharing requires at least {} continuing guardians",
+                old_peers.threshold()
+            );
+        }
+        let old_config = match old_config {
+            Some(config) if dealers.contains(our_id) => {
+                if config.consensus.epoch_pk_set != old_consensus.epoch_pk_set {
+                    bail!("Our config doesn't belong to the old federation");
+                }
+                Some(config)
+            }
+            _ if dealers.contains(our_id) => bail!("Continuing guardians need their old config"),
+            _ => None,
+        };
+        info!("Peer {} resharing keys...", our_id);
+
+        // make sure everyone reshares the same federation
+        let old_hash = Sha256::hash(&serde_json::to_vec(old_consensus)?);
+        let others = peers
+            .iter()
+            .filter(|peer| *peer != our_id)
+            .copied()
+            .collect::<Vec<_>>();
+        if let Err(Cancelled) = connections
+            .send(
+                &others,
+                MODULE_KEY_GLOBAL,
+                DkgPeerMsg::OldConsensus(old_hash),
+            )
+            .await
+        {
+            return Ok(Err(Cancelled));
+        }
+        for _ in &others {
+            match connections.receive(MODULE_KEY_GLOBAL).await {
+                Ok((_, DkgPeerMsg::OldConsensus(hash))) if hash == old_hash => {}
+                Ok((peer, msg)) => {
+                    bail!("Peer {peer} disagrees on the old federation config: {msg:?}")
+                }
+                Err(Cancelled) => return Ok(Err(Cancelled)),
+            }
+        }
+
+        let previous = HashMap::from([
+            (
+                KeyType::Hbbft,
+                PreviousKeys::from_threshold_crypto(
+                    &old_consensus.hbbft_pk_set,
+                    dealers,
+                    old_config.map(|config| &config.private.hbbft_sks.0),
+                )?,
+            ),
+            (
+                KeyType::Epoch,
+                PreviousKeys::from_threshold_crypto(
+                    &old_consensus.epoch_pk_set,
+                    dealers,
+                    old_config.map(|config| &config.private.epoch_sks.0),
+                )?,
+            ),
+        ]);
+
+        let mut dkg = DkgRunner::new(KeyType::Hbbft, peers.one_honest(), our_id, peers);
+        dkg.add(KeyType::Epoch, peers.threshold());
+        let keys = if let Ok(v) = dkg
+            .run_reshare_g1(MODULE_KEY_GLOBAL, dealers, previous, connections, &mut rng)
+            .await
+        {
+            v
+        } else {
+            return Ok(Err(Cancelled));
+        };
+        let (hbbft_pks, hbbft_sks) = keys[&KeyType::Hbbft].threshold_crypto();
+        let (epoch_pks, epoch_sks) = keys[&KeyType::Epoch].threshold_crypto();
+
+        let mut module_cfgs: BTreeMap<String, ServerModuleConfig> = Default::default();
+
+        for (name, gen) in module_config_gens {
+            let reshare = ModuleReshareParams {
+                dealers: dealers.to_vec(),
+                old_consensus: Self::get_or_error(&old_consensus.modules, name)?,
+                old_config: old_config
+                    .map(|config| config.get_module_config(name))
+                    .transpose()?,
+            };
+            module_cfgs.insert(
+                name.to_string(),
+                if let Ok(cfgs) = gen
+                    .distributed_reshare(
+                        connections,
+                        our_id,
+                        peers,
+                        &reshare,
+                        &params.modules,
+                        task_group,
+                    )
+                    .await?
+                {
+                    cfgs
+                } else {
+                    return Ok(Err(Cancelled));
+                },
+            );
+        }
+
+        let mut server = ServerConfig::from(
+            code_version,
+            params.clone(),
+            *our_id,
+            SerdeSecret(hbbft_sks),
+            hbbft_pks,
+            SerdeSecret(epoch_sks),
+            epoch_pks,
+            module_cfgs,
+        );
+        server.consensus.federation_name = old_consensus.federation_name.clone();
+        server.consensus.max_peg_out_per_epoch = old_consensus.max_peg_out_per_epoch;
+        server.consensus.snapshot_interval = old_consensus.snapshot_interval;
+        if let Some(old_config) = old_config {
+            server.local.max_connections = old_config.local.max_connections;
+            server.local.max_pending_transactions = old_config.local.max_pending_transactions;
+            server.local.max_requests_per_second = old_config.local.max_requests_per_second;
+            server.local.admin_password_hash = old_config.local.admin_password_hash.clone();
+            server.local.epoch_history_retention = old_config.local.epoch_history_retention;
+        }
+
+        info!("Resharing keys has completed successfully!");
+
+        Ok(Ok(server))
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -506,6 +721,7 @@ impl ServerConfigParams {
         peers: &BTreeMap<PeerId, PeerServerParams>,
         federation_name: String,
         bitcoind_rpc: String,
+        bitcoin_rpc_kind: BitcoinRpcKind,
         network: bitcoin::network::constants::Network,
         finality_delay: u32,
     ) -> ServerConfigParams {
@@ -539,6 +755,7 @@ impl ServerConfigParams {
                 .attach(WalletConfigGenParams {
                     network,
                     bitcoin_rpc: BitcoindRpcCfg {
+                        kind: bitcoin_rpc_kind,
                         btc_rpc_address: bitcoind_rpc,
                         btc_rpc_user: "bitcoin".to_string(),
                         btc_rpc_pass: "bitcoin".to_string(),
@@ -623,6 +840,7 @@ impl ServerConfigParams {
                     &peer_params,
                     federation_name.to_string(),
                     bitcoind_rpc.to_string(),
+                    BitcoinRpcKind::Bitcoind,
                     bitcoin::network::constants::Network::Regtest,
                     10,
                 );
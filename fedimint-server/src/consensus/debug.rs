@@ -21,6 +21,9 @@ pub fn epoch_message(consensus: &HbbftConsensusOutcome) -> String {
 fn item_message(item: &ConsensusItem) -> String {
     match item {
         ConsensusItem::EpochOutcomeSignatureShare(_) => "Outcome Signature".to_string(),
+        ConsensusItem::SnapshotSignatureShare(share) => {
+            format!("Snapshot Signature: epoch={}", share.epoch)
+        }
         // TODO: make this nice again
         ConsensusItem::Module(mci) => {
             format!("Module CI: module={} ci={}", mci.module_key(), mci)
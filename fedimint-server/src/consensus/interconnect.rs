@@ -25,12 +25,10 @@ impl<'a> ModuleInterconect for FedimintInterconnect<'a> {
                     .find(|endpoint| endpoint.path == path)
                     .ok_or_else(|| ApiError::not_found(String::from("Method not found")))?;
 
-                return (endpoint.handler)(
-                    module,
-                    self.fedimint.database_transaction().await,
-                    data,
-                )
-                .await;
+                return endpoint
+                    .handler
+                    .call(module, &self.fedimint.db, self.fedimint.decoders(), data)
+                    .await;
             }
         }
         panic!("Module not registered: {}", module_name);
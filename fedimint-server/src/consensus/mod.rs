@@ -2,17 +2,19 @@
 
 pub mod debug;
 mod interconnect;
+mod snapshot;
 
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use fedimint_api::core::ModuleKey;
-use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::core::{ModuleKey, MODULE_KEY_GLOBAL, MODULE_KEY_WALLET};
+use fedimint_api::db::{apply_migrations, Database, DatabaseTransaction, ReadDatabaseTransaction};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::registry::{ModuleDecoderRegistry, ServerModuleRegistry};
-use fedimint_api::module::{ModuleError, TransactionItemAmount};
+use fedimint_api::module::{InputMeta, ModuleError, TransactionItemAmount};
 use fedimint_api::server::{ServerModule, VerificationCache};
 use fedimint_api::{Amount, OutPoint, PeerId, TransactionId};
 use fedimint_core::epoch::*;
@@ -21,16 +23,19 @@ use futures::future::select_all;
 use hbbft::honey_badger::Batch;
 use itertools::Itertools;
 use rand::rngs::OsRng;
+use rayon::prelude::*;
 use thiserror::Error;
-use tokio::sync::Notify;
+use tokio::sync::{broadcast, Notify, OnceCell};
 use tracing::{debug, error, info_span, instrument, trace, warn, Instrument};
 
 use crate::config::{ModuleConfigGens, ServerConfig};
 use crate::consensus::interconnect::FedimintInterconnect;
 use crate::db::{
-    AcceptedTransactionKey, DropPeerKey, DropPeerKeyPrefix, EpochHistoryKey, LastEpochKey,
-    ProposedTransactionKey, ProposedTransactionKeyPrefix, RejectedTransactionKey,
+    get_global_database_migrations, AcceptedTransactionKey, DropPeerKey, DropPeerKeyPrefix,
+    EpochHistoryKey, LastEpochKey, ProposedTransactionKey, ProposedTransactionKeyPrefix,
+    RejectedTransactionKey, DATABASE_VERSION,
 };
+use crate::metrics;
 use crate::rng::RngGenerator;
 use crate::transaction::{Transaction, TransactionError};
 use crate::OsRngGen;
@@ -65,6 +70,10 @@ pub struct ConsensusProposal {
     pub drop_peers: Vec<PeerId>,
 }
 
+/// Number of signed epochs buffered for slow subscribers, lagging subscribers have to catch up
+/// from the database
+const SIGNED_EPOCH_CHANNEL_CAPACITY: usize = 16;
+
 // TODO: we should make other fields private and get rid of this
 #[non_exhaustive]
 pub struct FedimintConsensus {
@@ -81,6 +90,13 @@ pub struct FedimintConsensus {
 
     /// Notifies tasks when there is a new transaction
     pub transaction_notify: Arc<Notify>,
+
+    /// Notifies subscribers about epochs once they got signed by the federation
+    pub signed_epoch_sender: broadcast::Sender<SignedEpochOutcome>,
+
+    /// Number of submitted transactions waiting to be proposed, see
+    /// [`FedimintConsensus::pending_transactions`]
+    pending_transactions: OnceCell<AtomicUsize>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
@@ -92,29 +108,72 @@ pub struct AcceptedTransaction {
 #[derive(Debug)]
 struct VerificationCaches {
     caches: HashMap<ModuleKey, VerificationCache>,
+    /// Transactions whose inputs were valid at the start of the epoch and whose signature was
+    /// verified with the keys of these inputs
+    validated_inputs: HashMap<TransactionId, Vec<InputMeta>>,
 }
 
 struct FundingVerifier {
     input_amount: Amount,
     output_amount: Amount,
     fee_amount: Amount,
+    /// Amount of all outputs leaving the federation through the wallet module
+    peg_out_amount: Amount,
 }
 
 impl FedimintConsensus {
-    pub fn new(cfg: ServerConfig, db: Database, module_config_gens: ModuleConfigGens) -> Self {
-        Self {
+    /// Sets up consensus with `modules` after upgrading the database schema of the consensus and
+    /// every module to the version expected by the code, so no code path can run on an outdated
+    /// database
+    pub async fn new(
+        cfg: ServerConfig,
+        db: Database,
+        module_config_gens: ModuleConfigGens,
+        modules: Vec<ServerModule>,
+    ) -> anyhow::Result<Self> {
+        let mut registry = ServerModuleRegistry::default();
+        for module in modules {
+            registry.register(module);
+        }
+
+        let consensus = Self {
             rng_gen: Box::new(OsRngGen),
             cfg,
             module_config_gens,
-            modules: ServerModuleRegistry::default(),
+            modules: registry,
             db,
             transaction_notify: Arc::new(Notify::new()),
-        }
+            signed_epoch_sender: broadcast::channel(SIGNED_EPOCH_CHANNEL_CAPACITY).0,
+            pending_transactions: OnceCell::new(),
+        };
+        consensus.apply_migrations().await?;
+        Ok(consensus)
     }
 
-    pub fn register_module(&mut self, module: ServerModule) -> &mut Self {
-        self.modules.register(module);
-        self
+    /// Upgrades the consensus and every module's database schema to the version expected by the
+    /// code, all migrations are committed atomically.
+    async fn apply_migrations(&self) -> anyhow::Result<()> {
+        let mut dbtx = self.database_transaction().await;
+
+        apply_migrations(
+            &mut dbtx,
+            MODULE_KEY_GLOBAL,
+            DATABASE_VERSION,
+            get_global_database_migrations(),
+        )
+        .await?;
+
+        for module in self.modules.modules() {
+            apply_migrations(
+                &mut dbtx,
+                module.module_key(),
+                module.database_version(),
+                module.database_migrations(),
+            )
+            .await?;
+        }
+
+        dbtx.commit_tx().await
     }
 }
 
@@ -135,6 +194,29 @@ impl FedimintConsensus {
         self.db.begin_transaction(self.decoders()).await
     }
 
+    /// A transaction for lookups that never write, like those answering API requests
+    pub async fn read_database_transaction(&self) -> ReadDatabaseTransaction<'_> {
+        self.db.begin_read_transaction(self.decoders()).await
+    }
+
+    /// Counter of the transactions in the proposal queue, so submissions don't have to scan the
+    /// queue. It is read from the database on first use, afterwards submissions reserve a
+    /// [`ProposalQueueSlot`] before validating their transaction and epochs release the slots of
+    /// the transactions they dequeued once their changes are committed.
+    async fn pending_transactions(&self) -> &AtomicUsize {
+        self.pending_transactions
+            .get_or_init(|| async {
+                let pending = self
+                    .read_database_transaction()
+                    .await
+                    .find_by_prefix(&ProposedTransactionKeyPrefix)
+                    .await
+                    .count();
+                AtomicUsize::new(pending)
+            })
+            .await
+    }
+
     pub async fn submit_transaction(
         &self,
         transaction: Transaction,
@@ -155,16 +237,29 @@ impl FedimintConsensus {
 
         let mut pub_keys = Vec::new();
 
+        // reserve the slot up front, so concurrent submissions can't overfill the queue
+        let queue_slot = ProposalQueueSlot::reserve(
+            self.pending_transactions().await,
+            self.cfg.local.max_pending_transactions as usize,
+        )
+        .ok_or(TransactionSubmissionError::ProposalQueueFull(
+            self.cfg.local.max_pending_transactions,
+        ))?;
+
         // Create read-only DB tx so that the read state is consistent
         let mut dbtx = self.db.begin_transaction(self.decoders()).await;
 
+        let caches = self.build_verification_caches(std::iter::once(&transaction));
         for input in &transaction.inputs {
-            let module = self.modules.module(input.module_key());
-
-            let cache = module.build_verification_cache(&[input.clone()]);
-            let interconnect = self.build_interconnect();
-            let meta = module
-                .validate_input(&interconnect, &mut dbtx, &cache, input)
+            let meta = self
+                .modules
+                .module(input.module_key())
+                .validate_input(
+                    &self.build_interconnect(),
+                    &mut dbtx,
+                    caches.get_cache(input.module_key()),
+                    input,
+                )
                 .await
                 .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
 
@@ -180,10 +275,13 @@ impl FedimintConsensus {
                 .validate_output(&mut dbtx, output)
                 .await
                 .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
-            funding_verifier.add_output(amount);
+            funding_verifier.add_output(output.module_key(), amount);
         }
 
         funding_verifier.verify_funding()?;
+        // Transactions that could never fit into an epoch are rejected right away, whether they
+        // fit into the current one is only known once consensus orders them
+        self.verify_peg_out_limit(Amount::ZERO, funding_verifier.peg_out_amount)?;
 
         let new = dbtx
             .insert_entry(&ProposedTransactionKey(tx_hash), &transaction)
@@ -193,6 +291,8 @@ impl FedimintConsensus {
 
         if new.is_some() {
             warn!("Added consensus item was already in consensus queue");
+        } else {
+            queue_slot.keep();
         }
 
         self.transaction_notify.notify_one();
@@ -221,6 +321,7 @@ impl FedimintConsensus {
             epoch_outcome_signature_share: _epoch_outcome_signature_share_cis,
             transaction: transaction_cis,
             module: module_cis,
+            snapshot_signature_share: snapshot_signature_share_cis,
         } = consensus_outcome
             .contributions
             .into_iter()
@@ -250,10 +351,18 @@ impl FedimintConsensus {
         // Process transactions
         let mut rejected_txs: BTreeSet<TransactionId> = BTreeSet::new();
         {
+            // initialize the counter before we remove processed transactions from the queue
+            let pending_transactions = self.pending_transactions().await;
+            let mut dequeued_txs = 0;
             let mut dbtx = self.db.begin_transaction(self.decoders()).await;
 
-            let caches = self.build_verification_caches(transaction_cis.iter().map(|(_, tx)| tx));
+            let mut caches =
+                self.build_verification_caches(transaction_cis.iter().map(|(_, tx)| tx));
+            caches.validated_inputs = self
+                .verify_signatures(&mut dbtx, transaction_cis.iter().map(|(_, tx)| tx), &caches)
+                .await;
             let mut processed_txs: HashSet<TransactionId> = HashSet::new();
+            let mut epoch_peg_out_amount = Amount::ZERO;
 
             for (_, transaction) in transaction_cis {
                 let txid: TransactionId = transaction.tx_hash();
@@ -265,17 +374,28 @@ impl FedimintConsensus {
                 let span = info_span!("Processing transaction");
                 async {
                     trace!(?transaction);
-                    dbtx.remove_entry(&ProposedTransactionKey(txid))
+                    if dbtx
+                        .remove_entry(&ProposedTransactionKey(txid))
                         .await
-                        .expect("DB Error");
+                        .expect("DB Error")
+                        .is_some()
+                    {
+                        dequeued_txs += 1;
+                    }
 
                     dbtx.set_tx_savepoint().await;
                     // TODO: use borrowed transaction
                     match self
-                        .process_transaction(&mut dbtx, transaction.clone(), &caches)
+                        .process_transaction(
+                            &mut dbtx,
+                            transaction.clone(),
+                            &caches,
+                            &mut epoch_peg_out_amount,
+                        )
                         .await
                     {
                         Ok(()) => {
+                            metrics::TRANSACTIONS.with_label_values(&["accepted"]).inc();
                             dbtx.insert_entry(
                                 &AcceptedTransactionKey(txid),
                                 &AcceptedTransaction { epoch, transaction },
@@ -284,6 +404,7 @@ impl FedimintConsensus {
                             .expect("DB Error");
                         }
                         Err(error) => {
+                            metrics::TRANSACTIONS.with_label_values(&["rejected"]).inc();
                             rejected_txs.insert(txid);
                             dbtx.rollback_tx_to_savepoint().await;
                             warn!(%error, "Transaction failed");
@@ -300,6 +421,10 @@ impl FedimintConsensus {
                 .await;
             }
             dbtx.commit_tx().await.expect("DB Error");
+            let _ =
+                pending_transactions.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                    Some(pending.saturating_sub(dequeued_txs))
+                });
         }
 
         if let Some(reference_rejected_txs) = reference_rejected_txs.as_ref() {
@@ -322,6 +447,8 @@ impl FedimintConsensus {
             let epoch_history = self
                 .save_epoch_history(outcome, &mut dbtx, &mut drop_peers, rejected_txs)
                 .await;
+            self.add_snapshot_signature(&mut dbtx, &snapshot_signature_share_cis)
+                .await;
 
             for module in self.modules.modules() {
                 let module_drop_peers = module.end_consensus_epoch(&epoch_peers, &mut dbtx).await;
@@ -334,11 +461,27 @@ impl FedimintConsensus {
                     .expect("DB Error");
             }
 
+            if self.is_snapshot_epoch(epoch) {
+                self.take_snapshot(&mut dbtx, epoch).await;
+            }
+
             dbtx.commit_tx().await.expect("DB Error");
 
             epoch_history
         };
 
+        // Saving this epoch completed the signature of the previous one
+        if let Some(prev_epoch) = epoch.checked_sub(1) {
+            if let Some(signed_epoch) = self
+                .epoch_history(prev_epoch)
+                .await
+                .filter(|epoch| epoch.signature.is_some())
+            {
+                // ignore errors: there may be no subscribers
+                let _ = self.signed_epoch_sender.send(signed_epoch);
+            }
+        }
+
         let audit = self.audit().await;
         if audit.sum().milli_sat < 0 {
             panic!(
@@ -351,8 +494,7 @@ impl FedimintConsensus {
     }
 
     pub async fn get_last_epoch(&self) -> Option<u64> {
-        self.db
-            .begin_transaction(self.decoders())
+        self.read_database_transaction()
             .await
             .get_value(&LastEpochKey)
             .await
@@ -361,8 +503,7 @@ impl FedimintConsensus {
     }
 
     pub async fn epoch_history(&self, epoch: u64) -> Option<SignedEpochOutcome> {
-        self.db
-            .begin_transaction(self.decoders())
+        self.read_database_transaction()
             .await
             .get_value(&EpochHistoryKey(epoch))
             .await
@@ -479,36 +620,62 @@ impl FedimintConsensus {
             items.push(item);
         };
 
+        if let Some(share) = self.snapshot_signature_share(&mut dbtx).await {
+            items.push(ConsensusItem::SnapshotSignatureShare(share));
+        }
+
         ConsensusProposal { items, drop_peers }
     }
 
+    /// Applies `transaction` to the database, `epoch_peg_out_amount` accumulates the amount
+    /// pegged out by the transactions accepted so far in this epoch
     async fn process_transaction<'a>(
         &self,
         dbtx: &mut DatabaseTransaction<'a>,
         transaction: Transaction,
         caches: &VerificationCaches,
+        epoch_peg_out_amount: &mut Amount,
     ) -> Result<(), TransactionSubmissionError> {
         let mut funding_verifier = FundingVerifier::default();
 
         let tx_hash = transaction.tx_hash();
 
+        let validated_inputs = caches.validated_inputs.get(&tx_hash);
         let mut pub_keys = Vec::new();
-        for input in transaction.inputs.iter() {
-            let meta = self
-                .modules
-                .module(input.module_key())
-                .apply_input(
-                    &self.build_interconnect(),
-                    dbtx,
-                    input,
-                    caches.get_cache(input.module_key()),
-                )
-                .await
-                .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
-            pub_keys.push(meta.puk_keys);
+        for (idx, input) in transaction.inputs.iter().enumerate() {
+            let module = self.modules.module(input.module_key());
+            let cache = caches.get_cache(input.module_key());
+            let meta = match validated_inputs {
+                Some(validated_inputs) => {
+                    module
+                        .apply_validated_input(
+                            &self.build_interconnect(),
+                            dbtx,
+                            input,
+                            cache,
+                            validated_inputs[idx].clone(),
+                        )
+                        .await
+                }
+                None => {
+                    module
+                        .apply_input(&self.build_interconnect(), dbtx, input, cache)
+                        .await
+                }
+            }
+            .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
+            pub_keys.extend(meta.puk_keys);
             funding_verifier.add_input(meta.amount);
         }
-        transaction.validate_signature(pub_keys.into_iter().flatten())?;
+        let signed_keys = validated_inputs.map(|validated_inputs| {
+            validated_inputs
+                .iter()
+                .flat_map(|meta| meta.puk_keys.iter().copied())
+                .collect::<Vec<_>>()
+        });
+        if signed_keys.as_ref() != Some(&pub_keys) {
+            transaction.validate_signature(pub_keys.into_iter())?;
+        }
 
         for (idx, output) in transaction.outputs.into_iter().enumerate() {
             let out_point = OutPoint {
@@ -521,14 +688,34 @@ impl FedimintConsensus {
                 .apply_output(dbtx, &output, out_point)
                 .await
                 .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
-            funding_verifier.add_output(amount);
+            funding_verifier.add_output(output.module_key(), amount);
         }
 
         funding_verifier.verify_funding()?;
+        self.verify_peg_out_limit(*epoch_peg_out_amount, funding_verifier.peg_out_amount)?;
+        *epoch_peg_out_amount += funding_verifier.peg_out_amount;
 
         Ok(())
     }
 
+    /// Checks that pegging out `peg_out_amount` on top of `epoch_peg_out_amount` stays within the
+    /// federation's per epoch peg-out limit
+    fn verify_peg_out_limit(
+        &self,
+        epoch_peg_out_amount: Amount,
+        peg_out_amount: Amount,
+    ) -> Result<(), TransactionSubmissionError> {
+        match self.cfg.consensus.max_peg_out_per_epoch {
+            Some(limit) if epoch_peg_out_amount + peg_out_amount > limit => {
+                Err(TransactionSubmissionError::PegOutLimitExceeded {
+                    amount: peg_out_amount,
+                    limit,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn transaction_status(
         &self,
         txid: TransactionId,
@@ -563,8 +750,7 @@ impl FedimintConsensus {
         }
 
         let rejected: Option<String> = self
-            .db
-            .begin_transaction(self.decoders())
+            .read_database_transaction()
             .await
             .get_value(&RejectedTransactionKey(txid))
             .await
@@ -586,16 +772,75 @@ impl FedimintConsensus {
             .cloned()
             .into_group_map_by(|input| input.module_key());
 
-        // TODO: should probably run in parallel, but currently only the mint does anything at all
+        let modules = &self.modules;
         let caches = module_inputs
-            .into_iter()
+            .into_par_iter()
             .map(|(module_key, inputs)| {
-                let module = self.modules.module(module_key);
+                let module = modules.module(module_key);
                 (module_key, module.build_verification_cache(&inputs))
             })
             .collect();
 
-        VerificationCaches { caches }
+        VerificationCaches {
+            caches,
+            validated_inputs: HashMap::new(),
+        }
+    }
+
+    /// Validates the inputs of `transactions` at the start of the epoch and verifies their
+    /// signatures in parallel, returns the input metadata of the correctly signed ones
+    ///
+    /// Processing a transaction reuses the metadata of its inputs and only has to verify its
+    /// signature again if its keys changed during the epoch or its inputs weren't valid at the
+    /// start of it.
+    async fn verify_signatures<'a>(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        transactions: impl Iterator<Item = &'a Transaction>,
+        caches: &VerificationCaches,
+    ) -> HashMap<TransactionId, Vec<InputMeta>> {
+        let mut seen_txs = HashSet::new();
+        let mut signed_txs = vec![];
+
+        'transactions: for transaction in transactions {
+            let tx_hash = transaction.tx_hash();
+            if !seen_txs.insert(tx_hash) {
+                continue;
+            }
+
+            let mut input_metas = vec![];
+            for input in &transaction.inputs {
+                match self
+                    .modules
+                    .module(input.module_key())
+                    .validate_input(
+                        &self.build_interconnect(),
+                        dbtx,
+                        caches.get_cache(input.module_key()),
+                        input,
+                    )
+                    .await
+                {
+                    Ok(meta) => input_metas.push(meta),
+                    Err(_) => continue 'transactions,
+                }
+            }
+            signed_txs.push((tx_hash, transaction, input_metas));
+        }
+
+        signed_txs
+            .into_par_iter()
+            .filter(|(_, transaction, input_metas)| {
+                transaction
+                    .validate_signature(
+                        input_metas
+                            .iter()
+                            .flat_map(|meta| meta.puk_keys.iter().copied()),
+                    )
+                    .is_ok()
+            })
+            .map(|(tx_hash, _, input_metas)| (tx_hash, input_metas))
+            .collect()
     }
 
     pub async fn audit(&self) -> Audit {
@@ -612,15 +857,60 @@ impl FedimintConsensus {
     }
 }
 
+/// Slot in the proposal queue reserved for a transaction while it gets validated, the slot is
+/// released again when dropped unless the transaction got queued
+struct ProposalQueueSlot<'a> {
+    pending_transactions: &'a AtomicUsize,
+    queued: bool,
+}
+
+impl<'a> ProposalQueueSlot<'a> {
+    /// Reserves a slot if less than `max_pending_transactions` are pending
+    fn reserve(
+        pending_transactions: &'a AtomicUsize,
+        max_pending_transactions: usize,
+    ) -> Option<Self> {
+        pending_transactions
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                (pending < max_pending_transactions).then_some(pending + 1)
+            })
+            .ok()?;
+        Some(ProposalQueueSlot {
+            pending_transactions,
+            queued: false,
+        })
+    }
+
+    /// Keeps the slot for the queued transaction, it is released once an epoch processed it
+    fn keep(mut self) {
+        self.queued = true;
+    }
+}
+
+impl<'a> Drop for ProposalQueueSlot<'a> {
+    fn drop(&mut self) {
+        if !self.queued {
+            let _ = self.pending_transactions.fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |pending| Some(pending.saturating_sub(1)),
+            );
+        }
+    }
+}
+
 impl FundingVerifier {
     fn add_input(&mut self, input_amount: TransactionItemAmount) {
         self.input_amount += input_amount.amount;
         self.fee_amount += input_amount.fee;
     }
 
-    fn add_output(&mut self, output_amount: TransactionItemAmount) {
+    fn add_output(&mut self, module_key: ModuleKey, output_amount: TransactionItemAmount) {
         self.output_amount += output_amount.amount;
         self.fee_amount += output_amount.fee;
+        if module_key == MODULE_KEY_WALLET {
+            self.peg_out_amount += output_amount.amount;
+        }
     }
 
     fn verify_funding(self) -> Result<(), TransactionError> {
@@ -642,6 +932,7 @@ impl Default for FundingVerifier {
             input_amount: Amount::ZERO,
             output_amount: Amount::ZERO,
             fee_amount: Amount::ZERO,
+            peg_out_amount: Amount::ZERO,
         }
     }
 }
@@ -654,4 +945,73 @@ pub enum TransactionSubmissionError {
     ModuleError(TransactionId, ModuleError),
     #[error("Transaction conflict error")]
     TransactionConflictError,
+    #[error("Peg-out of {amount} exceeds the federation's per epoch limit of {limit}")]
+    PegOutLimitExceeded { amount: Amount, limit: Amount },
+    #[error("Too many pending transactions (limit {0}), try again later")]
+    ProposalQueueFull(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use fedimint_api::core::{MODULE_KEY_MINT, MODULE_KEY_WALLET};
+    use fedimint_api::module::TransactionItemAmount;
+    use fedimint_api::Amount;
+
+    use super::{FundingVerifier, ProposalQueueSlot};
+
+    fn verify(input: u64, output: u64, fee: u64) -> bool {
+        let mut verifier = FundingVerifier::default();
+        verifier.add_input(TransactionItemAmount {
+            amount: Amount::from_msats(input),
+            fee: Amount::ZERO,
+        });
+        verifier.add_output(
+            MODULE_KEY_MINT,
+            TransactionItemAmount {
+                amount: Amount::from_msats(output),
+                fee: Amount::from_msats(fee),
+            },
+        );
+        verifier.verify_funding().is_ok()
+    }
+
+    #[test]
+    fn funding_has_to_balance_exactly() {
+        assert!(verify(1000, 999, 1));
+        assert!(!verify(1000, 999, 0));
+        assert!(!verify(1000, 1000, 1));
+    }
+
+    #[test]
+    fn peg_outs_are_tracked() {
+        let mut verifier = FundingVerifier::default();
+        verifier.add_output(
+            MODULE_KEY_WALLET,
+            TransactionItemAmount {
+                amount: Amount::from_msats(5000),
+                fee: Amount::from_msats(10),
+            },
+        );
+        assert_eq!(verifier.peg_out_amount, Amount::from_msats(5000));
+    }
+
+    #[test]
+    fn proposal_queue_slots_are_reserved_until_released() {
+        let pending = AtomicUsize::new(0);
+
+        let queued = ProposalQueueSlot::reserve(&pending, 2).unwrap();
+        let rejected = ProposalQueueSlot::reserve(&pending, 2).unwrap();
+        assert!(ProposalQueueSlot::reserve(&pending, 2).is_none());
+
+        queued.keep();
+        drop(rejected);
+        assert_eq!(pending.load(Ordering::SeqCst), 1);
+
+        // processing the queued transaction in an epoch makes room again
+        pending.fetch_sub(1, Ordering::SeqCst);
+        assert!(ProposalQueueSlot::reserve(&pending, 1).is_some());
+        assert_eq!(pending.load(Ordering::SeqCst), 0);
+    }
 }
@@ -0,0 +1,278 @@
+//! Snapshots of the consensus state
+//!
+//! Every `snapshot_interval` epochs the guardians store a copy of each database entry of the
+//! consensus state and sign their hash with the epoch threshold key by proposing
+//! [`SnapshotSignatureShare`]s. A guardian missing epochs its peers already pruned starts from the
+//! latest signed snapshot and only processes the epochs after it.
+
+use fedimint_api::db::{prefix_range_end, DatabaseTransaction, IterOrder};
+use fedimint_api::PeerId;
+use fedimint_core::epoch::{
+    ConsensusSnapshot, ConsensusSnapshotHasher, ConsensusSnapshotHeader,
+    EpochOutcomeSignatureShare, EpochVerifyError, SignedEpochOutcome, SnapshotSignatureShare,
+};
+use tracing::{info, warn};
+
+use super::FedimintConsensus;
+use crate::db::{
+    ConsensusSnapshotEntryKey, ConsensusSnapshotHeaderKey, DbKeyPrefix, EpochHistoryKey,
+    LastEpochKey,
+};
+
+/// Entries of a snapshot read from the database at once
+const SNAPSHOT_BATCH_ENTRIES: usize = 1024;
+
+impl FedimintConsensus {
+    /// Database key prefixes of the consensus state, besides the modules' state this includes the
+    /// accepted transactions clients query the status of
+    fn consensus_state_prefixes(&self) -> Vec<u8> {
+        let mut prefixes = vec![DbKeyPrefix::AcceptedTransaction as u8];
+        for module in self.modules.modules() {
+            prefixes.extend(module.consensus_state_prefixes());
+        }
+        prefixes.sort_unstable();
+        prefixes
+    }
+
+    /// Whether a snapshot is taken after processing `epoch`
+    pub(super) fn is_snapshot_epoch(&self, epoch: u64) -> bool {
+        match self.cfg.consensus.snapshot_interval {
+            Some(interval) if interval > 0 => epoch > 0 && epoch % interval == 0,
+            _ => false,
+        }
+    }
+
+    /// Stores the consensus state after processing `epoch` entry by entry, replacing the previous
+    /// snapshot
+    ///
+    /// Has to run in the transaction committing the epoch, so the snapshot contains exactly the
+    /// state after it.
+    pub(super) async fn take_snapshot(&self, dbtx: &mut DatabaseTransaction<'_>, epoch: u64) {
+        dbtx.raw_remove_by_prefix(&[DbKeyPrefix::ConsensusSnapshotEntry as u8])
+            .await
+            .expect("DB Error");
+
+        let mut hasher = ConsensusSnapshotHasher::new(epoch);
+        let mut index = 0;
+        for prefix in self.consensus_state_prefixes() {
+            let end = prefix_range_end(&[prefix]);
+            let mut start = vec![prefix];
+            loop {
+                let entries = dbtx
+                    .raw_find_by_range(&start, end.as_deref(), IterOrder::Ascending)
+                    .await
+                    .take(SNAPSHOT_BATCH_ENTRIES)
+                    .collect::<Result<Vec<_>, _>>()
+                    .expect("DB error");
+                let last_batch = entries.len() < SNAPSHOT_BATCH_ENTRIES;
+                if let Some((last_key, _)) = entries.last() {
+                    // the smallest key following the last one
+                    start = last_key.clone();
+                    start.push(0);
+                }
+
+                for entry in entries {
+                    hasher.add_entry(&entry.0, &entry.1);
+                    dbtx.insert_entry(&ConsensusSnapshotEntryKey(index), &entry)
+                        .await
+                        .expect("DB Error");
+                    index += 1;
+                }
+                if last_batch {
+                    break;
+                }
+            }
+        }
+
+        let (num_entries, hash) = hasher.finalize();
+        let header = ConsensusSnapshotHeader {
+            epoch,
+            num_entries,
+            hash,
+            signature: None,
+        };
+        info!(epoch, entries = num_entries, "Took consensus snapshot");
+
+        dbtx.insert_entry(&ConsensusSnapshotHeaderKey, &header)
+            .await
+            .expect("DB Error");
+    }
+
+    /// Our share of the signature over the latest snapshot, as long as the federation didn't sign
+    /// it yet
+    pub(super) async fn snapshot_signature_share(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> Option<SnapshotSignatureShare> {
+        let header = dbtx
+            .get_value(&ConsensusSnapshotHeaderKey)
+            .await
+            .expect("DB error")
+            .filter(|header| header.signature.is_none())?;
+
+        Some(SnapshotSignatureShare {
+            epoch: header.epoch,
+            share: EpochOutcomeSignatureShare(self.cfg.private.epoch_sks.0.sign(header.hash)),
+        })
+    }
+
+    /// Signs the latest snapshot if `shares` contains enough valid signature shares, then prunes
+    /// the epoch history before it if we are configured to
+    pub(super) async fn add_snapshot_signature(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        shares: &[(PeerId, SnapshotSignatureShare)],
+    ) {
+        if shares.is_empty() {
+            return;
+        }
+
+        let mut header = match dbtx
+            .get_value(&ConsensusSnapshotHeaderKey)
+            .await
+            .expect("DB error")
+        {
+            Some(header) if header.signature.is_none() => header,
+            _ => return,
+        };
+
+        let pks = &self.cfg.consensus.epoch_pk_set;
+        if let Err(e) = header.add_sig(pks, shares.iter().map(|(peer, share)| (*peer, share))) {
+            warn!("Unable to sign snapshot of epoch {}: {:?}", header.epoch, e);
+            return;
+        }
+
+        info!(epoch = header.epoch, "Consensus snapshot got signed");
+        dbtx.insert_entry(&ConsensusSnapshotHeaderKey, &header)
+            .await
+            .expect("DB Error");
+
+        if let Some(retention) = self.cfg.local.epoch_history_retention {
+            prune_epoch_history(dbtx, header.epoch.saturating_sub(retention)).await;
+        }
+    }
+
+    /// The header of the latest snapshot, if the federation signed it already
+    pub async fn signed_snapshot_header(&self) -> Option<ConsensusSnapshotHeader> {
+        self.read_database_transaction()
+            .await
+            .get_value(&ConsensusSnapshotHeaderKey)
+            .await
+            .expect("DB error")
+            .filter(|header| header.signature.is_some())
+    }
+
+    /// The entries of the latest snapshot starting at index `start`, as many as fit into
+    /// `max_bytes` but at least one, if the federation signed it already
+    pub async fn signed_snapshot_part(
+        &self,
+        start: u64,
+        max_bytes: usize,
+    ) -> Option<(ConsensusSnapshotHeader, Vec<(Vec<u8>, Vec<u8>)>)> {
+        let mut dbtx = self.read_database_transaction().await;
+        let header = dbtx
+            .get_value(&ConsensusSnapshotHeaderKey)
+            .await
+            .expect("DB error")
+            .filter(|header| header.signature.is_some())?;
+
+        let mut entries = vec![];
+        let mut part_bytes = 0;
+        let mut index = start;
+        while index < header.num_entries {
+            let keys = (index..header.num_entries)
+                .take(SNAPSHOT_BATCH_ENTRIES)
+                .map(ConsensusSnapshotEntryKey)
+                .collect::<Vec<_>>();
+            index += keys.len() as u64;
+
+            for entry in dbtx.get_values(&keys).await.expect("DB error") {
+                let (key, value) = entry.expect("Snapshot entries are stored with their header");
+                part_bytes += key.len() + value.len();
+                if !entries.is_empty() && part_bytes > max_bytes {
+                    return Some((header, entries));
+                }
+                entries.push((key, value));
+            }
+        }
+
+        Some((header, entries))
+    }
+
+    /// Replaces our consensus state with a snapshot signed by the federation
+    ///
+    /// `epoch` has to be the signed outcome of the snapshot's epoch, the next epoch we process
+    /// builds on it.
+    pub async fn restore_snapshot(
+        &self,
+        header: ConsensusSnapshotHeader,
+        snapshot: ConsensusSnapshot,
+        epoch: SignedEpochOutcome,
+    ) -> Result<(), EpochVerifyError> {
+        let pk = self.cfg.consensus.epoch_pk_set.public_key();
+        header.verify(&pk, &snapshot)?;
+        epoch.verify_sig(&pk)?;
+        if epoch.outcome.epoch != snapshot.epoch || epoch.hash != epoch.outcome.hash() {
+            return Err(EpochVerifyError::InvalidEpochHash);
+        }
+
+        let mut dbtx = self.database_transaction().await;
+        for prefix in self.consensus_state_prefixes() {
+            dbtx.raw_remove_by_prefix(&[prefix])
+                .await
+                .expect("DB Error");
+        }
+        for (key, value) in &snapshot.entries {
+            dbtx.raw_insert_bytes(key, value.clone())
+                .await
+                .expect("DB Error");
+        }
+
+        info!(
+            epoch = snapshot.epoch,
+            entries = snapshot.entries.len(),
+            "Restoring consensus snapshot"
+        );
+        dbtx.insert_entry(&EpochHistoryKey(snapshot.epoch), &epoch)
+            .await
+            .expect("DB Error");
+        dbtx.insert_entry(&LastEpochKey, &EpochHistoryKey(snapshot.epoch))
+            .await
+            .expect("DB Error");
+        // keep the snapshot so we can serve it to other guardians as well
+        dbtx.raw_remove_by_prefix(&[DbKeyPrefix::ConsensusSnapshotEntry as u8])
+            .await
+            .expect("DB Error");
+        for (index, entry) in (0..).zip(snapshot.entries) {
+            dbtx.insert_entry(&ConsensusSnapshotEntryKey(index), &entry)
+                .await
+                .expect("DB Error");
+        }
+        dbtx.insert_entry(&ConsensusSnapshotHeaderKey, &header)
+            .await
+            .expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        Ok(())
+    }
+}
+
+/// Deletes the epochs before `epoch`, stopping at the first one that was already pruned
+async fn prune_epoch_history(dbtx: &mut DatabaseTransaction<'_>, epoch: u64) {
+    let mut pruned = 0;
+    for epoch in (0..epoch).rev() {
+        if dbtx
+            .remove_entry(&EpochHistoryKey(epoch))
+            .await
+            .expect("DB Error")
+            .is_none()
+        {
+            break;
+        }
+        pruned += 1;
+    }
+
+    if pruned > 0 {
+        info!(pruned, before = epoch, "Pruned epoch history");
+    }
+}
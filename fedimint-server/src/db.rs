@@ -1,9 +1,9 @@
 use std::fmt::Debug;
 
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion, MigrationMap};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::{PeerId, TransactionId};
-use fedimint_core::epoch::SignedEpochOutcome;
+use fedimint_core::epoch::{ConsensusSnapshotHeader, SignedEpochOutcome};
 use serde::Serialize;
 use strum_macros::EnumIter;
 
@@ -19,6 +19,16 @@ pub enum DbKeyPrefix {
     RejectedTransaction = 0x04,
     EpochHistory = 0x05,
     LastEpoch = 0x06,
+    ConsensusSnapshotEntry = 0x07,
+    ConsensusSnapshotHeader = 0x08,
+}
+
+/// Current version of the consensus database schema, not including the modules
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+/// Migrations of the consensus database schema, keyed by the version they upgrade from
+pub fn get_global_database_migrations() -> MigrationMap {
+    MigrationMap::new()
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -125,3 +135,33 @@ impl DatabaseKeyPrefixConst for LastEpochKey {
     type Key = Self;
     type Value = EpochHistoryKey;
 }
+
+/// Entry of the latest snapshot of the consensus state by its index, each snapshot entry is a
+/// raw key-value pair of the consensus state
+#[derive(Debug, Copy, Clone, Encodable, Decodable, Serialize)]
+pub struct ConsensusSnapshotEntryKey(pub u64);
+
+impl DatabaseKeyPrefixConst for ConsensusSnapshotEntryKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::ConsensusSnapshotEntry as u8;
+    type Key = Self;
+    type Value = (Vec<u8>, Vec<u8>);
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ConsensusSnapshotEntryKeyPrefix;
+
+impl DatabaseKeyPrefixConst for ConsensusSnapshotEntryKeyPrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::ConsensusSnapshotEntry as u8;
+    type Key = ConsensusSnapshotEntryKey;
+    type Value = (Vec<u8>, Vec<u8>);
+}
+
+/// Header of the snapshot whose entries are stored under [`ConsensusSnapshotEntryKey`]
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ConsensusSnapshotHeaderKey;
+
+impl DatabaseKeyPrefixConst for ConsensusSnapshotHeaderKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::ConsensusSnapshotHeader as u8;
+    type Key = Self;
+    type Value = ConsensusSnapshotHeader;
+}
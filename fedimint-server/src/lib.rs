@@ -1,19 +1,21 @@
 extern crate fedimint_api;
 
 use std::cmp::min;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use config::ServerConfig;
 use fedimint_api::cancellable::Cancellable;
-use fedimint_api::encoding::DecodeError;
+use fedimint_api::encoding::{DecodeError, DecodeLimits};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
 use fedimint_api::net::peers::PeerConnections;
 use fedimint_api::task::{TaskGroup, TaskHandle};
 use fedimint_api::{NumPeers, PeerId};
 use fedimint_core::epoch::{
-    ConsensusItem, EpochVerifyError, SerdeConsensusItem, SignedEpochOutcome,
+    ConsensusItem, ConsensusSnapshot, ConsensusSnapshotHeader, EpochVerifyError,
+    SerdeConsensusItem, SerdeEpochHistory, SignedEpochOutcome,
 };
 pub use fedimint_core::*;
 use hbbft::honey_badger::{Batch, HoneyBadger, Message, Step};
@@ -32,7 +34,7 @@ use crate::db::LastEpochKey;
 use crate::fedimint_api::net::peers::IPeerConnections;
 use crate::net::connect::{Connector, TlsTcpConnector};
 use crate::net::peers::PeerSlice;
-use crate::net::peers::{PeerConnector, ReconnectPeerConnections};
+use crate::net::peers::{PeerConnector, PeerStatusMap, ReconnectPeerConnections};
 use crate::rng::RngGenerator;
 
 /// The actual implementation of the federated mint
@@ -50,6 +52,9 @@ pub mod config;
 /// Implementation of multiplexed peer connections
 pub mod multiplexed;
 
+/// Prometheus metrics exporter
+pub mod metrics;
+
 /// Some abstractions to handle randomness
 mod rng;
 
@@ -58,11 +63,55 @@ type PeerMessage = (PeerId, EpochMessage);
 /// how many epochs ahead of consensus to rejoin
 const NUM_EPOCHS_REJOIN_AHEAD: u64 = 10;
 
+/// How many signed epochs a peer sends in response to a single [`EpochMessage::HistoryRequest`]
+const NUM_EPOCHS_PER_HISTORY_RESPONSE: u64 = 16;
+
+/// How long to wait for a peer to respond to a [`EpochMessage::HistoryRequest`] before asking
+/// the next one
+const HISTORY_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum size of the entries sent in a single [`EpochMessage::SnapshotPart`], well below the
+/// maximum message length
+const MAX_SNAPSHOT_PART_BYTES: usize = 8 * 1024 * 1024;
+
+/// Maximum number of entries of a snapshot we download from a peer
+const MAX_SNAPSHOT_ENTRIES: u64 = 50_000_000;
+
+/// Maximum total size of the entries of a snapshot we download from a peer, which we hold in
+/// memory until we can verify its hash
+const MAX_SNAPSHOT_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum EpochMessage {
     Continue(Message<PeerId>),
     RejoinRequest(u64),
+    /// Asks for the signed epoch history starting at the given epoch
+    HistoryRequest(u64),
+    /// Consecutive signed epochs starting at the requested one, fewer than
+    /// [`NUM_EPOCHS_PER_HISTORY_RESPONSE`] if the sender has no more
+    History(Vec<SerdeEpochHistory>),
+    /// Response to a [`EpochMessage::HistoryRequest`] for epochs the sender pruned, the history
+    /// has to be restored from its snapshot instead
+    HistoryPruned,
+    /// Asks for the entries of the latest signed snapshot starting at the given index
+    SnapshotRequest(u64),
+    SnapshotPart(SnapshotPart),
+}
+
+/// Consecutive entries of a signed [`ConsensusSnapshot`]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SnapshotPart {
+    pub header: ConsensusSnapshotHeader,
+    /// Index of the first entry in this part
+    pub start: u64,
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// A peer's response to our [`EpochMessage::HistoryRequest`]
+enum HistoryResponse {
+    Epochs(Vec<SignedEpochOutcome>),
+    Pruned,
 }
 
 type EpochStep = Step<Vec<SerdeConsensusItem>, PeerId>;
@@ -74,9 +123,13 @@ pub struct FedimintServer {
     pub hbbft: HoneyBadger<Vec<SerdeConsensusItem>, PeerId>,
     pub api: Arc<dyn IFederationApi>,
     pub peers: BTreeSet<PeerId>,
+    /// Connection status of our peers, reported by the admin API
+    pub peer_status: PeerStatusMap,
     pub rejoin_at_epoch: Option<HashMap<u64, HashSet<PeerId>>>,
     pub run_empty_epochs: u64,
     pub last_processed_epoch: Option<SignedEpochOutcome>,
+    /// Messages received while catching up that still need to be handled
+    pub pending_messages: VecDeque<PeerMessage>,
 }
 
 impl FedimintServer {
@@ -88,9 +141,11 @@ impl FedimintServer {
     ) -> anyhow::Result<()> {
         let server = FedimintServer::new(cfg.clone(), consensus, task_group).await;
         let server_consensus = server.consensus.clone();
+        let peer_status = server.peer_status.clone();
+        let api_task_group = task_group.clone();
         task_group
             .spawn("api-server", |handle| {
-                net::api::run_server(cfg, server_consensus, handle)
+                net::api::run_server(cfg, server_consensus, peer_status, api_task_group, handle)
             })
             .await;
         task_group
@@ -115,15 +170,25 @@ impl FedimintServer {
         consensus: FedimintConsensus,
         connector: PeerConnector<EpochMessage>,
         task_group: &mut TaskGroup,
+    ) -> Self {
+        let connections =
+            ReconnectPeerConnections::new(cfg.network_config(), connector, task_group).await;
+        let peer_status = connections.status();
+
+        Self::new_with_connections(cfg, consensus, connections.into_dyn(), peer_status).await
+    }
+
+    /// Like [`FedimintServer::new_with`], but communicating over already set up `connections`,
+    /// e.g. the ones of the simulated network in `net::sim` (enabled by the `sim` feature)
+    pub async fn new_with_connections(
+        cfg: ServerConfig,
+        consensus: FedimintConsensus,
+        connections: PeerConnections<EpochMessage>,
+        peer_status: PeerStatusMap,
     ) -> Self {
         cfg.validate_config(&cfg.local.identity, &consensus.module_config_gens)
             .expect("invalid config");
 
-        let connections =
-            ReconnectPeerConnections::new(cfg.network_config(), connector, task_group)
-                .await
-                .into_dyn();
-
         let net_info = NetworkInfo::new(
             cfg.local.identity,
             cfg.private.hbbft_sks.inner().clone(),
@@ -149,9 +214,11 @@ impl FedimintServer {
             cfg: cfg.clone(),
             api,
             peers: cfg.consensus.peers.keys().cloned().collect(),
+            peer_status,
             rejoin_at_epoch: None,
             run_empty_epochs: 0,
             last_processed_epoch: None,
+            pending_messages: VecDeque::new(),
         }
     }
 
@@ -160,9 +227,14 @@ impl FedimintServer {
         // FIXME: reusing the wallet CI leads to duplicate randomness beacons, not a problem for change, but maybe later for other use cases
         let mut rng = OsRng;
         let consensus = self.consensus.clone();
+        if self.catch_up().await.is_err() {
+            info!("Consensus task shut down while catching up");
+            return;
+        }
         self.start_consensus().await;
 
         while !task_handle.is_shutting_down() {
+            let epoch_timer = metrics::EPOCH_DURATION_SECONDS.start_timer();
             let outcomes = if let Ok(v) = self
                 .run_consensus_epoch(consensus.get_consensus_proposal(), &mut rng)
                 .await
@@ -171,10 +243,19 @@ impl FedimintServer {
             } else {
                 // `None` is supposed to mean the proccess is shutting down
                 debug_assert!(task_handle.is_shutting_down());
+                epoch_timer.stop_and_discard();
                 break;
             };
+            epoch_timer.observe_duration();
 
             for outcome in outcomes {
+                metrics::EPOCH_ITEMS.observe(
+                    outcome
+                        .contributions
+                        .values()
+                        .map(|items| items.len())
+                        .sum::<usize>() as f64,
+                );
                 info!("{}", consensus::debug::epoch_message(&outcome));
                 self.process_outcome(outcome)
                     .await
@@ -187,12 +268,7 @@ impl FedimintServer {
 
     /// Starts consensus by skipping to the last saved epoch history  and triggering a new epoch
     pub async fn start_consensus(&mut self) {
-        let db = self.consensus.db.clone();
-        let mut tx = db.begin_transaction(self.consensus.decoders()).await;
-
-        if let Some(key) = tx.get_value(&LastEpochKey).await.expect("DB error") {
-            self.last_processed_epoch = tx.get_value(&key).await.expect("DB error");
-        }
+        self.load_last_processed_epoch().await;
 
         let epoch = self.next_epoch_to_process();
         info!("Starting consensus at epoch {}", epoch);
@@ -201,6 +277,368 @@ impl FedimintServer {
         self.request_rejoin(1).await;
     }
 
+    /// Downloads the signed epochs we missed while being offline from our peers and processes
+    /// them, so we only have to rejoin HBBFT for the last few epochs
+    ///
+    /// Peers are asked one after another, we are done once every peer in turn failed to extend our
+    /// history or one of them sent us all the signed epochs it has. Messages unrelated to catching
+    /// up are kept for when consensus starts.
+    pub async fn catch_up(&mut self) -> Cancellable<()> {
+        self.load_last_processed_epoch().await;
+
+        let peers = self
+            .peers
+            .iter()
+            .copied()
+            .filter(|peer| *peer != self.cfg.local.identity)
+            .collect::<Vec<_>>();
+        let mut peers_without_progress = 0;
+
+        for peer in peers.iter().cycle() {
+            if peers_without_progress >= peers.len() {
+                break;
+            }
+
+            let start_epoch = self.next_epoch_to_process();
+            let history = match self.request_history(*peer, start_epoch).await? {
+                Some(HistoryResponse::Epochs(history)) => history,
+                Some(HistoryResponse::Pruned) => {
+                    if self.restore_snapshot_from(*peer, start_epoch).await? {
+                        peers_without_progress = 0;
+                    } else {
+                        peers_without_progress += 1;
+                    }
+                    continue;
+                }
+                None => {
+                    warn!(
+                        "Peer {} did not send us the history since epoch {}",
+                        peer, start_epoch
+                    );
+                    peers_without_progress += 1;
+                    continue;
+                }
+            };
+
+            let num_received = history.len() as u64;
+            match self.process_history(history).await {
+                Ok(()) if num_received == 0 => peers_without_progress += 1,
+                Ok(()) => {
+                    info!(
+                        "Caught up to epoch {} with the history of peer {}",
+                        start_epoch + num_received - 1,
+                        peer
+                    );
+                    if num_received < NUM_EPOCHS_PER_HISTORY_RESPONSE {
+                        break;
+                    }
+                    peers_without_progress = 0;
+                }
+                Err(e) => {
+                    warn!("Peer {} sent us invalid history: {:?}", peer, e);
+                    // the valid part of the history was processed nonetheless
+                    if self.next_epoch_to_process() == start_epoch {
+                        peers_without_progress += 1;
+                    } else {
+                        peers_without_progress = 0;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Asks `peer` for the history starting at `epoch`, returns `None` if it doesn't respond in
+    /// time or its response doesn't decode
+    async fn request_history(
+        &mut self,
+        peer: PeerId,
+        epoch: u64,
+    ) -> Cancellable<Option<HistoryResponse>> {
+        self.connections
+            .send(&[peer], EpochMessage::HistoryRequest(epoch))
+            .await?;
+
+        let response = self
+            .receive_response(peer, |msg| match msg {
+                EpochMessage::History(history) => Ok(Some(history)),
+                EpochMessage::HistoryPruned => Ok(None),
+                msg => Err(msg),
+            })
+            .await?;
+
+        let decoders = self.consensus.decoders();
+        Ok(match response {
+            // every epoch is an untrusted item of its own with a fresh decoding budget
+            Some(Some(history)) => history
+                .iter()
+                .map(|epoch| epoch.try_into_inner(&decoders.with_limits(DecodeLimits::UNTRUSTED)))
+                .collect::<Result<_, _>>()
+                .ok()
+                .map(HistoryResponse::Epochs),
+            Some(None) => Some(HistoryResponse::Pruned),
+            None => None,
+        })
+    }
+
+    /// Downloads the latest signed snapshot of `peer` and restores it if it is ahead of
+    /// `start_epoch`, returns whether it succeeded
+    async fn restore_snapshot_from(&mut self, peer: PeerId, start_epoch: u64) -> Cancellable<bool> {
+        let (header, snapshot) = match self.request_snapshot(peer).await? {
+            Some((header, snapshot)) if header.epoch >= start_epoch => (header, snapshot),
+            _ => {
+                warn!("Peer {} did not send us a snapshot to start from", peer);
+                return Ok(false);
+            }
+        };
+
+        // the snapshot's epoch is the base for verifying and processing the following ones
+        let epoch = match self.request_history(peer, header.epoch).await? {
+            Some(HistoryResponse::Epochs(history)) => history.into_iter().next(),
+            _ => None,
+        };
+        let epoch = match epoch {
+            Some(epoch) => epoch,
+            None => {
+                warn!(
+                    "Peer {} did not send us epoch {} of its snapshot",
+                    peer, header.epoch
+                );
+                return Ok(false);
+            }
+        };
+
+        let snapshot_epoch = header.epoch;
+        if let Err(e) = self
+            .consensus
+            .restore_snapshot(header, snapshot, epoch)
+            .await
+        {
+            warn!("Peer {} sent us an invalid snapshot: {:?}", peer, e);
+            return Ok(false);
+        }
+        info!(
+            "Restored the snapshot of epoch {} from peer {}",
+            snapshot_epoch, peer
+        );
+        self.load_last_processed_epoch().await;
+
+        Ok(true)
+    }
+
+    /// Downloads the latest signed snapshot of `peer` part by part, returns `None` if it stops
+    /// responding, sends parts that don't fit together or a snapshot that isn't signed by the
+    /// federation or is too large
+    async fn request_snapshot(
+        &mut self,
+        peer: PeerId,
+    ) -> Cancellable<Option<(ConsensusSnapshotHeader, ConsensusSnapshot)>> {
+        let epoch_pk = self.cfg.consensus.epoch_pk_set.public_key();
+        let mut header: Option<ConsensusSnapshotHeader> = None;
+        let mut entries = vec![];
+        let mut snapshot_bytes = 0;
+
+        loop {
+            let start = entries.len() as u64;
+            self.connections
+                .send(&[peer], EpochMessage::SnapshotRequest(start))
+                .await?;
+
+            let part = match self
+                .receive_response(peer, |msg| match msg {
+                    EpochMessage::SnapshotPart(part) => Ok(part),
+                    msg => Err(msg),
+                })
+                .await?
+            {
+                Some(part) => part,
+                None => return Ok(None),
+            };
+
+            match &header {
+                // the following parts have to carry the header of the first one
+                Some(header) if *header != part.header => return Ok(None),
+                Some(_) => {}
+                // the header is trusted once the federation signed it, its entries only once
+                // they match its hash
+                None => {
+                    if let Err(e) = part.header.verify_sig(&epoch_pk) {
+                        warn!("Peer {} sent us an unsigned snapshot: {:?}", peer, e);
+                        return Ok(None);
+                    }
+                    if part.header.num_entries > MAX_SNAPSHOT_ENTRIES {
+                        warn!(
+                            "Peer {} sent us a snapshot with too many entries: {}",
+                            peer, part.header.num_entries
+                        );
+                        return Ok(None);
+                    }
+                }
+            }
+
+            let num_entries = part.header.num_entries;
+            let consistent = part.start == start
+                && (!part.entries.is_empty() || start >= num_entries)
+                && start + part.entries.len() as u64 <= num_entries;
+            if !consistent {
+                return Ok(None);
+            }
+
+            snapshot_bytes += part
+                .entries
+                .iter()
+                .map(|(key, value)| (key.len() + value.len()) as u64)
+                .sum::<u64>();
+            if snapshot_bytes > MAX_SNAPSHOT_BYTES {
+                warn!("Peer {} sent us a snapshot that is too large", peer);
+                return Ok(None);
+            }
+
+            entries.extend(part.entries);
+            let header = header.get_or_insert(part.header);
+            if entries.len() as u64 >= num_entries {
+                let snapshot = ConsensusSnapshot {
+                    epoch: header.epoch,
+                    entries,
+                };
+                return Ok(Some((header.clone(), snapshot)));
+            }
+        }
+    }
+
+    /// Waits for the response of `peer` to our request, `response` returns the messages that
+    /// aren't one
+    ///
+    /// Requests of other peers catching up at the same time are answered, other messages are kept
+    /// for when consensus starts. Returns `None` if `peer` doesn't respond in time.
+    async fn receive_response<T>(
+        &mut self,
+        peer: PeerId,
+        response: impl Fn(EpochMessage) -> Result<T, EpochMessage>,
+    ) -> Cancellable<Option<T>> {
+        let deadline = tokio::time::Instant::now() + HISTORY_RESPONSE_TIMEOUT;
+        loop {
+            let (sender, msg) =
+                match tokio::time::timeout_at(deadline, self.connections.receive()).await {
+                    Ok(msg) => msg?,
+                    Err(_) => return Ok(None),
+                };
+
+            let msg = if sender == peer {
+                match response(msg) {
+                    Ok(response) => return Ok(Some(response)),
+                    Err(msg) => msg,
+                }
+            } else {
+                msg
+            };
+
+            match msg {
+                // late responses to earlier requests
+                EpochMessage::History(_)
+                | EpochMessage::HistoryPruned
+                | EpochMessage::SnapshotPart(_) => {}
+                EpochMessage::HistoryRequest(epoch) => self.send_history(sender, epoch).await?,
+                EpochMessage::SnapshotRequest(start) => {
+                    self.send_snapshot_part(sender, start).await?
+                }
+                msg => self.pending_messages.push_back((sender, msg)),
+            }
+        }
+    }
+
+    /// Verifies the signed epochs of `history` and processes them as long as they are valid
+    async fn process_history(
+        &mut self,
+        history: Vec<SignedEpochOutcome>,
+    ) -> Result<(), EpochVerifyError> {
+        let epoch_pk = self.cfg.consensus.epoch_pk_set.public_key();
+
+        for (expected_epoch, epoch) in (self.next_epoch_to_process()..).zip(history) {
+            if epoch.outcome.epoch != expected_epoch {
+                return Err(EpochVerifyError::MissingPreviousEpoch);
+            }
+            epoch.verify_hash(&self.last_processed_epoch)?;
+            epoch.verify_sig(&epoch_pk)?;
+
+            let processed = self
+                .consensus
+                .process_consensus_outcome(
+                    Batch {
+                        epoch: epoch.outcome.epoch,
+                        contributions: BTreeMap::from_iter(epoch.outcome.items),
+                    },
+                    &Some(epoch.outcome.rejected_txs),
+                )
+                .await;
+            self.last_processed_epoch = Some(processed);
+        }
+
+        Ok(())
+    }
+
+    /// Sends `peer` the signed epochs we have starting at `epoch`, or tells it to start from our
+    /// snapshot if we pruned them
+    async fn send_history(&mut self, peer: PeerId, epoch: u64) -> Cancellable<()> {
+        let mut history = vec![];
+        for epoch in epoch..epoch.saturating_add(NUM_EPOCHS_PER_HISTORY_RESPONSE) {
+            match self.consensus.epoch_history(epoch).await {
+                Some(epoch) if epoch.signature.is_some() => history.push((&epoch).into()),
+                _ => break,
+            }
+        }
+
+        if history.is_empty() {
+            let snapshot_epoch = self
+                .consensus
+                .signed_snapshot_header()
+                .await
+                .map(|header| header.epoch);
+            if snapshot_epoch.map_or(false, |snapshot_epoch| epoch < snapshot_epoch) {
+                return self
+                    .connections
+                    .send(&[peer], EpochMessage::HistoryPruned)
+                    .await;
+            }
+        }
+
+        self.connections
+            .send(&[peer], EpochMessage::History(history))
+            .await
+    }
+
+    /// Sends `peer` the entries of our latest signed snapshot starting at `start`, nothing if we
+    /// don't have one
+    async fn send_snapshot_part(&mut self, peer: PeerId, start: u64) -> Cancellable<()> {
+        let (header, entries) = match self
+            .consensus
+            .signed_snapshot_part(start, MAX_SNAPSHOT_PART_BYTES)
+            .await
+        {
+            Some(part) => part,
+            None => return Ok(()),
+        };
+        let part = SnapshotPart {
+            header,
+            start,
+            entries,
+        };
+
+        self.connections
+            .send(&[peer], EpochMessage::SnapshotPart(part))
+            .await
+    }
+
+    async fn load_last_processed_epoch(&mut self) {
+        let db = self.consensus.db.clone();
+        let mut tx = db.begin_transaction(self.consensus.decoders()).await;
+
+        if let Some(key) = tx.get_value(&LastEpochKey).await.expect("DB error") {
+            self.last_processed_epoch = tx.get_value(&key).await.expect("DB error");
+        }
+    }
+
     /// Returns the next epoch that we need to process, based on our saved history
     fn next_epoch_to_process(&self) -> u64 {
         self.last_processed_epoch
@@ -325,7 +763,10 @@ impl FedimintServer {
         outcomes.append(&mut self.handle_step(step).await?);
 
         while outcomes.is_empty() {
-            let msg = self.connections.receive().await?;
+            let msg = match self.pending_messages.pop_front() {
+                Some(msg) => msg,
+                None => self.connections.receive().await?,
+            };
             outcomes = self.handle_message(msg).await?;
         }
         Ok(outcomes)
@@ -380,6 +821,10 @@ impl FedimintServer {
             return Ok(None);
         }
 
+        if let Some(msg) = self.pending_messages.pop_front() {
+            return Ok(Some(msg));
+        }
+
         tokio::select! {
             () = self.consensus.transaction_notify.notified() => Ok(None),
             () = self.consensus.await_consensus_proposal() => Ok(None),
@@ -391,6 +836,11 @@ impl FedimintServer {
         match msg {
             (_, EpochMessage::Continue(peer_msg)) => self.hbbft.epoch() <= peer_msg.epoch(),
             (_, EpochMessage::RejoinRequest(_)) => false,
+            (_, EpochMessage::HistoryRequest(_)) => false,
+            (_, EpochMessage::History(_)) => false,
+            (_, EpochMessage::HistoryPruned) => false,
+            (_, EpochMessage::SnapshotRequest(_)) => false,
+            (_, EpochMessage::SnapshotPart(_)) => false,
         }
     }
 
@@ -415,6 +865,18 @@ impl FedimintServer {
                 self.run_empty_epochs = min(NUM_EPOCHS_REJOIN_AHEAD, epoch);
                 Ok(vec![])
             }
+            (peer, EpochMessage::HistoryRequest(epoch)) => {
+                self.send_history(peer, epoch).await?;
+                Ok(vec![])
+            }
+            (peer, EpochMessage::SnapshotRequest(start)) => {
+                self.send_snapshot_part(peer, start).await?;
+                Ok(vec![])
+            }
+            // we only process history and snapshots while catching up
+            (_, EpochMessage::History(_))
+            | (_, EpochMessage::HistoryPruned)
+            | (_, EpochMessage::SnapshotPart(_)) => Ok(vec![]),
         }
     }
 
@@ -466,7 +928,10 @@ fn module_parse_outcome(
         .filter_map(|(peer, cis)| {
             let decoded_cis = cis
                 .into_iter()
-                .map(|ci| ci.try_into_inner(module_registry))
+                .map(|ci| {
+                    // whole contributions are already bounded by the max peer message size
+                    ci.try_into_inner(&module_registry.with_limits(DecodeLimits::UNTRUSTED))
+                })
                 .collect::<Result<Vec<ConsensusItem>, DecodeError>>();
 
             match decoded_cis {
@@ -0,0 +1,111 @@
+//! Prometheus metrics of the consensus and the peer networking
+//!
+//! All metrics are registered in the default [`prometheus`] registry, modules register their own
+//! metrics there too so that [`run_metrics_server`] exports everything at once.
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use fedimint_api::task::TaskHandle;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::{
+    histogram_opts, register_histogram, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    Histogram, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use tracing::info;
+
+lazy_static! {
+    pub static ref EPOCH_DURATION_SECONDS: Histogram = register_histogram!(
+        "fedimint_epoch_duration_seconds",
+        "Time it took to reach consensus on an epoch"
+    )
+    .unwrap();
+    pub static ref EPOCH_ITEMS: Histogram = register_histogram!(histogram_opts!(
+        "fedimint_epoch_items",
+        "Number of consensus items contributed by all peers to an epoch",
+        vec![0.0, 1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0, 5000.0]
+    ))
+    .unwrap();
+    pub static ref TRANSACTIONS: IntCounterVec = register_int_counter_vec!(
+        "fedimint_transactions_total",
+        "Transactions processed by the consensus, by result (accepted or rejected)",
+        &["result"]
+    )
+    .unwrap();
+    pub static ref PEER_RECONNECTS: IntCounterVec = register_int_counter_vec!(
+        "fedimint_peer_reconnects_total",
+        "Attempts to reconnect to a peer",
+        &["peer"]
+    )
+    .unwrap();
+    pub static ref PEER_MESSAGE_QUEUE_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "fedimint_peer_message_queue_depth",
+        "Messages sent to a peer that it didn't acknowledge yet",
+        &["peer"]
+    )
+    .unwrap();
+}
+
+/// Serves all registered metrics in the Prometheus text format at `http://<bind>/metrics` until
+/// the task group shuts down
+pub async fn run_metrics_server(bind: SocketAddr, task_handle: TaskHandle) -> anyhow::Result<()> {
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(serve_metrics)) });
+    let server = hyper::Server::try_bind(&bind)?.serve(make_service);
+    let shutdown_rx = task_handle.make_shutdown_rx().await;
+
+    info!(%bind, "Serving metrics");
+    server
+        .with_graceful_shutdown(async {
+            let _ = shutdown_rx.await;
+        })
+        .await?;
+    Ok(())
+}
+
+async fn serve_metrics(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .expect("Valid response"));
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    encoder
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("Metrics can be encoded");
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .expect("Valid response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, Request, StatusCode};
+
+    use super::{serve_metrics, TRANSACTIONS};
+
+    #[tokio::test]
+    async fn metrics_are_served() {
+        TRANSACTIONS.with_label_values(&["accepted"]).inc();
+
+        let response = serve_metrics(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("fedimint_transactions_total{result=\"accepted\"}"));
+
+        let response = serve_metrics(Request::get("/other").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,101 @@
+//! Authentication of requests to the admin API
+use std::num::NonZeroU32;
+
+use rand::{CryptoRng, RngCore};
+use ring::{digest, pbkdf2};
+use serde::{Deserialize, Serialize};
+
+/// PBKDF2 iterations of new admin password hashes, which makes brute-forcing weak passwords
+/// costly while only delaying admin requests by a fraction of a second
+pub const ADMIN_PASSWORD_ITERATIONS: Option<NonZeroU32> = NonZeroU32::new(100_000);
+
+const SALT_LEN: usize = 16;
+
+/// Salted PBKDF2-HMAC-SHA256 hash of the password authenticating requests to the admin API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdminPasswordHash {
+    pub iterations: NonZeroU32,
+    #[serde(with = "hex::serde")]
+    pub salt: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub hash: Vec<u8>,
+}
+
+impl AdminPasswordHash {
+    /// Hashes `password` with a random salt
+    pub fn new(
+        password: &str,
+        iterations: NonZeroU32,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> AdminPasswordHash {
+        let mut salt = vec![0; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut hash = vec![0; digest::SHA256_OUTPUT_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+
+        AdminPasswordHash {
+            iterations,
+            salt,
+            hash,
+        }
+    }
+
+    /// Returns `true` if `password` is the hashed password, compared in constant time
+    pub fn verify(&self, password: &str) -> bool {
+        pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            self.iterations,
+            &self.salt,
+            password.as_bytes(),
+            &self.hash,
+        )
+        .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use rand::rngs::OsRng;
+
+    use super::AdminPasswordHash;
+
+    #[test]
+    fn verifies_only_the_hashed_password() {
+        let iterations = NonZeroU32::new(10).unwrap();
+        let hash = AdminPasswordHash::new("correct horse", iterations, OsRng);
+
+        assert!(hash.verify("correct horse"));
+        assert!(!hash.verify("correct horse "));
+        assert!(!hash.verify("wrong password"));
+        assert!(!hash.verify(""));
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        let iterations = NonZeroU32::new(10).unwrap();
+        let hash = AdminPasswordHash::new("correct horse", iterations, OsRng);
+        let other = AdminPasswordHash::new("correct horse", iterations, OsRng);
+
+        assert_ne!(hash.salt, other.salt);
+        assert_ne!(hash.hash, other.hash);
+        assert!(other.verify("correct horse"));
+    }
+
+    #[test]
+    fn hash_roundtrips_through_config() {
+        let hash = AdminPasswordHash::new("correct horse", NonZeroU32::new(10).unwrap(), OsRng);
+        let json = serde_json::to_string(&hash).unwrap();
+
+        let decoded: AdminPasswordHash = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, hash);
+        assert!(decoded.verify("correct horse"));
+    }
+}
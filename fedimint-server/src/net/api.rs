@@ -1,35 +1,75 @@
 //! Implements the client API through which users interact with the federation
+use std::collections::BTreeMap;
 use std::fmt::Formatter;
+use std::future::Future;
 use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
+use fedimint_api::module::audit::Audit;
 use fedimint_api::server::ServerModule;
+use fedimint_api::task::TaskGroup;
 use fedimint_api::{
     config::ClientConfig,
+    encoding::DecodeLimits,
     module::{api_endpoint, ApiEndpoint, ApiError},
     task::TaskHandle,
-    TransactionId,
+    PeerId, TransactionId,
 };
-use fedimint_core::epoch::SerdeEpochHistory;
+use fedimint_core::admin::{AdminRequest, PeerConnectionStatus};
+use fedimint_core::epoch::{SerdeEpochHistory, SignedEpochOutcome};
 use fedimint_core::outcome::TransactionStatus;
-use futures::FutureExt;
+use fedimint_wallet::config::WalletConfig;
+use fedimint_wallet::{PegOutSignatureItem, PendingPegOut, UnsignedPegOut};
+use futures::{FutureExt, Stream};
 use jsonrpsee::{
     server::ServerBuilder,
     types::{error::CallError, ErrorObject},
     RpcModule,
 };
-use tracing::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
 
 use crate::config::ServerConfig;
-use crate::consensus::FedimintConsensus;
+use crate::consensus::{FedimintConsensus, TransactionSubmissionError};
+use crate::db::ProposedTransactionKeyPrefix;
+use crate::net::admin::AdminPasswordHash;
+use crate::net::peers::PeerStatusMap;
+use crate::net::rate_limit::{self, ConnectionRateLimit, RateLimitLogger};
 use crate::transaction::SerdeTransaction;
 
 /// A state of fedimint server passed to each rpc handler callback
 #[derive(Clone)]
 pub struct RpcHandlerCtx {
     fedimint: Arc<FedimintConsensus>,
+    peer_status: PeerStatusMap,
+    /// Task group of the whole server, used by the admin API to shut it down
+    task_group: TaskGroup,
+}
+
+/// Rejects the request if it exceeds the rate limit of its connection, `acquire` takes a token
+/// from the bucket of the connection the request counts against
+fn check_rate_limit(
+    connection: Option<ConnectionRateLimit>,
+    acquire: impl FnOnce(&ConnectionRateLimit) -> bool,
+) -> Result<(), jsonrpsee::core::Error> {
+    let allowed = match connection {
+        Some(connection) => acquire(&connection),
+        None => {
+            error!("API call arrived on unknown connection");
+            false
+        }
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(jsonrpsee::core::Error::Call(CallError::Custom(
+            ErrorObject::owned(429, "Request rate limit exceeded", None::<()>),
+        )))
+    }
 }
 
 impl std::fmt::Debug for RpcHandlerCtx {
@@ -41,14 +81,23 @@ impl std::fmt::Debug for RpcHandlerCtx {
 pub async fn run_server(
     cfg: ServerConfig,
     fedimint: Arc<FedimintConsensus>,
+    peer_status: PeerStatusMap,
+    task_group: TaskGroup,
     task_handle: TaskHandle,
 ) {
     let state = RpcHandlerCtx {
         fedimint: fedimint.clone(),
+        peer_status,
+        task_group,
     };
     let mut rpc_module = RpcModule::new(state);
 
     attach_endpoints(&mut rpc_module, server_endpoints(), None);
+    attach_epoch_subscription(&mut rpc_module);
+    match &cfg.local.admin_password_hash {
+        Some(password_hash) => attach_admin_endpoints(&mut rpc_module, password_hash, &cfg),
+        None => info!("No admin password configured, admin API is disabled"),
+    }
 
     for module in fedimint.modules.modules() {
         attach_endpoints_erased(&mut rpc_module, module);
@@ -58,6 +107,7 @@ pub async fn run_server(
     let server = ServerBuilder::new()
         .max_connections(cfg.local.max_connections)
         .ping_interval(Duration::from_secs(10))
+        .set_logger(RateLimitLogger::new(cfg.local.max_requests_per_second))
         .build(&cfg.local.api_bind.to_string())
         .await
         .context(format!("Bind address: {}", cfg.local.api_bind))
@@ -97,18 +147,26 @@ fn attach_endpoints(
         };
 
         // Another memory leak that is fine because the function is only called once at startup
-        let handler: &'static _ = Box::leak(endpoint.handler);
+        let handler: &'static _ = Box::leak(Box::new(endpoint.handler));
 
         rpc_module
-            .register_async_method(path, move |params, state| async move {
-                let params = params.one::<serde_json::Value>()?;
-                let fedimint = &state.fedimint;
-                let dbtx = fedimint.database_transaction().await;
-                // Using AssertUnwindSafe here is far from ideal. In theory this means we could
-                // end up with an inconsistent state in theory. In practice most API functions
-                // are only reading and the few that do write anything are atomic. Lastly, this
-                // is only the last line of defense
-                AssertUnwindSafe((handler)(fedimint, dbtx, params))
+            .register_async_method(path, move |params, state| {
+                // has to be taken before the handler yields for the first time
+                let connection = rate_limit::calling_connection();
+                async move {
+                    check_rate_limit(connection, ConnectionRateLimit::try_acquire)?;
+                    let params = params.one::<serde_json::Value>()?;
+                    let fedimint = &state.fedimint;
+                    // Using AssertUnwindSafe here is far from ideal. In theory this means we could
+                    // end up with an inconsistent state in theory. In practice most API functions
+                    // are only reading and the few that do write anything are atomic. Lastly, this
+                    // is only the last line of defense
+                    AssertUnwindSafe(handler.call(
+                        fedimint,
+                        &fedimint.db,
+                        fedimint.decoders(),
+                        params,
+                    ))
                     .catch_unwind()
                     .await
                     .map_err(|_| {
@@ -124,6 +182,7 @@ fn attach_endpoints(
                             e.code, e.message, None::<()>,
                         )))
                     })
+                }
             })
             .expect("Failed to register async method");
     }
@@ -142,19 +201,28 @@ fn attach_endpoints_erased(
         // and path has to live till the end of program anyways.
         let path: &'static _ =
             Box::leak(format!("/{}{}", base_name, endpoint.path).into_boxed_str());
-        let handler: &'static _ = Box::leak(endpoint.handler);
+        let handler: &'static _ = Box::leak(Box::new(endpoint.handler));
 
         rpc_module
-            .register_async_method(path, move |params, state| async move {
-                // Hack to avoid Sync/Send issues
-                let params = params.one::<serde_json::Value>()?;
-                let fedimint = &state.fedimint;
-                let dbtx = fedimint.database_transaction().await;
-                // Using AssertUnwindSafe here is far from ideal. In theory this means we could
-                // end up with an inconsistent state in theory. In practice most API functions
-                // are only reading and the few that do write anything are atomic. Lastly, this
-                // is only the last line of defense
-                AssertUnwindSafe((handler)(fedimint.modules.module(module_key), dbtx, params))
+            .register_async_method(path, move |params, state| {
+                // has to be taken before the handler yields for the first time
+                let connection = rate_limit::calling_connection();
+                async move {
+                    check_rate_limit(connection, ConnectionRateLimit::try_acquire)?;
+                    // Hack to avoid Sync/Send issues
+                    let params = params.one::<serde_json::Value>()?;
+                    let fedimint = &state.fedimint;
+                    let module = fedimint.modules.module(module_key);
+                    // Using AssertUnwindSafe here is far from ideal. In theory this means we could
+                    // end up with an inconsistent state in theory. In practice most API functions
+                    // are only reading and the few that do write anything are atomic. Lastly, this
+                    // is only the last line of defense
+                    AssertUnwindSafe(handler.call(
+                        module,
+                        &fedimint.db,
+                        fedimint.decoders(),
+                        params,
+                    ))
                     .catch_unwind()
                     .await
                     .map_err(|_| {
@@ -170,11 +238,224 @@ fn attach_endpoints_erased(
                             e.code, e.message, None::<()>,
                         )))
                     })
+                }
             })
             .expect("Failed to register async method");
     }
 }
 
+/// Registers the endpoints of the admin API under `/admin`, their params have to be wrapped in
+/// an [`AdminRequest`] containing the admin password
+fn attach_admin_endpoints(
+    rpc_module: &mut RpcModule<RpcHandlerCtx>,
+    password_hash: &AdminPasswordHash,
+    cfg: &ServerConfig,
+) {
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/audit",
+        password_hash,
+        |state, _: ()| async move { Ok::<Audit, _>(state.fedimint.audit().await) },
+    );
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/peer_status",
+        password_hash,
+        |state, _: ()| async move {
+            Ok::<BTreeMap<PeerId, PeerConnectionStatus>, _>(
+                state.peer_status.lock().expect("poisoned").clone(),
+            )
+        },
+    );
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/pending_peg_outs",
+        password_hash,
+        |state, _: ()| async move {
+            let mut dbtx = state.fedimint.read_database_transaction().await;
+            Ok::<Vec<PendingPegOut>, _>(fedimint_wallet::pending_peg_outs(&mut dbtx).await)
+        },
+    );
+    if let Ok(wallet_cfg) = cfg.get_module_config_typed::<WalletConfig>("wallet") {
+        attach_wallet_signer_endpoints(rpc_module, password_hash, wallet_cfg);
+    }
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/proposal_queue",
+        password_hash,
+        |state, _: ()| async move {
+            let mut dbtx = state.fedimint.read_database_transaction().await;
+            Ok::<Vec<TransactionId>, _>(
+                dbtx.find_by_prefix(&ProposedTransactionKeyPrefix)
+                    .await
+                    .map(|res| res.expect("DB error").0 .0)
+                    .collect(),
+            )
+        },
+    );
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/shutdown",
+        password_hash,
+        |state, _: ()| async move {
+            warn!("Shutdown requested through the admin API");
+            // shut down in the background so the response can still be sent
+            let task_group = state.task_group.clone();
+            tokio::spawn(async move { task_group.shutdown().await });
+            Ok(())
+        },
+    );
+}
+
+/// Lets our external signer fetch the peg-outs it has to sign and submit its signatures, see
+/// [`WalletConfig::use_external_signer`]
+fn attach_wallet_signer_endpoints(
+    rpc_module: &mut RpcModule<RpcHandlerCtx>,
+    password_hash: &AdminPasswordHash,
+    wallet_cfg: WalletConfig,
+) {
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/wallet/unsigned_peg_outs",
+        password_hash,
+        |state, _: ()| async move {
+            let mut dbtx = state.fedimint.read_database_transaction().await;
+            Ok::<Vec<UnsignedPegOut>, _>(fedimint_wallet::unsigned_peg_outs(&mut dbtx).await)
+        },
+    );
+    attach_admin_endpoint(
+        rpc_module,
+        "/admin/wallet/submit_peg_out_signature",
+        password_hash,
+        move |state, signature: PegOutSignatureItem| {
+            let wallet_cfg = wallet_cfg.clone();
+            async move {
+                let mut dbtx = state.fedimint.database_transaction().await;
+                fedimint_wallet::submit_peg_out_signature(&mut dbtx, &wallet_cfg, signature)
+                    .await
+                    .map_err(|e| ApiError::bad_request(e.to_string()))?;
+                dbtx.commit_tx().await.expect("DB Error");
+                Ok::<_, ApiError>(())
+            }
+        },
+    );
+}
+
+fn attach_admin_endpoint<P, R, F, Fut>(
+    rpc_module: &mut RpcModule<RpcHandlerCtx>,
+    path: &'static str,
+    password_hash: &AdminPasswordHash,
+    handler: F,
+) where
+    P: DeserializeOwned + Send + 'static,
+    R: Serialize + Send + Sync + 'static,
+    F: Fn(Arc<RpcHandlerCtx>, P) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<R, ApiError>> + Send,
+{
+    let password_hash = Arc::new(password_hash.clone());
+    rpc_module
+        .register_async_method(path, move |params, state| {
+            let handler = handler.clone();
+            let password_hash = password_hash.clone();
+            // has to be taken before the handler yields for the first time
+            let connection = rate_limit::calling_connection();
+            async move {
+                // checked before the password, so guessing it is rate limited too
+                check_rate_limit(connection, ConnectionRateLimit::try_acquire_admin)?;
+                let request = params.one::<AdminRequest<P>>()?;
+                // hashing the password is slow on purpose, so it mustn't block the executor
+                let password = request.password;
+                let is_valid = tokio::task::spawn_blocking(move || password_hash.verify(&password))
+                    .await
+                    .expect("password verification panicked");
+                if !is_valid {
+                    warn!(path, "Admin API request with invalid password");
+                    return Err(jsonrpsee::core::Error::Call(CallError::Custom(
+                        ErrorObject::owned(401, "Invalid admin password", None::<()>),
+                    )));
+                }
+
+                handler(state, request.params).await.map_err(|e| {
+                    jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                        e.code, e.message, None::<()>,
+                    )))
+                })
+            }
+        })
+        .expect("Failed to register async method");
+}
+
+/// Lets clients subscribe to signed epochs starting at a given epoch number. Epochs that were
+/// already signed are sent first, afterwards every epoch is pushed as soon as its signature is
+/// complete (i.e. after the following epoch was saved).
+fn attach_epoch_subscription(rpc_module: &mut RpcModule<RpcHandlerCtx>) {
+    rpc_module
+        .register_subscription(
+            "/subscribe_epoch_history",
+            "/epoch_history",
+            "/unsubscribe_epoch_history",
+            |params, mut sink, state| {
+                if let Err(e) = check_rate_limit(
+                    rate_limit::calling_connection(),
+                    ConnectionRateLimit::try_acquire,
+                ) {
+                    let _ = sink.reject(e);
+                    return Ok(());
+                }
+                let from_epoch = params.one::<u64>()?;
+                let fedimint = state.fedimint.clone();
+
+                tokio::spawn(async move {
+                    let epochs = Box::pin(signed_epoch_stream(fedimint, from_epoch));
+                    let result = sink.pipe_from_stream(epochs).await;
+                    debug!(?result, "Epoch history subscription closed");
+                });
+
+                Ok(())
+            },
+        )
+        .expect("Failed to register subscription");
+}
+
+/// Stream of all signed epochs starting at `from_epoch`
+///
+/// The database is the source of truth, the broadcast channel is only used to wake up the stream
+/// and to skip the database lookup in the common case of an up-to-date subscriber.
+fn signed_epoch_stream(
+    fedimint: Arc<FedimintConsensus>,
+    from_epoch: u64,
+) -> impl Stream<Item = SerdeEpochHistory> {
+    // subscribe before reading from the database so no epoch can slip through in between
+    let receiver = fedimint.signed_epoch_sender.subscribe();
+
+    futures::stream::unfold(
+        (fedimint, receiver, from_epoch, None::<SignedEpochOutcome>),
+        |(fedimint, mut receiver, next_epoch, mut received)| async move {
+            loop {
+                let epoch = match received.take() {
+                    Some(epoch) if epoch.outcome.epoch == next_epoch => Some(epoch),
+                    _ => fedimint
+                        .epoch_history(next_epoch)
+                        .await
+                        .filter(|epoch| epoch.signature.is_some()),
+                };
+
+                if let Some(epoch) = epoch {
+                    let item: SerdeEpochHistory = (&epoch).into();
+                    return Some((item, (fedimint, receiver, next_epoch + 1, None)));
+                }
+
+                match receiver.recv().await {
+                    Ok(epoch) => received = Some(epoch),
+                    // we fell behind, the missed epochs will be read from the database
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    )
+}
+
 fn server_endpoints() -> Vec<ApiEndpoint<FedimintConsensus>> {
     vec![
         api_endpoint! {
@@ -184,13 +465,18 @@ fn server_endpoints() -> Vec<ApiEndpoint<FedimintConsensus>> {
                 // we need to convert it to string first
                 let string = serde_json::to_string(&transaction).map_err(|e| ApiError::bad_request(e.to_string()))?;
                 let serde_transaction: SerdeTransaction = serde_json::from_str(&string).map_err(|e| ApiError::bad_request(e.to_string()))?;
-                let transaction = serde_transaction.try_into_inner(&fedimint.modules.decoders()).map_err(|e| ApiError::bad_request(e.to_string()))?;
+                let transaction = serde_transaction.try_into_inner(&fedimint.modules.decoders().with_limits(DecodeLimits::UNTRUSTED)).map_err(|e| ApiError::bad_request(e.to_string()))?;
 
                 let tx_id = transaction.tx_hash();
 
                 fedimint.submit_transaction(transaction)
                     .await
-                    .map_err(|e| ApiError::bad_request(e.to_string()))?;
+                    .map_err(|e| match e {
+                        TransactionSubmissionError::ProposalQueueFull(_) => {
+                            ApiError::new(503, e.to_string())
+                        }
+                        e => ApiError::bad_request(e.to_string()),
+                    })?;
 
                 Ok(tx_id)
             }
@@ -1,18 +1,24 @@
 //! Adapter that implements a message based protocol on top of a stream based one
 use std::convert::TryInto;
 use std::fmt::Debug;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use bytes::{Buf, BufMut, BytesMut};
+use anyhow::format_err;
+use bincode::Options;
+use bytes::{BufMut, BytesMut};
 use futures::{Sink, Stream};
 use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio_util::codec::{FramedRead, FramedWrite};
 use tracing::{error, trace};
 
+/// Maximum length of an encoded message, peers announcing longer ones are disconnected before
+/// we buffer their message
+pub const MAX_MESSAGE_LEN: usize = 32 * 1024 * 1024;
+
 /// Owned [`FramedTransport`] trait object
 pub type AnyFramedTransport<M> = Box<dyn FramedTransport<M> + Send + Unpin + 'static>;
 
@@ -169,6 +175,14 @@ impl<T> BincodeCodec<T> {
             _pd: Default::default(),
         }
     }
+
+    /// Same encoding as [`bincode::serialize`], but refusing to allocate more than a message can
+    /// contain
+    fn bincode_options() -> impl bincode::Options {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(MAX_MESSAGE_LEN as u64)
+    }
 }
 
 impl<T> tokio_util::codec::Encoder<T> for BincodeCodec<T>
@@ -192,6 +206,12 @@ where
         // Lastly we update the length field by counting how many bytes have been written
         let new_len = dst.len();
         let encoded_len = new_len - old_len - 8;
+        if encoded_len > MAX_MESSAGE_LEN {
+            dst.truncate(old_len);
+            return Err(format_err!(
+                "Message of {encoded_len} bytes exceeds the maximum message length"
+            ));
+        }
         dst[old_len..old_len + 8].copy_from_slice(&encoded_len.to_le_bytes()[..]);
 
         Ok(())
@@ -211,18 +231,28 @@ where
         }
 
         let length = u64::from_le_bytes(src[0..8].try_into().expect("correct length"));
-        if src.len() < (length as usize) + 8 {
+        let length = match usize::try_from(length) {
+            Ok(length) if length <= MAX_MESSAGE_LEN => length,
+            _ => {
+                return Err(format_err!(
+                    "Message of {length} bytes exceeds the maximum message length"
+                ));
+            }
+        };
+
+        if src.len() < length + 8 {
             trace!(length, buffern_len = src.len(), "Received partial message");
             return Ok(None);
         } else {
             trace!(length, "Received full message");
         }
 
-        src.reader()
-            .read_exact(&mut [0u8; 8][..])
-            .expect("minimum length checked");
-
-        Ok(bincode::deserialize_from(src.reader()).map(Option::Some)?)
+        // bincode has to consume exactly the announced length, otherwise the next message would
+        // be read from the wrong position
+        let message = src.split_to(length + 8);
+        Ok(Self::bincode_options()
+            .deserialize(&message[8..])
+            .map(Option::Some)?)
     }
 }
 
@@ -301,4 +331,16 @@ mod tests {
 
         assert!(received.is_err());
     }
+
+    #[tokio::test]
+    async fn test_reject_oversized_message() {
+        let (mut sender, recipient) = tokio::io::duplex(1024);
+        let mut framed_recipient =
+            BidiFramed::<u64, WriteHalf<DuplexStream>, ReadHalf<DuplexStream>>::new(recipient);
+
+        // Announce a message we'd never be able to buffer
+        sender.write_all(&u64::MAX.to_le_bytes()).await.unwrap();
+
+        assert!(framed_recipient.next().await.unwrap().is_err());
+    }
 }
@@ -1,5 +1,9 @@
+pub mod admin;
 pub mod api;
 pub mod connect;
 pub mod framed;
 pub mod peers;
 mod queue;
+pub mod rate_limit;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
@@ -4,10 +4,11 @@
 //! [`ReconnectPeerConnections`], see these for details.
 
 use std::cmp::min;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::ops::Sub;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -15,6 +16,7 @@ use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::net::peers::IPeerConnections;
 use fedimint_api::task::{TaskGroup, TaskHandle};
 use fedimint_api::PeerId;
+use fedimint_core::admin::PeerConnectionStatus;
 use futures::future::select_all;
 use futures::{SinkExt, StreamExt};
 use hbbft::Target;
@@ -26,6 +28,7 @@ use tokio::time::Instant;
 use tracing::{debug, error, info, instrument, trace, warn};
 use url::Url;
 
+use crate::metrics;
 use crate::net::connect::{AnyConnector, SharedAnyConnector};
 use crate::net::framed::AnyFramedTransport;
 use crate::net::queue::{MessageId, MessageQueue, UniqueMessage};
@@ -37,6 +40,10 @@ const MAX_FAIL_RECONNECT_COUNTER: u64 = 300;
 /// [`ReconnectPeerConnections`]
 pub type PeerConnector<M> = AnyConnector<PeerMessage<M>>;
 
+/// Connection status of every peer, kept up to date by the peer IO tasks of
+/// [`ReconnectPeerConnections`]
+pub type PeerStatusMap = Arc<Mutex<BTreeMap<PeerId, PeerConnectionStatus>>>;
+
 /// Connection manager that automatically reconnects to peers
 ///
 /// `ReconnectPeerConnections` is based on a [`Connector`](crate::net::connect::Connector) object
@@ -45,6 +52,7 @@ pub type PeerConnector<M> = AnyConnector<PeerMessage<M>>;
 /// encrypted.
 pub struct ReconnectPeerConnections<T> {
     connections: HashMap<PeerId, PeerConnection<T>>,
+    status: PeerStatusMap,
 }
 
 struct PeerConnection<T> {
@@ -85,6 +93,7 @@ struct CommonPeerConnectionState<M> {
     connect: SharedAnyConnector<PeerMessage<M>>,
     incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
     last_received: Option<MessageId>,
+    status: PeerStatusMap,
 }
 
 struct DisconnectedPeerConnectionState {
@@ -115,6 +124,7 @@ where
         task_group: &mut TaskGroup,
     ) -> Self {
         let shared_connector: SharedAnyConnector<PeerMessage<T>> = connect.into();
+        let status = PeerStatusMap::default();
 
         let (connection_senders, connections) = cfg
             .peers
@@ -132,6 +142,7 @@ where
                             peer_address.clone(),
                             shared_connector.clone(),
                             connection_receiver,
+                            status.clone(),
                             task_group,
                         ),
                     ),
@@ -145,7 +156,15 @@ where
             })
             .await;
 
-        ReconnectPeerConnections { connections }
+        ReconnectPeerConnections {
+            connections,
+            status,
+        }
+    }
+
+    /// Returns a handle to the continuously updated connection status of all peers
+    pub fn status(&self) -> PeerStatusMap {
+        self.status.clone()
     }
 
     async fn run_listen_task(
@@ -256,6 +275,7 @@ where
         // which will disconnect when other tasks are shutting down returning here,
         // so we probably don't need any `timeout` here.
         while !task_handle.is_shutting_down() {
+            self.common.report_status(&self.state);
             if let Some(new_self) = self.state_transition().await {
                 self = new_self;
             } else {
@@ -282,10 +302,31 @@ where
     }
 }
 
+impl<M> PeerConnectionState<M> {
+    fn status(&self) -> PeerConnectionStatus {
+        match self {
+            PeerConnectionState::Disconnected(disconnected) => PeerConnectionStatus::Disconnected {
+                failed_reconnects: disconnected.failed_reconnect_counter,
+            },
+            PeerConnectionState::Connected(_) => PeerConnectionStatus::Connected,
+        }
+    }
+}
+
 impl<M> CommonPeerConnectionState<M>
 where
     M: Debug + Clone,
 {
+    fn report_status(&self, state: &PeerConnectionState<M>) {
+        self.status
+            .lock()
+            .expect("poisoned")
+            .insert(self.peer, state.status());
+        metrics::PEER_MESSAGE_QUEUE_DEPTH
+            .with_label_values(&[&self.peer.to_string()])
+            .set(self.resend_queue.len() as i64);
+    }
+
     async fn state_transition_connected(
         &mut self,
         mut connected: ConnectedPeerConnectionState<M>,
@@ -497,6 +538,9 @@ where
         &mut self,
         disconnected: DisconnectedPeerConnectionState,
     ) -> PeerConnectionState<M> {
+        metrics::PEER_RECONNECTS
+            .with_label_values(&[&self.peer.to_string()])
+            .inc();
         match self.try_reconnect().await {
             Ok(conn) => {
                 self.connect(conn, disconnected.failed_reconnect_counter)
@@ -531,6 +575,7 @@ where
         peer_address: Url,
         connect: SharedAnyConnector<PeerMessage<M>>,
         incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
+        status: PeerStatusMap,
         task_group: &mut TaskGroup,
     ) -> PeerConnection<M> {
         let (outgoing_sender, outgoing_receiver) = tokio::sync::mpsc::channel::<M>(1024);
@@ -546,6 +591,7 @@ where
                     peer_address,
                     connect,
                     incoming_connections,
+                    status,
                     &handle,
                 )
                 .await
@@ -574,6 +620,7 @@ where
         peer_address: Url,
         connect: SharedAnyConnector<PeerMessage<M>>,
         incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
+        status: PeerStatusMap,
         task_handle: &TaskHandle,
     ) {
         let common = CommonPeerConnectionState {
@@ -585,6 +632,7 @@ where
             connect,
             incoming_connections,
             last_received: None,
+            status,
         };
         let initial_state = common.disconnect(0);
 
@@ -604,6 +652,7 @@ mod tests {
 
     use fedimint_api::task::TaskGroup;
     use fedimint_api::PeerId;
+    use fedimint_core::admin::PeerConnectionStatus;
     use futures::Future;
 
     use crate::net::connect::mock::MockNetwork;
@@ -657,6 +706,17 @@ mod tests {
             assert_eq!(recv.0, PeerId::from(1));
             assert_eq!(recv.1, 42);
 
+            let status_b = peers_b.status();
+            timeout(async {
+                while status_b.lock().unwrap().get(&PeerId::from(1))
+                    != Some(&PeerConnectionStatus::Connected)
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("peer 1 should be reported as connected");
+
             peers_a.send(&[PeerId::from(3)], 21).await.unwrap();
 
             let mut peers_c = build_peers("127.0.0.1:3000", 3, task_group.clone()).await;
@@ -65,6 +65,14 @@ where
     pub fn iter(&self) -> impl Iterator<Item = &UniqueMessage<M>> {
         self.queue.iter()
     }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,220 @@
+//! Per-connection request rate limiting for the API server
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use jsonrpsee::server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol};
+use tracing::debug;
+
+/// Token bucket allowing bursts of up to one second worth of requests
+#[derive(Debug)]
+struct TokenBucket {
+    max_requests_per_second: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(max_requests_per_second: u32, now: Instant) -> Self {
+        TokenBucket {
+            max_requests_per_second,
+            tokens: max_requests_per_second as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Takes a token if one is available, refilling the bucket for the time passed since the
+    /// last call first
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        let capacity = self.max_requests_per_second as f64;
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * capacity).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Request rate limits of a single API connection
+///
+/// Admin API calls have their own bucket, so a client flooding the public API over the same
+/// connection can't lock the guardian out of the admin API.
+#[derive(Debug, Clone)]
+pub struct ConnectionRateLimit {
+    requests: Arc<Mutex<TokenBucket>>,
+    admin_requests: Arc<Mutex<TokenBucket>>,
+}
+
+impl ConnectionRateLimit {
+    fn new(max_requests_per_second: u32) -> Self {
+        let now = Instant::now();
+        ConnectionRateLimit {
+            requests: Arc::new(Mutex::new(TokenBucket::new(max_requests_per_second, now))),
+            admin_requests: Arc::new(Mutex::new(TokenBucket::new(max_requests_per_second, now))),
+        }
+    }
+
+    /// Returns `false` if the request exceeds the rate limit and has to be rejected
+    pub fn try_acquire(&self) -> bool {
+        Self::try_acquire_from(&self.requests)
+    }
+
+    /// Like [`ConnectionRateLimit::try_acquire`] for requests to the admin API
+    pub fn try_acquire_admin(&self) -> bool {
+        Self::try_acquire_from(&self.admin_requests)
+    }
+
+    fn try_acquire_from(bucket: &Mutex<TokenBucket>) -> bool {
+        let allowed = bucket
+            .lock()
+            .expect("lock poisoned")
+            .try_acquire(Instant::now());
+        if !allowed {
+            debug!("API connection exceeded its request rate limit");
+        }
+        allowed
+    }
+}
+
+thread_local! {
+    /// Rate limit of the connection whose call jsonrpsee is about to pass to its handler
+    static CALLING_CONNECTION: RefCell<Option<ConnectionRateLimit>> = RefCell::new(None);
+}
+
+/// Rate limit of the connection the call that is being dispatched arrived on
+///
+/// Handlers have to call this before they yield for the first time, see [`RateLimitLogger`].
+pub fn calling_connection() -> Option<ConnectionRateLimit> {
+    CALLING_CONNECTION.with(|connection| connection.borrow_mut().take())
+}
+
+/// Passes every RPC handler the rate limit of the connection its call arrived on.
+///
+/// jsonrpsee doesn't tell handlers which connection a call arrived on, and tower middleware only
+/// sees the HTTP request upgrading a connection to a websocket, not the calls made over it. The
+/// server does clone its logger for every connection it accepts though, and notifies the
+/// connection's logger about each call right before invoking the handler on the same thread.
+#[derive(Debug)]
+pub struct RateLimitLogger {
+    max_requests_per_second: u32,
+    /// `None` for the logger the server gets built with, the clones of every connection share
+    /// the limit of the connection
+    connection: Option<ConnectionRateLimit>,
+}
+
+impl RateLimitLogger {
+    pub fn new(max_requests_per_second: u32) -> Self {
+        RateLimitLogger {
+            max_requests_per_second,
+            connection: None,
+        }
+    }
+}
+
+impl Clone for RateLimitLogger {
+    fn clone(&self) -> Self {
+        let connection = self
+            .connection
+            .clone()
+            .unwrap_or_else(|| ConnectionRateLimit::new(self.max_requests_per_second));
+        RateLimitLogger {
+            max_requests_per_second: self.max_requests_per_second,
+            connection: Some(connection),
+        }
+    }
+}
+
+impl Logger for RateLimitLogger {
+    type Instant = ();
+
+    fn on_connect(&self, _: SocketAddr, _: &HttpRequest, _: TransportProtocol) {}
+
+    fn on_request(&self, _: TransportProtocol) -> Self::Instant {}
+
+    fn on_call(&self, _: &str, _: Params, _: MethodKind, _: TransportProtocol) {
+        CALLING_CONNECTION.with(|connection| *connection.borrow_mut() = self.connection.clone());
+    }
+
+    fn on_result(&self, _: &str, _: bool, _: Self::Instant, _: TransportProtocol) {}
+
+    fn on_response(&self, _: &str, _: Self::Instant, _: TransportProtocol) {}
+
+    fn on_disconnect(&self, _: SocketAddr, _: TransportProtocol) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use jsonrpsee::server::logger::{Logger, MethodKind, Params, TransportProtocol};
+
+    use super::{calling_connection, ConnectionRateLimit, RateLimitLogger, TokenBucket};
+
+    #[test]
+    fn token_bucket_limits_request_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, start);
+
+        assert!(bucket.try_acquire(start));
+        assert!(bucket.try_acquire(start));
+        assert!(!bucket.try_acquire(start));
+
+        // Half a second refills one token
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.try_acquire(later));
+        assert!(!bucket.try_acquire(later));
+
+        // The bucket never holds more than one second worth of tokens
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.try_acquire(much_later));
+        assert!(bucket.try_acquire(much_later));
+        assert!(!bucket.try_acquire(much_later));
+    }
+
+    fn call(logger: &RateLimitLogger) -> ConnectionRateLimit {
+        logger.on_call(
+            "/test",
+            Params::new(None),
+            MethodKind::MethodCall,
+            TransportProtocol::WebSocket,
+        );
+        calling_connection().expect("connection of the call is known")
+    }
+
+    #[test]
+    fn every_connection_has_its_own_limit() {
+        let server_logger = RateLimitLogger::new(2);
+        let first_connection = server_logger.clone();
+        let second_connection = server_logger.clone();
+
+        // jsonrpsee clones the logger of a connection for its requests
+        let first_request = first_connection.clone();
+        assert!(call(&first_request).try_acquire());
+        assert!(call(&first_connection).try_acquire());
+        assert!(!call(&first_request).try_acquire());
+
+        assert!(call(&second_connection).try_acquire());
+
+        // the connection is only handed to the handler of the call it belongs to
+        assert!(calling_connection().is_none());
+    }
+
+    #[test]
+    fn admin_calls_have_their_own_limit() {
+        let connection = RateLimitLogger::new(1).clone();
+
+        assert!(call(&connection).try_acquire());
+        assert!(!call(&connection).try_acquire());
+
+        assert!(call(&connection).try_acquire_admin());
+        assert!(!call(&connection).try_acquire_admin());
+    }
+}
@@ -0,0 +1,466 @@
+//! Simulated network connecting peers running in the same process
+//!
+//! [`SimNetwork`] delivers messages between [`SimPeerConnections`] subject to delays, drops,
+//! partitions and byzantine corruption drawn from a seeded RNG, so the schedule of a test only
+//! depends on its seed and the order in which peers send. Delays are measured with [`tokio::time`],
+//! tests running on a paused clock (`#[tokio::test(start_paused = true)]`) thus advance a virtual
+//! clock instead of actually waiting.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fedimint_api::cancellable::Cancellable;
+use fedimint_api::net::peers::IPeerConnections;
+use fedimint_api::PeerId;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::{trace, warn};
+
+/// How many random bit flips are tried to corrupt a message such that it still decodes
+const CORRUPTION_ATTEMPTS: usize = 32;
+
+/// Faults of the messages sent over the link between two peers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkFaults {
+    /// Probability between 0 and 1 that a message gets lost
+    pub drop_rate: f64,
+    /// Minimum time a message takes to arrive
+    pub min_delay: Duration,
+    /// Maximum time a message takes to arrive, messages sent over the same link still arrive in
+    /// the order they were sent like they would over a TCP connection
+    pub max_delay: Duration,
+}
+
+impl Default for LinkFaults {
+    fn default() -> Self {
+        LinkFaults {
+            drop_rate: 0.0,
+            min_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        }
+    }
+}
+
+/// How a byzantine peer's messages are tampered with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByzantineBehavior {
+    /// Every message has a random bit of its encoding flipped, so it still decodes but carries a
+    /// wrong field, messages for which no tried flip decodes are dropped
+    CorruptMessages,
+    /// Every message is sent a second time together with the next one to the same peer
+    ReplayMessages,
+}
+
+/// What happened to the messages sent so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimNetworkStats {
+    /// Messages queued for delivery, including corrupted and replayed ones
+    pub queued: u64,
+    /// Messages lost to link faults, partitions, disconnected receivers or failed decoding
+    pub dropped: u64,
+    /// Messages delivered with altered content
+    pub corrupted: u64,
+}
+
+/// In-process network of peers with configurable faults, see the [module docs](self)
+pub struct SimNetwork<M> {
+    state: Arc<Mutex<SimState>>,
+    _message: PhantomData<fn() -> M>,
+}
+
+struct SimState {
+    rng: StdRng,
+    default_faults: LinkFaults,
+    link_faults: BTreeMap<(PeerId, PeerId), LinkFaults>,
+    /// Groups of peers that can only reach peers of their own group, empty if not partitioned
+    partition: Vec<BTreeSet<PeerId>>,
+    byzantine: BTreeMap<PeerId, ByzantineBehavior>,
+    /// Inboxes of connected peers, peers without inbox are down
+    inboxes: BTreeMap<PeerId, Inbox>,
+    /// Delivery time of the last message per link, later messages are never delivered earlier
+    link_clock: BTreeMap<(PeerId, PeerId), Instant>,
+    /// Last message of replaying peers per link
+    last_sent: BTreeMap<(PeerId, PeerId), Vec<u8>>,
+    next_seq: u64,
+    stats: SimNetworkStats,
+}
+
+struct Inbox {
+    /// Encoded messages by delivery time, the sequence number orders messages due at the same time
+    messages: BTreeMap<(Instant, u64), (PeerId, Vec<u8>)>,
+    /// Wakes up the receiver, also identifies the connection the inbox belongs to
+    notify: Arc<Notify>,
+}
+
+enum Delivery {
+    Ready(PeerId, Vec<u8>),
+    NotBefore(Instant),
+    Empty,
+}
+
+impl<M> Clone for SimNetwork<M> {
+    fn clone(&self) -> Self {
+        SimNetwork {
+            state: self.state.clone(),
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<M> SimNetwork<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    /// Creates a network without faults whose random choices are derived from `seed`
+    pub fn new(seed: u64) -> Self {
+        SimNetwork {
+            state: Arc::new(Mutex::new(SimState {
+                rng: StdRng::seed_from_u64(seed),
+                default_faults: LinkFaults::default(),
+                link_faults: BTreeMap::new(),
+                partition: vec![],
+                byzantine: BTreeMap::new(),
+                inboxes: BTreeMap::new(),
+                link_clock: BTreeMap::new(),
+                last_sent: BTreeMap::new(),
+                next_seq: 0,
+                stats: SimNetworkStats::default(),
+            })),
+            _message: PhantomData,
+        }
+    }
+
+    /// Connects `peer` to the network, replacing its previous connections, e.g. after a restart
+    pub fn connect(&self, peer: PeerId) -> SimPeerConnections<M> {
+        let notify = Arc::new(Notify::new());
+        self.lock().inboxes.insert(
+            peer,
+            Inbox {
+                messages: BTreeMap::new(),
+                notify: notify.clone(),
+            },
+        );
+
+        SimPeerConnections {
+            id: peer,
+            network: self.clone(),
+            notify,
+            banned: BTreeSet::new(),
+        }
+    }
+
+    /// Disconnects a crashed peer, messages in flight to it are lost
+    pub fn disconnect(&self, peer: PeerId) {
+        let mut state = self.lock();
+        if let Some(inbox) = state.inboxes.remove(&peer) {
+            state.stats.dropped += inbox.messages.len() as u64;
+        }
+    }
+
+    /// Sets the faults of all links without faults of their own
+    pub fn set_faults(&self, faults: LinkFaults) {
+        self.lock().default_faults = faults;
+    }
+
+    /// Sets the faults of the link from `from` to `to`
+    pub fn set_link_faults(&self, from: PeerId, to: PeerId, faults: LinkFaults) {
+        self.lock().link_faults.insert((from, to), faults);
+    }
+
+    /// Resets all links to the faults set by [`SimNetwork::set_faults`]
+    pub fn clear_link_faults(&self) {
+        self.lock().link_faults.clear();
+    }
+
+    /// Splits the network into `groups`, messages between peers of different groups are lost
+    pub fn partition(&self, groups: &[&[PeerId]]) {
+        self.lock().partition = groups
+            .iter()
+            .map(|group| group.iter().copied().collect())
+            .collect();
+    }
+
+    /// Reconnects all groups of a partition
+    pub fn heal(&self) {
+        self.lock().partition.clear();
+    }
+
+    /// Makes the messages sent by `peer` misbehave, `None` makes it honest again
+    pub fn set_byzantine(&self, peer: PeerId, behavior: Option<ByzantineBehavior>) {
+        let mut state = self.lock();
+        match behavior {
+            Some(behavior) => state.byzantine.insert(peer, behavior),
+            None => state.byzantine.remove(&peer),
+        };
+    }
+
+    pub fn stats(&self) -> SimNetworkStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().expect("poisoned")
+    }
+
+    fn send(&self, from: PeerId, to: &[PeerId], msg: &M) {
+        let mut state = self.lock();
+        let state = &mut *state;
+
+        let behavior = state.byzantine.get(&from).copied();
+        for &peer in to {
+            let message = match behavior {
+                Some(ByzantineBehavior::CorruptMessages) => {
+                    match corrupt_message(msg, &mut state.rng) {
+                        Some(message) => {
+                            state.stats.corrupted += 1;
+                            message
+                        }
+                        None => {
+                            state.stats.dropped += 1;
+                            continue;
+                        }
+                    }
+                }
+                Some(ByzantineBehavior::ReplayMessages) => {
+                    let message = encode_message(msg);
+                    if let Some(previous) = state.last_sent.insert((from, peer), message.clone()) {
+                        state.enqueue(from, peer, previous);
+                    }
+                    message
+                }
+                None => encode_message(msg),
+            };
+
+            state.enqueue(from, peer, message);
+        }
+    }
+
+    fn next_delivery(&self, peer: PeerId, notify: &Arc<Notify>) -> Delivery {
+        let mut state = self.lock();
+        let inbox = match state.inboxes.get_mut(&peer) {
+            // Connections replaced by a reconnect don't receive anymore
+            Some(inbox) if Arc::ptr_eq(&inbox.notify, notify) => inbox,
+            _ => return Delivery::Empty,
+        };
+
+        match inbox.messages.keys().next().copied() {
+            Some(key @ (deliver_at, _)) if deliver_at <= Instant::now() => {
+                let (from, message) = inbox.messages.remove(&key).expect("key exists");
+                Delivery::Ready(from, message)
+            }
+            Some((deliver_at, _)) => Delivery::NotBefore(deliver_at),
+            None => Delivery::Empty,
+        }
+    }
+}
+
+impl SimState {
+    fn enqueue(&mut self, from: PeerId, to: PeerId, message: Vec<u8>) {
+        let faults = self
+            .link_faults
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(self.default_faults);
+        let partitioned = !self.partition.is_empty()
+            && !self
+                .partition
+                .iter()
+                .any(|group| group.contains(&from) && group.contains(&to));
+
+        if partitioned || !self.inboxes.contains_key(&to) || self.rng.gen_bool(faults.drop_rate) {
+            trace!(%from, %to, "Dropping message");
+            self.stats.dropped += 1;
+            return;
+        }
+
+        let delay = self.rng.gen_range(faults.min_delay..=faults.max_delay);
+        let link_clock = self
+            .link_clock
+            .entry((from, to))
+            .or_insert_with(Instant::now);
+        let deliver_at = (Instant::now() + delay).max(*link_clock);
+        *link_clock = deliver_at;
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.stats.queued += 1;
+
+        let inbox = self.inboxes.get_mut(&to).expect("checked above");
+        inbox.messages.insert((deliver_at, seq), (from, message));
+        inbox.notify.notify_one();
+    }
+}
+
+/// Connections of one peer to the others over a [`SimNetwork`]
+pub struct SimPeerConnections<M> {
+    id: PeerId,
+    network: SimNetwork<M>,
+    notify: Arc<Notify>,
+    banned: BTreeSet<PeerId>,
+}
+
+#[async_trait]
+impl<M> IPeerConnections<M> for SimPeerConnections<M>
+where
+    M: Serialize + DeserializeOwned + Unpin + Send,
+{
+    async fn send(&mut self, peers: &[PeerId], msg: M) -> Cancellable<()> {
+        let peers = peers
+            .iter()
+            .filter(|peer| !self.banned.contains(peer))
+            .copied()
+            .collect::<Vec<_>>();
+        self.network.send(self.id, &peers, &msg);
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Cancellable<(PeerId, M)> {
+        loop {
+            match self.network.next_delivery(self.id, &self.notify) {
+                Delivery::Ready(from, _) if self.banned.contains(&from) => {}
+                Delivery::Ready(from, message) => match bincode::deserialize(&message) {
+                    Ok(msg) => return Ok((from, msg)),
+                    Err(e) => warn!(%from, "Discarding undecodable message: {}", e),
+                },
+                Delivery::NotBefore(deliver_at) => {
+                    tokio::select! {
+                        () = tokio::time::sleep_until(deliver_at) => {},
+                        () = self.notify.notified() => {},
+                    }
+                }
+                Delivery::Empty => self.notify.notified().await,
+            }
+        }
+    }
+
+    async fn ban_peer(&mut self, peer: PeerId) {
+        self.banned.insert(peer);
+    }
+}
+
+fn encode_message<M: Serialize>(msg: &M) -> Vec<u8> {
+    bincode::serialize(msg).expect("Messages can always be encoded")
+}
+
+/// Encodes `msg` with a random bit flipped, returns `None` if none of
+/// [`CORRUPTION_ATTEMPTS`] flips results in a message that still decodes
+fn corrupt_message<M>(msg: &M, rng: &mut StdRng) -> Option<Vec<u8>>
+where
+    M: Serialize + DeserializeOwned,
+{
+    let encoded = encode_message(msg);
+    if encoded.is_empty() {
+        return Some(encoded);
+    }
+
+    (0..CORRUPTION_ATTEMPTS).find_map(|_| {
+        let mut corrupted = encoded.clone();
+        let bit = rng.gen_range(0..corrupted.len() * 8);
+        corrupted[bit / 8] ^= 1 << (bit % 8);
+        bincode::deserialize::<M>(&corrupted)
+            .ok()
+            .map(|_| corrupted)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fedimint_api::net::peers::IPeerConnections;
+    use fedimint_api::PeerId;
+    use tokio::time::Instant;
+
+    use super::{ByzantineBehavior, LinkFaults, SimNetwork};
+
+    #[tokio::test(start_paused = true)]
+    async fn delivers_in_order_after_delay() {
+        let network = SimNetwork::<u64>::new(0);
+        let (a, b) = (PeerId::from(0), PeerId::from(1));
+        let mut conn_a = network.connect(a);
+        let mut conn_b = network.connect(b);
+
+        network.set_faults(LinkFaults {
+            drop_rate: 0.0,
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+        });
+
+        let start = Instant::now();
+        for msg in 0..10 {
+            conn_a.send(&[b], msg).await.unwrap();
+        }
+        for msg in 0..10 {
+            assert_eq!(conn_b.receive().await.unwrap(), (a, msg));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(network.stats().queued, 10);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn partitions_and_disconnects_drop_messages() {
+        let network = SimNetwork::<u64>::new(0);
+        let (a, b) = (PeerId::from(0), PeerId::from(1));
+        let mut conn_a = network.connect(a);
+        let mut conn_b = network.connect(b);
+
+        network.partition(&[&[a], &[b]]);
+        conn_a.send(&[b], 1).await.unwrap();
+        network.heal();
+        conn_a.send(&[b], 2).await.unwrap();
+        assert_eq!(conn_b.receive().await.unwrap(), (a, 2));
+
+        network.disconnect(b);
+        conn_a.send(&[b], 3).await.unwrap();
+        let mut conn_b = network.connect(b);
+        conn_a.send(&[b], 4).await.unwrap();
+        assert_eq!(conn_b.receive().await.unwrap(), (a, 4));
+
+        assert_eq!(network.stats().dropped, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn byzantine_peers_tamper_with_messages() {
+        let network = SimNetwork::<(u64, bool)>::new(0);
+        let (a, b) = (PeerId::from(0), PeerId::from(1));
+        let mut conn_a = network.connect(a);
+        let mut conn_b = network.connect(b);
+
+        network.set_byzantine(a, Some(ByzantineBehavior::CorruptMessages));
+        conn_a.send(&[b], (42, true)).await.unwrap();
+        assert_ne!(conn_b.receive().await.unwrap(), (a, (42, true)));
+
+        network.set_byzantine(a, Some(ByzantineBehavior::ReplayMessages));
+        conn_a.send(&[b], (1, true)).await.unwrap();
+        conn_a.send(&[b], (2, true)).await.unwrap();
+        assert_eq!(conn_b.receive().await.unwrap(), (a, (1, true)));
+        assert_eq!(conn_b.receive().await.unwrap(), (a, (1, true)));
+        assert_eq!(conn_b.receive().await.unwrap(), (a, (2, true)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn same_seed_same_schedule() {
+        let schedule = |seed| {
+            let network = SimNetwork::<u64>::new(seed);
+            network.set_faults(LinkFaults {
+                drop_rate: 0.5,
+                ..LinkFaults::default()
+            });
+            let (a, b) = (PeerId::from(0), PeerId::from(1));
+            let _conn_b = network.connect(b);
+            for msg in 0..100 {
+                network.send(a, &[b], &msg);
+            }
+            network.stats()
+        };
+
+        assert_eq!(schedule(1), schedule(1));
+    }
+}
@@ -1,14 +1,14 @@
 //! Sled implementation of the `Database` trait. It should not be used anymore since it has known
 //! issues and is unmaintained. Please use `rocksdb` instead.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use fedimint_api::db::{
     DatabaseDeleteOperation, DatabaseInsertOperation, DatabaseOperation, DatabaseTransaction,
-    PrefixIter,
+    IterOrder, PrefixIter, ReadDatabaseTransaction,
 };
 use fedimint_api::db::{IDatabase, IDatabaseTransaction};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
@@ -63,6 +63,22 @@ impl IDatabase for SledDb {
         tx.set_tx_savepoint().await;
         tx
     }
+
+    async fn begin_read_transaction(
+        &self,
+        decoders: ModuleDecoderRegistry,
+    ) -> ReadDatabaseTransaction {
+        let sled_tx = SledTransaction {
+            operations: Vec::new(),
+            db: self,
+            num_pending_operations: 0,
+            num_savepoint_operations: 0,
+        };
+        ReadDatabaseTransaction::new(DatabaseTransaction::new(
+            SledReadTransaction(sled_tx),
+            decoders,
+        ))
+    }
 }
 
 // Sled database transaction should only be used for test code and never for production
@@ -113,6 +129,39 @@ impl<'a> IDatabaseTransaction<'a> for SledTransaction<'a> {
             .map(|bytes| bytes.to_vec()))
     }
 
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        // Single pass through the pending writes, `None` marks keys deleted by this transaction
+        let mut pending: HashMap<&[u8], Option<&Vec<u8>>> = HashMap::new();
+        let requested = keys.iter().map(Vec::as_slice).collect::<HashSet<_>>();
+        for op in &self.operations {
+            match op {
+                DatabaseOperation::Insert(insert_op)
+                    if requested.contains(insert_op.key.as_slice()) =>
+                {
+                    pending.insert(&insert_op.key, Some(&insert_op.value));
+                }
+                DatabaseOperation::Delete(delete_op)
+                    if requested.contains(delete_op.key.as_slice()) =>
+                {
+                    pending.insert(&delete_op.key, None);
+                }
+                _ => {}
+            }
+        }
+
+        keys.iter()
+            .map(|key| match pending.get(key.as_slice()) {
+                Some(value) => Ok(value.cloned()),
+                None => Ok(self
+                    .db
+                    .inner()
+                    .get(key)
+                    .map_err(anyhow::Error::from)?
+                    .map(|bytes| bytes.to_vec())),
+            })
+            .collect()
+    }
+
     async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let ret = self.raw_get_bytes(key).await;
         self.operations
@@ -159,6 +208,52 @@ impl<'a> IDatabaseTransaction<'a> for SledTransaction<'a> {
         Box::new(dbscan.into_iter())
     }
 
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        // ranges ending before they start are empty
+        if end.map_or(false, |end| end <= start) {
+            return Box::new(std::iter::empty());
+        }
+
+        let dbscan = match end {
+            Some(end) => self.db.inner().range(start..end),
+            None => self.db.inner().range(start..),
+        };
+        let mut entries = match dbscan
+            .map(|res| {
+                res.map(|(key_bytes, value_bytes)| (key_bytes.to_vec(), value_bytes.to_vec()))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()
+        {
+            Ok(entries) => entries,
+            Err(e) => return Box::new(std::iter::once(Err(anyhow::Error::from(e)))),
+        };
+
+        // Apply pending writes in order to support "read our own writes"
+        let in_range = |key: &[u8]| key >= start && end.map_or(true, |end| key < end);
+        for op in &self.operations {
+            match op {
+                DatabaseOperation::Insert(insert_op) if in_range(&insert_op.key) => {
+                    entries.insert(insert_op.key.clone(), insert_op.value.clone());
+                }
+                DatabaseOperation::Delete(delete_op) if in_range(&delete_op.key) => {
+                    entries.remove(&delete_op.key);
+                }
+                _ => {}
+            }
+        }
+
+        let entries = entries.into_iter().map(Ok);
+        match order {
+            IterOrder::Ascending => Box::new(entries),
+            IterOrder::Descending => Box::new(entries.rev()),
+        }
+    }
+
     async fn commit_tx(self: Box<Self>) -> Result<()> {
         let ret = self
             .db
@@ -195,6 +290,55 @@ impl<'a> IDatabaseTransaction<'a> for SledTransaction<'a> {
     }
 }
 
+/// Read-only transaction without pending writes, sled has no snapshots so like [`SledTransaction`]
+/// it reads the tree as it is at the time of each read
+#[derive(Debug)]
+pub struct SledReadTransaction<'a>(SledTransaction<'a>);
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for SledReadTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, _key: &[u8], _value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot insert into a read only transaction");
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.raw_get_bytes(key).await
+    }
+
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0.raw_get_bytes_batch(keys).await
+    }
+
+    async fn raw_remove_entry(&mut self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot remove from a read only transaction");
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixIter<'_> {
+        self.0.raw_find_by_prefix(key_prefix).await
+    }
+
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        self.0.raw_find_by_range(start, end, order).await
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        panic!("Cannot commit a read only transaction");
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        panic!("Cannot rollback a read only transaction");
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        panic!("Cannot set a savepoint in a read only transaction");
+    }
+}
+
 #[cfg(test)]
 mod fedimint_sled_tests {
     use crate::SledDb;
@@ -239,6 +383,11 @@ mod fedimint_sled_tests {
         .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_get_values() {
+        fedimint_api::db::verify_get_values(open_temp_db("fcb-sled-test-get-values").into()).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_prevent_dirty_reads() {
         fedimint_api::db::verify_prevent_dirty_reads(
@@ -255,6 +404,28 @@ mod fedimint_sled_tests {
         .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_range() {
+        fedimint_api::db::verify_find_by_range(open_temp_db("fcb-sled-test-find-by-range").into())
+            .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix_ordered() {
+        fedimint_api::db::verify_find_by_prefix_ordered(
+            open_temp_db("fcb-sled-test-find-by-prefix-ordered").into(),
+        )
+        .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_read_transaction() {
+        fedimint_api::db::verify_read_transaction(
+            open_temp_db("fcb-sled-test-read-transaction").into(),
+        )
+        .await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_commit() {
         fedimint_api::db::verify_commit(open_temp_db("fcb-sled-test-commit").into()).await;
@@ -275,4 +446,12 @@ mod fedimint_sled_tests {
         )
         .await;
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_apply_migrations() {
+        fedimint_api::db::verify_apply_migrations(
+            open_temp_db("fcb-sled-test-apply-migrations").into(),
+        )
+        .await;
+    }
 }
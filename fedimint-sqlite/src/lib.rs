@@ -1,8 +1,12 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use fedimint_api::db::{DatabaseTransaction, IDatabase, IDatabaseTransaction, PrefixIter};
+use fedimint_api::db::{
+    DatabaseTransaction, IDatabase, IDatabaseTransaction, IterOrder, PrefixIter,
+    ReadDatabaseTransaction,
+};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
 use sqlx::migrate::MigrateDatabase;
 use sqlx::sqlite::SqliteConnectOptions;
@@ -14,6 +18,10 @@ pub struct SqliteDb(SqlitePool);
 
 pub struct SqliteDbTransaction<'a>(Transaction<'a, Sqlite>);
 
+/// Keys looked up per query of a batch, stays below the default limit of 999 bound parameters
+/// of older SQLite versions
+const SQLITE_MAX_BATCH_KEYS: usize = 500;
+
 impl SqliteDb {
     pub async fn open(connection_string: &str) -> Result<SqliteDb, Error> {
         if !Sqlite::database_exists(connection_string)
@@ -59,6 +67,17 @@ impl IDatabase for SqliteDb {
         tx.set_tx_savepoint().await;
         tx
     }
+
+    async fn begin_read_transaction(
+        &self,
+        decoders: ModuleDecoderRegistry,
+    ) -> ReadDatabaseTransaction {
+        let sqlite_dbtx = self.0.begin().await.unwrap();
+        ReadDatabaseTransaction::new(DatabaseTransaction::new(
+            SqliteDbReadTransaction(SqliteDbTransaction(sqlite_dbtx)),
+            decoders,
+        ))
+    }
 }
 
 #[async_trait]
@@ -82,6 +101,29 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
             .map_err(anyhow::Error::from)
     }
 
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        // keys inserted multiple times resolve to the same value as in `raw_get_bytes`
+        let mut values = HashMap::new();
+        for chunk in keys.chunks(SQLITE_MAX_BATCH_KEYS) {
+            let query = format!(
+                "SELECT key, MAX(value) AS value FROM kv WHERE key IN ({}) GROUP BY key",
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut query_prepared = sqlx::query(&query);
+            for key in chunk {
+                query_prepared = query_prepared.bind(key);
+            }
+            for row in self.0.fetch_all(query_prepared).await? {
+                values.insert(
+                    row.get::<Vec<u8>, &str>("key"),
+                    row.get::<Vec<u8>, &str>("value"),
+                );
+            }
+        }
+
+        Ok(keys.iter().map(|key| values.get(key).cloned()).collect())
+    }
+
     async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let query_prepared =
             sqlx::query("SELECT rowid, value FROM kv WHERE key = ? ORDER BY value DESC LIMIT 1")
@@ -125,6 +167,46 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
         Box::new(rows)
     }
 
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        // blobs compare like byte strings, keys inserted multiple times resolve to the same value
+        // as in `raw_get_bytes`
+        let condition = if end.is_some() {
+            "key >= ? AND key < ?"
+        } else {
+            "key >= ?"
+        };
+        let direction = match order {
+            IterOrder::Ascending => "ASC",
+            IterOrder::Descending => "DESC",
+        };
+        let query = format!(
+            "SELECT key, MAX(value) AS value FROM kv WHERE {} GROUP BY key ORDER BY key {}",
+            condition, direction
+        );
+        let mut query_prepared = sqlx::query(&query).bind(start);
+        if let Some(end) = end {
+            query_prepared = query_prepared.bind(end);
+        }
+
+        let results = match self.0.fetch_all(query_prepared).await {
+            Ok(results) => results,
+            Err(e) => return Box::new(std::iter::once(Err(anyhow::Error::from(e)))),
+        };
+        let rows = results.into_iter().map(|row| {
+            Ok((
+                row.get::<Vec<u8>, &str>("key"),
+                row.get::<Vec<u8>, &str>("value"),
+            ))
+        });
+
+        Box::new(rows)
+    }
+
     async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
         let mut str_prefix = "".to_string();
         for prefix in key_prefix {
@@ -152,6 +234,54 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
     }
 }
 
+/// Read-only transaction that sets no savepoint, it only ever takes a read lock on the snapshot
+/// its first read sees and is rolled back when dropped
+pub struct SqliteDbReadTransaction<'a>(SqliteDbTransaction<'a>);
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for SqliteDbReadTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, _key: &[u8], _value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot insert into a read only transaction");
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.raw_get_bytes(key).await
+    }
+
+    async fn raw_get_bytes_batch(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0.raw_get_bytes_batch(keys).await
+    }
+
+    async fn raw_remove_entry(&mut self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
+        panic!("Cannot remove from a read only transaction");
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixIter<'_> {
+        self.0.raw_find_by_prefix(key_prefix).await
+    }
+
+    async fn raw_find_by_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        order: IterOrder,
+    ) -> PrefixIter<'_> {
+        self.0.raw_find_by_range(start, end, order).await
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        panic!("Cannot commit a read only transaction");
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        panic!("Cannot rollback a read only transaction");
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        panic!("Cannot set a savepoint in a read only transaction");
+    }
+}
+
 #[cfg(test)]
 mod fedimint_sqlite_tests {
     use std::fs;
@@ -193,6 +323,11 @@ mod fedimint_sqlite_tests {
             .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_get_values() {
+        fedimint_api::db::verify_get_values(open_temp_db("get-values").await.into()).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_prevent_dirty_reads() {
         fedimint_api::db::verify_prevent_dirty_reads(
@@ -206,6 +341,25 @@ mod fedimint_sqlite_tests {
         fedimint_api::db::verify_find_by_prefix(open_temp_db("find_by_prefix").await.into()).await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_range() {
+        fedimint_api::db::verify_find_by_range(open_temp_db("find_by_range").await.into()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix_ordered() {
+        fedimint_api::db::verify_find_by_prefix_ordered(
+            open_temp_db("find_by_prefix_ordered").await.into(),
+        )
+        .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_read_transaction() {
+        fedimint_api::db::verify_read_transaction(open_temp_db("read_transaction").await.into())
+            .await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_commit() {
         fedimint_api::db::verify_commit(open_temp_db("commit").await.into()).await;
@@ -250,4 +404,10 @@ mod fedimint_sqlite_tests {
         )
         .await;
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_apply_migrations() {
+        fedimint_api::db::verify_apply_migrations(open_temp_db("apply-migrations").await.into())
+            .await;
+    }
 }
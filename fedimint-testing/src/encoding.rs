@@ -0,0 +1,43 @@
+//! Checks of the consensus encoding shared by property tests and fuzz targets
+
+use std::fmt::Debug;
+use std::io::Cursor;
+
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+/// Asserts that `value` decodes from its encoding again and that the encoding reports its length
+/// correctly
+pub fn assert_roundtrip<T>(value: &T, modules: &ModuleDecoderRegistry)
+where
+    T: Encodable + Decodable + Eq + Debug,
+{
+    let mut bytes = Vec::new();
+    let len = value.consensus_encode(&mut bytes).expect("can't fail");
+    assert_eq!(len, bytes.len());
+
+    let mut cursor = Cursor::new(&bytes);
+    let decoded = T::consensus_decode(&mut cursor, modules).expect("roundtrip decodes");
+    assert_eq!(value, &decoded);
+    assert_eq!(cursor.position(), len as u64);
+}
+
+/// Decodes arbitrary `bytes` and, if they decode, asserts that they start with the canonical
+/// encoding of the decoded value, so no two encodings decode to the same value
+///
+/// Returns the decoded value, if any, for further checks.
+pub fn assert_canonical_decoding<T>(bytes: &[u8], modules: &ModuleDecoderRegistry) -> Option<T>
+where
+    T: Encodable + Decodable + Debug,
+{
+    let mut cursor = Cursor::new(bytes);
+    let decoded = T::consensus_decode(&mut cursor, modules).ok()?;
+    let consumed = &bytes[..cursor.position() as usize];
+    assert_eq!(
+        decoded.consensus_encode_to_vec().expect("can't fail"),
+        consumed,
+        "{:?} has more than one encoding",
+        decoded
+    );
+    Some(decoded)
+}
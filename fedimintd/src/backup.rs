@@ -0,0 +1,692 @@
+//! Encrypted backups of a guardian's database
+//!
+//! A backup contains every database entry, read in a single database transaction so it is a
+//! consistent snapshot even while consensus keeps committing epochs. After a short plaintext
+//! preamble the archive consists of frames, each prefixed by its length and encrypted with the key
+//! derived from the guardian password (see [`crate::encrypt`]). Frames are numbered and carry the
+//! random id of their backup, so a restore fails if frames got reordered, dropped or mixed
+//! between backups.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, ensure, format_err, Context};
+use bitcoin::hashes::{sha256, Hash};
+use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::task::TaskHandle;
+use fedimint_api::PeerId;
+use fedimint_core::all_decoders;
+use fedimint_server::config::ServerConfig;
+use rand::Rng;
+use ring::aead::LessSafeKey;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// File extension of backup archives
+pub const BACKUP_EXT: &str = "fmbackup";
+
+/// Identifies backup archives, followed by the version of their format
+const BACKUP_MAGIC: &[u8; 8] = b"FMBACKUP";
+
+const BACKUP_VERSION: u8 = 1;
+
+/// Plaintext size of the entries after which a frame is written, a frame holds at least one entry
+const FRAME_TARGET_BYTES: usize = 1024 * 1024;
+
+/// Frames worth of entries read ahead of the thread encrypting and writing them
+const BACKUP_CHANNEL_FRAMES: usize = 4;
+
+/// Maximum size of an encrypted frame, so a corrupted length prefix can't exhaust our memory
+const MAX_FRAME_BYTES: u32 = 64 * 1024 * 1024;
+
+/// Tree of sled databases
+const SLED_TREE: &str = "mint";
+
+/// Ties a backup to the guardian and federation it was taken of
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct BackupHeader {
+    pub peer: PeerId,
+    /// Hash of the consensus config, changes if the federation or its keys do
+    pub config_hash: sha256::Hash,
+    /// Seconds since the unix epoch when the backup was taken
+    pub timestamp: u64,
+}
+
+impl BackupHeader {
+    fn new(cfg: &ServerConfig) -> BackupHeader {
+        BackupHeader {
+            peer: cfg.local.identity,
+            config_hash: config_hash(cfg),
+            timestamp: unix_time(),
+        }
+    }
+
+    /// Checks that the backup was taken of the guardian with the config `cfg`
+    pub fn verify(&self, cfg: &ServerConfig) -> anyhow::Result<()> {
+        ensure!(
+            self.peer == cfg.local.identity,
+            "Backup is of guardian {}, not of guardian {}",
+            self.peer,
+            cfg.local.identity
+        );
+        ensure!(
+            self.config_hash == config_hash(cfg),
+            "Backup was taken with a different consensus config"
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Encodable, Decodable)]
+enum FrameContent {
+    Header(BackupHeader),
+    Entries(Vec<(Vec<u8>, Vec<u8>)>),
+    /// Last frame of a complete backup with the total number of entries
+    End(u64),
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct Frame {
+    backup_id: [u8; 32],
+    index: u64,
+    content: FrameContent,
+}
+
+struct FrameWriter<'a, W> {
+    writer: W,
+    key: &'a LessSafeKey,
+    backup_id: [u8; 32],
+    index: u64,
+}
+
+impl<'a, W: Write> FrameWriter<'a, W> {
+    fn new(mut writer: W, key: &'a LessSafeKey) -> anyhow::Result<Self> {
+        writer.write_all(BACKUP_MAGIC)?;
+        writer.write_all(&[BACKUP_VERSION])?;
+        Ok(FrameWriter {
+            writer,
+            key,
+            backup_id: rand::thread_rng().gen(),
+            index: 0,
+        })
+    }
+
+    fn write(&mut self, content: FrameContent) -> anyhow::Result<()> {
+        let frame = Frame {
+            backup_id: self.backup_id,
+            index: self.index,
+            content,
+        };
+        let encrypted = aead::encrypt(frame.consensus_encode_to_vec()?, self.key)?;
+        let len = u32::try_from(encrypted.len())
+            .ok()
+            .filter(|len| *len <= MAX_FRAME_BYTES)
+            .context("Database entry is too large for a backup")?;
+
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(&encrypted)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> anyhow::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+struct FrameReader<'a, R> {
+    reader: R,
+    key: &'a LessSafeKey,
+    backup_id: Option<[u8; 32]>,
+    index: u64,
+}
+
+impl<'a, R: Read> FrameReader<'a, R> {
+    fn new(mut reader: R, key: &'a LessSafeKey) -> anyhow::Result<Self> {
+        let mut magic = [0; BACKUP_MAGIC.len() + 1];
+        reader
+            .read_exact(&mut magic)
+            .context("Not a backup archive")?;
+        ensure!(
+            magic[..BACKUP_MAGIC.len()] == BACKUP_MAGIC[..],
+            "Not a backup archive"
+        );
+        let version = magic[BACKUP_MAGIC.len()];
+        ensure!(
+            version == BACKUP_VERSION,
+            "Unsupported backup version {}",
+            version
+        );
+
+        Ok(FrameReader {
+            reader,
+            key,
+            backup_id: None,
+            index: 0,
+        })
+    }
+
+    fn next(&mut self) -> anyhow::Result<FrameContent> {
+        let mut len = [0; 4];
+        self.reader
+            .read_exact(&mut len)
+            .context("Backup archive is truncated")?;
+        let len = u32::from_be_bytes(len);
+        ensure!(
+            len <= MAX_FRAME_BYTES,
+            "Backup frame {} is too large",
+            self.index
+        );
+
+        let mut encrypted = vec![0; len as usize];
+        self.reader
+            .read_exact(&mut encrypted)
+            .context("Backup archive is truncated")?;
+        let plaintext = aead::decrypt(&mut encrypted, self.key)
+            .context("Could not decrypt backup, was it taken with another password?")?;
+        let frame = Frame::consensus_decode(&mut &plaintext[..], &ModuleDecoderRegistry::default())
+            .map_err(|e| format_err!("Invalid backup frame {}: {}", self.index, e))?;
+
+        ensure!(
+            frame.index == self.index,
+            "Backup frame {} is out of order",
+            self.index
+        );
+        let backup_id = *self.backup_id.get_or_insert(frame.backup_id);
+        ensure!(
+            frame.backup_id == backup_id,
+            "Backup frame {} belongs to another backup",
+            self.index
+        );
+        self.index += 1;
+
+        Ok(frame.content)
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        let mut rest = [0; 1];
+        ensure!(
+            self.reader.read(&mut rest)? == 0,
+            "Backup archive continues after its end"
+        );
+        Ok(())
+    }
+}
+
+/// Writes every entry of `db` into an encrypted backup archive, returns the number of entries
+/// and the writer
+///
+/// Entries are read on the async runtime while encrypting and writing the frames runs on a
+/// blocking thread, so slow disks don't stall consensus.
+pub async fn write_backup<W: Write + Send + 'static>(
+    db: &Database,
+    cfg: &ServerConfig,
+    key: Arc<LessSafeKey>,
+    writer: W,
+) -> anyhow::Result<(u64, W)> {
+    let header = BackupHeader::new(cfg);
+    let (entries_tx, mut entries_rx) = mpsc::channel(BACKUP_CHANNEL_FRAMES);
+    let frame_writer = tokio::task::spawn_blocking(move || {
+        let mut frames = FrameWriter::new(writer, &key)?;
+        frames.write(FrameContent::Header(header))?;
+        let mut num_entries = 0;
+        while let Some(entries) = entries_rx.blocking_recv() {
+            num_entries += entries.len() as u64;
+            frames.write(FrameContent::Entries(entries))?;
+        }
+        frames.write(FrameContent::End(num_entries))?;
+        Ok::<_, anyhow::Error>((num_entries, frames.finish()?))
+    });
+
+    let read_result = send_entries(db, entries_tx).await;
+    // the writer's error explains why it stopped receiving entries
+    let written = frame_writer.await.context("Backup writer panicked")??;
+    read_result?;
+    Ok(written)
+}
+
+/// Reads every entry of `db` in one transaction and sends them in batches of about a frame
+async fn send_entries(
+    db: &Database,
+    entries_tx: mpsc::Sender<Vec<(Vec<u8>, Vec<u8>)>>,
+) -> anyhow::Result<()> {
+    let mut dbtx = db.begin_read_transaction(all_decoders()).await;
+    let mut entries = vec![];
+    let mut entries_bytes = 0;
+    for entry in dbtx.raw_find_by_prefix(&[]).await {
+        let (db_key, value) = entry?;
+        entries_bytes += db_key.len() + value.len();
+        entries.push((db_key, value));
+
+        if entries_bytes >= FRAME_TARGET_BYTES {
+            entries_tx
+                .send(std::mem::take(&mut entries))
+                .await
+                .map_err(|_| format_err!("Backup writer stopped"))?;
+            entries_bytes = 0;
+        }
+    }
+    if !entries.is_empty() {
+        entries_tx
+            .send(entries)
+            .await
+            .map_err(|_| format_err!("Backup writer stopped"))?;
+    }
+    Ok(())
+}
+
+/// Writes a backup of `db` into a new file in `dir` named after the time it was taken
+pub async fn write_backup_file(
+    db: &Database,
+    cfg: &ServerConfig,
+    key: Arc<LessSafeKey>,
+    dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir
+        .join(format!("backup-{}", unix_time()))
+        .with_extension(BACKUP_EXT);
+
+    // an interrupted backup must not look like a complete one
+    let tmp_path = path.with_extension("tmp");
+    let writer = BufWriter::new(File::create(&tmp_path)?);
+    let (_, writer) = write_backup(db, cfg, key, writer).await?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    tokio::task::spawn_blocking(move || file.sync_all()).await??;
+    std::fs::rename(&tmp_path, &path)?;
+
+    Ok(path)
+}
+
+/// Writes a backup of `db` into `dir` every `interval` until the task group shuts down, keeping
+/// only the newest `retention` backups
+pub async fn run_periodic_backups(
+    db: Database,
+    cfg: ServerConfig,
+    key: LessSafeKey,
+    dir: PathBuf,
+    interval: Duration,
+    retention: NonZeroUsize,
+    task_handle: TaskHandle,
+) {
+    let key = Arc::new(key);
+    let mut shutdown_rx = task_handle.make_shutdown_rx().await;
+    while !task_handle.is_shutting_down() {
+        match write_backup_file(&db, &cfg, key.clone(), &dir).await {
+            Ok(path) => {
+                info!(?path, "Wrote database backup");
+                if let Err(e) = prune_backups(&dir, retention) {
+                    warn!("Failed to delete old database backups: {:#}", e);
+                }
+            }
+            Err(e) => warn!("Failed to write database backup: {:#}", e),
+        }
+
+        tokio::select! {
+            _ = fedimint_api::task::sleep(interval) => {},
+            _ = &mut shutdown_rx => break,
+        }
+    }
+}
+
+/// Deletes the oldest backup archives in `dir` so only the newest `retention` remain
+fn prune_backups(dir: &Path, retention: NonZeroUsize) -> anyhow::Result<()> {
+    let mut backups = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let timestamp = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("backup-"))
+            .and_then(|name| name.strip_suffix(&format!(".{}", BACKUP_EXT)))
+            .and_then(|timestamp| timestamp.parse::<u64>().ok());
+        if let Some(timestamp) = timestamp {
+            backups.push((timestamp, path));
+        }
+    }
+
+    backups.sort_unstable();
+    let num_old = backups.len().saturating_sub(retention.get());
+    for (_, path) in backups.into_iter().take(num_old) {
+        std::fs::remove_file(&path)?;
+        info!(?path, "Deleted old database backup");
+    }
+    Ok(())
+}
+
+/// Restores a backup archive written by [`write_backup`] into the empty database `db`, returns
+/// the number of entries
+///
+/// The archive has to be of the guardian with the config `cfg`, nothing is written unless the
+/// whole archive is valid.
+pub async fn restore_backup(
+    db: &Database,
+    cfg: &ServerConfig,
+    key: &LessSafeKey,
+    reader: impl Read,
+) -> anyhow::Result<u64> {
+    let mut frames = FrameReader::new(reader, key)?;
+    let header = match frames.next()? {
+        FrameContent::Header(header) => header,
+        _ => bail!("Backup archive has no header"),
+    };
+    header.verify(cfg)?;
+
+    let mut dbtx = db.begin_transaction(all_decoders()).await;
+    ensure_empty(&mut dbtx).await?;
+
+    let mut num_entries = 0;
+    loop {
+        match frames.next()? {
+            FrameContent::Entries(entries) => {
+                for (db_key, value) in entries {
+                    dbtx.raw_insert_bytes(&db_key, value).await?;
+                    num_entries += 1;
+                }
+            }
+            FrameContent::End(expected_entries) => {
+                ensure!(
+                    expected_entries == num_entries,
+                    "Backup archive is missing entries"
+                );
+                break;
+            }
+            FrameContent::Header(_) => bail!("Backup archive has more than one header"),
+        }
+    }
+    frames.finish()?;
+
+    dbtx.commit_tx().await?;
+    info!(
+        num_entries,
+        timestamp = header.timestamp,
+        "Restored database backup"
+    );
+    Ok(num_entries)
+}
+
+/// Copies every entry of `from` into the empty database `to`, returns the number of entries
+pub async fn copy_database(from: &Database, to: &Database) -> anyhow::Result<u64> {
+    let mut to_dbtx = to.begin_transaction(all_decoders()).await;
+    ensure_empty(&mut to_dbtx).await?;
+
+    let mut from_dbtx = from.begin_read_transaction(all_decoders()).await;
+    let mut num_entries = 0;
+    for entry in from_dbtx.raw_find_by_prefix(&[]).await {
+        let (db_key, value) = entry?;
+        to_dbtx.raw_insert_bytes(&db_key, value).await?;
+        num_entries += 1;
+    }
+
+    to_dbtx.commit_tx().await?;
+    Ok(num_entries)
+}
+
+async fn ensure_empty(dbtx: &mut DatabaseTransaction<'_>) -> anyhow::Result<()> {
+    ensure!(
+        dbtx.raw_find_by_prefix(&[]).await.next().is_none(),
+        "The database is not empty"
+    );
+    Ok(())
+}
+
+/// Database implementations backups can be restored and copied into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DbBackend {
+    Rocksdb,
+    Sled,
+    Sqlite,
+}
+
+impl DbBackend {
+    /// Opens the database at `path`, creating it if it doesn't exist
+    pub async fn open(self, path: &Path) -> anyhow::Result<Database> {
+        Ok(match self {
+            DbBackend::Rocksdb => fedimint_rocksdb::RocksDb::open(path)?.into(),
+            DbBackend::Sled => fedimint_sled::SledDb::open(path, SLED_TREE)?.into(),
+            DbBackend::Sqlite => {
+                fedimint_sqlite::SqliteDb::open(&format!("sqlite://{}", path.display()))
+                    .await?
+                    .into()
+            }
+        })
+    }
+}
+
+/// Hash of the consensus config a backup has to be restored with
+fn config_hash(cfg: &ServerConfig) -> sha256::Hash {
+    let json = serde_json::to_vec(&cfg.consensus).expect("Config serializes");
+    sha256::Hash::hash(&json)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::num::NonZeroUsize;
+    use std::sync::Arc;
+
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use fedimint_api::{sats, PeerId};
+    use fedimint_core::all_decoders;
+    use fedimint_server::config::{ModuleConfigGens, ServerConfig, ServerConfigParams};
+    use rand::rngs::OsRng;
+    use ring::aead::{LessSafeKey, UnboundKey, CHACHA20_POLY1305};
+
+    use super::{prune_backups, restore_backup, write_backup, BACKUP_EXT, BACKUP_MAGIC};
+
+    const PREAMBLE_LEN: usize = BACKUP_MAGIC.len() + 1;
+
+    fn configs() -> BTreeMap<PeerId, ServerConfig> {
+        let peers = [PeerId::from(0), PeerId::from(1)];
+        let params =
+            ServerConfigParams::gen_local(&peers, sats(100_000), 4000, "test", "127.0.0.1:18443");
+        ServerConfig::trusted_dealer_gen("", &peers, &params, ModuleConfigGens::new(), OsRng)
+    }
+
+    fn key(byte: u8) -> Arc<LessSafeKey> {
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &[byte; 32]).expect("valid key length");
+        Arc::new(LessSafeKey::new(key))
+    }
+
+    /// Database whose entries span two entry frames
+    async fn database() -> Database {
+        let db: Database = MemDatabase::new().into();
+        let mut dbtx = db.begin_transaction(all_decoders()).await;
+        for i in 0..3u8 {
+            dbtx.raw_insert_bytes(&[0x42, i], vec![i; 600 * 1024])
+                .await
+                .unwrap();
+        }
+        dbtx.raw_insert_bytes(&[0x43], vec![]).await.unwrap();
+        dbtx.commit_tx().await.unwrap();
+        db
+    }
+
+    async fn entries(db: &Database) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut dbtx = db.begin_read_transaction(all_decoders()).await;
+        dbtx.raw_find_by_prefix(&[])
+            .await
+            .map(|entry| entry.unwrap())
+            .collect()
+    }
+
+    async fn backup(db: &Database, cfg: &ServerConfig) -> Vec<u8> {
+        write_backup(db, cfg, key(1), vec![]).await.unwrap().1
+    }
+
+    /// Splits an archive into its frames including their length prefixes
+    fn split_frames(archive: &[u8]) -> Vec<Vec<u8>> {
+        let mut rest = &archive[PREAMBLE_LEN..];
+        let mut frames = vec![];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (frame, tail) = rest.split_at(4 + len);
+            frames.push(frame.to_vec());
+            rest = tail;
+        }
+        frames
+    }
+
+    fn join_frames(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut archive = BACKUP_MAGIC.to_vec();
+        archive.push(super::BACKUP_VERSION);
+        archive.extend(frames.concat());
+        archive
+    }
+
+    /// Restores `archive` into an empty database, which has to stay empty if the restore fails
+    async fn restore(cfg: &ServerConfig, key: &LessSafeKey, archive: &[u8]) -> anyhow::Result<u64> {
+        let db: Database = MemDatabase::new().into();
+        let result = restore_backup(&db, cfg, key, archive).await;
+        if result.is_err() {
+            assert!(entries(&db).await.is_empty());
+        }
+        result
+    }
+
+    async fn assert_restore_fails(cfg: &ServerConfig, archive: &[u8], error: &str) {
+        let err = restore(cfg, &key(1), archive).await.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains(error),
+            "expected '{}', got '{:#}'",
+            error,
+            err
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn restores_written_backup() {
+        let cfgs = configs();
+        let db = database().await;
+        let archive = backup(&db, &cfgs[&PeerId::from(0)]).await;
+        // header, two entry frames and the end
+        assert_eq!(split_frames(&archive).len(), 4);
+
+        let restored: Database = MemDatabase::new().into();
+        let num_entries = restore_backup(&restored, &cfgs[&PeerId::from(0)], &key(1), &archive[..])
+            .await
+            .unwrap();
+        assert_eq!(num_entries, 4);
+        assert_eq!(entries(&restored).await, entries(&db).await);
+
+        let err = restore_backup(&restored, &cfgs[&PeerId::from(0)], &key(1), &archive[..])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not empty"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn rejects_wrong_password() {
+        let cfgs = configs();
+        let cfg = &cfgs[&PeerId::from(0)];
+        let archive = backup(&database().await, cfg).await;
+
+        let err = restore(cfg, &key(2), &archive).await.unwrap_err();
+        assert!(err.to_string().contains("another password"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn rejects_truncated_backup() {
+        let cfgs = configs();
+        let cfg = &cfgs[&PeerId::from(0)];
+        let archive = backup(&database().await, cfg).await;
+        let frames = split_frames(&archive);
+
+        assert_restore_fails(cfg, &archive[..archive.len() - 1], "truncated").await;
+        assert_restore_fails(cfg, &archive[..PREAMBLE_LEN + 2], "truncated").await;
+        assert_restore_fails(cfg, &archive[..4], "Not a backup archive").await;
+        // missing frames at the end or in the middle
+        assert_restore_fails(cfg, &join_frames(&frames[..3]), "truncated").await;
+        assert_restore_fails(
+            cfg,
+            &join_frames(&[frames[0].clone(), frames[1].clone(), frames[3].clone()]),
+            "out of order",
+        )
+        .await;
+
+        let mut extended = archive.clone();
+        extended.extend(&frames[3]);
+        assert_restore_fails(cfg, &extended, "continues after its end").await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn rejects_reordered_frames() {
+        let cfgs = configs();
+        let cfg = &cfgs[&PeerId::from(0)];
+        let mut frames = split_frames(&backup(&database().await, cfg).await);
+
+        frames.swap(1, 2);
+        assert_restore_fails(cfg, &join_frames(&frames), "out of order").await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn rejects_frames_of_another_backup() {
+        let cfgs = configs();
+        let cfg = &cfgs[&PeerId::from(0)];
+        let db = database().await;
+        let mut frames = split_frames(&backup(&db, cfg).await);
+        let other_frames = split_frames(&backup(&db, cfg).await);
+
+        frames[2] = other_frames[2].clone();
+        assert_restore_fails(cfg, &join_frames(&frames), "belongs to another backup").await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn rejects_backup_of_other_guardian() {
+        let cfgs = configs();
+        let archive = backup(&database().await, &cfgs[&PeerId::from(0)]).await;
+
+        assert_restore_fails(&cfgs[&PeerId::from(1)], &archive, "is of guardian").await;
+
+        let other_federation = configs();
+        assert_restore_fails(
+            &other_federation[&PeerId::from(0)],
+            &archive,
+            "different consensus config",
+        )
+        .await;
+    }
+
+    #[test]
+    fn prunes_oldest_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = |timestamp: u64| {
+            dir.path()
+                .join(format!("backup-{}", timestamp))
+                .with_extension(BACKUP_EXT)
+        };
+        for timestamp in [900, 1000, 1100, 1200] {
+            std::fs::write(backup_path(timestamp), []).unwrap();
+        }
+        let unrelated = [dir.path().join("backup-800.tmp"), dir.path().join("notes")];
+        for path in &unrelated {
+            std::fs::write(path, []).unwrap();
+        }
+
+        prune_backups(dir.path(), NonZeroUsize::new(2).unwrap()).unwrap();
+        assert!(!backup_path(900).exists());
+        assert!(!backup_path(1000).exists());
+        assert!(backup_path(1100).exists());
+        assert!(backup_path(1200).exists());
+        assert!(unrelated.iter().all(|path| path.exists()));
+
+        prune_backups(dir.path(), NonZeroUsize::new(5).unwrap()).unwrap();
+        assert!(backup_path(1100).exists());
+        assert!(backup_path(1200).exists());
+    }
+}
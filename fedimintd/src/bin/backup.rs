@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use clap::{Parser, Subcommand};
+use fedimint_api::db::Database;
+use fedimintd::backup::{copy_database, restore_backup, write_backup_file, DbBackend};
+use fedimintd::encrypt::*;
+use fedimintd::*;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes an encrypted backup of the database of a stopped guardian, running guardians write
+    /// backups if started with `--backup-dir`
+    Create {
+        /// Directory of the guardian's configs and database
+        cfg_path: PathBuf,
+
+        /// Directory to write the backup to
+        #[arg(long = "out-dir")]
+        dir_out_path: PathBuf,
+
+        /// The password that encrypts the configs, will prompt if not passed in
+        #[arg(long, env = "FM_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Restores an encrypted backup into an empty database after checking it was taken of the
+    /// guardian with the configs in `cfg_path`
+    Restore {
+        /// Directory of the guardian's configs
+        cfg_path: PathBuf,
+
+        /// Backup archive to restore
+        backup: PathBuf,
+
+        /// Database to restore into, defaults to the guardian's database in `cfg_path`
+        #[arg(long = "db-path")]
+        db_path: Option<PathBuf>,
+
+        /// Backend of the database to restore into
+        #[arg(long, value_enum, default_value_t = DbBackend::Rocksdb)]
+        backend: DbBackend,
+
+        /// The password that encrypts the configs, will prompt if not passed in
+        #[arg(long, env = "FM_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Copies all entries of a database into an empty database, possibly of another backend
+    Copy {
+        /// Database to copy from, must not be in use
+        from: PathBuf,
+
+        /// Database to copy into
+        to: PathBuf,
+
+        #[arg(long = "from-backend", value_enum, default_value_t = DbBackend::Rocksdb)]
+        from_backend: DbBackend,
+
+        #[arg(long = "to-backend", value_enum, default_value_t = DbBackend::Rocksdb)]
+        to_backend: DbBackend,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    match Cli::parse().command {
+        Command::Create {
+            cfg_path,
+            dir_out_path,
+            password,
+        } => {
+            let key = get_key(password, cfg_path.join(SALT_FILE));
+            let cfg = read_server_configs(&key, cfg_path.clone());
+            let db: Database = fedimint_rocksdb::RocksDb::open(cfg_path.join(DB_FILE))
+                .context("Error opening DB, is the guardian still running?")?
+                .into();
+
+            let path = write_backup_file(&db, &cfg, Arc::new(key), &dir_out_path).await?;
+            println!("{}", path.display());
+        }
+        Command::Restore {
+            cfg_path,
+            backup,
+            db_path,
+            backend,
+            password,
+        } => {
+            let key = get_key(password, cfg_path.join(SALT_FILE));
+            let cfg = read_server_configs(&key, cfg_path.clone());
+            let db = backend
+                .open(&db_path.unwrap_or_else(|| cfg_path.join(DB_FILE)))
+                .await?;
+
+            let reader = BufReader::new(File::open(backup)?);
+            let num_entries = restore_backup(&db, &cfg, &key, reader).await?;
+            println!("Restored {} entries", num_entries);
+        }
+        Command::Copy {
+            from,
+            to,
+            from_backend,
+            to_backend,
+        } => {
+            ensure!(from.exists(), "{} does not exist", from.display());
+            let from = from_backend.open(&from).await?;
+            let to = to_backend.open(&to).await?;
+
+            let num_entries = copy_database(&from, &to).await?;
+            println!("Copied {} entries", num_entries);
+        }
+    }
+
+    Ok(())
+}
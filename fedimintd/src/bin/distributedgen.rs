@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::io::{Read, Write};
 use std::net::SocketAddr;
@@ -7,6 +7,7 @@ use std::sync::Arc;
 
 use clap::{Parser, Subcommand};
 use fedimint_api::cancellable::Cancellable;
+use fedimint_api::config::BitcoinRpcKind;
 use fedimint_api::module::FederationModuleConfigGen;
 use fedimint_api::net::peers::IMuxPeerConnections;
 use fedimint_api::task::TaskGroup;
@@ -14,9 +15,11 @@ use fedimint_api::{Amount, PeerId};
 use fedimint_core::modules::ln::LightningModuleConfigGen;
 use fedimint_core::modules::mint::MintConfigGenerator;
 use fedimint_server::config::{
-    ModuleConfigGens, PeerServerParams, ServerConfig, ServerConfigParams,
+    ModuleConfigGens, PeerServerParams, ServerConfig, ServerConfigConsensus, ServerConfigParams,
 };
 use fedimint_server::multiplexed::PeerConnectionMultiplexer;
+use fedimint_server::net::admin::{AdminPasswordHash, ADMIN_PASSWORD_ITERATIONS};
+use fedimint_wallet::config::WalletConfigConsensus;
 use fedimint_wallet::WalletConfigGenerator;
 use fedimintd::encrypt::*;
 use fedimintd::*;
@@ -86,6 +89,11 @@ enum Command {
         #[arg(long = "bitcoind-rpc", default_value = "127.0.0.1:18443")]
         bitcoind_rpc: String,
 
+        /// Backend serving `--bitcoind-rpc`: `bitcoind`, `esplora` or `electrum`, which only
+        /// supports regtest and signet
+        #[arg(long = "bitcoin-rpc-kind", default_value = "bitcoind")]
+        bitcoin_rpc_kind: BitcoinRpcKind,
+
         /// Max denomination of notes issued by the federation (in millisats)
         /// default = 1 BTC
         #[arg(long = "max_denomination", default_value = "100000000000")]
@@ -105,6 +113,65 @@ enum Command {
         password: Option<String>,
     },
 
+    /// All guardians of the new federation must run this at the same time to reshare the keys of
+    /// an existing federation, e.g. to add, remove or replace guardians
+    ///
+    /// Continuing guardians keep their peer ids and have to pass `--old-config-dir`, joining
+    /// guardians have to pass `--old-consensus` and get the freed up ids of removed guardians
+    /// first. Joining guardians need a copy of the database of a continuing guardian before
+    /// starting, and clients need to download the new client config afterwards.
+    Reshare {
+        /// Directory to output all the generated config files, joining guardians pass the output
+        /// of `create-cert`
+        #[arg(long = "out-dir")]
+        dir_out_path: PathBuf,
+
+        /// Config directory of a continuing guardian
+        #[arg(long = "old-config-dir")]
+        old_config_dir: Option<PathBuf>,
+
+        /// Consensus config of the old federation, only needed by joining guardians
+        #[arg(long = "old-consensus")]
+        old_consensus: Option<PathBuf>,
+
+        /// Address we bind to for exposing the API
+        #[arg(long = "bind-api", default_value = "127.0.0.1:8173")]
+        bind_api: SocketAddr,
+
+        /// Address we bind to for federation communication
+        #[arg(long = "bind-p2p", default_value = "127.0.0.1:8174")]
+        bind_p2p: SocketAddr,
+
+        /// Comma-separated list of ids of guardians leaving the federation
+        #[arg(long = "remove-peers", value_delimiter = ',')]
+        remove_peers: Vec<u16>,
+
+        /// Comma-separated list of connection certs of guardians joining the federation
+        #[arg(long = "certs", value_delimiter = ',')]
+        certs: Vec<String>,
+
+        /// `bitcoind` json rpc endpoint
+        #[arg(long = "bitcoind-rpc", default_value = "127.0.0.1:18443")]
+        bitcoind_rpc: String,
+
+        /// Backend serving `--bitcoind-rpc`: `bitcoind`, `esplora` or `electrum`, which only
+        /// supports regtest and signet
+        #[arg(long = "bitcoin-rpc-kind", default_value = "bitcoind")]
+        bitcoin_rpc_kind: BitcoinRpcKind,
+
+        /// The password that encrypts the configs, will prompt if not passed in
+        #[arg(env = "FM_PASSWORD")]
+        password: Option<String>,
+    },
+
+    /// Prints the salted hash of a password for the admin API, which has to be set as
+    /// `admin_password_hash` in the local config to enable the admin API
+    HashAdminPassword {
+        /// The admin password, will prompt if not passed in
+        #[arg(env = "FM_ADMIN_PASSWORD")]
+        password: Option<String>,
+    },
+
     ConfigDecrypt {
         /// Encrypted config file
         #[arg(long = "in-file")]
@@ -177,6 +244,7 @@ async fn main() {
             bind_p2p,
             bind_api,
             bitcoind_rpc,
+            bitcoin_rpc_kind,
             max_denomination,
             network,
             finality_delay,
@@ -192,6 +260,7 @@ async fn main() {
                 federation_name,
                 certs,
                 bitcoind_rpc,
+                bitcoin_rpc_kind,
                 network,
                 finality_delay,
                 rustls::PrivateKey(pk_bytes),
@@ -208,9 +277,75 @@ async fn main() {
             encrypted_json_write(&server.private, &key, dir_out_path.join(PRIVATE_CONFIG));
             write_nonprivate_configs(&server, dir_out_path, &module_config_gens);
         }
+        Command::Reshare {
+            dir_out_path,
+            old_config_dir,
+            old_consensus,
+            bind_api,
+            bind_p2p,
+            remove_peers,
+            certs,
+            bitcoind_rpc,
+            bitcoin_rpc_kind,
+            password,
+        } => {
+            // continuing guardians may write their new configs to a fresh directory
+            if let Some(old_config_dir) = &old_config_dir {
+                if !dir_out_path.join(SALT_FILE).exists() {
+                    fs::copy(old_config_dir.join(SALT_FILE), dir_out_path.join(SALT_FILE))
+                        .expect("Could not copy salt file");
+                }
+            }
+            let key = get_key(password, dir_out_path.join(SALT_FILE));
+            let old_config = old_config_dir.map(|dir| read_server_configs(&key, dir));
+            let old_consensus = match (&old_config, old_consensus) {
+                (Some(config), _) => config.consensus.clone(),
+                (None, Some(path)) => plaintext_json_read(path),
+                (None, None) => panic!("Either --old-config-dir or --old-consensus is required"),
+            };
+
+            let server = if let Ok(v) = run_reshare(
+                bind_p2p,
+                bind_api,
+                &dir_out_path,
+                &key,
+                old_consensus,
+                old_config,
+                remove_peers.into_iter().map(PeerId::from).collect(),
+                certs,
+                bitcoind_rpc,
+                bitcoin_rpc_kind,
+                &mut task_group,
+            )
+            .await
+            {
+                v
+            } else {
+                info!("Canceled");
+                return;
+            };
+
+            encrypted_json_write(&server.private, &key, dir_out_path.join(PRIVATE_CONFIG));
+            write_nonprivate_configs(&server, dir_out_path, &module_config_gens);
+        }
         Command::VersionHash => {
             println!("{}", CODE_VERSION);
         }
+        Command::HashAdminPassword { password } => {
+            let password = match password {
+                None => rpassword::prompt_password("Enter the admin password: ").unwrap(),
+                Some(password) => password,
+            };
+            let hash = AdminPasswordHash::new(
+                &password,
+                ADMIN_PASSWORD_ITERATIONS.expect("non-zero"),
+                OsRng,
+            );
+            println!(
+                "{}",
+                serde_json::to_string(&hash).expect("serialization can't fail")
+            );
+        }
         Command::ConfigDecrypt {
             in_file,
             out_file,
@@ -259,6 +394,7 @@ async fn run_dkg(
     federation_name: String,
     certs: Vec<String>,
     bitcoind_rpc: String,
+    bitcoin_rpc_kind: BitcoinRpcKind,
     network: bitcoin::network::constants::Network,
     finality_delay: u32,
     pk: rustls::PrivateKey,
@@ -288,6 +424,7 @@ async fn run_dkg(
         &peers,
         federation_name,
         bitcoind_rpc,
+        bitcoin_rpc_kind,
         network,
         finality_delay,
     );
@@ -323,6 +460,128 @@ async fn run_dkg(
     .expect("failed to run DKG to generate configs")
 }
 
+#[allow(clippy::too_many_arguments)]
+async fn run_reshare(
+    bind_p2p: SocketAddr,
+    bind_api: SocketAddr,
+    dir_out_path: &Path,
+    key: &LessSafeKey,
+    old_consensus: ServerConfigConsensus,
+    old_config: Option<ServerConfig>,
+    remove_peers: BTreeSet<PeerId>,
+    certs: Vec<String>,
+    bitcoind_rpc: String,
+    bitcoin_rpc_kind: BitcoinRpcKind,
+    task_group: &mut TaskGroup,
+) -> Cancellable<ServerConfig> {
+    let mut peers: BTreeMap<PeerId, PeerServerParams> = old_consensus
+        .peers
+        .iter()
+        .filter(|(peer, _)| !remove_peers.contains(peer))
+        .map(|(peer, params)| {
+            (
+                *peer,
+                PeerServerParams {
+                    cert: params.tls_cert.clone(),
+                    p2p_url: params.hbbft.clone(),
+                    api_url: params.api_addr.clone(),
+                    name: params.name.clone(),
+                },
+            )
+        })
+        .collect();
+    let dealers: Vec<PeerId> = peers.keys().cloned().collect();
+
+    // joining guardians take over the ids of removed ones first, since ids have to be contiguous
+    let next_ids = remove_peers
+        .iter()
+        .cloned()
+        .chain((old_consensus.peers.len()..).map(|id| PeerId::from(id as u16)));
+    for (id, cert) in next_ids.zip(certs.into_iter().sorted()) {
+        peers.insert(id, parse_peer_params(cert));
+    }
+    let peer_ids: Vec<PeerId> = peers.keys().cloned().collect();
+    assert!(
+        peer_ids
+            .iter()
+            .enumerate()
+            .all(|(idx, peer)| *peer == PeerId::from(idx as u16)),
+        "Guardians can only be removed if they are replaced or have the highest ids"
+    );
+
+    let (our_id, pk) = match &old_config {
+        Some(config) => (config.local.identity, config.private.tls_key.clone()),
+        None => {
+            let cert_string =
+                fs::read_to_string(dir_out_path.join(TLS_CERT)).expect("Can't read file.");
+            let our_params = parse_peer_params(cert_string);
+            let our_id = peers
+                .iter()
+                .find(|(_peer, params)| params.cert == our_params.cert)
+                .map(|(peer, _)| *peer)
+                .expect("could not find our cert among peers");
+            let pk = rustls::PrivateKey(encrypted_read(key, dir_out_path.join(TLS_PK)));
+            (our_id, pk)
+        }
+    };
+
+    // the wallet and mint keep their settings from the old federation, only the local bitcoin
+    // rpc is taken from the params
+    let old_wallet: WalletConfigConsensus = serde_json::from_value(
+        old_consensus
+            .modules
+            .get("wallet")
+            .expect("Old federation has no wallet")
+            .clone(),
+    )
+    .expect("Invalid wallet config");
+    let params = ServerConfigParams::gen_params(
+        bind_p2p,
+        bind_api,
+        pk,
+        our_id,
+        Amount::ZERO,
+        &peers,
+        old_consensus.federation_name.clone(),
+        bitcoind_rpc,
+        bitcoin_rpc_kind,
+        old_wallet.network,
+        old_wallet.finality_delay,
+    );
+    let server_conn = fedimint_server::config::connect(
+        params.fed_network.clone(),
+        params.tls.clone(),
+        task_group,
+    )
+    .await;
+    let connections = PeerConnectionMultiplexer::new(server_conn).into_dyn();
+
+    let module_config_gens: ModuleConfigGens = BTreeMap::from([
+        (
+            "wallet",
+            Arc::new(WalletConfigGenerator) as Arc<dyn FederationModuleConfigGen + Send + Sync>,
+        ),
+        ("mint", Arc::new(MintConfigGenerator)),
+        ("ln", Arc::new(LightningModuleConfigGen)),
+    ]);
+
+    ServerConfig::distributed_reshare(
+        CODE_VERSION,
+        &connections,
+        &our_id,
+        &peer_ids,
+        &dealers,
+        &old_consensus,
+        old_config.as_ref(),
+        &params,
+        module_config_gens,
+        OsRng,
+        task_group,
+    )
+    .await
+    .expect("failed to reshare keys")
+}
+
 fn parse_peer_params(url: String) -> PeerServerParams {
     let split: Vec<&str> = url.split('@').collect();
     assert_eq!(split.len(), 4, "Cannot parse cert string");
@@ -1,6 +1,9 @@
 use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
 use fedimint_api::db::Database;
@@ -12,10 +15,12 @@ use fedimint_ln::LightningModuleConfigGen;
 use fedimint_mint::MintConfigGenerator;
 use fedimint_server::config::ModuleConfigGens;
 use fedimint_server::consensus::FedimintConsensus;
+use fedimint_server::metrics::run_metrics_server;
 use fedimint_server::FedimintServer;
 use fedimint_wallet::config::WalletConfig;
 use fedimint_wallet::Wallet;
 use fedimint_wallet::WalletConfigGenerator;
+use fedimintd::backup::run_periodic_backups;
 use fedimintd::encrypt::*;
 use fedimintd::ui::run_ui;
 use fedimintd::*;
@@ -30,6 +35,26 @@ pub struct ServerOpts {
     pub password: Option<String>,
     #[arg(default_value = None)]
     pub ui_port: Option<u32>,
+    /// Address to serve Prometheus metrics on (at `/metrics`), disabled if not set
+    #[arg(long = "metrics-bind", env = "FM_METRICS_BIND")]
+    pub metrics_bind: Option<SocketAddr>,
+    /// Directory to periodically write encrypted backups of the database to, disabled if not set
+    #[arg(long = "backup-dir", env = "FM_BACKUP_DIR")]
+    pub backup_dir: Option<PathBuf>,
+    /// Seconds between two database backups
+    #[arg(
+        long = "backup-interval",
+        env = "FM_BACKUP_INTERVAL",
+        default_value_t = 3600
+    )]
+    pub backup_interval: u64,
+    /// Number of database backups to keep, older ones are deleted after each new backup
+    #[arg(
+        long = "backup-retention",
+        env = "FM_BACKUP_RETENTION",
+        default_value_t = NonZeroUsize::new(24).expect("not zero")
+    )]
+    pub backup_retention: NonZeroUsize,
     #[cfg(feature = "telemetry")]
     #[clap(long)]
     pub with_telemetry: bool,
@@ -91,7 +116,7 @@ async fn main() -> anyhow::Result<()> {
     let db: Database = fedimint_rocksdb::RocksDb::open(opts.cfg_path.join(DB_FILE))
         .expect("Error opening DB")
         .into();
-    let btc_rpc = fedimint_bitcoind::bitcoincore_rpc::make_bitcoind_rpc(
+    let btc_rpc = fedimint_bitcoind::make_bitcoin_rpc(
         &cfg.get_module_config_typed::<WalletConfig>("wallet")?
             .local
             .btc_rpc,
@@ -103,6 +128,28 @@ async fn main() -> anyhow::Result<()> {
 
     task_group.install_kill_handler();
 
+    if let Some(metrics_bind) = opts.metrics_bind {
+        task_group
+            .spawn("metrics-server", move |handle| async move {
+                run_metrics_server(metrics_bind, handle)
+                    .await
+                    .expect("Failed to run metrics server")
+            })
+            .await;
+    }
+
+    if let Some(backup_dir) = opts.backup_dir {
+        let db = db.clone();
+        let cfg = cfg.clone();
+        let interval = Duration::from_secs(opts.backup_interval);
+        let retention = opts.backup_retention;
+        task_group
+            .spawn("database-backup", move |handle| async move {
+                run_periodic_backups(db, cfg, key, backup_dir, interval, retention, handle).await
+            })
+            .await;
+    }
+
     let module_config_gens: ModuleConfigGens = BTreeMap::from([
         (
             "wallet",
@@ -126,10 +173,13 @@ async fn main() -> anyhow::Result<()> {
 
     let ln = LightningModule::new(cfg.get_module_config_typed("ln")?);
 
-    let mut consensus = FedimintConsensus::new(cfg.clone(), db, module_config_gens);
-    consensus.register_module(mint.into());
-    consensus.register_module(ln.into());
-    consensus.register_module(wallet.into());
+    let consensus = FedimintConsensus::new(
+        cfg.clone(),
+        db,
+        module_config_gens,
+        vec![mint.into(), ln.into(), wallet.into()],
+    )
+    .await?;
 
     FedimintServer::run(cfg, consensus, &mut task_group).await?;
 
@@ -155,6 +155,8 @@ fn trusted_dealer_gen(
                     hbbft_pk_set: netinf.public_key_set().clone(),
                     epoch_pk_set: epoch_keys.public_key_set().clone(),
                     modules: Default::default(),
+                    max_peg_out_per_epoch: None,
+                    snapshot_interval: Some(10_000),
                 },
                 local: ServerConfigLocal {
                     identity: id,
@@ -167,6 +169,10 @@ fn trusted_dealer_gen(
                     tls_cert: tls_keys[&id].0.clone(),
                     modules: Default::default(),
                     max_connections: 1000,
+                    max_pending_transactions: 10_000,
+                    max_requests_per_second: 100,
+                    admin_password_hash: None,
+                    epoch_history_retention: None,
                 },
                 private: ServerConfigPrivate {
                     tls_key: tls_keys[&id].1.clone(),
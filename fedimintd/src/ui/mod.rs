@@ -119,6 +119,7 @@ async fn deal(
     let btc_rpc_user = parts[0].to_string();
     let btc_rpc_pass = parts[1].to_string();
     let btc_rpc = BitcoindRpcCfg {
+        kind: Default::default(),
         btc_rpc_address,
         btc_rpc_user,
         btc_rpc_pass,
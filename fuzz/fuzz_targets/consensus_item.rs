@@ -0,0 +1,14 @@
+//! Decodes arbitrary bytes as a [`ConsensusItem`] like guardians do for contributions of peers
+#![no_main]
+
+use fedimint_core::all_decoders;
+use fedimint_core::epoch::ConsensusItem;
+use fedimint_testing::encoding::{assert_canonical_decoding, assert_roundtrip};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let modules = all_decoders();
+    if let Some(item) = assert_canonical_decoding::<ConsensusItem>(data, &modules) {
+        assert_roundtrip(&item, &modules);
+    }
+});
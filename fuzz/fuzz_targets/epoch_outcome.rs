@@ -0,0 +1,15 @@
+//! Decodes arbitrary bytes as a [`SignedEpochOutcome`] like guardians do for the epoch history
+//! downloaded from peers
+#![no_main]
+
+use fedimint_core::all_decoders;
+use fedimint_core::epoch::SignedEpochOutcome;
+use fedimint_testing::encoding::{assert_canonical_decoding, assert_roundtrip};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let modules = all_decoders();
+    if let Some(outcome) = assert_canonical_decoding::<SignedEpochOutcome>(data, &modules) {
+        assert_roundtrip(&outcome, &modules);
+    }
+});
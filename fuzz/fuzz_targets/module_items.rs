@@ -0,0 +1,25 @@
+//! Decodes arbitrary bytes as the module specific items, dispatching on their module key through
+//! the [`ModuleDecoderRegistry`](fedimint_api::module::registry::ModuleDecoderRegistry) of all
+//! modules
+#![no_main]
+
+use fedimint_api::core::{ConsensusItem, Input, Output, OutputOutcome};
+use fedimint_core::all_decoders;
+use fedimint_testing::encoding::{assert_canonical_decoding, assert_roundtrip};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let modules = all_decoders();
+    if let Some(input) = assert_canonical_decoding::<Input>(data, &modules) {
+        assert_roundtrip(&input, &modules);
+    }
+    if let Some(output) = assert_canonical_decoding::<Output>(data, &modules) {
+        assert_roundtrip(&output, &modules);
+    }
+    if let Some(outcome) = assert_canonical_decoding::<OutputOutcome>(data, &modules) {
+        assert_roundtrip(&outcome, &modules);
+    }
+    if let Some(item) = assert_canonical_decoding::<ConsensusItem>(data, &modules) {
+        assert_roundtrip(&item, &modules);
+    }
+});
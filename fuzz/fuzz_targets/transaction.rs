@@ -0,0 +1,14 @@
+//! Decodes arbitrary bytes as a [`Transaction`] like guardians do for submitted transactions
+#![no_main]
+
+use fedimint_core::all_decoders;
+use fedimint_core::transaction::Transaction;
+use fedimint_testing::encoding::{assert_canonical_decoding, assert_roundtrip};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let modules = all_decoders();
+    if let Some(tx) = assert_canonical_decoding::<Transaction>(data, &modules) {
+        assert_roundtrip(&tx, &modules);
+    }
+});
@@ -4,7 +4,7 @@ use bitcoin::{Address, Amount, Transaction};
 use clap::{Parser, Subcommand};
 use fedimint_server::modules::wallet::txoproof::TxOutProof;
 use ln_gateway::{
-    config::GatewayConfig,
+    config::{GatewayConfig, LightningBackendConfig, LndConfig},
     rpc::{
         rpc_client::RpcClient, BalancePayload, ConnectFedPayload, DepositAddressPayload,
         DepositPayload, WithdrawPayload,
@@ -37,6 +37,15 @@ pub enum Commands {
         announce_address: Url,
         /// The gateway configuration directory
         out_dir: PathBuf,
+        /// Connect to LND over gRPC at this address instead of running as a CLN plugin
+        #[clap(long, requires_all = ["lnd_tls_cert", "lnd_macaroon"])]
+        lnd_rpc_address: Option<Url>,
+        /// Path to LND's TLS certificate
+        #[clap(long)]
+        lnd_tls_cert: Option<PathBuf>,
+        /// Path to the LND macaroon used for authentication
+        #[clap(long)]
+        lnd_macaroon: Option<PathBuf>,
     },
     /// Display CLI version hash
     VersionHash,
@@ -80,7 +89,21 @@ async fn main() {
             bind_address: address,
             announce_address,
             mut out_dir,
+            lnd_rpc_address,
+            lnd_tls_cert,
+            lnd_macaroon,
         } => {
+            let lightning = match (lnd_rpc_address, lnd_tls_cert, lnd_macaroon) {
+                (Some(rpc_address), Some(tls_cert_path), Some(macaroon_path)) => {
+                    LightningBackendConfig::Lnd(LndConfig {
+                        rpc_address,
+                        tls_cert_path,
+                        macaroon_path,
+                    })
+                }
+                _ => LightningBackendConfig::Cln,
+            };
+
             // Recursively create config directory if it doesn't exist
             std::fs::create_dir_all(&out_dir).expect("Failed to create config directory");
             // Create config file
@@ -97,6 +120,8 @@ async fn main() {
                     password: source_password(cli.rpcpassword),
                     // TODO: Remove this field with hardcoded value once we have fixed Issue 664:
                     default_federation: FederationId("Hals_trusty_mint".into()),
+                    lightning,
+                    fees: Default::default(),
                 },
             )
             .expect("Failed to write gateway configs to file");
@@ -7,7 +7,13 @@ use fedimint_server::modules::{
     ln::contracts::{ContractId, Preimage},
     wallet::txoproof::TxOutProof,
 };
-use mint_client::{FederationId, GatewayClient, PaymentParameters};
+use mint_client::{
+    ln::{
+        lnurl::{LnurlInvoice, LnurlInvoicesRequest, LnurlRegisterPayload, LnurlRegistration},
+        PaymentPart,
+    },
+    FederationId, GatewayClient, PaymentParameters,
+};
 use rand::{CryptoRng, RngCore};
 use tracing::{debug, info, instrument, warn};
 
@@ -19,15 +25,12 @@ pub struct GatewayActor {
 
 impl GatewayActor {
     pub async fn new(client: Arc<GatewayClient>) -> Result<Self> {
+        let actor = Self { client };
+
         // Retry gateway registration
         match retry(
             String::from("Register With Federation"),
-            #[allow(clippy::unit_arg)]
-            || async {
-                Ok(client
-                    .register_with_federation(client.config().into())
-                    .await?)
-            },
+            || async { Ok(actor.register_with_federation().await?) },
             Duration::from_secs(1),
             5,
         )
@@ -37,7 +40,17 @@ impl GatewayActor {
             Err(e) => warn!("Failed to connect with federation: {}", e),
         }
 
-        Ok(Self { client })
+        Ok(actor)
+    }
+
+    /// Registers the gateway with the federation, which lists it for
+    /// [`GATEWAY_REGISTRATION_TTL`](fedimint_server::modules::ln::GATEWAY_REGISTRATION_TTL) from
+    /// now on
+    pub async fn register_with_federation(&self) -> Result<()> {
+        Ok(self
+            .client
+            .register_with_federation(self.client.config().into())
+            .await?)
     }
 
     async fn fetch_all_coins(&self) {
@@ -67,11 +80,14 @@ impl GatewayActor {
         Ok(preimage)
     }
 
+    /// Pays the invoice of an outgoing contract, or only `part` of it if the contract funds
+    /// one part of a multi-part payment
     #[instrument(skip_all, fields(%contract_id))]
     pub async fn pay_invoice(
         &self,
         ln_rpc: Arc<dyn LnRpc>,
         contract_id: ContractId,
+        part: Option<PaymentPart>,
     ) -> Result<OutPoint> {
         debug!("Fetching contract");
         let rng = rand::rngs::OsRng;
@@ -79,7 +95,7 @@ impl GatewayActor {
 
         let payment_params = self
             .client
-            .validate_outgoing_account(&contract_account)
+            .validate_outgoing_account(&contract_account, part)
             .await?;
 
         debug!(
@@ -99,7 +115,12 @@ impl GatewayActor {
                 .await
                 .unwrap_or(false);
 
-        let preimage_res = if is_internal_payment {
+        let preimage_res = if is_internal_payment && payment_params.is_partial() {
+            // Offers can only be bought as a whole
+            Err(LnGatewayError::Other(anyhow::anyhow!(
+                "Multi-part payments of internal invoices are not supported"
+            )))
+        } else if is_internal_payment {
             self.buy_preimage_internal(&payment_params.payment_hash, &payment_params.invoice_amount)
                 .await
         } else {
@@ -160,14 +181,27 @@ impl GatewayActor {
         invoice: lightning_invoice::Invoice,
         payment_params: &PaymentParameters,
     ) -> Result<Preimage> {
-        match ln_rpc
-            .pay(
-                invoice,
-                payment_params.max_delay,
-                payment_params.max_fee_percent(),
-            )
-            .await
-        {
+        let pay_result = if payment_params.is_partial() {
+            ln_rpc
+                .pay_partial(
+                    invoice,
+                    payment_params.part_id,
+                    payment_params.payment_amount,
+                    payment_params.max_delay,
+                    payment_params.max_fee_percent(),
+                )
+                .await
+        } else {
+            ln_rpc
+                .pay(
+                    invoice,
+                    payment_params.max_delay,
+                    payment_params.max_fee_percent(),
+                )
+                .await
+        };
+
+        match pay_result {
             Ok(preimage) => {
                 debug!(?preimage, "Successfully paid LN invoice");
                 Ok(preimage)
@@ -229,6 +263,40 @@ impl GatewayActor {
             .map(|out_point| out_point.txid)
     }
 
+    pub async fn register_lnurl_user(
+        &self,
+        payload: LnurlRegisterPayload,
+    ) -> Result<LnurlRegistration> {
+        Ok(self.client.register_lnurl_user(payload).await?)
+    }
+
+    pub async fn is_lnurl_user(&self, username: &str) -> bool {
+        self.client.get_lnurl_registration(username).await.is_some()
+    }
+
+    /// Creates an invoice for a payment to `username` from one of the offers the user registered,
+    /// the federation only decrypts the preimage once we funded the incoming contract
+    pub async fn create_lnurl_invoice(
+        &self,
+        username: &str,
+        amount: Amount,
+        description_hash: sha256::Hash,
+    ) -> Result<LnurlInvoice> {
+        let rng = rand::rngs::OsRng;
+        Ok(self
+            .client
+            .create_lnurl_invoice(username, amount, description_hash, rng)
+            .await?)
+    }
+
+    pub async fn list_lnurl_invoices(
+        &self,
+        username: &str,
+        request: &LnurlInvoicesRequest,
+    ) -> Result<Vec<LnurlInvoice>> {
+        Ok(self.client.list_lnurl_invoices(username, request).await?)
+    }
+
     pub async fn get_balance(&self) -> Result<Amount> {
         self.fetch_all_coins().await;
 
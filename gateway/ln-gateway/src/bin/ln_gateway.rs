@@ -1,17 +1,26 @@
-use cln_plugin::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use cln_plugin::{anyhow, Error};
 use fedimint_api::task::TaskGroup;
 use fedimint_server::config::load_from_file;
 use ln_gateway::{
     client::{GatewayClientBuilder, RocksDbFactory, StandardGatewayClientBuilder},
     cln::{build_cln_rpc, ClnRpcRef},
-    config::GatewayConfig,
+    config::{GatewayConfig, LightningBackendConfig},
+    ln::LnRpc,
+    lnd::build_lnd_rpc,
     rpc::{GatewayRequest, GatewayRpcSender},
     LnGateway,
 };
 use tokio::sync::mpsc;
 use tracing::error;
 
-/// Fedimint gateway packaged as a CLN plugin
+/// If set, the gateway runs standalone using the config in this directory instead of as a
+/// CLN plugin
+const GATEWAY_DATA_DIR_ENV: &str = "FM_GATEWAY_DATA_DIR";
+
+/// Fedimint gateway packaged as a CLN plugin, or running standalone next to an LND node
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let mut args = std::env::args();
@@ -25,18 +34,40 @@ async fn main() -> Result<(), Error> {
 
     // Create message channels
     let (tx, rx) = mpsc::channel::<GatewayRequest>(100);
+    let mut task_group = TaskGroup::new();
+
+    let (ln_rpc, work_dir, gw_cfg): (Arc<dyn LnRpc>, PathBuf, GatewayConfig) =
+        match std::env::var_os(GATEWAY_DATA_DIR_ENV) {
+            Some(work_dir) => {
+                let work_dir = PathBuf::from(work_dir);
+                let gw_cfg = load_gateway_config(&work_dir);
 
-    let ClnRpcRef { ln_rpc, work_dir } = build_cln_rpc(GatewayRpcSender::new(tx.clone())).await?;
+                let lnd_cfg = match &gw_cfg.lightning {
+                    LightningBackendConfig::Lnd(lnd_cfg) => lnd_cfg.clone(),
+                    LightningBackendConfig::Cln => {
+                        return Err(anyhow!("Standalone gateway requires an LND config"))
+                    }
+                };
+                let ln_rpc: Arc<dyn LnRpc> =
+                    build_lnd_rpc(&lnd_cfg, GatewayRpcSender::new(tx.clone()), &mut task_group)
+                        .await?;
 
-    let gw_cfg_path = work_dir.clone().join("gateway.config");
-    let gw_cfg: GatewayConfig = load_from_file(&gw_cfg_path).expect("Failed to parse config");
+                (ln_rpc, work_dir, gw_cfg)
+            }
+            None => {
+                let ClnRpcRef { ln_rpc, work_dir } =
+                    build_cln_rpc(GatewayRpcSender::new(tx.clone())).await?;
+                let gw_cfg = load_gateway_config(&work_dir);
+
+                (ln_rpc as Arc<dyn LnRpc>, work_dir, gw_cfg)
+            }
+        };
 
     // Create federation client builder
     let client_builder: GatewayClientBuilder =
         StandardGatewayClientBuilder::new(work_dir.clone(), RocksDbFactory.into()).into();
 
     // Create gateway instance
-    let task_group = TaskGroup::new();
     let gateway = LnGateway::new(gw_cfg, ln_rpc, client_builder, tx, rx, task_group.clone()).await;
 
     if let Err(e) = gateway.run().await {
@@ -48,3 +79,8 @@ async fn main() -> Result<(), Error> {
 
     Ok(())
 }
+
+fn load_gateway_config(work_dir: &std::path::Path) -> GatewayConfig {
+    let gw_cfg_path = work_dir.join("gateway.config");
+    load_from_file(&gw_cfg_path).expect("Failed to parse config")
+}
@@ -139,6 +139,7 @@ impl IGatewayClientBuilder for StandardGatewayClientBuilder {
             timelock_delta: 10,
             node_pub_key: node_pubkey,
             api: announce_address,
+            fees: Default::default(),
         })
     }
 
@@ -19,6 +19,9 @@ use crate::{
     rpc::GatewayRpcSender,
 };
 
+/// How long we wait for the recipient to receive all parts of a multi-part payment
+const PARTIAL_PAYMENT_TIMEOUT_SECS: u32 = 90;
+
 /// The core-lightning `htlc_accepted` event's `amount` field has a "msat" suffix
 fn as_fedimint_amount<'de, D>(amount: D) -> Result<Amount, D::Error>
 where
@@ -131,6 +134,130 @@ impl LnRpc for Mutex<cln_rpc::ClnRpc> {
             }
         }
     }
+
+    /// c-lightning's `pay` can't send parts of a multi-part payment, so we find a route ourselves
+    /// and send a single part of the payment using `sendpay`
+    #[instrument(name = "LnRpc::pay_partial", skip(self))]
+    async fn pay_partial(
+        &self,
+        invoice: lightning_invoice::Invoice,
+        part_id: u16,
+        part_amount: Amount,
+        max_delay: u64,
+        max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError> {
+        debug!("Attempting to pay part of invoice");
+
+        let total_amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(LightningError(None))?;
+        let payment_secret = invoice
+            .payment_secret()
+            .0
+            .to_vec()
+            .try_into()
+            .map_err(|e| {
+                error!(?e, "Invalid payment secret");
+                LightningError(None)
+            })?;
+        let payee =
+            model::primitives::PublicKey::from_slice(&invoice.recover_payee_pub_key().serialize())
+                .map_err(|e| {
+                    error!(?e, "Invalid payee public key");
+                    LightningError(None)
+                })?;
+
+        let route = match self
+            .lock()
+            .await
+            .call(Request::GetRoute(model::GetrouteRequest {
+                id: payee,
+                amount_msat: model::primitives::Amount::from_msat(part_amount.msats),
+                riskfactor: 10,
+                cltv: Some(invoice.min_final_cltv_expiry() as f64),
+                fromid: None,
+                fuzzpercent: None,
+                exclude: None,
+                maxhops: None,
+            }))
+            .await
+        {
+            Ok(Response::GetRoute(route)) => route.route,
+            Ok(_) => unreachable!("unexpected response from C-lightning"),
+            Err(cln_rpc::RpcError { code, message }) => {
+                debug!(?code, %message, "c-lightning getroute returned error");
+                return Err(LightningError(code));
+            }
+        };
+
+        let first_hop = route.first().ok_or(LightningError(None))?;
+        let fee_msat = first_hop
+            .amount_msat
+            .msat()
+            .saturating_sub(part_amount.msats);
+        if fee_msat as f64 > part_amount.msats as f64 * max_fee_percent
+            || first_hop.delay as u64 > max_delay
+        {
+            debug!(
+                fee_msat,
+                delay = first_hop.delay,
+                "Route exceeds fee or delay limit"
+            );
+            return Err(LightningError(None));
+        }
+
+        let sendpay_result = self
+            .lock()
+            .await
+            .call(Request::SendPay(model::SendpayRequest {
+                route: route
+                    .into_iter()
+                    .map(|hop| model::requests::SendpayRoute {
+                        amount_msat: hop.amount_msat,
+                        id: hop.id,
+                        delay: hop.delay as u16,
+                        channel: hop.channel,
+                    })
+                    .collect(),
+                payment_hash: *invoice.payment_hash(),
+                label: None,
+                amount_msat: Some(model::primitives::Amount::from_msat(total_amount)),
+                bolt11: Some(invoice.to_string()),
+                payment_secret: Some(payment_secret),
+                partid: Some(part_id),
+                localinvreqid: None,
+                groupid: None,
+            }))
+            .await;
+        if let Err(cln_rpc::RpcError { code, message }) = sendpay_result {
+            debug!(?code, %message, "c-lightning sendpay returned error");
+            return Err(LightningError(code));
+        }
+
+        match self
+            .lock()
+            .await
+            .call(Request::WaitSendPay(model::WaitsendpayRequest {
+                payment_hash: *invoice.payment_hash(),
+                timeout: Some(PARTIAL_PAYMENT_TIMEOUT_SECS),
+                partid: Some(part_id.into()),
+                groupid: None,
+            }))
+            .await
+        {
+            Ok(Response::WaitSendPay(waitsendpay)) => {
+                let preimage = waitsendpay.payment_preimage.ok_or(LightningError(None))?;
+                debug!("Successfully paid part of invoice");
+                let slice: [u8; 32] = preimage.to_vec().try_into().unwrap();
+                Ok(Preimage(slice))
+            }
+            Ok(_) => unreachable!("unexpected response from C-lightning"),
+            Err(cln_rpc::RpcError { code, message }) => {
+                debug!(?code, %message, "c-lightning waitsendpay returned error");
+                Err(LightningError(code))
+            }
+        }
+    }
 }
 
 /// Handle core-lightning "htlc_accepted" events by attempting to buy this preimage from the federation
@@ -142,7 +269,10 @@ async fn htlc_accepted_hook(
     let htlc_accepted: HtlcAccepted = serde_json::from_value(value)?;
     let preimage = plugin
         .state()
-        .send(ReceivePaymentPayload { htlc_accepted })
+        .send(ReceivePaymentPayload {
+            payment_hash: htlc_accepted.htlc.payment_hash,
+            amount: htlc_accepted.htlc.amount,
+        })
         .await?;
     let pk = preimage.to_public_key()?;
     Ok(serde_json::json!({
@@ -1,5 +1,7 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
+use fedimint_server::modules::ln::GatewayFee;
 use mint_client::FederationId;
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -15,4 +17,31 @@ pub struct GatewayConfig {
     // FIXME: Issue 664: We should avoid having a special reference to a federation
     // all requests, including `ReceivePaymentPayload`, should contain the federation id
     pub default_federation: FederationId,
+    /// Lightning node implementation the gateway talks to
+    #[serde(default)]
+    pub lightning: LightningBackendConfig,
+    /// Fee we charge for paying invoices on behalf of federation users
+    #[serde(default)]
+    pub fees: GatewayFee,
+}
+
+/// The lightning node backing the gateway
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LightningBackendConfig {
+    /// The gateway runs as a core-lightning plugin
+    #[default]
+    Cln,
+    /// The gateway runs standalone and connects to LND over gRPC
+    Lnd(LndConfig),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LndConfig {
+    /// LND gRPC endpoint, e.g. `https://localhost:10009`
+    pub rpc_address: Url,
+    /// LND's `tls.cert`
+    pub tls_cert_path: PathBuf,
+    /// Macaroon granting access to the lightning and router services, e.g. `admin.macaroon`
+    pub macaroon_path: PathBuf,
 }
@@ -3,6 +3,8 @@ pub mod client;
 pub mod cln;
 pub mod config;
 pub mod ln;
+pub mod lnd;
+pub mod lnurl;
 pub mod rpc;
 pub mod utils;
 
@@ -17,11 +19,20 @@ use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use bitcoin::Address;
 use bitcoin_hashes::sha256::Hash as Sha256Hash;
+use bitcoin_hashes::Hash;
 use fedimint_api::{task::TaskGroup, Amount, TransactionId};
-use fedimint_server::modules::ln::contracts::Preimage;
+use fedimint_server::modules::ln::{contracts::Preimage, GATEWAY_REGISTRATION_TTL};
 use mint_client::{
-    api::WsFederationConnect, ln::PayInvoicePayload, mint::MintClientError, ClientError,
-    FederationId, GatewayClient,
+    api::WsFederationConnect,
+    ln::{
+        lnurl::{
+            LnurlInvoice, LnurlRegisterPayload, LnurlRegistration, LNURL_MAX_SENDABLE,
+            LNURL_MIN_SENDABLE,
+        },
+        PayInvoicePayload,
+    },
+    mint::MintClientError,
+    ClientError, FederationId, GatewayClient,
 };
 use thiserror::Error;
 use tokio::sync::{mpsc, Mutex};
@@ -32,15 +43,21 @@ use crate::{
     client::GatewayClientBuilder,
     config::GatewayConfig,
     ln::{LightningError, LnRpc},
+    lnurl::{lnurl_metadata, LnurlCallbackLimiter, LnurlPayParams},
     rpc::{
         rpc_server::run_webserver, BalancePayload, ConnectFedPayload, DepositAddressPayload,
         DepositPayload, GatewayInfo, GatewayRequest, GatewayRpcSender, InfoPayload,
-        ReceivePaymentPayload, WithdrawPayload,
+        LnurlCallbackPayload, LnurlInvoicesPayload, LnurlPayRequestPayload, ReceivePaymentPayload,
+        WithdrawPayload,
     },
 };
 
 pub type Result<T> = std::result::Result<T, LnGatewayError>;
 
+/// Interval of renewing our federation registrations, leaves plenty of time to retry before they
+/// expire
+const REGISTRATION_INTERVAL: Duration = Duration::from_secs(GATEWAY_REGISTRATION_TTL.as_secs() / 2);
+
 pub struct LnGateway {
     config: GatewayConfig,
     actors: Mutex<HashMap<Sha256Hash, Arc<GatewayActor>>>,
@@ -49,6 +66,7 @@ pub struct LnGateway {
     receiver: mpsc::Receiver<GatewayRequest>,
     client_builder: GatewayClientBuilder,
     task_group: TaskGroup,
+    lnurl_callbacks: Mutex<LnurlCallbackLimiter>,
 }
 
 impl LnGateway {
@@ -69,6 +87,7 @@ impl LnGateway {
             receiver,
             client_builder,
             task_group,
+            lnurl_callbacks: Mutex::new(LnurlCallbackLimiter::default()),
         };
 
         ln_gw.load_federation_actors().await;
@@ -78,7 +97,9 @@ impl LnGateway {
 
     async fn load_federation_actors(&self) {
         if let Ok(configs) = self.client_builder.load_configs() {
-            for config in configs {
+            for mut config in configs {
+                // Advertise our current fee even if it changed since joining the federation
+                config.fees = self.config.fees;
                 let client = self
                     .client_builder
                     .build(config.clone())
@@ -137,11 +158,12 @@ impl LnGateway {
             .await
             .expect("Failed to get node pubkey from Lightning node");
 
-        let gw_client_cfg = self
+        let mut gw_client_cfg = self
             .client_builder
             .create_config(connect, node_pub_key, self.config.announce_address.clone())
             .await
             .expect("Failed to create gateway client config");
+        gw_client_cfg.fees = self.config.fees;
 
         let client = Arc::new(
             self.client_builder
@@ -180,15 +202,16 @@ impl LnGateway {
     }
 
     async fn handle_receive_invoice_msg(&self, payload: ReceivePaymentPayload) -> Result<Preimage> {
-        let ReceivePaymentPayload { htlc_accepted } = payload;
+        let ReceivePaymentPayload {
+            payment_hash,
+            amount: invoice_amount,
+        } = payload;
 
-        let invoice_amount = htlc_accepted.htlc.amount;
-        let payment_hash = htlc_accepted.htlc.payment_hash;
         debug!("Incoming htlc for payment hash {}", payment_hash);
 
         // FIXME: Issue 664: We should avoid having a special reference to a federation
         // all requests, including `ReceivePaymentPayload`, should contain the federation id
-        // TODO: Parse federation id from routing hint in htlc_accepted message
+        // TODO: Parse federation id from routing hint of the intercepted htlc
         self.select_actor(self.config.default_federation.clone())
             .await?
             .buy_preimage_internal(&payment_hash, &invoice_amount)
@@ -199,10 +222,13 @@ impl LnGateway {
         let PayInvoicePayload {
             federation_id,
             contract_id,
+            part,
         } = payload;
 
         let actor = self.select_actor(federation_id).await?;
-        let outpoint = actor.pay_invoice(self.ln_rpc.clone(), contract_id).await?;
+        let outpoint = actor
+            .pay_invoice(self.ln_rpc.clone(), contract_id, part)
+            .await?;
         actor
             .await_outgoing_contract_claimed(contract_id, outpoint)
             .await?;
@@ -249,6 +275,93 @@ impl LnGateway {
             .await
     }
 
+    // FIXME: Issue 664: We can only receive payments for the default federation, so LNURL
+    // usernames are registered with the default federation's client
+    async fn handle_lnurl_register_msg(
+        &self,
+        payload: LnurlRegisterPayload,
+    ) -> Result<LnurlRegistration> {
+        if payload.federation_id != self.config.default_federation {
+            return Err(LnGatewayError::UnknownFederation);
+        }
+        self.select_actor(payload.federation_id.clone())
+            .await?
+            .register_lnurl_user(payload)
+            .await
+    }
+
+    async fn handle_lnurl_pay_request_msg(
+        &self,
+        payload: LnurlPayRequestPayload,
+    ) -> Result<LnurlPayParams> {
+        let actor = self
+            .select_actor(self.config.default_federation.clone())
+            .await?;
+        if !actor.is_lnurl_user(&payload.username).await {
+            return Err(ClientError::UnknownLnurlUsername.into());
+        }
+
+        Ok(LnurlPayParams::new(
+            &self.config.announce_address,
+            &payload.username,
+        ))
+    }
+
+    async fn handle_lnurl_callback_msg(
+        &self,
+        payload: LnurlCallbackPayload,
+    ) -> Result<LnurlInvoice> {
+        let LnurlCallbackPayload { username, amount } = payload;
+
+        if amount < LNURL_MIN_SENDABLE || LNURL_MAX_SENDABLE < amount {
+            return Err(LnGatewayError::Other(anyhow::anyhow!(
+                "Amount {} is outside of the sendable range",
+                amount
+            )));
+        }
+
+        let actor = self
+            .select_actor(self.config.default_federation.clone())
+            .await?;
+        if !actor.is_lnurl_user(&username).await {
+            return Err(ClientError::UnknownLnurlUsername.into());
+        }
+        if !self
+            .lnurl_callbacks
+            .lock()
+            .await
+            .try_acquire(&username, Instant::now())
+        {
+            return Err(LnGatewayError::RateLimited);
+        }
+
+        // The invoice has to commit to the metadata we sent in the pay request
+        let metadata = lnurl_metadata(&self.config.announce_address, &username);
+        actor
+            .create_lnurl_invoice(&username, amount, Sha256Hash::hash(metadata.as_bytes()))
+            .await
+    }
+
+    async fn handle_lnurl_invoices_msg(
+        &self,
+        payload: LnurlInvoicesPayload,
+    ) -> Result<Vec<LnurlInvoice>> {
+        self.select_actor(self.config.default_federation.clone())
+            .await?
+            .list_lnurl_invoices(&payload.username, &payload.request)
+            .await
+    }
+
+    /// Renews our registration with every connected federation so that we stay listed
+    async fn register_with_federations(&self) {
+        let actors: Vec<_> = self.actors.lock().await.values().cloned().collect();
+        for actor in actors {
+            if let Err(e) = actor.register_with_federation().await {
+                warn!("Failed to renew federation registration: {}", e);
+            }
+        }
+    }
+
     pub async fn run(mut self) -> Result<()> {
         let mut tg = self.task_group.clone();
 
@@ -271,6 +384,8 @@ impl LnGateway {
 
         // TODO: try to drive forward outgoing and incoming payments that were interrupted
         let loop_ctrl = tg.make_handle();
+        // Connecting a federation registered us with it just now
+        let mut next_registration = Instant::now() + REGISTRATION_INTERVAL;
         loop {
             // Shut down main loop if requested
             if loop_ctrl.is_shutting_down() {
@@ -279,6 +394,11 @@ impl LnGateway {
 
             let least_wait_until = Instant::now() + Duration::from_millis(100);
 
+            if Instant::now() >= next_registration {
+                self.register_with_federations().await;
+                next_registration = Instant::now() + REGISTRATION_INTERVAL;
+            }
+
             // Handle messages from webserver and plugin
             while let Ok(msg) = self.receiver.try_recv() {
                 tracing::trace!("Gateway received message {:?}", msg);
@@ -321,6 +441,26 @@ impl LnGateway {
                             .handle(|payload| self.handle_withdraw_msg(payload))
                             .await;
                     }
+                    GatewayRequest::LnurlRegister(inner) => {
+                        inner
+                            .handle(|payload| self.handle_lnurl_register_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::LnurlPayRequest(inner) => {
+                        inner
+                            .handle(|payload| self.handle_lnurl_pay_request_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::LnurlCallback(inner) => {
+                        inner
+                            .handle(|payload| self.handle_lnurl_callback_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::LnurlInvoices(inner) => {
+                        inner
+                            .handle(|payload| self.handle_lnurl_invoices_msg(payload))
+                            .await;
+                    }
                 }
             }
 
@@ -346,6 +486,8 @@ pub enum LnGatewayError {
     MintClientE(#[from] MintClientError),
     #[error("Actor not found")]
     UnknownFederation,
+    #[error("Too many requests, try again later")]
+    RateLimited,
     #[error("Other: {0:?}")]
     Other(#[from] anyhow::Error),
 }
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use fedimint_api::Amount;
 use fedimint_server::modules::ln::contracts::Preimage;
 use secp256k1::PublicKey;
 
@@ -14,6 +15,19 @@ pub trait LnRpc: Send + Sync + 'static {
         max_delay: u64,
         max_fee_percent: f64,
     ) -> Result<Preimage, LightningError>;
+
+    /// Attempt to pay `part_amount` of a multi-part payment invoice and block till the part
+    /// succeeds, fails or times out. The recipient only releases the preimage once all parts
+    /// arrived, which may be sent by other nodes. Parts sent by the same node need distinct
+    /// `part_id`s.
+    async fn pay_partial(
+        &self,
+        invoice: lightning_invoice::Invoice,
+        part_id: u16,
+        part_amount: Amount,
+        max_delay: u64,
+        max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError>;
 }
 
 #[derive(Debug)]
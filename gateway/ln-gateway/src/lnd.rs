@@ -0,0 +1,374 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_api::{task::TaskGroup, Amount};
+use fedimint_server::modules::ln::contracts::Preimage;
+use futures::SinkExt;
+use secp256k1::PublicKey;
+use tonic::{
+    codegen::InterceptedService,
+    metadata::{Ascii, MetadataValue},
+    service::Interceptor,
+    transport::{Certificate, Channel, ClientTlsConfig},
+    Request, Status, Streaming,
+};
+use tracing::{debug, error, instrument};
+use url::Host;
+
+use crate::{
+    config::LndConfig,
+    ln::{LightningError, LnRpc},
+    rpc::{GatewayRpcSender, ReceivePaymentPayload},
+};
+
+pub mod lnrpc {
+    tonic::include_proto!("lnrpc");
+}
+
+pub mod routerrpc {
+    tonic::include_proto!("routerrpc");
+}
+
+use lnrpc::{
+    fee_limit, htlc_attempt::HtlcStatus, lightning_client::LightningClient, payment::PaymentStatus,
+    FeeLimit, GetInfoRequest, MppRecord, Payment, QueryRoutesRequest,
+};
+use routerrpc::{
+    router_client::RouterClient, ForwardHtlcInterceptRequest, ForwardHtlcInterceptResponse,
+    ResolveHoldForwardAction, SendPaymentRequest, SendToRouteRequest, TrackPaymentRequest,
+};
+
+/// How long LND may spend trying to pay an invoice
+const PAYMENT_TIMEOUT_SECS: i32 = 90;
+
+/// How long we wait for the federation to sell us the preimage of an intercepted HTLC before
+/// letting LND forward it normally
+const HTLC_INTERCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Capacity of the channel buffering our resolutions of intercepted HTLCs
+const HTLC_RESOLUTION_CHANNEL_CAPACITY: usize = 100;
+
+/// Attaches the hex encoded macaroon LND uses for authentication to every request
+#[derive(Debug, Clone)]
+pub struct MacaroonInterceptor {
+    macaroon: MetadataValue<Ascii>,
+}
+
+impl Interceptor for MacaroonInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        request
+            .metadata_mut()
+            .insert("macaroon", self.macaroon.clone());
+        Ok(request)
+    }
+}
+
+type LndService = InterceptedService<Channel, MacaroonInterceptor>;
+
+/// [`LnRpc`] implementation talking to LND over its gRPC interface
+#[derive(Debug, Clone)]
+pub struct LndRpc {
+    lightning: LightningClient<LndService>,
+    router: RouterClient<LndService>,
+}
+
+impl LndRpc {
+    /// Connect to the LND node described by `config`
+    pub async fn connect(config: &LndConfig) -> anyhow::Result<Self> {
+        let tls_cert = tokio::fs::read(&config.tls_cert_path).await?;
+        let macaroon = tokio::fs::read(&config.macaroon_path).await?;
+
+        // LND's self-signed certificate always includes `localhost`, which we fall back to if
+        // the node is addressed by IP since those can't be verified by our TLS stack
+        let domain_name = match config.rpc_address.host() {
+            Some(Host::Domain(domain)) => domain.to_string(),
+            _ => "localhost".to_string(),
+        };
+        let tls_config = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(tls_cert))
+            .domain_name(domain_name);
+
+        let channel = Channel::from_shared(config.rpc_address.to_string())?
+            .tls_config(tls_config)?
+            .connect()
+            .await?;
+
+        Ok(Self::new(channel, &macaroon))
+    }
+
+    /// Use an already established `channel` to LND, authenticating with `macaroon`
+    pub fn new(channel: Channel, macaroon: &[u8]) -> Self {
+        let interceptor = MacaroonInterceptor {
+            macaroon: hex::encode(macaroon)
+                .parse()
+                .expect("hex is always valid ascii"),
+        };
+
+        LndRpc {
+            lightning: LightningClient::with_interceptor(channel.clone(), interceptor.clone()),
+            router: RouterClient::with_interceptor(channel, interceptor),
+        }
+    }
+
+    /// Intercept all HTLCs LND forwards and try to buy their preimage from the federation,
+    /// which is the LND equivalent of core-lightning's `htlc_accepted` hook
+    pub async fn run_htlc_interceptor(&self, sender: GatewayRpcSender) -> anyhow::Result<()> {
+        let (resolution_sender, resolution_receiver) =
+            futures::channel::mpsc::channel(HTLC_RESOLUTION_CHANNEL_CAPACITY);
+
+        let mut htlcs = self
+            .router
+            .clone()
+            .htlc_interceptor(resolution_receiver)
+            .await?
+            .into_inner();
+
+        while let Some(htlc) = htlcs.message().await? {
+            let sender = sender.clone();
+            let mut resolution_sender = resolution_sender.clone();
+            tokio::spawn(async move {
+                let resolution = resolve_intercepted_htlc(&sender, htlc).await;
+                if resolution_sender.send(resolution).await.is_err() {
+                    error!("LND closed the htlc interceptor stream");
+                }
+            });
+        }
+
+        Err(anyhow!("LND closed the htlc interceptor stream"))
+    }
+}
+
+/// Connect to LND and intercept its HTLCs on behalf of the gateway
+pub async fn build_lnd_rpc(
+    config: &LndConfig,
+    sender: GatewayRpcSender,
+    task_group: &mut TaskGroup,
+) -> anyhow::Result<Arc<LndRpc>> {
+    let lnd = Arc::new(LndRpc::connect(config).await?);
+
+    let interceptor = lnd.clone();
+    task_group
+        .spawn("lnd htlc interceptor", move |_| async move {
+            if let Err(e) = interceptor.run_htlc_interceptor(sender).await {
+                error!("LND htlc interceptor stopped: {:?}", e);
+            }
+        })
+        .await;
+
+    Ok(lnd)
+}
+
+/// Settle the HTLC if the federation sells us its preimage, otherwise tell LND to proceed normally
+async fn resolve_intercepted_htlc(
+    sender: &GatewayRpcSender,
+    htlc: ForwardHtlcInterceptRequest,
+) -> ForwardHtlcInterceptResponse {
+    let preimage = match sha256::Hash::from_slice(&htlc.payment_hash) {
+        Ok(payment_hash) => tokio::time::timeout(
+            HTLC_INTERCEPT_TIMEOUT,
+            sender.send(ReceivePaymentPayload {
+                payment_hash,
+                amount: Amount::from_msats(htlc.incoming_amount_msat),
+            }),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("htlc interception timeout"))),
+        Err(e) => Err(e.into()),
+    };
+
+    match preimage {
+        Ok(preimage) => ForwardHtlcInterceptResponse {
+            incoming_circuit_key: htlc.incoming_circuit_key,
+            action: ResolveHoldForwardAction::Settle as i32,
+            preimage: preimage.0.to_vec(),
+        },
+        Err(e) => {
+            error!("htlc interception error {:?}", e);
+            ForwardHtlcInterceptResponse {
+                incoming_circuit_key: htlc.incoming_circuit_key,
+                action: ResolveHoldForwardAction::Resume as i32,
+                preimage: vec![],
+            }
+        }
+    }
+}
+
+fn lnd_error(status: Status) -> LightningError {
+    debug!(code = ?status.code(), message = %status.message(), "LND returned error");
+    LightningError(Some(status.code() as i32))
+}
+
+fn max_fee_msat(amount_msat: u64, max_fee_percent: f64) -> i64 {
+    (amount_msat as f64 * max_fee_percent) as i64
+}
+
+fn parse_preimage(preimage: Vec<u8>) -> Result<Preimage, LightningError> {
+    let slice: [u8; 32] = preimage.try_into().map_err(|preimage| {
+        error!(?preimage, "LND returned invalid preimage");
+        LightningError(None)
+    })?;
+    Ok(Preimage(slice))
+}
+
+/// Wait for the final update of a payment and return its preimage if it succeeded
+async fn await_payment_preimage(
+    mut payment_updates: Streaming<Payment>,
+) -> Result<Preimage, LightningError> {
+    while let Some(payment) = payment_updates.message().await.map_err(lnd_error)? {
+        match payment.status() {
+            PaymentStatus::Succeeded => {
+                let preimage = hex::decode(&payment.payment_preimage).map_err(|e| {
+                    error!(?e, "LND returned invalid preimage");
+                    LightningError(None)
+                })?;
+                return parse_preimage(preimage);
+            }
+            PaymentStatus::Failed => return Err(LightningError(None)),
+            PaymentStatus::Unknown | PaymentStatus::InFlight => {}
+        }
+    }
+
+    debug!("LND stopped sending payment updates");
+    Err(LightningError(None))
+}
+
+#[async_trait]
+impl LnRpc for LndRpc {
+    #[instrument(name = "LnRpc::pubkey", skip(self))]
+    async fn pubkey(&self) -> Result<PublicKey, LightningError> {
+        let info = self
+            .lightning
+            .clone()
+            .get_info(GetInfoRequest {})
+            .await
+            .map_err(lnd_error)?
+            .into_inner();
+
+        PublicKey::from_str(&info.identity_pubkey).map_err(|e| {
+            error!(?e, "LND returned invalid node pubkey");
+            LightningError(None)
+        })
+    }
+
+    #[instrument(name = "LnRpc::pay", skip(self))]
+    async fn pay(
+        &self,
+        invoice: lightning_invoice::Invoice,
+        max_delay: u64,
+        max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError> {
+        debug!("Attempting to pay invoice");
+
+        let invoice_amount = invoice.amount_milli_satoshis().unwrap_or_default();
+        let payment_updates = self
+            .router
+            .clone()
+            .send_payment_v2(SendPaymentRequest {
+                payment_request: invoice.to_string(),
+                timeout_seconds: PAYMENT_TIMEOUT_SECS,
+                fee_limit_msat: max_fee_msat(invoice_amount, max_fee_percent),
+                cltv_limit: max_delay as i32,
+                no_inflight_updates: true,
+            })
+            .await
+            .map_err(lnd_error)?
+            .into_inner();
+
+        let result = await_payment_preimage(payment_updates).await;
+        match &result {
+            Ok(_) => debug!("Successfully paid invoice"),
+            Err(_) => debug!("LND failed to pay invoice"),
+        }
+        result
+    }
+
+    /// `SendPaymentV2` would split the payment itself, so we find a route for our part and
+    /// attach the multi-part payment record to its last hop before sending it. LND tracks the
+    /// HTLCs of a payment itself, so it doesn't need the part id.
+    #[instrument(name = "LnRpc::pay_partial", skip(self))]
+    async fn pay_partial(
+        &self,
+        invoice: lightning_invoice::Invoice,
+        _part_id: u16,
+        part_amount: Amount,
+        max_delay: u64,
+        max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError> {
+        debug!("Attempting to pay part of invoice");
+
+        let total_amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(LightningError(None))?;
+
+        let mut route = self
+            .lightning
+            .clone()
+            .query_routes(QueryRoutesRequest {
+                pub_key: invoice.recover_payee_pub_key().to_string(),
+                amt_msat: part_amount.msats as i64,
+                final_cltv_delta: invoice.min_final_cltv_expiry() as i32,
+                fee_limit: Some(FeeLimit {
+                    limit: Some(fee_limit::Limit::FixedMsat(max_fee_msat(
+                        part_amount.msats,
+                        max_fee_percent,
+                    ))),
+                }),
+                cltv_limit: max_delay as u32,
+            })
+            .await
+            .map_err(lnd_error)?
+            .into_inner()
+            .routes
+            .into_iter()
+            .next()
+            .ok_or(LightningError(None))?;
+
+        let last_hop = route.hops.last_mut().ok_or(LightningError(None))?;
+        last_hop.tlv_payload = true;
+        last_hop.mpp_record = Some(MppRecord {
+            payment_addr: invoice.payment_secret().0.to_vec(),
+            total_amt_msat: total_amount as i64,
+        });
+
+        let attempt = self
+            .router
+            .clone()
+            .send_to_route_v2(SendToRouteRequest {
+                payment_hash: invoice.payment_hash()[..].to_vec(),
+                route: Some(route),
+            })
+            .await
+            .map_err(lnd_error)?
+            .into_inner();
+
+        let result = match attempt.status() {
+            HtlcStatus::Succeeded => parse_preimage(attempt.preimage),
+            HtlcStatus::Failed => Err(LightningError(None)),
+            // The HTLC isn't resolved yet, e.g. because the recipient still waits for other
+            // parts, so we follow the payment until it either succeeds or fails
+            HtlcStatus::InFlight => {
+                debug!("Part of invoice still in flight");
+                let payment_updates = self
+                    .router
+                    .clone()
+                    .track_payment_v2(TrackPaymentRequest {
+                        payment_hash: invoice.payment_hash()[..].to_vec(),
+                        no_inflight_updates: true,
+                    })
+                    .await
+                    .map_err(lnd_error)?
+                    .into_inner();
+                await_payment_preimage(payment_updates).await
+            }
+        };
+        match &result {
+            Ok(_) => debug!("Successfully paid part of invoice"),
+            Err(_) => debug!("LND failed to pay part of invoice"),
+        }
+        result
+    }
+}
@@ -0,0 +1,121 @@
+//! LNURL-pay service that lets payers pay the lightning addresses `username@<gateway domain>` of
+//! users who registered with the gateway, see [`mint_client::ln::lnurl`]
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use mint_client::ln::lnurl::{LNURL_MAX_SENDABLE, LNURL_MIN_SENDABLE};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+use crate::LnGatewayError;
+
+/// Invoices payers may request for a single username per [`LNURL_CALLBACK_WINDOW`]
+pub const LNURL_CALLBACKS_PER_WINDOW: u32 = 10;
+
+/// Window in which the invoice requests for a username are counted
+pub const LNURL_CALLBACK_WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits how many invoices anonymous payers can request for a username, since every new invoice
+/// uses up one of the user's offers and submits a transaction to the federation
+#[derive(Debug, Default)]
+pub struct LnurlCallbackLimiter {
+    /// Start of the current window and the requests counted in it for every username, only
+    /// registered usernames are tracked
+    windows: HashMap<String, (Instant, u32)>,
+}
+
+impl LnurlCallbackLimiter {
+    /// Returns `false` if payers requested too many invoices for `username` recently
+    pub fn try_acquire(&mut self, username: &str, now: Instant) -> bool {
+        let (window_start, requests) = self.windows.entry(username.to_string()).or_insert((now, 0));
+        if LNURL_CALLBACK_WINDOW <= now.saturating_duration_since(*window_start) {
+            *window_start = now;
+            *requests = 0;
+        }
+
+        if *requests < LNURL_CALLBACKS_PER_WINDOW {
+            *requests += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Response to the first request of the LNURL-pay protocol (LUD-06) telling the payer's wallet
+/// how much it may pay and where to request the invoice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlPayParams {
+    pub callback: Url,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    /// JSON encoded metadata, the invoice commits to its hash
+    pub metadata: String,
+    pub tag: String,
+}
+
+impl LnurlPayParams {
+    pub fn new(announce_address: &Url, username: &str) -> Self {
+        LnurlPayParams {
+            callback: announce_address
+                .join(&format!("lnurl/{}/callback", username))
+                .expect("usernames only contain characters valid in URLs"),
+            min_sendable: LNURL_MIN_SENDABLE.msats,
+            max_sendable: LNURL_MAX_SENDABLE.msats,
+            metadata: lnurl_metadata(announce_address, username),
+            tag: "payRequest".to_string(),
+        }
+    }
+}
+
+/// Metadata shown to the payer, identifies the payee by their lightning address (LUD-16)
+pub fn lnurl_metadata(announce_address: &Url, username: &str) -> String {
+    let domain = announce_address.host_str().unwrap_or_default();
+    json!([
+        ["text/plain", format!("Payment to {}", username)],
+        ["text/identifier", format!("{}@{}", username, domain)],
+    ])
+    .to_string()
+}
+
+/// Errors are reported to LNURL wallets in the format defined by LUD-06
+#[derive(Debug)]
+pub struct LnurlError(pub LnGatewayError);
+
+impl From<LnGatewayError> for LnurlError {
+    fn from(e: LnGatewayError) -> Self {
+        LnurlError(e)
+    }
+}
+
+impl From<anyhow::Error> for LnurlError {
+    fn from(e: anyhow::Error) -> Self {
+        LnurlError(LnGatewayError::Other(e))
+    }
+}
+
+impl fmt::Display for LnurlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl IntoResponse for LnurlError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            LnGatewayError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let body = Json(json!({
+            "status": "ERROR",
+            "reason": self.0.to_string(),
+        }));
+        (status, body).into_response()
+    }
+}
@@ -5,15 +5,22 @@ use std::io::Cursor;
 
 use anyhow::{anyhow, Error};
 use bitcoin::{Address, Transaction, XOnlyPublicKey};
+use bitcoin_hashes::sha256::Hash as Sha256Hash;
 use fedimint_api::{Amount, TransactionId};
 use fedimint_server::{modules::ln::contracts::Preimage, modules::wallet::txoproof::TxOutProof};
 use futures::Future;
-use mint_client::{ln::PayInvoicePayload, FederationId};
+use mint_client::{
+    ln::{
+        lnurl::{LnurlInvoice, LnurlInvoicesRequest, LnurlRegisterPayload, LnurlRegistration},
+        PayInvoicePayload,
+    },
+    FederationId,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::sync::{mpsc, oneshot};
 use tracing::error;
 
-use crate::{cln::HtlcAccepted, LnGatewayError, Result};
+use crate::{lnurl::LnurlPayParams, LnGatewayError, Result};
 
 #[derive(Debug, Clone)]
 pub struct GatewayRpcSender {
@@ -61,7 +68,10 @@ pub struct ConnectFedPayload {
 pub struct ReceivePaymentPayload {
     // NOTE: On ReceivePayment signal from ln_rpc,
     // we extract the relevant federation id from the accepted htlc
-    pub htlc_accepted: HtlcAccepted,
+    /// Payment hash of the intercepted htlc
+    pub payment_hash: Sha256Hash,
+    /// Amount of the intercepted htlc
+    pub amount: Amount,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -96,6 +106,24 @@ pub struct WithdrawPayload {
     pub address: Address,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LnurlPayRequestPayload {
+    pub username: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LnurlCallbackPayload {
+    pub username: String,
+    /// Amount the payer wants to pay, chosen within the bounds of [`LnurlPayParams`]
+    pub amount: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LnurlInvoicesPayload {
+    pub username: String,
+    pub request: LnurlInvoicesRequest,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FederationInfo {
     pub federation_id: FederationId,
@@ -118,6 +146,10 @@ pub enum GatewayRequest {
     DepositAddress(GatewayRequestInner<DepositAddressPayload>),
     Deposit(GatewayRequestInner<DepositPayload>),
     Withdraw(GatewayRequestInner<WithdrawPayload>),
+    LnurlRegister(GatewayRequestInner<LnurlRegisterPayload>),
+    LnurlPayRequest(GatewayRequestInner<LnurlPayRequestPayload>),
+    LnurlCallback(GatewayRequestInner<LnurlCallbackPayload>),
+    LnurlInvoices(GatewayRequestInner<LnurlInvoicesPayload>),
 }
 
 #[derive(Debug)]
@@ -162,6 +194,26 @@ impl_gateway_request_trait!(
 );
 impl_gateway_request_trait!(DepositPayload, TransactionId, GatewayRequest::Deposit);
 impl_gateway_request_trait!(WithdrawPayload, TransactionId, GatewayRequest::Withdraw);
+impl_gateway_request_trait!(
+    LnurlRegisterPayload,
+    LnurlRegistration,
+    GatewayRequest::LnurlRegister
+);
+impl_gateway_request_trait!(
+    LnurlPayRequestPayload,
+    LnurlPayParams,
+    GatewayRequest::LnurlPayRequest
+);
+impl_gateway_request_trait!(
+    LnurlCallbackPayload,
+    LnurlInvoice,
+    GatewayRequest::LnurlCallback
+);
+impl_gateway_request_trait!(
+    LnurlInvoicesPayload,
+    Vec<LnurlInvoice>,
+    GatewayRequest::LnurlInvoices
+);
 
 impl<T> GatewayRequestInner<T>
 where
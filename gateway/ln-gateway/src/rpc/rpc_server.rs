@@ -1,17 +1,28 @@
 use std::net::SocketAddr;
 
-use axum::{response::IntoResponse, routing::post, Extension, Json, Router};
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Json, Router,
+};
 use axum_macros::debug_handler;
-use mint_client::ln::PayInvoicePayload;
+use fedimint_api::Amount;
+use mint_client::ln::{
+    lnurl::{LnurlInvoicesRequest, LnurlRegisterPayload},
+    PayInvoicePayload,
+};
+use serde::Deserialize;
 use serde_json::json;
 use tower_http::{auth::RequireAuthorizationLayer, cors::CorsLayer};
 use tracing::instrument;
 
 use super::{
     BalancePayload, ConnectFedPayload, DepositAddressPayload, DepositPayload, GatewayRpcSender,
-    InfoPayload, WithdrawPayload,
+    InfoPayload, LnurlCallbackPayload, LnurlInvoicesPayload, LnurlPayRequestPayload,
+    WithdrawPayload,
 };
-use crate::LnGatewayError;
+use crate::{lnurl::LnurlError, LnGatewayError};
 
 pub async fn run_webserver(
     authkey: String,
@@ -19,7 +30,13 @@ pub async fn run_webserver(
     sender: GatewayRpcSender,
 ) -> axum::response::Result<()> {
     // Public routes on gateway webserver
-    let routes = Router::new().route("/pay_invoice", post(pay_invoice));
+    let routes = Router::new()
+        .route("/health", get(health))
+        .route("/pay_invoice", post(pay_invoice))
+        .route("/lnurl/register", post(lnurl_register))
+        .route("/lnurl/:username/invoices", post(lnurl_invoices))
+        .route("/.well-known/lnurlp/:username", get(lnurl_pay_request))
+        .route("/lnurl/:username/callback", get(lnurl_callback));
 
     // Authenticated, public routes used for gateway administration
     let admin_routes = Router::new()
@@ -45,6 +62,12 @@ pub async fn run_webserver(
     Ok(())
 }
 
+/// Lets clients check that the gateway is up before funding outgoing contracts for it
+#[debug_handler]
+async fn health() -> impl IntoResponse {
+    Json(json!({ "status": "OK" }))
+}
+
 /// Display gateway ecash token balance
 #[debug_handler]
 #[instrument(skip_all, err)]
@@ -118,3 +141,61 @@ async fn connect(
     rpc.send(payload).await?;
     Ok(())
 }
+
+/// Register a username to receive LNURL-pay payments or add offers to it, signed by the username's
+/// key
+#[instrument(skip_all, err)]
+async fn lnurl_register(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<LnurlRegisterPayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let registration = rpc.send(payload).await?;
+    Ok(Json(json!(registration)))
+}
+
+/// List the invoices created for payments to a username, so its owner can claim them, signed by
+/// the username's key
+#[instrument(skip_all, err)]
+async fn lnurl_invoices(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Path(username): Path<String>,
+    Json(request): Json<LnurlInvoicesRequest>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let invoices = rpc.send(LnurlInvoicesPayload { username, request }).await?;
+    Ok(Json(json!(invoices)))
+}
+
+/// First step of LNURL-pay, resolves the lightning address `username@<domain>`
+#[instrument(skip_all, err)]
+async fn lnurl_pay_request(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Path(username): Path<String>,
+) -> Result<impl IntoResponse, LnurlError> {
+    let params = rpc.send(LnurlPayRequestPayload { username }).await?;
+    Ok(Json(json!(params)))
+}
+
+#[derive(Debug, Deserialize)]
+struct LnurlCallbackQuery {
+    /// Amount in msat
+    amount: u64,
+}
+
+/// Second step of LNURL-pay, creates an invoice for the amount chosen by the payer
+#[instrument(skip_all, err)]
+async fn lnurl_callback(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Path(username): Path<String>,
+    Query(query): Query<LnurlCallbackQuery>,
+) -> Result<impl IntoResponse, LnurlError> {
+    let lnurl_invoice = rpc
+        .send(LnurlCallbackPayload {
+            username,
+            amount: Amount::from_msats(query.amount),
+        })
+        .await?;
+    Ok(Json(json!({
+        "pr": lnurl_invoice.invoice.to_string(),
+        "routes": [],
+    })))
+}
@@ -69,6 +69,7 @@ impl IGatewayClientBuilder for TestGatewayClientBuilder {
             timelock_delta: 10,
             node_pub_key: node_pubkey,
             api: announce_address,
+            fees: Default::default(),
         })
     }
 
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use anyhow::Result;
 use async_trait::async_trait;
 use bitcoin::{secp256k1, KeyPair};
+use fedimint_api::Amount;
 use fedimint_ln::contracts::Preimage;
 use lightning_invoice::Invoice;
 use ln_gateway::ln::{LightningError, LnRpc};
@@ -44,4 +45,17 @@ impl LnRpc for MockLnRpc {
 
         Ok(self.preimage.clone())
     }
+
+    async fn pay_partial(
+        &self,
+        _invoice: Invoice,
+        _part_id: u16,
+        part_amount: Amount,
+        _max_delay: u64,
+        _max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError> {
+        *self.amount_sent.lock().await += part_amount.msats;
+
+        Ok(self.preimage.clone())
+    }
 }
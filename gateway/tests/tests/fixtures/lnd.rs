@@ -0,0 +1,244 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use bitcoin::{secp256k1, KeyPair};
+use fedimint_ln::contracts::Preimage;
+use futures::{stream, Stream, StreamExt};
+use ln_gateway::lnd::{
+    lnrpc::{
+        self, htlc_attempt::HtlcStatus, lightning_server::LightningServer, payment::PaymentStatus,
+        GetInfoRequest, GetInfoResponse, Hop, HtlcAttempt, Payment, QueryRoutesRequest,
+        QueryRoutesResponse, Route,
+    },
+    routerrpc::{
+        self, router_server::RouterServer, ForwardHtlcInterceptRequest,
+        ForwardHtlcInterceptResponse, SendPaymentRequest, SendToRouteRequest, TrackPaymentRequest,
+    },
+    LndRpc,
+};
+use ln_gateway::utils::retry;
+use rand::rngs::OsRng;
+use tokio::sync::Mutex;
+use tonic::{
+    metadata::MetadataMap,
+    transport::{Channel, Server},
+    Request, Response, Status, Streaming,
+};
+
+/// Macaroon the mock node expects clients to authenticate with
+pub const MOCK_MACAROON: &[u8] = b"mock-lnd-macaroon";
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Mock LND node implementing the subset of its gRPC interface used by the gateway
+pub struct MockLnd {
+    pub preimage: Preimage,
+    pub node_pubkey: secp256k1::PublicKey,
+    /// HTLCs the node hands to the gateway's interceptor
+    intercepted_htlcs: Vec<ForwardHtlcInterceptRequest>,
+    /// Resolutions the gateway sent for the intercepted HTLCs
+    pub htlc_resolutions: Arc<Mutex<Vec<ForwardHtlcInterceptResponse>>>,
+    /// Amount paid via `SendToRouteV2`
+    pub amount_sent: Mutex<u64>,
+    /// Whether `SendToRouteV2` returns before the HTLC got settled
+    pub in_flight_parts: bool,
+}
+
+impl MockLnd {
+    pub fn new(intercepted_htlcs: Vec<ForwardHtlcInterceptRequest>) -> Self {
+        let ctx = secp256k1::Secp256k1::new();
+        let kp = KeyPair::new(&ctx, &mut OsRng);
+
+        Self {
+            preimage: Preimage([1; 32]),
+            node_pubkey: secp256k1::PublicKey::from_keypair(&kp),
+            intercepted_htlcs,
+            htlc_resolutions: Arc::new(Mutex::new(vec![])),
+            amount_sent: Mutex::new(0),
+            in_flight_parts: false,
+        }
+    }
+
+    fn authenticate(metadata: &MetadataMap) -> Result<(), Status> {
+        match metadata.get("macaroon") {
+            Some(macaroon) if macaroon.as_bytes() == hex::encode(MOCK_MACAROON).as_bytes() => {
+                Ok(())
+            }
+            _ => Err(Status::unauthenticated("invalid macaroon")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl lnrpc::lightning_server::Lightning for MockLnd {
+    async fn get_info(
+        &self,
+        request: Request<GetInfoRequest>,
+    ) -> Result<Response<GetInfoResponse>, Status> {
+        Self::authenticate(request.metadata())?;
+
+        Ok(Response::new(GetInfoResponse {
+            identity_pubkey: self.node_pubkey.to_string(),
+            alias: "mock-lnd".to_string(),
+        }))
+    }
+
+    async fn query_routes(
+        &self,
+        request: Request<QueryRoutesRequest>,
+    ) -> Result<Response<QueryRoutesResponse>, Status> {
+        Self::authenticate(request.metadata())?;
+        let request = request.into_inner();
+
+        let hop = Hop {
+            chan_id: 1,
+            expiry: request.final_cltv_delta as u32,
+            amt_to_forward_msat: request.amt_msat,
+            fee_msat: 0,
+            pub_key: request.pub_key,
+            tlv_payload: true,
+            mpp_record: None,
+        };
+        Ok(Response::new(QueryRoutesResponse {
+            routes: vec![Route {
+                total_time_lock: hop.expiry,
+                hops: vec![hop],
+                total_fees_msat: 0,
+                total_amt_msat: request.amt_msat,
+            }],
+            success_prob: 1.0,
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl routerrpc::router_server::Router for MockLnd {
+    type SendPaymentV2Stream = ResponseStream<Payment>;
+
+    async fn send_payment_v2(
+        &self,
+        request: Request<SendPaymentRequest>,
+    ) -> Result<Response<Self::SendPaymentV2Stream>, Status> {
+        Self::authenticate(request.metadata())?;
+        let request = request.into_inner();
+
+        let mut in_flight = Payment {
+            payment_request: request.payment_request,
+            ..Default::default()
+        };
+        in_flight.set_status(PaymentStatus::InFlight);
+        let mut succeeded = Payment {
+            payment_preimage: hex::encode(self.preimage.0),
+            ..in_flight.clone()
+        };
+        succeeded.set_status(PaymentStatus::Succeeded);
+
+        Ok(Response::new(
+            Box::pin(stream::iter(vec![Ok(in_flight), Ok(succeeded)])) as Self::SendPaymentV2Stream,
+        ))
+    }
+
+    async fn send_to_route_v2(
+        &self,
+        request: Request<SendToRouteRequest>,
+    ) -> Result<Response<HtlcAttempt>, Status> {
+        Self::authenticate(request.metadata())?;
+        let route = request
+            .into_inner()
+            .route
+            .ok_or_else(|| Status::invalid_argument("missing route"))?;
+        let last_hop = route
+            .hops
+            .last()
+            .ok_or_else(|| Status::invalid_argument("empty route"))?;
+        if last_hop.mpp_record.is_none() {
+            return Err(Status::invalid_argument("missing mpp record"));
+        }
+        *self.amount_sent.lock().await += last_hop.amt_to_forward_msat as u64;
+
+        let mut attempt = HtlcAttempt {
+            route: Some(route),
+            ..Default::default()
+        };
+        if self.in_flight_parts {
+            attempt.set_status(HtlcStatus::InFlight);
+        } else {
+            attempt.preimage = self.preimage.0.to_vec();
+            attempt.set_status(HtlcStatus::Succeeded);
+        }
+        Ok(Response::new(attempt))
+    }
+
+    type TrackPaymentV2Stream = ResponseStream<Payment>;
+
+    async fn track_payment_v2(
+        &self,
+        request: Request<TrackPaymentRequest>,
+    ) -> Result<Response<Self::TrackPaymentV2Stream>, Status> {
+        Self::authenticate(request.metadata())?;
+        let request = request.into_inner();
+
+        let mut succeeded = Payment {
+            payment_hash: hex::encode(request.payment_hash),
+            payment_preimage: hex::encode(self.preimage.0),
+            ..Default::default()
+        };
+        succeeded.set_status(PaymentStatus::Succeeded);
+
+        Ok(Response::new(
+            Box::pin(stream::iter(vec![Ok(succeeded)])) as Self::TrackPaymentV2Stream
+        ))
+    }
+
+    type HtlcInterceptorStream = ResponseStream<ForwardHtlcInterceptRequest>;
+
+    async fn htlc_interceptor(
+        &self,
+        request: Request<Streaming<ForwardHtlcInterceptResponse>>,
+    ) -> Result<Response<Self::HtlcInterceptorStream>, Status> {
+        Self::authenticate(request.metadata())?;
+
+        let mut resolutions = request.into_inner();
+        let htlc_resolutions = self.htlc_resolutions.clone();
+        tokio::spawn(async move {
+            while let Ok(Some(resolution)) = resolutions.message().await {
+                htlc_resolutions.lock().await.push(resolution);
+            }
+        });
+
+        // Keep the stream open like LND does, even after all HTLCs were handed out
+        let htlcs = stream::iter(self.intercepted_htlcs.clone().into_iter().map(Ok));
+        Ok(Response::new(
+            Box::pin(htlcs.chain(stream::pending())) as Self::HtlcInterceptorStream
+        ))
+    }
+}
+
+/// Serve `mock` over gRPC and connect an [`LndRpc`] to it
+pub async fn mock_lnd_rpc(mock: Arc<MockLnd>) -> Result<LndRpc> {
+    let port = portpicker::pick_unused_port().expect("Failed to pick port");
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let server = Server::builder()
+        .add_service(LightningServer::from_arc(mock.clone()))
+        .add_service(RouterServer::from_arc(mock))
+        .serve(address);
+    tokio::spawn(server);
+
+    let channel = retry(
+        "connect to mock lnd".to_string(),
+        || async {
+            Ok(Channel::from_shared(format!("http://{}", address))?
+                .connect()
+                .await?)
+        },
+        Duration::from_millis(100),
+        30,
+    )
+    .await?;
+
+    Ok(LndRpc::new(channel, MOCK_MACAROON))
+}
@@ -14,6 +14,7 @@ use tokio::sync::mpsc;
 pub mod client;
 pub mod fed;
 pub mod ln;
+pub mod lnd;
 
 pub struct Fixtures {
     pub bitcoin: Box<dyn BitcoinTest>,
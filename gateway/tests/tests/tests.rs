@@ -2,21 +2,35 @@ mod fixtures;
 
 use std::future::Future;
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1;
+use fedimint_api::Amount;
+use fixtures::lnd::{mock_lnd_rpc, MockLnd};
 use fixtures::{fixtures, Fixtures};
+use lightning::ln::PaymentSecret;
+use lightning_invoice::{Currency, InvoiceBuilder};
+use ln_gateway::lnd::routerrpc::{
+    CircuitKey, ForwardHtlcInterceptRequest, ResolveHoldForwardAction,
+};
+use ln_gateway::lnurl::{LnurlCallbackLimiter, LNURL_CALLBACKS_PER_WINDOW, LNURL_CALLBACK_WINDOW};
 use ln_gateway::rpc::rpc_client::{Error, Response};
 use ln_gateway::{
     config::GatewayConfig,
+    ln::LnRpc,
     rpc::{
         rpc_client::RpcClient, BalancePayload, ConnectFedPayload, DepositAddressPayload,
-        DepositPayload, WithdrawPayload,
+        DepositPayload, GatewayRequest, GatewayRpcSender, WithdrawPayload,
     },
     utils::retry,
 };
 use mint_client::api::WsFederationConnect;
 use mint_client::FederationId;
+use rand::rngs::OsRng;
+use tokio::sync::mpsc;
 use url::Url;
 
 #[tokio::test(flavor = "multi_thread")]
@@ -33,6 +47,8 @@ async fn test_gateway_authentication() -> Result<()> {
         default_federation: federation_id.clone(),
         bind_address: gw_bind_address,
         announce_address: gw_announce_address.clone(),
+        lightning: Default::default(),
+        fees: Default::default(),
     };
 
     let Fixtures {
@@ -157,3 +173,144 @@ where
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_lnd_pubkey_and_pay() -> Result<()> {
+    let mock = Arc::new(MockLnd::new(vec![]));
+    let lnd = mock_lnd_rpc(mock.clone()).await?;
+
+    assert_eq!(lnd.pubkey().await.unwrap(), mock.node_pubkey);
+
+    let ctx = secp256k1::Secp256k1::new();
+    let (payee_key, _) = ctx.generate_keypair(&mut OsRng);
+    let invoice = InvoiceBuilder::new(Currency::Regtest)
+        .description("".to_string())
+        .payment_hash(sha256::Hash::hash(&mock.preimage.0))
+        .current_timestamp()
+        .min_final_cltv_expiry(18)
+        .payment_secret(PaymentSecret([0; 32]))
+        .basic_mpp()
+        .amount_milli_satoshis(2000)
+        .build_signed(|m| ctx.sign_ecdsa_recoverable(m, &payee_key))
+        .unwrap();
+
+    assert_eq!(
+        lnd.pay(invoice.clone(), 144, 0.01).await.unwrap(),
+        mock.preimage
+    );
+    assert_eq!(
+        lnd.pay_partial(invoice, 1, Amount::from_msats(1000), 144, 0.01)
+            .await
+            .unwrap(),
+        mock.preimage
+    );
+    assert_eq!(*mock.amount_sent.lock().await, 1000);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_lnd_pay_partial_in_flight() -> Result<()> {
+    let mut mock = MockLnd::new(vec![]);
+    mock.in_flight_parts = true;
+    let mock = Arc::new(mock);
+    let lnd = mock_lnd_rpc(mock.clone()).await?;
+
+    let ctx = secp256k1::Secp256k1::new();
+    let (payee_key, _) = ctx.generate_keypair(&mut OsRng);
+    let invoice = InvoiceBuilder::new(Currency::Regtest)
+        .description("".to_string())
+        .payment_hash(sha256::Hash::hash(&mock.preimage.0))
+        .current_timestamp()
+        .min_final_cltv_expiry(18)
+        .payment_secret(PaymentSecret([0; 32]))
+        .basic_mpp()
+        .amount_milli_satoshis(2000)
+        .build_signed(|m| ctx.sign_ecdsa_recoverable(m, &payee_key))
+        .unwrap();
+
+    // the part only counts as paid once the tracked payment succeeded
+    assert_eq!(
+        lnd.pay_partial(invoice, 1, Amount::from_msats(1000), 144, 0.01)
+            .await
+            .unwrap(),
+        mock.preimage
+    );
+    assert_eq!(*mock.amount_sent.lock().await, 1000);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_lnd_htlc_interceptor() -> Result<()> {
+    let preimage = MockLnd::new(vec![]).preimage;
+    let payment_hash = sha256::Hash::hash(&preimage.0);
+    let circuit_key = CircuitKey {
+        chan_id: 1,
+        htlc_id: 0,
+    };
+    let mock = Arc::new(MockLnd::new(vec![ForwardHtlcInterceptRequest {
+        incoming_circuit_key: Some(circuit_key.clone()),
+        incoming_amount_msat: 1000,
+        payment_hash: payment_hash[..].to_vec(),
+        ..Default::default()
+    }]));
+    let lnd = mock_lnd_rpc(mock.clone()).await?;
+
+    let (tx, mut rx) = mpsc::channel::<GatewayRequest>(100);
+    tokio::spawn(async move { lnd.run_htlc_interceptor(GatewayRpcSender::new(tx)).await });
+
+    // Act as the gateway selling the preimage of the intercepted HTLC
+    match rx.recv().await {
+        Some(GatewayRequest::ReceivePayment(inner)) => {
+            inner
+                .handle(|payload| {
+                    let preimage = preimage.clone();
+                    async move {
+                        assert_eq!(payload.payment_hash, payment_hash);
+                        assert_eq!(payload.amount, Amount::from_msats(1000));
+                        Ok(preimage)
+                    }
+                })
+                .await
+        }
+        _ => panic!("Expected intercepted HTLC to be sent to the gateway"),
+    }
+
+    let resolution = retry(
+        "await htlc resolution".to_string(),
+        || async {
+            mock.htlc_resolutions
+                .lock()
+                .await
+                .first()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("HTLC not resolved yet"))
+        },
+        Duration::from_millis(100),
+        30,
+    )
+    .await?;
+    assert_eq!(resolution.incoming_circuit_key, Some(circuit_key));
+    assert_eq!(resolution.action(), ResolveHoldForwardAction::Settle);
+    assert_eq!(resolution.preimage, preimage.0.to_vec());
+
+    Ok(())
+}
+
+#[test]
+fn test_lnurl_callbacks_are_limited_per_username() {
+    let mut limiter = LnurlCallbackLimiter::default();
+    let start = Instant::now();
+
+    for _ in 0..LNURL_CALLBACKS_PER_WINDOW {
+        assert!(limiter.try_acquire("satoshi", start));
+    }
+    assert!(!limiter.try_acquire("satoshi", start));
+
+    // Other usernames have their own limit
+    assert!(limiter.try_acquire("hal", start));
+
+    // The limit resets once the window passed
+    assert!(limiter.try_acquire("satoshi", start + LNURL_CALLBACK_WINDOW));
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -20,6 +21,8 @@ pub struct FakeLightningTest {
     pub gateway_node_pub_key: secp256k1::PublicKey,
     gateway_node_sec_key: secp256k1::SecretKey,
     amount_sent: Arc<Mutex<u64>>,
+    /// Parts of multi-part payments sent so far, which like on a real node need distinct ids
+    parts_sent: Arc<Mutex<BTreeSet<(sha256::Hash, u16)>>>,
 }
 
 impl FakeLightningTest {
@@ -33,6 +36,7 @@ impl FakeLightningTest {
             gateway_node_sec_key: SecretKey::from_keypair(&kp),
             gateway_node_pub_key: PublicKey::from_keypair(&kp),
             amount_sent,
+            parts_sent: Default::default(),
         }
     }
 }
@@ -48,6 +52,7 @@ impl LightningTest for FakeLightningTest {
             .current_timestamp()
             .min_final_cltv_expiry(0)
             .payment_secret(PaymentSecret([0; 32]))
+            .basic_mpp()
             .amount_milli_satoshis(amount.msats)
             .expiry_time(Duration::from_secs(
                 expiry_time.unwrap_or(DEFAULT_EXPIRY_TIME),
@@ -77,4 +82,25 @@ impl LnRpc for FakeLightningTest {
 
         Ok(self.preimage.clone())
     }
+
+    async fn pay_partial(
+        &self,
+        invoice: lightning_invoice::Invoice,
+        part_id: u16,
+        part_amount: Amount,
+        _max_delay: u64,
+        _max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError> {
+        if !self
+            .parts_sent
+            .lock()
+            .unwrap()
+            .insert((*invoice.payment_hash(), part_id))
+        {
+            return Err(LightningError(None));
+        }
+        *self.amount_sent.lock().unwrap() += part_amount.msats;
+
+        Ok(self.preimage.clone())
+    }
 }
@@ -24,7 +24,7 @@ use fedimint_api::core::{
     MODULE_KEY_WALLET,
 };
 use fedimint_api::db::mem_impl::MemDatabase;
-use fedimint_api::db::Database;
+use fedimint_api::db::{Database, DatabaseKeyPrefix, DatabaseKeyPrefixConst};
 use fedimint_api::module::FederationModuleConfigGen;
 use fedimint_api::net::peers::IMuxPeerConnections;
 use fedimint_api::task::{timeout, TaskGroup};
@@ -34,12 +34,13 @@ use fedimint_api::TieredMulti;
 use fedimint_api::{sats, Amount};
 use fedimint_bitcoind::BitcoindRpc;
 use fedimint_ln::LightningModule;
-use fedimint_ln::{LightningGateway, LightningModuleConfigGen};
+use fedimint_ln::{GatewayFee, LightningGateway, LightningModuleConfigGen};
 use fedimint_mint::{Mint, MintConfigGenerator, MintOutput};
 use fedimint_server::config::{connect, ServerConfig, DEFAULT_P2P_PORT};
 use fedimint_server::config::{ModuleConfigGens, ServerConfigParams};
 use fedimint_server::consensus::{ConsensusProposal, HbbftConsensusOutcome};
 use fedimint_server::consensus::{FedimintConsensus, TransactionSubmissionError};
+use fedimint_server::epoch::ConsensusSnapshot;
 use fedimint_server::multiplexed::PeerConnectionMultiplexer;
 use fedimint_server::net::connect::mock::MockNetwork;
 use fedimint_server::net::connect::{Connector, TlsTcpConnector};
@@ -70,7 +71,10 @@ use mint_client::{
 use rand::rngs::OsRng;
 use rand::RngCore;
 use real::{RealBitcoinTest, RealLightningTest};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 use url::Url;
@@ -80,6 +84,7 @@ use crate::ConsensusItem;
 
 mod fake;
 mod real;
+pub mod sim;
 mod utils;
 
 static BASE_PORT: AtomicU16 = AtomicU16::new(DEFAULT_P2P_PORT + 10000);
@@ -122,7 +127,26 @@ pub async fn test<B>(
 where
     B: Future<Output = ()>,
 {
-    let fixtures = fixtures(num_peers).await?;
+    test_with_config(num_peers, |_| {}, f).await
+}
+
+/// Like [`test`], but lets `modify_config` change the generated server configs before the
+/// federation is started
+pub async fn test_with_config<B>(
+    num_peers: u16,
+    modify_config: impl FnOnce(&mut BTreeMap<PeerId, ServerConfig>),
+    f: impl FnOnce(
+        FederationTest,
+        UserTest<UserClientConfig>,
+        Box<dyn BitcoinTest>,
+        GatewayTest,
+        Box<dyn LightningTest>,
+    ) -> B,
+) -> anyhow::Result<()>
+where
+    B: Future<Output = ()>,
+{
+    let fixtures = fixtures_with_config(num_peers, modify_config).await?;
     f(
         fixtures.fed,
         fixtures.user,
@@ -137,6 +161,14 @@ where
 /// Generates the fixtures for an integration test and spawns API and HBBFT consensus threads for
 /// federation nodes starting at port DEFAULT_P2P_PORT.
 pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
+    fixtures_with_config(num_peers, |_| {}).await
+}
+
+/// Like [`fixtures`], but lets `modify_config` change the generated server configs
+pub async fn fixtures_with_config(
+    num_peers: u16,
+    modify_config: impl FnOnce(&mut BTreeMap<PeerId, ServerConfig>),
+) -> anyhow::Result<Fixtures> {
     let mut task_group = TaskGroup::new();
     let base_port = BASE_PORT.fetch_add(num_peers * 10, Ordering::Relaxed);
 
@@ -154,19 +186,12 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
         ServerConfigParams::gen_local(&peers, sats(100000), base_port, "test", "127.0.0.1:18443");
     let max_evil = hbbft::util::max_faulty(peers.len());
 
-    let module_config_gens: ModuleConfigGens = BTreeMap::from([
-        (
-            "wallet",
-            Arc::new(WalletConfigGenerator) as Arc<dyn FederationModuleConfigGen + Send + Sync>,
-        ),
-        ("mint", Arc::new(MintConfigGenerator)),
-        ("ln", Arc::new(LightningModuleConfigGen)),
-    ]);
+    let module_config_gens = module_config_gens();
 
     match env::var("FM_TEST_DISABLE_MOCKS") {
         Ok(s) if s == "1" => {
             info!("Testing with REAL Bitcoin and Lightning services");
-            let (server_config, client_config) = distributed_config(
+            let (mut server_config, _) = distributed_config(
                 "",
                 &peers,
                 params,
@@ -176,6 +201,10 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
             )
             .await
             .expect("distributed config should not be canceled");
+            modify_config(&mut server_config);
+            let client_config = server_config[&PeerId::from(0)]
+                .consensus
+                .to_client_config(&module_config_gens);
 
             let dir = env::var("FM_TEST_DIR").expect("Must have test dir defined for real tests");
             let wallet_config: WalletConfig = server_config
@@ -185,7 +214,7 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
                 .1
                 .get_module_config_typed("wallet")
                 .unwrap();
-            let bitcoin_rpc = fedimint_bitcoind::bitcoincore_rpc::make_bitcoind_rpc(
+            let bitcoin_rpc = fedimint_bitcoind::make_bitcoin_rpc(
                 &wallet_config.local.btc_rpc,
                 task_group.make_handle(),
             )
@@ -245,13 +274,14 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
         }
         _ => {
             info!("Testing with FAKE Bitcoin and Lightning services");
-            let server_config = ServerConfig::trusted_dealer_gen(
+            let mut server_config = ServerConfig::trusted_dealer_gen(
                 "",
                 &peers,
                 &params,
                 module_config_gens.clone(),
                 OsRng,
             );
+            modify_config(&mut server_config);
             let client_config = server_config[&PeerId::from(0)]
                 .consensus
                 .to_client_config(&module_config_gens);
@@ -301,6 +331,50 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
     }
 }
 
+/// Config generators of the modules every test federation runs
+fn module_config_gens() -> ModuleConfigGens {
+    BTreeMap::from([
+        (
+            "wallet",
+            Arc::new(WalletConfigGenerator) as Arc<dyn FederationModuleConfigGen + Send + Sync>,
+        ),
+        ("mint", Arc::new(MintConfigGenerator)),
+        ("ln", Arc::new(LightningModuleConfigGen)),
+    ])
+}
+
+/// Sets up the consensus of a guardian with the modules of [`module_config_gens`]
+async fn new_consensus(
+    cfg: &ServerConfig,
+    db: Database,
+    btc_rpc: BitcoindRpc,
+    module_config_gens: ModuleConfigGens,
+    task_group: &mut TaskGroup,
+) -> FedimintConsensus {
+    let mint = Mint::new(cfg.get_module_config_typed("mint").unwrap());
+
+    let wallet = Wallet::new_with_bitcoind(
+        cfg.get_module_config_typed("wallet").unwrap(),
+        db.clone(),
+        btc_rpc,
+        task_group,
+        all_decoders(),
+    )
+    .await
+    .expect("Couldn't create wallet");
+
+    let ln = LightningModule::new(cfg.get_module_config_typed("ln").unwrap());
+
+    FedimintConsensus::new(
+        cfg.clone(),
+        db,
+        module_config_gens,
+        vec![mint.into(), wallet.into(), ln.into()],
+    )
+    .await
+    .expect("Failed to migrate the database")
+}
+
 pub fn peers(peers: &[u16]) -> Vec<PeerId> {
     peers
         .iter()
@@ -418,6 +492,67 @@ impl GatewayTest {
         client_config: ClientConfig,
         node_pub_key: secp256k1::PublicKey,
         bind_port: u16,
+    ) -> Self {
+        Self::with_adapter(
+            Arc::new(ln_client_adapter),
+            client_config,
+            node_pub_key,
+            bind_port,
+        )
+        .await
+    }
+
+    /// Creates another gateway for the same federation that sends payments through the same
+    /// lightning node. The federation lists gateways by node key, so it announces a random one.
+    pub async fn new_gateway_sharing_node(&self) -> Self {
+        let ctx = bitcoin::secp256k1::Secp256k1::new();
+        let node_pub_key = secp256k1::PublicKey::from_keypair(&KeyPair::new(&ctx, &mut OsRng));
+        let bind_port = self
+            .client
+            .config()
+            .api
+            .port()
+            .expect("Gateway API has a port")
+            + 1;
+
+        Self::with_adapter(
+            self.adapter.clone(),
+            self.client.config().client_config,
+            node_pub_key,
+            bind_port,
+        )
+        .await
+    }
+
+    /// Answers every request to our API with `200 OK` until the returned task is aborted, standing
+    /// in for our webserver in health probes as we don't run the gateway in tests
+    pub async fn serve_health_probes(&self) -> JoinHandle<()> {
+        let port = self
+            .client
+            .config()
+            .api
+            .port()
+            .expect("Gateway API has a port");
+        let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+            .await
+            .expect("Gateway API port is free");
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await;
+            }
+        })
+    }
+
+    async fn with_adapter(
+        adapter: Arc<LnRpcAdapter>,
+        client_config: ClientConfig,
+        node_pub_key: secp256k1::PublicKey,
+        bind_port: u16,
     ) -> Self {
         let mut rng = OsRng;
         let ctx = bitcoin::secp256k1::Secp256k1::new();
@@ -428,6 +563,8 @@ impl GatewayTest {
             node_pub_key,
             api: Url::parse("http://example.com")
                 .expect("Could not parse URL to generate GatewayClientConfig API endpoint"),
+            fees: GatewayFee::default(),
+            timelock_delta: 10,
         };
 
         let bind_addr: SocketAddr = format!("127.0.0.1:{}", bind_port).parse().unwrap();
@@ -439,6 +576,7 @@ impl GatewayTest {
             timelock_delta: 10,
             api: announce_addr.clone(),
             node_pub_key,
+            fees: GatewayFee::default(),
         };
 
         // Create federation client builder for the gateway
@@ -446,7 +584,6 @@ impl GatewayTest {
             StandardGatewayClientBuilder::new(PathBuf::new(), MemDbFactory.into()).into();
 
         let (sender, receiver) = tokio::sync::mpsc::channel::<GatewayRequest>(100);
-        let adapter = Arc::new(ln_client_adapter);
         let ln_rpc = Arc::clone(&adapter);
 
         let gw_cfg = GatewayConfig {
@@ -454,6 +591,8 @@ impl GatewayTest {
             announce_address: announce_addr,
             password: "abc".into(),
             default_federation: FederationId(gw_client_cfg.client_config.federation_name.clone()),
+            lightning: Default::default(),
+            fees: GatewayFee::default(),
         };
 
         let gateway = LnGateway::new(
@@ -504,6 +643,18 @@ impl UserTest<UserClientConfig> {
         let user = create_user_client(self.config.clone(), peers, MemDatabase::new().into()).await;
         UserTest::new(Arc::new(user))
     }
+
+    /// Create a user with a different client config, e.g. one that is outdated
+    pub async fn new_user_with_config(
+        &self,
+        config: UserClientConfig,
+    ) -> UserTest<UserClientConfig> {
+        let peers = (0..config.0.nodes.len())
+            .map(|id| PeerId::from(id as u16))
+            .collect();
+        let user = create_user_client(config, peers, MemDatabase::new().into()).await;
+        UserTest::new(Arc::new(user))
+    }
 }
 
 impl<T: AsRef<ClientConfig> + Clone> UserTest<T> {
@@ -623,6 +774,21 @@ impl FederationTest {
         Ok(())
     }
 
+    /// Number of open epoch history subscriptions summed over all servers
+    pub fn epoch_history_subscriptions(&self) -> usize {
+        self.servers
+            .iter()
+            .map(|server| {
+                server
+                    .borrow()
+                    .fedimint
+                    .consensus
+                    .signed_epoch_sender
+                    .receiver_count()
+            })
+            .sum()
+    }
+
     /// Returns a fixture that only calls on a subset of the peers.  Note that PeerIds are always
     /// starting at 0 in tests.
     pub fn subset_peers(&self, peers: &[u16]) -> Self {
@@ -730,6 +896,58 @@ impl FederationTest {
         }
     }
 
+    /// Returns the entries under `key_prefix` in the database of every federation node
+    pub fn find_by_prefix<KP>(&self, key_prefix: &KP) -> Vec<Vec<(KP::Key, KP::Value)>>
+    where
+        KP: DatabaseKeyPrefix + DatabaseKeyPrefixConst,
+    {
+        self.servers
+            .iter()
+            .map(|server| {
+                block_on(async {
+                    let svr = server.borrow();
+                    let mut dbtx = svr.database.begin_transaction(all_decoders()).await;
+                    let entries = dbtx
+                        .find_by_prefix(key_prefix)
+                        .await
+                        .map(|res| res.expect("DB Error"))
+                        .collect();
+                    entries
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces the consensus state of `peer` with the latest snapshot signed by the federation,
+    /// like a new guardian starting from it, and returns the restored snapshot
+    pub async fn restore_signed_snapshot(&self, peer: u16) -> ConsensusSnapshot {
+        let source = self.servers[0].borrow().fedimint.consensus.clone();
+        let (header, entries) = source
+            .signed_snapshot_part(0, usize::MAX)
+            .await
+            .expect("No signed snapshot");
+        let epoch = source
+            .epoch_history(header.epoch)
+            .await
+            .expect("Epoch of the snapshot is missing");
+        let snapshot = ConsensusSnapshot {
+            epoch: header.epoch,
+            entries,
+        };
+
+        let target = self
+            .servers
+            .iter()
+            .map(|server| server.borrow().fedimint.consensus.clone())
+            .find(|consensus| consensus.cfg.local.identity == PeerId::from(peer))
+            .expect("Unknown peer");
+        target
+            .restore_snapshot(header, snapshot.clone(), epoch)
+            .await
+            .expect("Snapshot is valid");
+        snapshot
+    }
+
     /// Returns the maximum the fed's balance sheet has reached during the test.
     pub fn max_balance_sheet(&self) -> u64 {
         assert!(*self.max_balance_sheet.borrow() >= 0);
@@ -875,6 +1093,7 @@ impl FederationTest {
                     return false;
                 }
                 ConsensusItem::EpochOutcomeSignatureShare(_) => continue,
+                ConsensusItem::SnapshotSignatureShare(_) => continue,
                 _ => return false,
             }
         }
@@ -975,25 +1194,14 @@ impl FederationTest {
             let db = database_gen();
             let mut task_group = task_group.clone();
 
-            let mint = Mint::new(cfg.get_module_config_typed("mint").unwrap());
-
-            let wallet = Wallet::new_with_bitcoind(
-                cfg.get_module_config_typed("wallet").unwrap(),
+            let consensus = new_consensus(
+                cfg,
                 db.clone(),
                 btc_rpc.clone(),
+                module_config_gens.clone(),
                 &mut task_group.clone(),
-                all_decoders(),
             )
-            .await
-            .expect("Couldn't create wallet");
-
-            let ln = LightningModule::new(cfg.get_module_config_typed("ln").unwrap());
-
-            let mut consensus =
-                FedimintConsensus::new(cfg.clone(), db.clone(), module_config_gens.clone());
-            consensus.register_module(mint.into());
-            consensus.register_module(wallet.into());
-            consensus.register_module(ln.into());
+            .await;
 
             let fedimint =
                 FedimintServer::new_with(cfg.clone(), consensus, connect_gen(cfg), &mut task_group)
@@ -1001,9 +1209,18 @@ impl FederationTest {
 
             let cfg = cfg.clone();
             let consensus = fedimint.consensus.clone();
+            let peer_status = fedimint.peer_status.clone();
+            let api_task_group = task_group.clone();
             task_group
                 .spawn("rpc server", move |handle| async {
-                    fedimint_server::net::api::run_server(cfg, consensus, handle).await
+                    fedimint_server::net::api::run_server(
+                        cfg,
+                        consensus,
+                        peer_status,
+                        api_task_group,
+                        handle,
+                    )
+                    .await
                 })
                 .await;
 
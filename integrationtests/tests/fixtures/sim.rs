@@ -0,0 +1,664 @@
+//! Deterministic simulation of a whole federation running in a single process
+//!
+//! Every guardian runs its consensus as a local task, talking to the others over a
+//! [`SimNetwork`] that injects the faults a scenario asks for. Tests run on tokio's paused clock
+//! (`#[tokio::test(start_paused = true)]` inside a [`LocalSet`](tokio::task::LocalSet)), so
+//! network delays advance a virtual clock and a scenario only depends on its seed. Users created
+//! with [`SimFederation::new_user`] submit their transactions straight to the consensus of the
+//! running guardians.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bitcoin::hashes::sha256;
+use bitcoin::{Address, XOnlyPublicKey};
+use fedimint_api::db::mem_impl::MemDatabase;
+use fedimint_api::db::Database;
+use fedimint_api::net::peers::IPeerConnections;
+use fedimint_api::task::TaskGroup;
+use fedimint_api::{msats, sats, Amount, PeerId, TransactionId};
+use fedimint_ln::contracts::incoming::IncomingContractOffer;
+use fedimint_ln::contracts::ContractId;
+use fedimint_ln::{ContractAccount, LightningGateway};
+use fedimint_mint::db::ECashUserBackupSnapshot;
+use fedimint_mint::SignedBackupRequest;
+use fedimint_server::config::{
+    ModuleConfigGens, ServerConfig, ServerConfigParams, DEFAULT_P2P_PORT,
+};
+use fedimint_server::consensus::FedimintConsensus;
+use fedimint_server::db::{
+    ConsensusSnapshotEntryKey, ConsensusSnapshotHeaderKey, EpochHistoryKey, LastEpochKey,
+};
+use fedimint_server::epoch::{ConsensusSnapshotHeader, SignedEpochOutcome};
+use fedimint_server::net::sim::SimNetwork;
+use fedimint_server::outcome::TransactionStatus;
+use fedimint_server::transaction::legacy::Transaction as LegacyTransaction;
+use fedimint_server::{all_decoders, EpochMessage, FedimintServer};
+use fedimint_testing::btc::fixtures::FakeBitcoinTest;
+use fedimint_testing::btc::BitcoinTest;
+use fedimint_wallet::config::WalletConfig;
+use fedimint_wallet::PegOutFees;
+use mint_client::api::{ApiError, IFederationApi};
+use mint_client::{UserClient, UserClientConfig};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use threshold_crypto::PublicKey;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use super::{module_config_gens, new_consensus, rocks};
+
+/// How often [`SimFederation::run_until_epoch`] checks the progress of the guardians
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Virtual time after which a scenario is considered stuck
+const EPOCH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Federation of guardians connected by a [`SimNetwork`], see the [module docs](self)
+pub struct SimFederation {
+    pub network: SimNetwork<EpochMessage>,
+    pub peers: Vec<PeerId>,
+    cfgs: BTreeMap<PeerId, ServerConfig>,
+    module_config_gens: ModuleConfigGens,
+    bitcoin: FakeBitcoinTest,
+    dir: PathBuf,
+    databases: BTreeMap<PeerId, Database>,
+    running: BTreeMap<PeerId, SimGuardian>,
+    running_consensus: Arc<RunningConsensus>,
+    api_history_downloads: Arc<AtomicU64>,
+    submitted_transactions: Arc<Mutex<Vec<TransactionId>>>,
+    rng: StdRng,
+}
+
+/// A guardian that is currently up
+struct SimGuardian {
+    consensus_task: JoinHandle<()>,
+    task_group: TaskGroup,
+}
+
+impl SimFederation {
+    /// Creates a federation of `num_peers` guardians with fresh on-disk databases, all of them
+    /// are stopped until [`SimFederation::start`] is called
+    pub fn new(num_peers: u16, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let peers = (0..num_peers).map(PeerId::from).collect::<Vec<_>>();
+        let params = ServerConfigParams::gen_local(
+            &peers,
+            sats(100000),
+            DEFAULT_P2P_PORT,
+            "sim",
+            "127.0.0.1:18443",
+        );
+        let module_config_gens = module_config_gens();
+        let cfgs = ServerConfig::trusted_dealer_gen(
+            "",
+            &peers,
+            &params,
+            module_config_gens.clone(),
+            StdRng::seed_from_u64(rng.next_u64()),
+        );
+
+        let dir = env::temp_dir().join(format!("fedimint-sim-{}-{}", seed, rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).expect("Could not create database dir");
+        let databases = peers
+            .iter()
+            .map(|peer| {
+                let db: Database = rocks(dir.to_string_lossy().into_owned()).into();
+                (*peer, db)
+            })
+            .collect();
+
+        SimFederation {
+            network: SimNetwork::new(rng.next_u64()),
+            peers,
+            cfgs,
+            module_config_gens,
+            bitcoin: FakeBitcoinTest::new(),
+            dir,
+            databases,
+            running: BTreeMap::new(),
+            running_consensus: Default::default(),
+            api_history_downloads: Arc::new(AtomicU64::new(0)),
+            submitted_transactions: Default::default(),
+            rng,
+        }
+    }
+
+    /// Changes the config of all guardians before any of them starts
+    pub fn configure(&mut self, f: impl FnMut(&mut ServerConfig)) {
+        assert!(self.running.is_empty(), "guardians already run");
+        self.cfgs.values_mut().for_each(f);
+    }
+
+    /// Gives the stopped guardian `peer` an empty database, like setting it up on a new machine
+    /// with its config restored from a backup
+    pub fn replace(&mut self, peer: PeerId) {
+        assert!(!self.running.contains_key(&peer), "{} still runs", peer);
+        info!("Replacing the database of guardian {}", peer);
+        let db: Database = rocks(self.dir.to_string_lossy().into_owned()).into();
+        self.databases.insert(peer, db);
+    }
+
+    /// Starts all guardians
+    pub async fn start_all(&mut self) {
+        for peer in self.peers.clone() {
+            self.start(peer).await;
+        }
+    }
+
+    /// Starts the guardian `peer` from whatever state its database is in
+    pub async fn start(&mut self, peer: PeerId) {
+        assert!(!self.running.contains_key(&peer), "{} already runs", peer);
+        info!("Starting guardian {}", peer);
+
+        let mut task_group = TaskGroup::new();
+        let consensus = self.consensus(peer, &mut task_group).await;
+        let connections = self.network.connect(peer);
+        let mut server = FedimintServer::new_with_connections(
+            self.cfgs[&peer].clone(),
+            consensus,
+            connections.into_dyn(),
+            Default::default(),
+        )
+        .await;
+        server.api = Arc::new(self.api());
+        self.running_consensus
+            .insert(peer, server.consensus.clone());
+
+        let rng = StdRng::seed_from_u64(self.rng.next_u64());
+        let consensus_task = tokio::task::spawn_local(run_guardian(server, rng));
+        self.running.insert(
+            peer,
+            SimGuardian {
+                consensus_task,
+                task_group,
+            },
+        );
+    }
+
+    /// Stops the guardian `peer` wherever it currently is, like a crash would, without touching
+    /// the state already committed to its database
+    pub async fn crash(&mut self, peer: PeerId) {
+        info!("Crashing guardian {}", peer);
+        let guardian = self.running.remove(&peer).expect("guardian is not running");
+        self.running_consensus.remove(peer);
+        guardian.consensus_task.abort();
+        let _ = guardian.consensus_task.await;
+        guardian.task_group.shutdown().await;
+        self.network.disconnect(peer);
+    }
+
+    /// Last epoch the guardian `peer` committed to its database
+    pub async fn last_epoch(&self, peer: PeerId) -> Option<u64> {
+        last_epoch(&self.databases[&peer]).await
+    }
+
+    /// Whether the guardian `peer` has `epoch` in its history, it may have pruned it
+    pub async fn has_epoch(&self, peer: PeerId, epoch: u64) -> bool {
+        self.databases[&peer]
+            .begin_transaction(all_decoders())
+            .await
+            .get_value(&EpochHistoryKey(epoch))
+            .await
+            .expect("DB error")
+            .is_some()
+    }
+
+    /// Header and entries of the latest snapshot the guardian `peer` took or restored, the entries
+    /// are a copy of its consensus state after the snapshot's epoch
+    pub async fn snapshot(
+        &self,
+        peer: PeerId,
+    ) -> Option<(ConsensusSnapshotHeader, Vec<(Vec<u8>, Vec<u8>)>)> {
+        let mut dbtx = self.databases[&peer]
+            .begin_transaction(all_decoders())
+            .await;
+        let header = dbtx
+            .get_value(&ConsensusSnapshotHeaderKey)
+            .await
+            .expect("DB error")?;
+        let keys = (0..header.num_entries)
+            .map(ConsensusSnapshotEntryKey)
+            .collect::<Vec<_>>();
+        let entries = dbtx
+            .get_values(&keys)
+            .await
+            .expect("DB error")
+            .into_iter()
+            .map(|entry| entry.expect("snapshot entry is missing"))
+            .collect();
+
+        Some((header, entries))
+    }
+
+    /// Creates a user whose client submits its transactions straight to the running guardians
+    pub async fn new_user(&self) -> UserClient {
+        let config = self.cfgs[&self.peers[0]]
+            .consensus
+            .to_client_config(&self.module_config_gens);
+        UserClient::new_with_api(
+            UserClientConfig(config),
+            MemDatabase::new().into(),
+            self.api().into(),
+            Default::default(),
+        )
+        .await
+    }
+
+    /// Pegs in `amount` for a new user who sends half of the issued e-cash to a second user who
+    /// reissues it, so the guardians process wallet and mint transactions while the scenario's
+    /// faults are injected
+    ///
+    /// Only the guardians in `peers` have to make progress, the others may be faulty
+    pub async fn transfer_ecash(&mut self, peers: &[PeerId], amount: Amount) {
+        assert_eq!(amount.msats % 1000, 0);
+        let sender = self.new_user().await;
+        let receiver = self.new_user().await;
+
+        let address = sender.get_new_pegin_address(&mut self.rng).await;
+        let (proof, tx) = self
+            .bitcoin
+            .send_and_mine_block(&address, bitcoin::Amount::from_sat(amount.msats / 1000));
+        let wallet: WalletConfig = self.cfgs[&self.peers[0]]
+            .get_module_config_typed("wallet")
+            .expect("wallet is configured");
+        self.bitcoin
+            .mine_blocks(wallet.consensus.finality_delay as u64);
+        // guardians have to agree on a block height that confirms the peg-in first
+        self.run_epochs(peers, 2).await;
+
+        sender
+            .peg_in(proof, tx, &mut self.rng)
+            .await
+            .expect("peg-in was rejected");
+        assert_all_fetched(sender.fetch_all_coins().await);
+        assert_eq!(sender.coins().await.total_amount(), amount);
+
+        let sent = msats(amount.msats / 2);
+        let ecash = sender
+            .spend_ecash(sent, &mut self.rng)
+            .await
+            .expect("sender can't spend e-cash");
+        receiver
+            .reissue(ecash, &mut self.rng)
+            .await
+            .expect("reissue was rejected");
+        assert_all_fetched(receiver.fetch_all_coins().await);
+        assert_eq!(receiver.coins().await.total_amount(), sent);
+        assert_eq!(
+            sender.coins().await.total_amount(),
+            msats(amount.msats - sent.msats)
+        );
+    }
+
+    /// Number of epochs guardians downloaded from the API instead of receiving them from peers
+    pub fn api_history_downloads(&self) -> u64 {
+        self.api_history_downloads.load(Ordering::Relaxed)
+    }
+
+    /// Waits until all of `peers` committed `epoch`
+    ///
+    /// # Panics
+    /// If they don't within [`EPOCH_TIMEOUT`] of virtual time
+    pub async fn run_until_epoch(&self, peers: &[PeerId], epoch: u64) {
+        let start = tokio::time::Instant::now();
+        loop {
+            let mut done = true;
+            for peer in peers {
+                done &= self.last_epoch(*peer).await >= Some(epoch);
+            }
+            if done {
+                return;
+            }
+
+            assert!(
+                start.elapsed() < EPOCH_TIMEOUT,
+                "{:?} did not reach epoch {}, network stats: {:?}",
+                peers,
+                epoch,
+                self.network.stats()
+            );
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Waits until all of `peers` committed `epochs` more epochs than the most advanced of them
+    pub async fn run_epochs(&self, peers: &[PeerId], epochs: u64) {
+        let mut last = None;
+        for peer in peers {
+            last = last.max(self.last_epoch(*peer).await);
+        }
+        let target = last.map_or(epochs.saturating_sub(1), |last| last + epochs);
+        self.run_until_epoch(peers, target).await;
+    }
+
+    /// Stops all guardians and checks that their states agree
+    ///
+    /// # Panics
+    /// * If two guardians committed different outcomes for the same epoch
+    /// * If a transaction submitted by a user was rejected, not processed by any guardian or
+    ///   accepted in different epochs by two guardians
+    /// * If the balance sheet of a guardian is negative
+    /// * If guardians that committed the same epochs have different balance sheets
+    pub async fn finish(mut self) {
+        for peer in self.running.keys().copied().collect::<Vec<_>>() {
+            self.crash(peer).await;
+        }
+
+        let mut epoch_hashes: BTreeMap<u64, (PeerId, sha256::Hash)> = BTreeMap::new();
+        let mut audits: BTreeMap<Option<u64>, (PeerId, i64)> = BTreeMap::new();
+        let mut accepted: BTreeMap<TransactionId, (PeerId, u64)> = BTreeMap::new();
+        let submitted = self
+            .submitted_transactions
+            .lock()
+            .expect("poisoned")
+            .clone();
+        for peer in self.peers.clone() {
+            let db = self.databases[&peer].clone();
+            let last_epoch = last_epoch(&db).await;
+
+            let mut dbtx = db.begin_transaction(all_decoders()).await;
+            for epoch in 0..=last_epoch.unwrap_or(0) {
+                let outcome = match dbtx
+                    .get_value(&EpochHistoryKey(epoch))
+                    .await
+                    .expect("DB error")
+                {
+                    Some(outcome) => outcome,
+                    None => continue,
+                };
+                let (other, hash) = *epoch_hashes.entry(epoch).or_insert((peer, outcome.hash));
+                assert_eq!(
+                    hash, outcome.hash,
+                    "{} and {} disagree on epoch {}",
+                    peer, other, epoch
+                );
+            }
+            drop(dbtx);
+
+            let mut task_group = TaskGroup::new();
+            let consensus = self.consensus(peer, &mut task_group).await;
+            for txid in &submitted {
+                match consensus.transaction_status(*txid).await {
+                    Some(TransactionStatus::Accepted { epoch, .. }) => {
+                        let (other, other_epoch) = *accepted.entry(*txid).or_insert((peer, epoch));
+                        assert_eq!(
+                            epoch, other_epoch,
+                            "{} and {} accepted {} in different epochs",
+                            peer, other, txid
+                        );
+                    }
+                    Some(TransactionStatus::Rejected(error)) => {
+                        panic!("{} rejected {}: {}", peer, txid, error)
+                    }
+                    None => {}
+                }
+            }
+            let audit = consensus.audit().await;
+            task_group.shutdown().await;
+            let sum = audit.sum().milli_sat;
+            assert!(
+                sum >= 0,
+                "{} has a negative balance sheet:\n{}",
+                peer,
+                audit
+            );
+
+            let (other, other_sum) = *audits.entry(last_epoch).or_insert((peer, sum));
+            assert_eq!(
+                sum, other_sum,
+                "{} and {} have different balance sheets at epoch {:?}",
+                peer, other, last_epoch
+            );
+        }
+
+        assert_eq!(
+            accepted.len(),
+            submitted.len(),
+            "not all submitted transactions were processed"
+        );
+
+        info!(
+            "Simulation finished, network stats: {:?}",
+            self.network.stats()
+        );
+        drop(self.databases);
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+
+    fn api(&self) -> SimFederationApi {
+        SimFederationApi {
+            databases: self.databases.clone(),
+            history_downloads: self.api_history_downloads.clone(),
+            running_consensus: self.running_consensus.clone(),
+            submitted_transactions: self.submitted_transactions.clone(),
+        }
+    }
+
+    async fn consensus(&self, peer: PeerId, task_group: &mut TaskGroup) -> FedimintConsensus {
+        new_consensus(
+            &self.cfgs[&peer],
+            self.databases[&peer].clone(),
+            self.bitcoin.clone().into(),
+            self.module_config_gens.clone(),
+            task_group,
+        )
+        .await
+    }
+}
+
+/// Runs consensus epochs forever, proposing even if there is nothing to agree on so scenarios
+/// make progress without submitting transactions
+async fn run_guardian(mut server: FedimintServer, mut rng: StdRng) {
+    if server.catch_up().await.is_err() {
+        return;
+    }
+    server.start_consensus().await;
+    loop {
+        server.run_empty_epochs = 1;
+        let consensus = server.consensus.clone();
+        let proposal = async move { consensus.get_consensus_proposal().await };
+        let outcomes = match server.run_consensus_epoch(proposal, &mut rng).await {
+            Ok(outcomes) => outcomes,
+            Err(_) => return,
+        };
+
+        for outcome in outcomes {
+            server
+                .process_outcome(outcome)
+                .await
+                .expect("epoch history is valid");
+        }
+    }
+}
+
+/// Panics if a user couldn't fetch any of its issued e-cash
+fn assert_all_fetched<T, E: Debug>(results: Vec<Result<T, E>>) {
+    for result in results {
+        result.expect("Could not fetch issued e-cash");
+    }
+}
+
+async fn last_epoch(db: &Database) -> Option<u64> {
+    db.begin_transaction(all_decoders())
+        .await
+        .get_value(&LastEpochKey)
+        .await
+        .expect("DB error")
+        .map(|key| key.0)
+}
+
+/// Consensus of the guardians that are currently up
+#[derive(Default)]
+struct RunningConsensus(Mutex<BTreeMap<PeerId, Arc<FedimintConsensus>>>);
+
+impl RunningConsensus {
+    fn insert(&self, peer: PeerId, consensus: Arc<FedimintConsensus>) {
+        self.0.lock().expect("poisoned").insert(peer, consensus);
+    }
+
+    fn remove(&self, peer: PeerId) {
+        self.0.lock().expect("poisoned").remove(&peer);
+    }
+
+    fn all(&self) -> Vec<Arc<FedimintConsensus>> {
+        self.0.lock().expect("poisoned").values().cloned().collect()
+    }
+}
+
+impl Debug for RunningConsensus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let peers = self
+            .0
+            .lock()
+            .expect("poisoned")
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        f.debug_tuple("RunningConsensus").field(&peers).finish()
+    }
+}
+
+/// Serves the epoch history a guardian downloads when catching up straight from the databases of
+/// all guardians and hands the transactions of users to the running guardians, the simulation
+/// doesn't run the API servers
+#[derive(Debug)]
+struct SimFederationApi {
+    databases: BTreeMap<PeerId, Database>,
+    history_downloads: Arc<AtomicU64>,
+    running_consensus: Arc<RunningConsensus>,
+    submitted_transactions: Arc<Mutex<Vec<TransactionId>>>,
+}
+
+#[async_trait]
+impl IFederationApi for SimFederationApi {
+    async fn fetch_tx_outcome(
+        &self,
+        tx: TransactionId,
+    ) -> mint_client::api::Result<TransactionStatus> {
+        // the real API answers with a 404 the client retries on until a guardian processed it
+        let start = tokio::time::Instant::now();
+        while start.elapsed() < EPOCH_TIMEOUT {
+            for consensus in self.running_consensus.all() {
+                if let Some(status) = consensus.transaction_status(tx).await {
+                    return Ok(status);
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Err(ApiError::NoResult)
+    }
+
+    async fn submit_transaction(
+        &self,
+        tx: LegacyTransaction,
+    ) -> mint_client::api::Result<TransactionId> {
+        let tx = tx.into_type_erased();
+        let txid = tx.tx_hash();
+
+        // guardians that fell behind may reject a transaction the others already accept
+        let mut accepted = false;
+        let mut error = "no guardian is running".to_string();
+        for consensus in self.running_consensus.all() {
+            match consensus.submit_transaction(tx.clone()).await {
+                Ok(()) => accepted = true,
+                Err(e) => error = e.to_string(),
+            }
+        }
+        if !accepted {
+            return Err(ApiError::TransactionRejected(error));
+        }
+
+        self.submitted_transactions
+            .lock()
+            .expect("poisoned")
+            .push(txid);
+        Ok(txid)
+    }
+
+    async fn fetch_epoch_history(
+        &self,
+        epoch: u64,
+        _epoch_pk: PublicKey,
+    ) -> mint_client::api::Result<SignedEpochOutcome> {
+        self.history_downloads.fetch_add(1, Ordering::Relaxed);
+        for db in self.databases.values() {
+            let outcome = db
+                .begin_transaction(all_decoders())
+                .await
+                .get_value(&EpochHistoryKey(epoch))
+                .await
+                .expect("DB error");
+            if let Some(outcome) = outcome {
+                return Ok(outcome);
+            }
+        }
+        Err(ApiError::NoResult)
+    }
+
+    async fn fetch_last_epoch(&self) -> mint_client::api::Result<u64> {
+        let mut last = None;
+        for db in self.databases.values() {
+            last = last.max(last_epoch(db).await);
+        }
+        last.ok_or(ApiError::NoResult)
+    }
+
+    async fn fetch_contract(
+        &self,
+        _contract: ContractId,
+    ) -> mint_client::api::Result<ContractAccount> {
+        unimplemented!()
+    }
+
+    async fn fetch_offer(
+        &self,
+        _payment_hash: sha256::Hash,
+    ) -> mint_client::api::Result<IncomingContractOffer> {
+        unimplemented!()
+    }
+
+    async fn offer_exists(&self, _payment_hash: sha256::Hash) -> mint_client::api::Result<bool> {
+        unimplemented!()
+    }
+
+    async fn fetch_consensus_block_height(&self) -> mint_client::api::Result<u64> {
+        unimplemented!()
+    }
+
+    async fn fetch_peg_out_fees(
+        &self,
+        _address: &Address,
+        _amount: &bitcoin::Amount,
+    ) -> mint_client::api::Result<Option<PegOutFees>> {
+        unimplemented!()
+    }
+
+    async fn fetch_gateways(&self) -> mint_client::api::Result<Vec<LightningGateway>> {
+        unimplemented!()
+    }
+
+    async fn register_gateway(&self, _gateway: LightningGateway) -> mint_client::api::Result<()> {
+        unimplemented!()
+    }
+
+    async fn upload_ecash_backup(
+        &self,
+        _request: &SignedBackupRequest,
+    ) -> mint_client::api::Result<()> {
+        unimplemented!()
+    }
+
+    async fn download_ecash_backup(
+        &self,
+        _id: &XOnlyPublicKey,
+    ) -> mint_client::api::Result<Vec<ECashUserBackupSnapshot>> {
+        unimplemented!()
+    }
+}
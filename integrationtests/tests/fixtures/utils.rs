@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use bitcoin::secp256k1::PublicKey;
+use fedimint_api::Amount;
 use fedimint_ln::contracts::Preimage;
 use ln_gateway::ln::{LightningError, LnRpc};
 use tokio::sync::Mutex;
@@ -30,6 +31,21 @@ impl LnRpcAdapter {
     pub async fn fail_invoice(&self, invoice: lightning_invoice::Invoice, times: u8) {
         self.fail_invoices.lock().await.insert(invoice, times + 1);
     }
+
+    /// Returns true if paying `invoice` should fail this time
+    async fn should_fail(&self, invoice: &lightning_invoice::Invoice) -> bool {
+        let mut fail_invoices = self.fail_invoices.lock().await;
+        fail_invoices.entry(invoice.clone()).and_modify(|counter| {
+            *counter -= 1;
+        });
+        if let Some(counter) = fail_invoices.get(invoice) {
+            if *counter > 0 {
+                return true;
+            }
+        }
+        fail_invoices.remove(invoice);
+        false
+    }
 }
 
 #[async_trait]
@@ -44,19 +60,25 @@ impl LnRpc for LnRpcAdapter {
         max_delay: u64,
         max_fee_percent: f64,
     ) -> Result<Preimage, LightningError> {
-        self.fail_invoices
-            .lock()
-            .await
-            .entry(invoice.clone())
-            .and_modify(|counter| {
-                *counter -= 1;
-            });
-        if let Some(counter) = self.fail_invoices.lock().await.get(&invoice) {
-            if *counter > 0 {
-                return Err(LightningError(None));
-            }
+        if self.should_fail(&invoice).await {
+            return Err(LightningError(None));
         }
-        self.fail_invoices.lock().await.remove(&invoice);
         self.client.pay(invoice, max_delay, max_fee_percent).await
     }
+
+    async fn pay_partial(
+        &self,
+        invoice: lightning_invoice::Invoice,
+        part_id: u16,
+        part_amount: Amount,
+        max_delay: u64,
+        max_fee_percent: f64,
+    ) -> Result<Preimage, LightningError> {
+        if self.should_fail(&invoice).await {
+            return Err(LightningError(None));
+        }
+        self.client
+            .pay_partial(invoice, part_id, part_amount, max_delay, max_fee_percent)
+            .await
+    }
 }
@@ -1,32 +1,54 @@
 mod fixtures;
 
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 use assert_matches::assert_matches;
 use bitcoin::{Amount, KeyPair};
 use fedimint_api::cancellable::Cancellable;
+use fedimint_api::config::{TypedClientModuleConfig, TypedServerModuleConfig};
 use fedimint_api::core::MODULE_KEY_LN;
 use fedimint_api::task::TaskGroup;
-use fedimint_api::{msats, sats, TieredMulti};
+use fedimint_api::{msats, sats, PeerId, TieredMulti};
 use fedimint_ln::contracts::{Preimage, PreimageDecryptionShare};
-use fedimint_ln::LightningConsensusItem;
+use fedimint_ln::{GatewayFee, LightningConsensusItem, LightningGateway};
 use fedimint_mint::{MintOutputConfirmation, OutputConfirmationSignatures};
-use fedimint_server::consensus::TransactionSubmissionError::TransactionError;
+use fedimint_server::consensus::TransactionSubmissionError::{ProposalQueueFull, TransactionError};
 use fedimint_server::epoch::ConsensusItem;
+use fedimint_server::net::admin::AdminPasswordHash;
+use fedimint_server::net::sim::{ByzantineBehavior, LinkFaults};
 use fedimint_server::transaction::legacy::Output;
 use fedimint_server::transaction::TransactionError::UnbalancedTransaction;
-use fedimint_wallet::PegOutSignatureItem;
+use fedimint_wallet::config::{PreviousPegIn, WalletClientConfig, WalletConfig};
+use fedimint_wallet::db::{
+    DbKeyPrefix, ExternalSignerWaitPrefixKey, LegacyTransactionPrefixKey, LegacyUTXOPrefixKey,
+    PegInHandoverKey, PendingTransactionPrefixKey, UTXOPrefixKey,
+};
+use fedimint_wallet::keys::CompressedPublicKey;
 use fedimint_wallet::WalletConsensusItem::PegOutSignature;
+use fedimint_wallet::{sign_peg_out, UnsignedPegOut};
+use fedimint_wallet::{PegInDescriptor, PegOutSignatureItem};
 use fixtures::{rng, secp, sha256};
 use futures::future::{join_all, Either};
+use futures::StreamExt;
+use lightning_invoice::InvoiceDescription;
+use ln_gateway::LnGatewayError;
+use mint_client::api::WsFederationApi;
+use mint_client::ln::lnurl::{LnurlInvoicesRequest, LnurlRegisterPayload, LNURL_REQUEST_MAX_AGE};
+use mint_client::ln::PaymentPart;
 use mint_client::mint::MintClient;
+use mint_client::operations::{OperationFilter, OperationKind, OperationStatus};
+use mint_client::query::CurrentConsensus;
 use mint_client::transaction::TransactionBuilder;
 use mint_client::ClientError;
 use threshold_crypto::{SecretKey, SecretKeyShare};
+use tokio::task::LocalSet;
 use tracing::debug;
 
-use crate::fixtures::{assert_ci, peers, test, FederationTest};
+use crate::fixtures::sim::SimFederation;
+use crate::fixtures::{assert_ci, peers, test, test_with_config, FederationTest};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn peg_in_and_peg_out_with_fees() -> Result<()> {
@@ -172,6 +194,72 @@ async fn ecash_can_be_exchanged_directly_between_users() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn ecash_operations_are_logged() -> Result<()> {
+    test(2, |fed, user_send, bitcoin, _, _| async move {
+        let user_receive = user_send.new_user_with_peers(peers(&[0])).await;
+        fed.mine_and_mint(&user_send, &*bitcoin, sats(5000)).await;
+
+        let ecash = fed.spend_ecash(&user_send, sats(3500)).await;
+        let outpoint = user_receive.client.reissue(ecash, rng()).await.unwrap();
+        fed.run_consensus_epochs(2).await;
+
+        let sent = user_send
+            .client
+            .list_operations(&OperationFilter::default())
+            .await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].kind, OperationKind::SpendEcash);
+        assert_eq!(sent[0].amount, sats(3500));
+
+        let reissue_filter = OperationFilter {
+            kinds: vec![OperationKind::Reissue],
+            ..Default::default()
+        };
+        let received = user_receive.client.list_operations(&reissue_filter).await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].amount + received[0].fees, sats(3500));
+        assert_eq!(received[0].txid, Some(outpoint.txid));
+        assert_eq!(received[0].status, OperationStatus::Pending);
+
+        user_receive.client.fetch_coins(outpoint).await.unwrap();
+        let received = user_receive.client.list_operations(&reissue_filter).await;
+        assert_eq!(received[0].status, OperationStatus::Completed);
+
+        // Operations are listed most recent first
+        let ecash = fed.spend_ecash(&user_receive, sats(1000)).await;
+        user_send.client.reissue(ecash, rng()).await.unwrap();
+        fed.run_consensus_epochs(2).await;
+        user_receive
+            .client
+            .refresh_operation_statuses()
+            .await
+            .unwrap();
+        let history = user_receive
+            .client
+            .list_operations(&OperationFilter::default())
+            .await;
+        assert_eq!(
+            history.iter().map(|op| op.kind).collect::<Vec<_>>(),
+            vec![OperationKind::SpendEcash, OperationKind::Reissue]
+        );
+        assert!(history
+            .iter()
+            .all(|op| op.status == OperationStatus::Completed));
+
+        let filter = OperationFilter {
+            kinds: vec![OperationKind::PegIn],
+            ..Default::default()
+        };
+        assert!(user_receive
+            .client
+            .list_operations(&filter)
+            .await
+            .is_empty());
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn ecash_cannot_double_spent_with_different_nodes() -> Result<()> {
     test(2, |fed, user1, bitcoin, _, _| async move {
@@ -299,6 +387,306 @@ async fn drop_peers_who_dont_contribute_peg_out_psbts() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn peg_outs_can_be_signed_by_external_signers() -> Result<()> {
+    // Peers 2 and 3 use external signers, so the threshold of 3 signatures requires one of them
+    let external_signers = [PeerId::from(2), PeerId::from(3)];
+    let external_keys = &std::sync::Mutex::new(BTreeMap::new());
+
+    test_with_config(
+        4,
+        |configs| {
+            for (peer, cfg) in configs.iter_mut() {
+                let mut wallet: WalletConfig = cfg.get_module_config_typed("wallet").unwrap();
+                if external_signers.contains(peer) {
+                    let key = wallet.use_external_signer(*peer).unwrap();
+                    external_keys.lock().unwrap().insert(*peer, key);
+                }
+                wallet.consensus.external_signers = external_signers.into_iter().collect();
+                cfg.add_modules(BTreeMap::from([("wallet".to_string(), wallet.to_erased())]));
+                cfg.local.admin_password_hash = Some(AdminPasswordHash::new(
+                    "signer password",
+                    NonZeroU32::new(1).unwrap(),
+                    rng(),
+                ));
+            }
+        },
+        |fed, user, bitcoin, _, _| async move {
+            let signer_key = external_keys.lock().unwrap()[&PeerId::from(3)];
+            let signer_api = WsFederationApi::from_config(&user.client.config().0);
+            let signer = PeerId::from(3);
+
+            fed.mine_and_mint(&user, &*bitcoin, sats(3000)).await;
+            let peg_out_address = bitcoin.get_new_address();
+            user.peg_out(1000, &peg_out_address).await;
+            fed.run_consensus_epochs(2).await;
+
+            // Only peers 0 and 1 signed so far
+            let unsigned: Vec<UnsignedPegOut> = signer_api
+                .admin_request(
+                    signer,
+                    "/admin/wallet/unsigned_peg_outs",
+                    "signer password",
+                    (),
+                )
+                .await
+                .unwrap();
+            assert_eq!(unsigned.len(), 1);
+
+            // Signing requires the admin password
+            let psbt = unsigned[0].decode_psbt().unwrap();
+            let signature = sign_peg_out(&psbt, &signer_key, &secp());
+            assert!(signer_api
+                .admin_request::<_, ()>(
+                    signer,
+                    "/admin/wallet/submit_peg_out_signature",
+                    "wrong password",
+                    signature.clone(),
+                )
+                .await
+                .is_err());
+            assert!(signer_api
+                .admin_request::<_, ()>(
+                    signer,
+                    "/admin/wallet/submit_peg_out_signature",
+                    "",
+                    signature.clone(),
+                )
+                .await
+                .is_err());
+            let missing_password = signer_api
+                .request::<_, ()>(
+                    "/admin/wallet/submit_peg_out_signature",
+                    signature.clone(),
+                    CurrentConsensus::new(1),
+                )
+                .await;
+            assert!(missing_password.is_err());
+
+            // The air-gapped signer only ever sees the PSBT
+            signer_api
+                .admin_request::<_, ()>(
+                    signer,
+                    "/admin/wallet/submit_peg_out_signature",
+                    "signer password",
+                    signature,
+                )
+                .await
+                .unwrap();
+
+            fed.run_consensus_epochs(2).await;
+            fed.broadcast_transactions().await;
+            assert_eq!(
+                bitcoin.mine_block_and_get_received(&peg_out_address),
+                sats(1000)
+            );
+            assert!(!fed.subset_peers(&[0, 1]).has_dropped_peer(2));
+            assert!(!fed.subset_peers(&[0, 1]).has_dropped_peer(3));
+            assert_eq!(fed.max_balance_sheet(), 0);
+        },
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drop_external_signers_who_dont_sign_in_time() -> Result<()> {
+    // Peers 2 and 3 use external signers that never sign, so the peg-out can't be finalized
+    let external_signers = [PeerId::from(2), PeerId::from(3)];
+
+    test_with_config(
+        4,
+        |configs| {
+            for (peer, cfg) in configs.iter_mut() {
+                let mut wallet: WalletConfig = cfg.get_module_config_typed("wallet").unwrap();
+                if external_signers.contains(peer) {
+                    wallet.use_external_signer(*peer).unwrap();
+                }
+                wallet.consensus.external_signers = external_signers.into_iter().collect();
+                wallet.consensus.external_signer_timeout = 1;
+                cfg.add_modules(BTreeMap::from([("wallet".to_string(), wallet.to_erased())]));
+            }
+        },
+        |fed, user, bitcoin, _, _| async move {
+            fed.mine_and_mint(&user, &*bitcoin, sats(3000)).await;
+            let peg_out_address = bitcoin.get_new_address();
+            user.peg_out(1000, &peg_out_address).await;
+            fed.run_consensus_epochs(2).await;
+
+            // External signers get some time to sign
+            assert!(!fed.subset_peers(&[0, 1]).has_dropped_peer(2));
+            assert!(!fed.subset_peers(&[0, 1]).has_dropped_peer(3));
+
+            bitcoin.mine_blocks(2);
+            fed.run_consensus_epochs(1).await;
+
+            assert!(fed.subset_peers(&[0, 1]).has_dropped_peer(2));
+            assert!(fed.subset_peers(&[0, 1]).has_dropped_peer(3));
+        },
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn external_signer_waits_are_restored_from_snapshots() -> Result<()> {
+    // Peers 2 and 3 use external signers that don't sign, so the guardians keep waiting for them
+    let external_signers = [PeerId::from(2), PeerId::from(3)];
+
+    test_with_config(
+        4,
+        |configs| {
+            for (peer, cfg) in configs.iter_mut() {
+                let mut wallet: WalletConfig = cfg.get_module_config_typed("wallet").unwrap();
+                if external_signers.contains(peer) {
+                    wallet.use_external_signer(*peer).unwrap();
+                }
+                wallet.consensus.external_signers = external_signers.into_iter().collect();
+                wallet.consensus.external_signer_timeout = 10;
+                cfg.add_modules(BTreeMap::from([("wallet".to_string(), wallet.to_erased())]));
+                cfg.consensus.snapshot_interval = Some(2);
+            }
+        },
+        |fed, user, bitcoin, _, _| async move {
+            fed.mine_and_mint(&user, &*bitcoin, sats(3000)).await;
+            let peg_out_address = bitcoin.get_new_address();
+            user.peg_out(1000, &peg_out_address).await;
+            fed.run_consensus_epochs(2).await;
+            let waits = |peer: usize| {
+                fed.find_by_prefix(&ExternalSignerWaitPrefixKey)[peer]
+                    .iter()
+                    .map(|(key, waiting_since)| (key.0, *waiting_since))
+                    .collect::<Vec<_>>()
+            };
+            let waiting = waits(0);
+            assert_eq!(waiting.len(), 1);
+
+            // a snapshot is taken every other epoch and signed in the following one
+            for _ in 0..3 {
+                bitcoin.mine_blocks(1);
+                fed.run_consensus_epochs(1).await;
+            }
+
+            let snapshot = fed.restore_signed_snapshot(1).await;
+            assert!(snapshot
+                .entries
+                .iter()
+                .any(|(key, _)| key[0] == DbKeyPrefix::ExternalSignerWait as u8));
+            // the restored guardian doesn't start waiting for the external signers from scratch
+            assert_eq!(waits(1), waiting);
+            assert!(!fed.subset_peers(&[0, 1]).has_dropped_peer(2));
+        },
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handover_sweeps_previous_peg_in_descriptor() -> Result<()> {
+    // Peer 3 gets replaced by a guardian with a new peg-in key, the others keep their keys
+    let replaced_peer = PeerId::from(3);
+    let new_key = bitcoin::secp256k1::SecretKey::new(&mut rng());
+
+    test_with_config(
+        4,
+        |configs| {
+            for (peer, cfg) in configs.iter_mut() {
+                let mut wallet: WalletConfig = cfg.get_module_config_typed("wallet").unwrap();
+                let mut previous_keys = wallet.consensus.peer_peg_in_keys.clone();
+                previous_keys.remove(&replaced_peer);
+                wallet.consensus.previous_peg_in = Some(PreviousPegIn {
+                    peg_in_descriptor: wallet.consensus.peg_in_descriptor.clone(),
+                    peer_peg_in_keys: previous_keys,
+                });
+
+                let new_pubkey = CompressedPublicKey::new(new_key.public_key(&secp()));
+                wallet
+                    .consensus
+                    .peer_peg_in_keys
+                    .insert(replaced_peer, new_pubkey);
+                wallet.consensus.peg_in_descriptor = PegInDescriptor::new_wsh_sortedmulti(
+                    3, // threshold of 4 peers
+                    wallet
+                        .consensus
+                        .peer_peg_in_keys
+                        .values()
+                        .copied()
+                        .collect(),
+                )
+                .unwrap();
+                if *peer == replaced_peer {
+                    wallet.private.peg_in_key = Some(new_key);
+                }
+                cfg.add_modules(BTreeMap::from([("wallet".to_string(), wallet.to_erased())]));
+            }
+        },
+        |fed, user, bitcoin, _, _| async move {
+            let previous_descriptor = fed
+                .wallet
+                .consensus
+                .previous_peg_in
+                .clone()
+                .unwrap()
+                .peg_in_descriptor;
+
+            // A client that didn't update its config still pegs in to the previous descriptor
+            let mut legacy_config = user.config.clone();
+            let mut legacy_wallet: WalletClientConfig =
+                legacy_config.0.get_module("wallet").unwrap();
+            legacy_wallet.peg_in_descriptor = previous_descriptor.clone();
+            legacy_config
+                .0
+                .modules
+                .insert("wallet".to_string(), legacy_wallet.to_erased());
+            let legacy_user = user.new_user_with_config(legacy_config).await;
+
+            // Funds of the previous descriptor are handed over in the first epoch
+            fed.mine_spendable_utxo(&legacy_user, &*bitcoin, Amount::from_sat(5000))
+                .await;
+            fed.run_consensus_epochs(1).await;
+
+            for handover in fed.find_by_prefix(&PegInHandoverKey) {
+                assert_eq!(handover[0].1, previous_descriptor.to_string());
+            }
+            assert!(fed.find_by_prefix(&LegacyUTXOPrefixKey)[0].is_empty());
+            let legacy_txs = fed.find_by_prefix(&LegacyTransactionPrefixKey);
+            assert_eq!(legacy_txs[0].len(), 1);
+            let sweep_txid = legacy_txs[0][0].0 .0;
+
+            // Only the guardians holding keys of the previous descriptor sign the sweep
+            fed.run_consensus_epochs(1).await;
+            assert!(fed.find_by_prefix(&LegacyTransactionPrefixKey)[0].is_empty());
+            assert!(fed.find_by_prefix(&PendingTransactionPrefixKey)[0]
+                .iter()
+                .any(|(key, _)| key.0 == sweep_txid));
+            assert!(!fed.has_dropped_peer(3));
+
+            // The swept funds belong to the current descriptor once confirmed
+            fed.broadcast_transactions().await;
+            bitcoin.mine_blocks(1 + fed.wallet.consensus.finality_delay as u64);
+            fed.run_consensus_epochs(1).await;
+            for utxos in fed.find_by_prefix(&UTXOPrefixKey) {
+                assert_eq!(utxos.len(), 1);
+                assert_eq!(utxos[0].0 .0.txid, sweep_txid);
+            }
+
+            // Peg-ins to the previous descriptor after the handover get swept too
+            let peg_in_address = legacy_user.client.get_new_pegin_address(rng()).await;
+            let (proof, tx) = bitcoin.send_and_mine_block(&peg_in_address, Amount::from_sat(5000));
+            bitcoin.mine_blocks(fed.wallet.consensus.finality_delay as u64);
+            fed.run_consensus_epochs(1).await;
+
+            legacy_user.client.peg_in(proof, tx, rng()).await.unwrap();
+            fed.run_consensus_epochs(2).await; // peg in epoch + partial sigs epoch
+            legacy_user.assert_total_coins(sats(5000)).await;
+            assert!(fed.find_by_prefix(&LegacyUTXOPrefixKey)[0].is_empty());
+            assert_eq!(fed.find_by_prefix(&LegacyTransactionPrefixKey)[0].len(), 1);
+
+            fed.run_consensus_epochs(1).await;
+            assert!(fed.find_by_prefix(&LegacyTransactionPrefixKey)[0].is_empty());
+            assert!(!fed.has_dropped_peer(3));
+        },
+    )
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn drop_peers_who_dont_contribute_decryption_shares() -> Result<()> {
     test(4, |fed, user, bitcoin, gateway, _| async move {
@@ -388,6 +776,74 @@ async fn drop_peers_who_contribute_bad_sigs() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn ensure_live_gateway_switches_to_gateway_answering_probes() -> Result<()> {
+    test(2, |_, user, _, gateway, _| async move {
+        let second_gateway = gateway.new_gateway_sharing_node().await;
+        let first_key = gateway.keys.node_pub_key;
+        let second_key = second_gateway.keys.node_pub_key;
+        user.client
+            .switch_active_gateway(Some(first_key))
+            .await
+            .unwrap();
+
+        // Neither gateway answers health probes yet
+        assert_matches!(
+            user.client.ensure_live_gateway(sats(1000)).await,
+            Err(ClientError::NoLiveGateway)
+        );
+
+        // We switch away from the offline active gateway to one that answers
+        let second_health = second_gateway.serve_health_probes().await;
+        let live = user.client.ensure_live_gateway(sats(1000)).await.unwrap();
+        assert_eq!(live.node_pub_key, second_key);
+        let active = user.client.fetch_active_gateway().await.unwrap();
+        assert_eq!(active.node_pub_key, second_key);
+
+        // The active gateway is kept as long as it answers
+        let first_health = gateway.serve_health_probes().await;
+        let live = user.client.ensure_live_gateway(sats(1000)).await.unwrap();
+        assert_eq!(live.node_pub_key, second_key);
+
+        second_health.abort();
+        assert!(second_health.await.unwrap_err().is_cancelled());
+        let live = user.client.ensure_live_gateway(sats(1000)).await.unwrap();
+        assert_eq!(live.node_pub_key, first_key);
+
+        first_health.abort();
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn active_gateway_is_refreshed_from_registration() -> Result<()> {
+    test(2, |_, user, _, gateway, _| async move {
+        user.client
+            .switch_active_gateway(Some(gateway.keys.node_pub_key))
+            .await
+            .unwrap();
+
+        // The gateway raises its fees after we saved it as our active gateway
+        let fees = GatewayFee {
+            base_msat: 1_000,
+            proportional_millionths: 100,
+        };
+        let mut registration: LightningGateway = gateway.client.config().into();
+        registration.fees = fees;
+        gateway
+            .client
+            .register_with_federation(registration)
+            .await
+            .unwrap();
+
+        let active = user.client.refresh_active_gateway().await.unwrap();
+        assert_eq!(active.fees, fees);
+        let active = user.client.fetch_active_gateway().await.unwrap();
+        assert_eq!(active.fees, fees);
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn lightning_gateway_pays_internal_invoice() -> Result<()> {
     test(2, |fed, user, bitcoin, gateway, lightning| async move {
@@ -439,7 +895,7 @@ async fn lightning_gateway_pays_internal_invoice() -> Result<()> {
         let claim_outpoint = tokio::join!(
             gateway
                 .actor
-                .pay_invoice(gateway.adapter.clone(), contract_id),
+                .pay_invoice(gateway.adapter.clone(), contract_id, None),
             async {
                 // buy preimage from offer, decrypt preimage, claim outgoing contract, mint the tokens
                 fed.await_consensus_epochs(4).await.unwrap();
@@ -506,7 +962,7 @@ async fn lightning_gateway_pays_outgoing_invoice() -> Result<()> {
 
         let claim_outpoint = gateway
             .actor
-            .pay_invoice(gateway.adapter.clone(), contract_id)
+            .pay_invoice(gateway.adapter.clone(), contract_id, None)
             .await
             .unwrap();
         fed.run_consensus_epochs(2).await; // contract to mint coins, sign coins
@@ -526,6 +982,82 @@ async fn lightning_gateway_pays_outgoing_invoice() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn lightning_gateways_pay_parts_of_mpp_invoice() -> Result<()> {
+    test(2, |fed, user, bitcoin, gateway, lightning| async move {
+        // Both gateways send their part through the same lightning node
+        let second_gateway = gateway.new_gateway_sharing_node().await;
+        let invoice = lightning.invoice(sats(1000), None).await;
+
+        fed.mine_and_mint(&user, &*bitcoin, sats(2000)).await;
+        let (parts, txid) = user
+            .client
+            .fund_outgoing_mpp_ln_contracts(invoice, 2, rng())
+            .await
+            .unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_ne!(parts[0].id, parts[1].id);
+        assert_ne!(parts[0].out_idx, parts[1].out_idx);
+        assert_ne!(parts[0].gateway.mint_pub_key, parts[1].gateway.mint_pub_key);
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| part.amount)
+                .sum::<fedimint_api::Amount>(),
+            sats(1000)
+        );
+
+        fed.run_consensus_epochs(1).await;
+        user.client
+            .await_outgoing_mpp_acceptance(&parts, txid)
+            .await
+            .unwrap();
+
+        // The recipient only settles once all parts arrived, so they have to be paid concurrently
+        let claims = join_all(parts.iter().map(|part| {
+            let gateway = if part.gateway.mint_pub_key == gateway.keys.mint_pub_key {
+                &gateway
+            } else {
+                &second_gateway
+            };
+            let payment_part = PaymentPart {
+                id: part.id,
+                amount: part.amount,
+            };
+            async move {
+                let claim_outpoint = gateway
+                    .actor
+                    .pay_invoice(
+                        gateway.adapter.clone(),
+                        part.contract_id,
+                        Some(payment_part),
+                    )
+                    .await
+                    .unwrap();
+                (gateway, part.contract_id, claim_outpoint)
+            }
+        }))
+        .await;
+        fed.run_consensus_epochs(2).await; // contracts to mint coins, sign coins
+
+        for (gateway, contract_id, claim_outpoint) in claims {
+            gateway
+                .actor
+                .await_outgoing_contract_claimed(contract_id, claim_outpoint)
+                .await
+                .unwrap();
+        }
+        user.assert_total_coins(sats(2000 - 1010)).await; // 1% LN fee on each part
+        gateway.user.assert_total_coins(sats(505)).await;
+        second_gateway.user.assert_total_coins(sats(505)).await;
+
+        tokio::time::sleep(Duration::from_millis(500)).await; // FIXME need to wait for listfunds to update
+        assert_eq!(lightning.amount_sent().await, sats(1000));
+        assert_eq!(fed.max_balance_sheet(), 0);
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn lightning_gateway_claims_refund_for_internal_invoice() -> Result<()> {
     test(2, |fed, user, bitcoin, gateway, lightning| async move {
@@ -576,7 +1108,7 @@ async fn lightning_gateway_claims_refund_for_internal_invoice() -> Result<()> {
         let response = tokio::join!(
             gateway
                 .actor
-                .pay_invoice(gateway.adapter.clone(), contract_id),
+                .pay_invoice(gateway.adapter.clone(), contract_id, None),
             async {
                 // we should run 4 epocks to buy preimage from offer, decrypt preimage, claim outgoing contract, mint the tokens
                 // but we only run 1 epoch to simulate timeout in preimage decryption
@@ -674,6 +1206,161 @@ async fn receive_lightning_payment_valid_preimage() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn receive_lightning_address_payment() -> Result<()> {
+    test(2, |fed, user, bitcoin, gateway, _| async move {
+        let starting_balance = sats(2000);
+        let payment_amount = sats(100);
+
+        fed.mine_and_mint(&gateway.user, &*bitcoin, starting_balance)
+            .await;
+
+        // User registers a username together with two offers
+        let payload = user
+            .client
+            .create_lnurl_register_payload("satoshi".into(), 2)
+            .await;
+        let registration = gateway
+            .actor
+            .register_lnurl_user(payload.clone())
+            .await
+            .unwrap();
+        assert_eq!(registration.next_index, 2);
+        assert_eq!(registration.offers, payload.offers);
+
+        // Replayed registrations can't add the offers twice, other keys can't take the username
+        assert!(gateway
+            .actor
+            .register_lnurl_user(payload.clone())
+            .await
+            .is_err());
+        let other_keypair = KeyPair::new(&secp(), &mut rng());
+        let takeover = LnurlRegisterPayload::new(
+            &secp(),
+            &other_keypair,
+            payload.federation_id.clone(),
+            "satoshi".into(),
+            vec![],
+        );
+        assert_matches!(
+            gateway.actor.register_lnurl_user(takeover).await,
+            Err(LnGatewayError::ClientError(ClientError::LnurlUsernameTaken))
+        );
+
+        // Someone pays the lightning address, the gateway creates the invoice from the first offer
+        let metadata_hash = sha256(b"metadata");
+        let invoice = tokio::join!(
+            gateway
+                .actor
+                .create_lnurl_invoice("satoshi", payment_amount, metadata_hash),
+            fed.await_consensus_epochs(1),
+        )
+        .0
+        .unwrap();
+        assert_eq!(invoice.index, 0);
+        assert_matches!(
+            invoice.invoice.description(),
+            InvoiceDescription::Hash(hash) if hash.0 == metadata_hash
+        );
+
+        // Gateway buys the preimage, which only the federation could decrypt
+        let (outpoint, contract_id) = gateway
+            .actor
+            .buy_preimage_offer(invoice.invoice.payment_hash(), &payment_amount, rng())
+            .await
+            .unwrap();
+        fed.run_consensus_epochs(2).await; // 1 epoch to process contract, 1 for preimage decryption
+        let preimage = gateway
+            .actor
+            .await_preimage_decryption(outpoint)
+            .await
+            .unwrap();
+        let payment_key = user.client.lnurl_payment_keypair(0).x_only_public_key().0;
+        assert_eq!(preimage.to_public_key().unwrap(), payment_key);
+
+        // Only recent requests signed by the registered key may list the invoices
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let user_keypair = user.client.lnurl_keypair();
+        let stale_request = LnurlInvoicesRequest::new(
+            &secp(),
+            &user_keypair,
+            "satoshi",
+            now - 2 * LNURL_REQUEST_MAX_AGE,
+        );
+        let forged_request = LnurlInvoicesRequest::new(&secp(), &other_keypair, "satoshi", now);
+        for request in [stale_request, forged_request] {
+            assert_matches!(
+                gateway.actor.list_lnurl_invoices("satoshi", &request).await,
+                Err(LnGatewayError::ClientError(
+                    ClientError::InvalidLnurlSignature
+                ))
+            );
+        }
+        let request = LnurlInvoicesRequest::new(&secp(), &user_keypair, "satoshi", now);
+        let invoices = gateway
+            .actor
+            .list_lnurl_invoices("satoshi", &request)
+            .await
+            .unwrap();
+        assert_eq!(invoices, vec![invoice]);
+
+        // User settles the payment by claiming the contract
+        let outpoints = user
+            .client
+            .claim_lnurl_invoices(invoices, rng())
+            .await
+            .unwrap();
+        assert_eq!(outpoints.len(), 1);
+        fed.run_consensus_epochs(2).await; // 1 epoch to process contract, 1 to sweep ecash from contract
+
+        user.assert_total_coins(payment_amount).await;
+        gateway
+            .user
+            .assert_total_coins(starting_balance - payment_amount)
+            .await;
+        let contract = user
+            .client
+            .ln_client()
+            .get_incoming_contract(contract_id)
+            .await
+            .unwrap();
+        assert_eq!(contract.amount, sats(0));
+        assert_eq!(fed.max_balance_sheet(), 0);
+
+        // Every offer is used once, the gateway can't create invoices once they are used up
+        let second_invoice = tokio::join!(
+            gateway
+                .actor
+                .create_lnurl_invoice("satoshi", payment_amount, metadata_hash),
+            fed.await_consensus_epochs(1),
+        )
+        .0
+        .unwrap();
+        assert_eq!(second_invoice.index, 1);
+        assert_matches!(
+            gateway
+                .actor
+                .create_lnurl_invoice("satoshi", payment_amount * 2, metadata_hash)
+                .await,
+            Err(LnGatewayError::ClientError(
+                ClientError::LnurlOffersExhausted
+            ))
+        );
+
+        // Requesting the same amount again hands out the unpaid invoice instead
+        let reused_invoice = gateway
+            .actor
+            .create_lnurl_invoice("satoshi", payment_amount, metadata_hash)
+            .await
+            .unwrap();
+        assert_eq!(reused_invoice, second_invoice);
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn receive_lightning_payment_invalid_preimage() -> Result<()> {
     test(2, |fed, user, bitcoin, gateway, _| async move {
@@ -910,6 +1597,86 @@ async fn unbalanced_transactions_get_rejected() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn transactions_beyond_proposal_queue_limit_get_rejected() -> Result<()> {
+    test_with_config(
+        2,
+        |configs| {
+            for cfg in configs.values_mut() {
+                cfg.local.max_pending_transactions = 1;
+            }
+        },
+        |fed, user, _, _, _| async move {
+            let mut transactions = vec![];
+            for idx in 0..2u8 {
+                let kp = KeyPair::new(&secp(), &mut rng());
+                let offer_output = user.client.ln_client().create_offer_output(
+                    sats(100),
+                    sha256(&[idx]),
+                    Preimage(kp.x_only_public_key().0.serialize()),
+                    None,
+                );
+                let mut builder = TransactionBuilder::default();
+                builder.output(Output::LN(offer_output));
+                let tx = builder.build(&user.client, rng()).await;
+                transactions.push(tx.into_type_erased());
+            }
+
+            fed.submit_transaction(transactions[0].clone())
+                .await
+                .unwrap();
+            assert_matches!(
+                fed.submit_transaction(transactions[1].clone()).await,
+                Err(ProposalQueueFull(1))
+            );
+
+            // Processing the queued transaction makes room for the next one
+            fed.run_consensus_epochs(1).await;
+            fed.submit_transaction(transactions[1].clone())
+                .await
+                .unwrap();
+        },
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peg_outs_beyond_epoch_limit_get_rejected() -> Result<()> {
+    test_with_config(
+        2,
+        |configs| {
+            for cfg in configs.values_mut() {
+                cfg.consensus.max_peg_out_per_epoch = Some(sats(2000));
+            }
+        },
+        |fed, user, bitcoin, _, _| async move {
+            fed.mine_and_mint(&user, &*bitcoin, sats(5000)).await;
+            let peg_out_address = bitcoin.get_new_address();
+
+            let peg_out = user
+                .client
+                .new_peg_out_with_fees(Amount::from_sat(500), peg_out_address.clone())
+                .await
+                .unwrap();
+            user.client.peg_out(peg_out, rng()).await.unwrap();
+
+            let peg_out = user
+                .client
+                .new_peg_out_with_fees(Amount::from_sat(3000), peg_out_address)
+                .await
+                .unwrap();
+            let error = user.client.peg_out(peg_out, rng()).await.unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("exceeds the federation's per epoch limit"),
+                "unexpected error: {error}"
+            );
+        },
+    )
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn can_have_federations_with_one_peer() -> Result<()> {
     test(1, |fed, user, bitcoin, _, _| async move {
@@ -938,6 +1705,47 @@ async fn can_get_signed_epoch_history() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn subscribe_to_signed_epoch_history() -> Result<()> {
+    test(2, |fed, user, bitcoin, _, _| async move {
+        let pubkey = fed.cfg.consensus.epoch_pk_set.public_key();
+        let api = WsFederationApi::from_config(&user.client.config().0);
+        let mut epochs = Box::pin(api.subscribe_epoch_history(0, pubkey).await.unwrap());
+        await_epoch_history_subscriptions(&fed, 2).await;
+
+        fed.mine_and_mint(&user, &*bitcoin, sats(1000)).await;
+        fed.mine_and_mint(&user, &*bitcoin, sats(1000)).await;
+
+        let epoch0 = epochs.next().await.unwrap().unwrap();
+        let epoch1 = epochs.next().await.unwrap().unwrap();
+        assert_eq!(epoch0.outcome.epoch, 0);
+        assert_eq!(epoch1.outcome.epoch, 1);
+        assert_eq!(epoch0.verify_sig(&pubkey), Ok(()));
+        assert_eq!(epoch1.verify_sig(&pubkey), Ok(()));
+        assert_eq!(epoch1.verify_hash(&Some(epoch0)), Ok(()));
+
+        // dropping the stream unsubscribes from all servers
+        drop(epochs);
+        await_epoch_history_subscriptions(&fed, 0).await;
+
+        // a new subscription resumes from the requested epoch
+        let mut epochs = Box::pin(api.subscribe_epoch_history(1, pubkey).await.unwrap());
+        assert_eq!(epochs.next().await.unwrap().unwrap().outcome.epoch, 1);
+    })
+    .await
+}
+
+/// Waits for the servers to open or close subscriptions, which happens in the background
+async fn await_epoch_history_subscriptions(fed: &FederationTest, expected: usize) {
+    for _ in 0..50 {
+        if fed.epoch_history_subscriptions() == expected {
+            return;
+        }
+        fedimint_api::task::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(fed.epoch_history_subscriptions(), expected);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn rejoin_consensus_single_peer() -> Result<()> {
     test(4, |fed, user, bitcoin, _, _| async move {
@@ -1040,3 +1848,171 @@ async fn ecash_can_be_recovered() -> Result<()> {
     })
     .await
 }
+
+#[tokio::test(start_paused = true)]
+async fn sim_consensus_survives_lossy_links() {
+    LocalSet::new()
+        .run_until(async {
+            let mut sim = SimFederation::new(4, 1);
+            let faults = LinkFaults {
+                drop_rate: 0.2,
+                min_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(500),
+            };
+            for to in peers(&[0, 1, 2]) {
+                sim.network.set_link_faults(PeerId::from(3), to, faults);
+            }
+            sim.network.set_faults(LinkFaults {
+                max_delay: Duration::from_millis(100),
+                ..LinkFaults::default()
+            });
+
+            sim.start_all().await;
+            sim.transfer_ecash(&peers(&[0, 1, 2]), sats(10_000)).await;
+            sim.run_until_epoch(&peers(&[0, 1, 2]), 5).await;
+            assert!(sim.network.stats().dropped > 0);
+
+            sim.finish().await;
+        })
+        .await
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_partitioned_guardian_catches_up_after_healing() {
+    LocalSet::new()
+        .run_until(async {
+            let mut sim = SimFederation::new(4, 2);
+            sim.start_all().await;
+            sim.run_until_epoch(&sim.peers, 2).await;
+
+            sim.network.partition(&[&peers(&[0, 1, 2]), &peers(&[3])]);
+            sim.transfer_ecash(&peers(&[0, 1, 2]), sats(10_000)).await;
+            sim.run_epochs(&peers(&[0, 1, 2]), 5).await;
+            let majority_epoch = sim.last_epoch(PeerId::from(0)).await.unwrap();
+            assert!(sim.last_epoch(PeerId::from(3)).await < Some(majority_epoch));
+
+            sim.network.heal();
+            sim.run_until_epoch(&sim.peers, majority_epoch + 2).await;
+
+            sim.finish().await;
+        })
+        .await
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_consensus_tolerates_byzantine_guardian() {
+    LocalSet::new()
+        .run_until(async {
+            let mut sim = SimFederation::new(4, 3);
+            sim.network
+                .set_byzantine(PeerId::from(3), Some(ByzantineBehavior::CorruptMessages));
+
+            sim.start_all().await;
+            sim.transfer_ecash(&peers(&[0, 1, 2]), sats(10_000)).await;
+            sim.run_until_epoch(&peers(&[0, 1, 2]), 5).await;
+            assert!(sim.network.stats().corrupted > 0);
+
+            sim.finish().await;
+        })
+        .await
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_crashed_guardian_restarts_from_its_database() {
+    LocalSet::new()
+        .run_until(async {
+            let mut sim = SimFederation::new(4, 4);
+            sim.start_all().await;
+            sim.run_until_epoch(&sim.peers, 3).await;
+
+            sim.crash(PeerId::from(1)).await;
+            let crashed_epoch = sim.last_epoch(PeerId::from(1)).await;
+            sim.transfer_ecash(&peers(&[0, 2, 3]), sats(10_000)).await;
+            sim.run_epochs(&peers(&[0, 2, 3]), 5).await;
+            assert_eq!(sim.last_epoch(PeerId::from(1)).await, crashed_epoch);
+
+            sim.start(PeerId::from(1)).await;
+            let majority_epoch = sim.last_epoch(PeerId::from(0)).await.unwrap();
+            sim.run_until_epoch(&sim.peers, majority_epoch + 2).await;
+
+            sim.finish().await;
+        })
+        .await
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_long_offline_guardian_catches_up_from_peers() {
+    LocalSet::new()
+        .run_until(async {
+            let mut sim = SimFederation::new(4, 5);
+            sim.start_all().await;
+            sim.run_until_epoch(&sim.peers, 2).await;
+
+            sim.crash(PeerId::from(3)).await;
+            sim.run_epochs(&peers(&[0, 1, 2]), 300).await;
+            let downloads_before_restart = sim.api_history_downloads();
+
+            sim.start(PeerId::from(3)).await;
+            let majority_epoch = sim.last_epoch(PeerId::from(0)).await.unwrap();
+            sim.run_until_epoch(&sim.peers, majority_epoch + 2).await;
+
+            // only the epochs that weren't signed yet when catching up come from the API
+            let downloads = sim.api_history_downloads() - downloads_before_restart;
+            assert!(
+                downloads < 100,
+                "downloaded {} epochs from the API",
+                downloads
+            );
+
+            sim.finish().await;
+        })
+        .await
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_replaced_guardian_starts_from_snapshot() {
+    LocalSet::new()
+        .run_until(async {
+            let mut sim = SimFederation::new(4, 6);
+            sim.configure(|cfg| {
+                cfg.consensus.snapshot_interval = Some(20);
+                cfg.local.epoch_history_retention = Some(5);
+            });
+            sim.start_all().await;
+            sim.run_until_epoch(&sim.peers, 2).await;
+
+            sim.crash(PeerId::from(3)).await;
+            sim.run_epochs(&peers(&[0, 1, 2]), 100).await;
+            assert!(!sim.has_epoch(PeerId::from(0), 0).await);
+
+            sim.replace(PeerId::from(3));
+            sim.start(PeerId::from(3)).await;
+            let majority_epoch = sim.last_epoch(PeerId::from(0)).await.unwrap();
+            sim.run_until_epoch(&sim.peers, majority_epoch + 2).await;
+
+            // the replaced guardian never saw the pruned epochs
+            assert!(!sim.has_epoch(PeerId::from(3), 0).await);
+
+            // once all guardians took the next snapshot from their own state, the replaced
+            // guardian's consensus state has to match its peers'
+            let next_snapshot_epoch = (majority_epoch / 20 + 1) * 20;
+            sim.run_until_epoch(&sim.peers, next_snapshot_epoch + 2)
+                .await;
+            let (header, state) = sim.snapshot(PeerId::from(3)).await.unwrap();
+            assert_eq!(header.epoch, next_snapshot_epoch);
+            assert!(!state.is_empty());
+            for peer in peers(&[0, 1, 2]) {
+                let (peer_header, peer_state) = sim.snapshot(peer).await.unwrap();
+                assert_eq!(peer_header.epoch, header.epoch);
+                assert_eq!(peer_header.hash, header.hash);
+                assert_eq!(
+                    peer_state, state,
+                    "{} has a different consensus state",
+                    peer
+                );
+            }
+
+            sim.finish().await;
+        })
+        .await
+}
@@ -10,31 +10,36 @@ use crate::{DummyInput, DummyOutput, DummyOutputConfirmation, DummyOutputOutcome
 pub struct DummyModuleDecoder;
 
 impl PluginDecode for DummyModuleDecoder {
-    fn decode_input(mut d: &mut dyn io::Read) -> Result<Input, DecodeError> {
-        Ok(Input::from(DummyInput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
-        )?))
+    fn decode_input(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError> {
+        Ok(Input::from(DummyInput::consensus_decode(&mut d, modules)?))
     }
-    fn decode_output(mut d: &mut dyn io::Read) -> Result<Output, DecodeError> {
+    fn decode_output(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError> {
         Ok(Output::from(DummyOutput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
 
-    fn decode_output_outcome(mut d: &mut dyn io::Read) -> Result<OutputOutcome, DecodeError> {
+    fn decode_output_outcome(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError> {
         Ok(OutputOutcome::from(DummyOutputOutcome::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
 
     fn decode_consensus_item(
         mut r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
     ) -> Result<fedimint_api::core::ConsensusItem, DecodeError> {
         Ok(ConsensusItem::from(
-            DummyOutputConfirmation::consensus_decode(&mut r, &ModuleDecoderRegistry::default())?,
+            DummyOutputConfirmation::consensus_decode(&mut r, modules)?,
         ))
     }
 }
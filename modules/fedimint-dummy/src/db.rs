@@ -1,4 +1,4 @@
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use serde::Serialize;
 use strum_macros::EnumIter;
@@ -10,6 +10,9 @@ pub enum DbKeyPrefix {
     Example = 0x80,
 }
 
+/// Current version of the dummy module's database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
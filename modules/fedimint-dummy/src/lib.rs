@@ -10,14 +10,14 @@ use fedimint_api::config::{
     TypedServerModuleConfig,
 };
 use fedimint_api::core::ModuleKey;
-use fedimint_api::db::DatabaseTransaction;
+use fedimint_api::db::{DatabaseTransaction, DatabaseVersion, MigrationMap};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::__reexports::serde_json;
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
 use fedimint_api::module::{
     api_endpoint, ApiEndpoint, FederationModuleConfigGen, InputMeta, ModuleError,
-    TransactionItemAmount,
+    ModuleReshareParams, TransactionItemAmount,
 };
 use fedimint_api::net::peers::MuxPeerConnections;
 use fedimint_api::task::TaskGroup;
@@ -26,6 +26,7 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::config::{DummyConfig, DummyConfigConsensus, DummyConfigPrivate};
+use crate::db::DATABASE_VERSION;
 
 pub mod common;
 pub mod config;
@@ -97,6 +98,25 @@ impl FederationModuleConfigGen for DummyConfigGenerator {
         Ok(Ok(server.to_erased()))
     }
 
+    async fn distributed_reshare(
+        &self,
+        _connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        _our_id: &PeerId,
+        _peers: &[PeerId],
+        reshare: &ModuleReshareParams,
+        _params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let server = DummyConfig {
+            private: DummyConfigPrivate {
+                something_private: 3,
+            },
+            consensus: serde_json::from_value(reshare.old_consensus.clone())?,
+        };
+
+        Ok(Ok(server.to_erased()))
+    }
+
     fn to_client_config(&self, config: ServerModuleConfig) -> anyhow::Result<ClientModuleConfig> {
         Ok(config
             .to_typed::<DummyConfig>()?
@@ -178,6 +198,18 @@ impl ServerModulePlugin for Dummy {
         &DummyModuleDecoder
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        DATABASE_VERSION
+    }
+
+    fn database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    fn consensus_state_prefixes(&self) -> Vec<u8> {
+        vec![db::DbKeyPrefix::Example as u8]
+    }
+
     async fn await_consensus_proposal(&self, _dbtx: &mut DatabaseTransaction<'_>) {}
 
     async fn consensus_proposal(
@@ -11,30 +11,38 @@ use crate::{LightningConsensusItem, LightningInput, LightningOutput, LightningOu
 pub struct LightningModuleDecoder;
 
 impl PluginDecode for LightningModuleDecoder {
-    fn decode_input(mut d: &mut dyn io::Read) -> Result<Input, DecodeError> {
+    fn decode_input(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError> {
         Ok(Input::from(LightningInput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
-    fn decode_output(mut d: &mut dyn io::Read) -> Result<Output, DecodeError> {
+    fn decode_output(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError> {
         Ok(Output::from(LightningOutput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
 
-    fn decode_output_outcome(mut d: &mut dyn io::Read) -> Result<OutputOutcome, DecodeError> {
+    fn decode_output_outcome(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError> {
         Ok(OutputOutcome::from(
-            LightningOutputOutcome::consensus_decode(&mut d, &ModuleDecoderRegistry::default())?,
+            LightningOutputOutcome::consensus_decode(&mut d, modules)?,
         ))
     }
 
     fn decode_consensus_item(
         mut r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
     ) -> Result<fedimint_api::core::ConsensusItem, DecodeError> {
         Ok(ConsensusItem::from(
-            LightningConsensusItem::consensus_decode(&mut r, &ModuleDecoderRegistry::default())?,
+            LightningConsensusItem::consensus_decode(&mut r, modules)?,
         ))
     }
 }
@@ -80,14 +80,34 @@ impl TypedServerModuleConfig for LightningConfig {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FeeConsensus {
     pub contract_input: fedimint_api::Amount,
+    /// Parts-per-million of the contract amount charged on top of `contract_input`
+    #[serde(default)]
+    pub contract_input_ppm: u64,
     pub contract_output: fedimint_api::Amount,
+    /// Parts-per-million of the contract amount charged on top of `contract_output`
+    #[serde(default)]
+    pub contract_output_ppm: u64,
+}
+
+impl FeeConsensus {
+    /// Fee for spending `amount` from a contract
+    pub fn contract_input_fee(&self, amount: fedimint_api::Amount) -> fedimint_api::Amount {
+        self.contract_input + amount.mul_ppm(self.contract_input_ppm)
+    }
+
+    /// Fee for funding a contract with `amount`
+    pub fn contract_output_fee(&self, amount: fedimint_api::Amount) -> fedimint_api::Amount {
+        self.contract_output + amount.mul_ppm(self.contract_output_ppm)
+    }
 }
 
 impl Default for FeeConsensus {
     fn default() -> Self {
         Self {
             contract_input: fedimint_api::Amount::ZERO,
+            contract_input_ppm: 0,
             contract_output: fedimint_api::Amount::ZERO,
+            contract_output_ppm: 0,
         }
     }
 }
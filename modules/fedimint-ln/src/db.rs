@@ -1,12 +1,18 @@
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use std::time::SystemTime;
+
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseTransaction, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::{OutPoint, PeerId};
+use futures::future::BoxFuture;
 use secp256k1::PublicKey;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
 use crate::contracts::{incoming::IncomingContractOffer, ContractId, PreimageDecryptionShare};
-use crate::{ContractAccount, LightningGateway, LightningOutputOutcome};
+use crate::{
+    ContractAccount, LightningGateway, LightningGatewayRegistration, LightningGatewayV0,
+    LightningOutputOutcome, GATEWAY_REGISTRATION_TTL,
+};
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -19,6 +25,9 @@ pub enum DbKeyPrefix {
     LightningGateway = 0x45,
 }
 
+/// Current version of the lightning module's database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(2);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -125,14 +134,249 @@ pub struct LightningGatewayKey(pub PublicKey);
 impl DatabaseKeyPrefixConst for LightningGatewayKey {
     const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
     type Key = Self;
-    type Value = LightningGateway;
+    type Value = LightningGatewayRegistration;
 }
 
 #[derive(Debug, Encodable, Decodable)]
 pub struct LightningGatewayKeyPrefix;
 
 impl DatabaseKeyPrefixConst for LightningGatewayKeyPrefix {
+    const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
+    type Key = LightningGatewayKey;
+    type Value = LightningGatewayRegistration;
+}
+
+/// Gateway registrations in the layout of database version 0
+#[derive(Debug, Encodable, Decodable)]
+pub struct LightningGatewayKeyPrefixV0;
+
+impl DatabaseKeyPrefixConst for LightningGatewayKeyPrefixV0 {
+    const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
+    type Key = LightningGatewayKey;
+    type Value = LightningGatewayV0;
+}
+
+/// Adds the default fee and timelock delta to gateways registered before they advertised them
+pub fn migrate_to_v1<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        let gateways_v0 = dbtx
+            .find_by_prefix(&LightningGatewayKeyPrefixV0)
+            .await
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        dbtx.remove_by_prefix(&LightningGatewayKeyPrefixV0).await?;
+        for (key, gateway) in gateways_v0 {
+            dbtx.insert_new_entry(
+                &LightningGatewayKeyV1(key.0),
+                &LightningGateway::from(gateway),
+            )
+            .await?;
+        }
+        Ok(())
+    })
+}
+
+/// A gateway registration in the layout of database version 1
+#[derive(Debug, Encodable, Decodable)]
+pub struct LightningGatewayKeyV1(pub PublicKey);
+
+impl DatabaseKeyPrefixConst for LightningGatewayKeyV1 {
+    const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
+    type Key = Self;
+    type Value = LightningGateway;
+}
+
+/// Gateway registrations in the layout of database version 1
+#[derive(Debug, Encodable, Decodable)]
+pub struct LightningGatewayKeyPrefixV1;
+
+impl DatabaseKeyPrefixConst for LightningGatewayKeyPrefixV1 {
     const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
     type Key = LightningGatewayKey;
     type Value = LightningGateway;
 }
+
+/// Gives gateways registered before registrations expired a fresh TTL, so they stay listed if they
+/// keep re-registering
+pub fn migrate_to_v2<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        let gateways_v1 = dbtx
+            .find_by_prefix(&LightningGatewayKeyPrefixV1)
+            .await
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        dbtx.remove_by_prefix(&LightningGatewayKeyPrefixV1).await?;
+        let valid_until = SystemTime::now() + GATEWAY_REGISTRATION_TTL;
+        for (key, info) in gateways_v1 {
+            dbtx.insert_new_entry(&key, &LightningGatewayRegistration { info, valid_until })
+                .await?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use fedimint_api::core::MODULE_KEY_LN;
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::{
+        apply_migrations, Database, DatabaseKeyPrefixConst, DatabaseVersion, DatabaseVersionKey,
+        MigrationMap,
+    };
+    use fedimint_api::encoding::{Decodable, Encodable};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use secp256k1::{KeyPair, PublicKey, Secp256k1};
+
+    use super::{
+        migrate_to_v1, migrate_to_v2, DbKeyPrefix, LightningGatewayKey,
+        LightningGatewayKeyPrefixV1, LightningGatewayKeyV1,
+    };
+    use crate::{
+        GatewayFee, LightningGateway, LightningGatewayV0, DEFAULT_GATEWAY_TIMELOCK_DELTA,
+        GATEWAY_REGISTRATION_TTL,
+    };
+
+    /// A gateway registration in the layout of database version 0
+    #[derive(Debug, Encodable, Decodable)]
+    struct LightningGatewayKeyV0(PublicKey);
+
+    impl DatabaseKeyPrefixConst for LightningGatewayKeyV0 {
+        const DB_PREFIX: u8 = DbKeyPrefix::LightningGateway as u8;
+        type Key = Self;
+        type Value = LightningGatewayV0;
+    }
+
+    fn gateway_v0(seed: u8) -> LightningGatewayV0 {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[seed; 32]).unwrap();
+        LightningGatewayV0 {
+            mint_pub_key: keypair.x_only_public_key().0,
+            node_pub_key: keypair.public_key(),
+            api: format!("http://gateway-{}.example.com", seed)
+                .parse()
+                .unwrap(),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn migrate_to_v1_adds_default_fee_and_timelock_delta() {
+        let db: Database = MemDatabase::new().into();
+
+        // Write a database fixture using the version 0 layout
+        let gateways_v0 = vec![gateway_v0(1), gateway_v0(2)];
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        for gateway in &gateways_v0 {
+            dbtx.insert_new_entry(&LightningGatewayKeyV0(gateway.node_pub_key), gateway)
+                .await
+                .unwrap();
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut migrations = MigrationMap::new();
+        migrations.insert(DatabaseVersion(0), migrate_to_v1);
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        apply_migrations(&mut dbtx, MODULE_KEY_LN, DatabaseVersion(1), migrations)
+            .await
+            .expect("Migration failed");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey(MODULE_KEY_LN))
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(1))
+        );
+        let mut gateways_v1 = dbtx
+            .find_by_prefix(&LightningGatewayKeyPrefixV1)
+            .await
+            .map(|res| res.unwrap())
+            .collect::<Vec<_>>();
+        gateways_v1.sort_by_key(|(key, _)| key.0);
+        let mut expected = gateways_v0
+            .into_iter()
+            .map(|gateway| {
+                let node_pub_key = gateway.node_pub_key;
+                let gateway = LightningGateway {
+                    mint_pub_key: gateway.mint_pub_key,
+                    node_pub_key,
+                    api: gateway.api,
+                    fees: GatewayFee {
+                        base_msat: 0,
+                        proportional_millionths: 10_000,
+                    },
+                    timelock_delta: DEFAULT_GATEWAY_TIMELOCK_DELTA,
+                };
+                (node_pub_key, gateway)
+            })
+            .collect::<Vec<_>>();
+        expected.sort_by_key(|(key, _)| *key);
+        assert_eq!(
+            gateways_v1
+                .into_iter()
+                .map(|(key, gateway)| (key.0, gateway))
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        // Commit to surpress the warning message
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn migrate_to_v2_gives_gateways_a_fresh_ttl() {
+        let db: Database = MemDatabase::new().into();
+
+        // Write a database fixture using the version 1 layout
+        let gateways_v1 = vec![
+            LightningGateway::from(gateway_v0(1)),
+            LightningGateway::from(gateway_v0(2)),
+        ];
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        dbtx.insert_new_entry(&DatabaseVersionKey(MODULE_KEY_LN), &DatabaseVersion(1))
+            .await
+            .unwrap();
+        for gateway in &gateways_v1 {
+            dbtx.insert_new_entry(&LightningGatewayKeyV1(gateway.node_pub_key), gateway)
+                .await
+                .unwrap();
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let before = SystemTime::now();
+        let mut migrations = MigrationMap::new();
+        migrations.insert(DatabaseVersion(0), migrate_to_v1);
+        migrations.insert(DatabaseVersion(1), migrate_to_v2);
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        apply_migrations(&mut dbtx, MODULE_KEY_LN, DatabaseVersion(2), migrations)
+            .await
+            .expect("Migration failed");
+        dbtx.commit_tx().await.expect("DB Error");
+        let after = SystemTime::now();
+
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey(MODULE_KEY_LN))
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(2))
+        );
+        for gateway in gateways_v1 {
+            let registration = dbtx
+                .get_value(&LightningGatewayKey(gateway.node_pub_key))
+                .await
+                .unwrap()
+                .expect("Gateway registration was migrated");
+            assert_eq!(registration.info, gateway);
+            assert!(before + GATEWAY_REGISTRATION_TTL <= registration.valid_until);
+            assert!(registration.valid_until <= after + GATEWAY_REGISTRATION_TTL);
+        }
+
+        // Commit to surpress the warning message
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+}
@@ -16,6 +16,7 @@ pub mod contracts;
 pub mod db;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Sub;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use bitcoin_hashes::Hash as BitcoinHash;
@@ -24,17 +25,19 @@ use db::{LightningGatewayKey, LightningGatewayKeyPrefix};
 use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::config::TypedServerModuleConsensusConfig;
 use fedimint_api::config::{
-    ClientModuleConfig, ConfigGenParams, DkgPeerMsg, DkgRunner, ServerModuleConfig,
+    ClientModuleConfig, ConfigGenParams, DkgPeerMsg, DkgRunner, PreviousKeys, ServerModuleConfig,
     TypedServerModuleConfig,
 };
 use fedimint_api::core::{ModuleKey, MODULE_KEY_LN};
-use fedimint_api::db::DatabaseTransaction;
+use fedimint_api::db::{
+    DatabaseTransaction, DatabaseVersion, MigrationMap, ReadDatabaseTransaction,
+};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
 use fedimint_api::module::{
     api_endpoint, ApiEndpoint, ApiError, FederationModuleConfigGen, InputMeta, IntoModuleError,
-    ModuleError, TransactionItemAmount,
+    ModuleError, ModuleReshareParams, TransactionItemAmount,
 };
 use fedimint_api::net::peers::MuxPeerConnections;
 use fedimint_api::task::TaskGroup;
@@ -58,7 +61,7 @@ use crate::contracts::{
 use crate::db::{
     AgreedDecryptionShareKey, AgreedDecryptionShareKeyPrefix, ContractKey, ContractKeyPrefix,
     ContractUpdateKey, OfferKey, OfferKeyPrefix, ProposeDecryptionShareKey,
-    ProposeDecryptionShareKeyPrefix,
+    ProposeDecryptionShareKeyPrefix, DATABASE_VERSION,
 };
 
 /// The lightning module implements an account system. It does not have the privacy guarantees of
@@ -200,11 +203,91 @@ impl std::fmt::Display for LightningOutputOutcome {
     }
 }
 
+/// Safety margin in blocks that gateways used before they advertised one
+pub const DEFAULT_GATEWAY_TIMELOCK_DELTA: u64 = 10;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
 pub struct LightningGateway {
     pub mint_pub_key: secp256k1::XOnlyPublicKey,
     pub node_pub_key: secp256k1::PublicKey,
     pub api: Url,
+    /// Fee the gateway charges for paying an invoice, outgoing contracts have to be funded with
+    /// the invoice amount plus this fee
+    #[serde(default)]
+    pub fees: GatewayFee,
+    /// Blocks the gateway needs between paying an invoice and the timelock of the outgoing
+    /// contract expiring, so that it can safely claim the contract
+    #[serde(default = "default_gateway_timelock_delta")]
+    pub timelock_delta: u64,
+}
+
+/// Time a gateway registration stays valid, gateways have to re-register before it expires to stay
+/// listed
+pub const GATEWAY_REGISTRATION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A gateway registered with this guardian, only listed until `valid_until` unless it re-registers
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq)]
+pub struct LightningGatewayRegistration {
+    pub info: LightningGateway,
+    pub valid_until: SystemTime,
+}
+
+impl LightningGatewayRegistration {
+    pub fn is_expired(&self) -> bool {
+        self.valid_until < SystemTime::now()
+    }
+}
+
+fn default_gateway_timelock_delta() -> u64 {
+    DEFAULT_GATEWAY_TIMELOCK_DELTA
+}
+
+/// Routing fee of a gateway, made up like the fees of lightning channels
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GatewayFee {
+    pub base_msat: u32,
+    pub proportional_millionths: u32,
+}
+
+impl GatewayFee {
+    /// Fee for paying `amount`
+    pub fn fee(&self, amount: Amount) -> Amount {
+        let proportional =
+            (amount.msats as u128 * self.proportional_millionths as u128 / 1_000_000) as u64;
+        Amount::from_msats(self.base_msat as u64 + proportional)
+    }
+}
+
+/// Defaults to the 1% margin clients added to outgoing contracts before gateways advertised their
+/// fees
+impl Default for GatewayFee {
+    fn default() -> Self {
+        GatewayFee {
+            base_msat: 0,
+            proportional_millionths: 10_000,
+        }
+    }
+}
+
+/// [`LightningGateway`] as stored by databases of version 0, before gateways advertised their fee
+/// and timelock delta
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct LightningGatewayV0 {
+    pub mint_pub_key: secp256k1::XOnlyPublicKey,
+    pub node_pub_key: secp256k1::PublicKey,
+    pub api: Url,
+}
+
+impl From<LightningGatewayV0> for LightningGateway {
+    fn from(gateway: LightningGatewayV0) -> Self {
+        LightningGateway {
+            mint_pub_key: gateway.mint_pub_key,
+            node_pub_key: gateway.node_pub_key,
+            api: gateway.api,
+            fees: GatewayFee::default(),
+            timelock_delta: DEFAULT_GATEWAY_TIMELOCK_DELTA,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize)]
@@ -290,6 +373,61 @@ impl FederationModuleConfigGen for LightningModuleConfigGen {
         Ok(Ok(server.to_erased()))
     }
 
+    async fn distributed_reshare(
+        &self,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        our_id: &PeerId,
+        peers: &[PeerId],
+        reshare: &ModuleReshareParams,
+        _params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let old_consensus: LightningConfigConsensus =
+            serde_json::from_value(reshare.old_consensus.clone())?;
+        let old_sks = reshare
+            .old_config
+            .clone()
+            .map(|config| config.to_typed::<LightningConfig>())
+            .transpose()?
+            .map(|config| config.private.threshold_sec_key.0);
+        let previous = PreviousKeys::from_threshold_crypto(
+            &old_consensus.threshold_pub_keys,
+            &reshare.dealers,
+            old_sks.as_ref(),
+        )?;
+
+        let mut dkg = DkgRunner::new((), peers.threshold(), our_id, peers);
+        let g1 = if let Ok(g1) = dkg
+            .run_reshare_g1(
+                MODULE_KEY_LN,
+                &reshare.dealers,
+                HashMap::from([((), previous)]),
+                connections,
+                &mut OsRng,
+            )
+            .await
+        {
+            g1
+        } else {
+            return Ok(Err(Cancelled));
+        };
+
+        let (pks, sks) = g1[&()].threshold_crypto();
+
+        let server = LightningConfig {
+            consensus: LightningConfigConsensus {
+                threshold_pub_keys: pks,
+                threshold: peers.threshold(),
+                fee_consensus: old_consensus.fee_consensus,
+            },
+            private: LightningConfigPrivate {
+                threshold_sec_key: SerdeSecret(sks),
+            },
+        };
+
+        Ok(Ok(server.to_erased()))
+    }
+
     fn to_client_config(&self, config: ServerModuleConfig) -> anyhow::Result<ClientModuleConfig> {
         Ok(config
             .to_typed::<LightningConfig>()?
@@ -328,6 +466,27 @@ impl ServerModulePlugin for LightningModule {
         &LightningModuleDecoder
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        DATABASE_VERSION
+    }
+
+    fn database_migrations(&self) -> MigrationMap {
+        let mut migrations = MigrationMap::new();
+        migrations.insert(DatabaseVersion(0), db::migrate_to_v1);
+        migrations.insert(DatabaseVersion(1), db::migrate_to_v2);
+        migrations
+    }
+
+    fn consensus_state_prefixes(&self) -> Vec<u8> {
+        // our own decryption shares and the gateways registered with us differ between guardians
+        vec![
+            db::DbKeyPrefix::Contract as u8,
+            db::DbKeyPrefix::Offer as u8,
+            db::DbKeyPrefix::AgreedDecryptionShare as u8,
+            db::DbKeyPrefix::ContractUpdate as u8,
+        ]
+    }
+
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>) {
         if self.consensus_proposal(dbtx).await.is_empty() {
             std::future::pending().await
@@ -440,7 +599,11 @@ impl ServerModulePlugin for LightningModule {
         Ok(InputMeta {
             amount: TransactionItemAmount {
                 amount: input.amount,
-                fee: self.cfg.consensus.fee_consensus.contract_input,
+                fee: self
+                    .cfg
+                    .consensus
+                    .fee_consensus
+                    .contract_input_fee(input.amount),
             },
             puk_keys: vec![pub_key],
         })
@@ -502,7 +665,11 @@ impl ServerModulePlugin for LightningModule {
                 } else {
                     Ok(TransactionItemAmount {
                         amount: contract.amount,
-                        fee: self.cfg.consensus.fee_consensus.contract_output,
+                        fee: self
+                            .cfg
+                            .consensus
+                            .fee_consensus
+                            .contract_output_fee(contract.amount),
                     })
                 }
             }
@@ -840,10 +1007,10 @@ impl ServerModulePlugin for LightningModule {
         vec![
             api_endpoint! {
                 "/account",
-                async |module: &LightningModule, dbtx, contract_id: ContractId| -> ContractAccount {
-                    module
-                        .get_contract_account(&mut dbtx, contract_id)
+                async |_module: &LightningModule, dbtx, contract_id: ContractId| -> ContractAccount {
+                    dbtx.get_value(&ContractKey(contract_id))
                         .await
+                        .expect("DB error")
                         .ok_or_else(|| ApiError::not_found(String::from("Contract not found")))
                 }
             },
@@ -873,7 +1040,7 @@ impl ServerModulePlugin for LightningModule {
             },
             api_endpoint! {
                 "/register_gateway",
-                async |module: &LightningModule, dbtx, gateway: LightningGateway| -> () {
+                async |module: &LightningModule, dbtx: DatabaseTransaction, gateway: LightningGateway| -> () {
                     module.register_gateway(&mut dbtx, gateway).await;
                     dbtx.commit_tx().await.expect("DB Error");
                     Ok(())
@@ -903,7 +1070,7 @@ impl LightningModule {
 
     pub async fn get_offer(
         &self,
-        dbtx: &mut DatabaseTransaction<'_>,
+        dbtx: &mut ReadDatabaseTransaction<'_>,
         payment_hash: bitcoin_hashes::sha256::Hash,
     ) -> Option<IncomingContractOffer> {
         dbtx.get_value(&OfferKey(payment_hash))
@@ -913,7 +1080,7 @@ impl LightningModule {
 
     pub async fn get_offers(
         &self,
-        dbtx: &mut DatabaseTransaction<'_>,
+        dbtx: &mut ReadDatabaseTransaction<'_>,
     ) -> Vec<IncomingContractOffer> {
         dbtx.find_by_prefix(&OfferKeyPrefix)
             .await
@@ -931,21 +1098,38 @@ impl LightningModule {
             .expect("DB error")
     }
 
-    pub async fn list_gateways(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<LightningGateway> {
+    /// Gateways whose registration hasn't expired yet. Expired registrations are only filtered out
+    /// here and not deleted, as this guardian's clock must not change the database consensus runs
+    /// on. Gateways re-registering overwrite their previous registration.
+    pub async fn list_gateways(
+        &self,
+        dbtx: &mut ReadDatabaseTransaction<'_>,
+    ) -> Vec<LightningGateway> {
         dbtx.find_by_prefix(&LightningGatewayKeyPrefix)
             .await
             .map(|res| res.expect("DB error").1)
+            .filter(|registration| !registration.is_expired())
+            .map(|registration| registration.info)
             .collect()
     }
 
+    /// Registers `gateway` for [`GATEWAY_REGISTRATION_TTL`], replacing any previous registration
+    /// of the same node
     pub async fn register_gateway(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
         gateway: LightningGateway,
     ) {
-        dbtx.insert_entry(&LightningGatewayKey(gateway.node_pub_key), &gateway)
-            .await
-            .expect("DB error");
+        let registration = LightningGatewayRegistration {
+            info: gateway,
+            valid_until: SystemTime::now() + GATEWAY_REGISTRATION_TTL,
+        };
+        dbtx.insert_entry(
+            &LightningGatewayKey(registration.info.node_pub_key),
+            &registration,
+        )
+        .await
+        .expect("DB error");
     }
 }
 
@@ -996,3 +1180,111 @@ pub enum LightningModuleError {
     #[error("Cancellation request wasn't properly signed")]
     InvalidCancellationSignature,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use fedimint_api::config::ConfigGenParams;
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::module::FederationModuleConfigGen;
+    use fedimint_api::PeerId;
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use crate::db::LightningGatewayKey;
+    use crate::{
+        GatewayFee, LightningGateway, LightningGatewayRegistration, LightningModule,
+        LightningModuleConfigGen, GATEWAY_REGISTRATION_TTL,
+    };
+
+    fn module() -> LightningModule {
+        let peer = PeerId::from(0);
+        let cfg = LightningModuleConfigGen.trusted_dealer_gen(&[peer], &ConfigGenParams::new());
+        LightningModule::new(cfg[&peer].to_typed().unwrap())
+    }
+
+    fn gateway(seed: u8) -> LightningGateway {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[seed; 32]).unwrap();
+        LightningGateway {
+            mint_pub_key: keypair.x_only_public_key().0,
+            node_pub_key: keypair.public_key(),
+            api: format!("http://gateway-{}.example.com", seed)
+                .parse()
+                .unwrap(),
+            fees: GatewayFee::default(),
+            timelock_delta: 10,
+        }
+    }
+
+    #[test]
+    fn registration_expires_after_valid_until() {
+        let now = SystemTime::now();
+        let registration = |valid_until| LightningGatewayRegistration {
+            info: gateway(1),
+            valid_until,
+        };
+
+        assert!(!registration(now + Duration::from_secs(60)).is_expired());
+        assert!(registration(now - Duration::from_secs(1)).is_expired());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn expired_gateways_are_not_listed() {
+        let module = module();
+        let db: Database = MemDatabase::new().into();
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+
+        let live = gateway(1);
+        let expired = gateway(2);
+        module.register_gateway(&mut dbtx, live.clone()).await;
+        dbtx.insert_entry(
+            &LightningGatewayKey(expired.node_pub_key),
+            &LightningGatewayRegistration {
+                info: expired.clone(),
+                valid_until: SystemTime::now() - Duration::from_secs(1),
+            },
+        )
+        .await
+        .unwrap();
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut read_dbtx = db
+            .begin_read_transaction(ModuleDecoderRegistry::default())
+            .await;
+        assert_eq!(
+            module.list_gateways(&mut read_dbtx).await,
+            vec![live.clone()]
+        );
+
+        // The expired registration is kept, so the guardian's clock can't change consensus state
+        assert!(read_dbtx
+            .get_value(&LightningGatewayKey(expired.node_pub_key))
+            .await
+            .unwrap()
+            .is_some());
+
+        // Re-registering renews the registration
+        let before = SystemTime::now();
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+        module.register_gateway(&mut dbtx, expired.clone()).await;
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut read_dbtx = db
+            .begin_read_transaction(ModuleDecoderRegistry::default())
+            .await;
+        let registration = read_dbtx
+            .get_value(&LightningGatewayKey(expired.node_pub_key))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(before + GATEWAY_REGISTRATION_TTL <= registration.valid_until);
+        let mut listed = module.list_gateways(&mut read_dbtx).await;
+        listed.sort_by_key(|gateway| gateway.node_pub_key);
+        let mut expected = vec![live, expired];
+        expected.sort_by_key(|gateway| gateway.node_pub_key);
+        assert_eq!(listed, expected);
+    }
+}
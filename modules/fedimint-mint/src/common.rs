@@ -65,31 +65,34 @@ impl SignedBackupRequest {
 pub struct MintModuleDecoder;
 
 impl PluginDecode for MintModuleDecoder {
-    fn decode_input(mut d: &mut dyn io::Read) -> Result<Input, DecodeError> {
-        Ok(Input::from(MintInput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
-        )?))
+    fn decode_input(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError> {
+        Ok(Input::from(MintInput::consensus_decode(&mut d, modules)?))
     }
-    fn decode_output(mut d: &mut dyn io::Read) -> Result<Output, DecodeError> {
-        Ok(Output::from(MintOutput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
-        )?))
+    fn decode_output(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError> {
+        Ok(Output::from(MintOutput::consensus_decode(&mut d, modules)?))
     }
 
-    fn decode_output_outcome(mut d: &mut dyn io::Read) -> Result<OutputOutcome, DecodeError> {
+    fn decode_output_outcome(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError> {
         Ok(OutputOutcome::from(MintOutputOutcome::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
 
     fn decode_consensus_item(
         mut r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
     ) -> Result<fedimint_api::core::ConsensusItem, DecodeError> {
         Ok(ConsensusItem::from(
-            MintOutputConfirmation::consensus_decode(&mut r, &ModuleDecoderRegistry::default())?,
+            MintOutputConfirmation::consensus_decode(&mut r, modules)?,
         ))
     }
 }
@@ -111,14 +111,34 @@ impl TypedServerModuleConfig for MintConfig {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FeeConsensus {
     pub coin_issuance_abs: fedimint_api::Amount,
+    /// Parts-per-million of the issued amount charged on top of `coin_issuance_abs`
+    #[serde(default)]
+    pub coin_issuance_ppm: u64,
     pub coin_spend_abs: fedimint_api::Amount,
+    /// Parts-per-million of the spent amount charged on top of `coin_spend_abs`
+    #[serde(default)]
+    pub coin_spend_ppm: u64,
+}
+
+impl FeeConsensus {
+    /// Fee for spending `note_count` notes worth `amount` in total
+    pub fn coin_spend_fee(&self, amount: Amount, note_count: usize) -> Amount {
+        self.coin_spend_abs * (note_count as u64) + amount.mul_ppm(self.coin_spend_ppm)
+    }
+
+    /// Fee for issuing `note_count` notes worth `amount` in total
+    pub fn coin_issuance_fee(&self, amount: Amount, note_count: usize) -> Amount {
+        self.coin_issuance_abs * (note_count as u64) + amount.mul_ppm(self.coin_issuance_ppm)
+    }
 }
 
 impl Default for FeeConsensus {
     fn default() -> Self {
         Self {
             coin_issuance_abs: fedimint_api::Amount::ZERO,
+            coin_issuance_ppm: 0,
             coin_spend_abs: fedimint_api::Amount::ZERO,
+            coin_spend_ppm: 0,
         }
     }
 }
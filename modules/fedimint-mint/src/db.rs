@@ -1,6 +1,6 @@
 use std::time::SystemTime;
 
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::{Amount, OutPoint, PeerId};
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,9 @@ pub enum DbKeyPrefix {
     EcashBackup = 0x15,
 }
 
+/// Current version of the mint module's database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
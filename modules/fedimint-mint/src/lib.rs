@@ -3,6 +3,7 @@ use std::hash::Hash;
 use std::iter::FromIterator;
 use std::ops::Sub;
 
+use anyhow::format_err;
 use async_trait::async_trait;
 pub use common::{BackupRequest, SignedBackupRequest};
 use config::FeeConsensus;
@@ -10,18 +11,20 @@ use db::{ECashUserBackupSnapshot, EcashBackupKey};
 use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::config::TypedServerModuleConsensusConfig;
 use fedimint_api::config::{
-    scalar, ClientModuleConfig, ConfigGenParams, DkgPeerMsg, DkgRunner, ModuleConfigGenParams,
-    ServerModuleConfig, TypedServerModuleConfig,
+    scalar, ClientModuleConfig, ConfigGenParams, DkgKeys, DkgPeerMsg, DkgRunner,
+    ModuleConfigGenParams, PreviousKeys, ServerModuleConfig, TypedServerModuleConfig,
 };
 use fedimint_api::core::{ModuleKey, MODULE_KEY_MINT};
-use fedimint_api::db::DatabaseTransaction;
+use fedimint_api::db::{
+    DatabaseTransaction, DatabaseVersion, MigrationMap, ReadDatabaseTransaction,
+};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::__reexports::serde_json;
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
 use fedimint_api::module::{
     api_endpoint, ApiEndpoint, ApiError, FederationModuleConfigGen, InputMeta, IntoModuleError,
-    ModuleError, TransactionItemAmount,
+    ModuleError, ModuleReshareParams, TransactionItemAmount,
 };
 use fedimint_api::net::peers::MuxPeerConnections;
 use fedimint_api::task::TaskGroup;
@@ -42,6 +45,7 @@ use tbs::{
 };
 use thiserror::Error;
 use threshold_crypto::group::Curve;
+use threshold_crypto::G2Projective;
 use tracing::{debug, error, info, warn};
 
 use crate::common::MintModuleDecoder;
@@ -49,13 +53,14 @@ use crate::config::{MintConfig, MintConfigConsensus, MintConfigPrivate};
 use crate::db::{
     MintAuditItemKey, MintAuditItemKeyPrefix, NonceKey, OutputOutcomeKey,
     ProposedPartialSignatureKey, ProposedPartialSignaturesKeyPrefix, ReceivedPartialSignatureKey,
-    ReceivedPartialSignatureKeyOutputPrefix, ReceivedPartialSignaturesKeyPrefix,
+    ReceivedPartialSignatureKeyOutputPrefix, ReceivedPartialSignaturesKeyPrefix, DATABASE_VERSION,
 };
 
 pub mod config;
 
 pub mod common;
 pub mod db;
+mod metrics;
 
 /// By default, the maximum notes per denomination when change-making for users
 const DEFAULT_MAX_NOTES_PER_DENOMINATION: u16 = 3;
@@ -217,39 +222,83 @@ impl FederationModuleConfigGen for MintConfigGenerator {
             return Ok(Err(Cancelled));
         };
 
-        let amounts_keys = g2
-            .into_iter()
-            .map(|(amount, keys)| (amount, keys.tbs()))
-            .collect::<HashMap<_, _>>();
+        let server = mint_config_from_dkg(
+            peers,
+            g2,
+            Default::default(),
+            DEFAULT_MAX_NOTES_PER_DENOMINATION,
+        );
 
-        let server = MintConfig {
-            private: MintConfigPrivate {
-                tbs_sks: amounts_keys
-                    .iter()
-                    .map(|(amount, (_, sks))| (*amount, *sks))
-                    .collect(),
-            },
-            consensus: MintConfigConsensus {
-                peer_tbs_pks: peers
-                    .iter()
-                    .map(|peer| {
-                        let pks = amounts_keys
-                            .iter()
-                            .map(|(amount, (pks, _))| {
-                                let pks = PublicKeyShare(pks.evaluate(scalar(peer)).to_affine());
-                                (*amount, pks)
-                            })
-                            .collect::<Tiered<PublicKeyShare>>();
+        Ok(Ok(server.to_erased()))
+    }
 
-                        (*peer, pks)
+    async fn distributed_reshare(
+        &self,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        our_id: &PeerId,
+        peers: &[PeerId],
+        reshare: &ModuleReshareParams,
+        _params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let old_consensus: MintConfigConsensus =
+            serde_json::from_value(reshare.old_consensus.clone())?;
+        let old_sks = reshare
+            .old_config
+            .clone()
+            .map(|config| config.to_typed::<MintConfig>())
+            .transpose()?
+            .map(|config| config.private.tbs_sks);
+
+        let amounts = old_consensus
+            .peer_tbs_pks
+            .values()
+            .next()
+            .ok_or_else(|| format_err!("Mint config has no peers"))?
+            .tiers()
+            .copied()
+            .collect::<Vec<_>>();
+        let previous = amounts
+            .iter()
+            .map(|amount| {
+                let pk_shares = old_consensus
+                    .peer_tbs_pks
+                    .iter()
+                    .map(|(peer, pks)| {
+                        let pk = pks
+                            .get(*amount)
+                            .ok_or_else(|| format_err!("{} has no key for {}", peer, amount))?;
+                        Ok((*peer, *pk))
                     })
-                    .collect(),
-                fee_consensus: Default::default(),
-                threshold: peers.threshold(),
-                max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
-            },
+                    .collect::<anyhow::Result<_>>()?;
+                let sks = old_sks.as_ref().and_then(|sks| sks.get(*amount)).copied();
+                Ok((*amount, PreviousKeys::from_tbs(pk_shares, sks)))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        let mut dkg = DkgRunner::multi(amounts, peers.threshold(), our_id, peers);
+        let g2 = if let Ok(g2) = dkg
+            .run_reshare_g2(
+                MODULE_KEY_MINT,
+                &reshare.dealers,
+                previous,
+                connections,
+                &mut OsRng,
+            )
+            .await
+        {
+            g2
+        } else {
+            return Ok(Err(Cancelled));
         };
 
+        let server = mint_config_from_dkg(
+            peers,
+            g2,
+            old_consensus.fee_consensus,
+            old_consensus.max_notes_per_denomination,
+        );
+
         Ok(Ok(server.to_erased()))
     }
 
@@ -272,6 +321,47 @@ impl FederationModuleConfigGen for MintConfigGenerator {
     }
 }
 
+/// Builds our config from the tbs keys of every denomination
+fn mint_config_from_dkg(
+    peers: &[PeerId],
+    keys: HashMap<Amount, DkgKeys<G2Projective>>,
+    fee_consensus: FeeConsensus,
+    max_notes_per_denomination: u16,
+) -> MintConfig {
+    let amounts_keys = keys
+        .into_iter()
+        .map(|(amount, keys)| (amount, keys.tbs()))
+        .collect::<HashMap<_, _>>();
+
+    MintConfig {
+        private: MintConfigPrivate {
+            tbs_sks: amounts_keys
+                .iter()
+                .map(|(amount, (_, sks))| (*amount, *sks))
+                .collect(),
+        },
+        consensus: MintConfigConsensus {
+            peer_tbs_pks: peers
+                .iter()
+                .map(|peer| {
+                    let pks = amounts_keys
+                        .iter()
+                        .map(|(amount, (pks, _))| {
+                            let pks = PublicKeyShare(pks.evaluate(scalar(peer)).to_affine());
+                            (*amount, pks)
+                        })
+                        .collect::<Tiered<PublicKeyShare>>();
+
+                    (*peer, pks)
+                })
+                .collect(),
+            fee_consensus,
+            threshold: peers.threshold(),
+            max_notes_per_denomination,
+        },
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MintConfigGenParams {
     pub mint_amounts: Vec<Amount>,
@@ -350,6 +440,24 @@ impl ServerModulePlugin for Mint {
         &MintModuleDecoder
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        DATABASE_VERSION
+    }
+
+    fn database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    fn consensus_state_prefixes(&self) -> Vec<u8> {
+        // our own partial signatures and the users' backups differ between guardians
+        vec![
+            db::DbKeyPrefix::CoinNonce as u8,
+            db::DbKeyPrefix::ReceivedPartialSig as u8,
+            db::DbKeyPrefix::OutputOutcome as u8,
+            db::DbKeyPrefix::MintAuditItem as u8,
+        ]
+    }
+
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>) {
         if self.consensus_proposal(dbtx).await.is_empty() {
             std::future::pending().await
@@ -418,6 +526,7 @@ impl ServerModulePlugin for Mint {
         verification_cache: &Self::VerificationCache,
         input: &'a Self::Input,
     ) -> Result<InputMeta, ModuleError> {
+        let mut nonces = HashSet::new();
         for (amount, coin) in input.iter_items() {
             let coin_valid = verification_cache
                 .valid_coins
@@ -429,20 +538,31 @@ impl ServerModulePlugin for Mint {
                 return Err(MintError::InvalidSignature).into_module_error_other();
             }
 
-            if dbtx
-                .get_value(&NonceKey(coin.0))
-                .await
-                .expect("DB error")
-                .is_some()
-            {
+            // Spending the same coin twice within one input would count its amount twice
+            if !nonces.insert(coin.0) {
                 return Err(MintError::SpentCoin).into_module_error_other();
             }
         }
 
+        let nonce_keys = nonces.into_iter().map(NonceKey).collect::<Vec<_>>();
+        if dbtx
+            .get_values(&nonce_keys)
+            .await
+            .expect("DB error")
+            .iter()
+            .any(Option::is_some)
+        {
+            return Err(MintError::SpentCoin).into_module_error_other();
+        }
+
         Ok(InputMeta {
             amount: TransactionItemAmount {
                 amount: input.total_amount(),
-                fee: self.cfg.consensus.fee_consensus.coin_spend_abs * (input.item_count() as u64),
+                fee: self
+                    .cfg
+                    .consensus
+                    .fee_consensus
+                    .coin_spend_fee(input.total_amount(), input.item_count()),
             },
             puk_keys: input
                 .iter_items()
@@ -462,9 +582,30 @@ impl ServerModulePlugin for Mint {
             .validate_input(interconnect, dbtx, cache, input)
             .await?;
 
+        self.apply_validated_input(interconnect, dbtx, input, cache, meta)
+            .await
+    }
+
+    async fn apply_validated_input<'a, 'b, 'c>(
+        &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b Self::Input,
+        _cache: &Self::VerificationCache,
+        meta: InputMeta,
+    ) -> Result<InputMeta, ModuleError> {
+        // The notes' signatures, amounts and keys don't change during the epoch, only an earlier
+        // input may have spent them since they were validated
         for (amount, coin) in input.iter_items() {
             let key = NonceKey(coin.0);
-            dbtx.insert_new_entry(&key, &()).await.expect("DB Error");
+            if dbtx
+                .insert_entry(&key, &())
+                .await
+                .expect("DB Error")
+                .is_some()
+            {
+                return Err(MintError::SpentCoin).into_module_error_other();
+            }
             dbtx.insert_new_entry(&MintAuditItemKey::Redemption(key), &amount)
                 .await
                 .expect("DB Error");
@@ -497,8 +638,11 @@ impl ServerModulePlugin for Mint {
         } else {
             Ok(TransactionItemAmount {
                 amount: output.total_amount(),
-                fee: self.cfg.consensus.fee_consensus.coin_issuance_abs
-                    * (output.item_count() as u64),
+                fee: self
+                    .cfg
+                    .consensus
+                    .fee_consensus
+                    .coin_issuance_fee(output.total_amount(), output.item_count()),
             })
         }
     }
@@ -650,6 +794,8 @@ impl ServerModulePlugin for Mint {
         dbtx.insert_entry(&MintAuditItemKey::RedemptionTotal, &redemptions)
             .await
             .expect("DB Error");
+        metrics::MINT_ISSUED_MSATS.set(issuances.msats as i64);
+        metrics::MINT_REDEEMED_MSATS.set(redemptions.msats as i64);
 
         drop_peers.into_iter().collect()
     }
@@ -704,7 +850,7 @@ impl ServerModulePlugin for Mint {
         vec![
             api_endpoint! {
                 "/backup",
-                async |module: &Mint, dbtx, request: SignedBackupRequest| -> () {
+                async |module: &Mint, dbtx: DatabaseTransaction, request: SignedBackupRequest| -> () {
                     module
                         .handle_backup_request(&mut dbtx, request).await?;
                     dbtx.commit_tx().await.map_err(|e| ApiError::new(1000, format!("Transaction error: {}", e)))?;
@@ -760,7 +906,7 @@ impl Mint {
 
     async fn handle_recover_request(
         &self,
-        dbtx: &mut DatabaseTransaction<'_>,
+        dbtx: &mut ReadDatabaseTransaction<'_>,
         id: secp256k1_zkp::XOnlyPublicKey,
     ) -> Option<ECashUserBackupSnapshot> {
         dbtx.get_value(&EcashBackupKey(id)).await.expect("DB error")
@@ -1110,17 +1256,29 @@ impl From<InvalidAmountTierError> for MintError {
 
 #[cfg(test)]
 mod test {
+    use async_trait::async_trait;
     use fedimint_api::config::{
         ClientModuleConfig, ConfigGenParams, ServerModuleConfig, TypedServerModuleConsensusConfig,
     };
-    use fedimint_api::module::FederationModuleConfigGen;
-    use fedimint_api::{Amount, PeerId, TieredMulti};
-    use tbs::{blind_message, unblind_signature, verify, AggregatePublicKey, BlindingKey, Message};
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use fedimint_api::module::__reexports::serde_json;
+    use fedimint_api::module::interconnect::ModuleInterconect;
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::module::{
+        ApiError, FederationModuleConfigGen, InputMeta, TransactionItemAmount,
+    };
+    use fedimint_api::{Amount, PeerId, ServerModulePlugin, TieredMulti};
+    use secp256k1_zkp::{KeyPair, Secp256k1};
+    use tbs::{
+        blind_message, unblind_signature, verify, AggregatePublicKey, BlindingKey, Message,
+        MessagePoint,
+    };
 
     use crate::config::{FeeConsensus, MintClientConfig};
     use crate::{
         BlindNonce, CombineError, Mint, MintConfig, MintConfigConsensus, MintConfigGenParams,
-        MintConfigGenerator, MintConfigPrivate, PeerErrorType,
+        MintConfigGenerator, MintConfigPrivate, MintInput, Nonce, Note, PeerErrorType,
     };
 
     const THRESHOLD: usize = 1;
@@ -1303,6 +1461,58 @@ mod test {
             .contains(&(PeerId::from(3), PeerErrorType::DifferentNonce)));
     }
 
+    struct NoInterconnect;
+
+    #[async_trait]
+    impl ModuleInterconect for NoInterconnect {
+        async fn call(
+            &self,
+            _module: &'static str,
+            _path: String,
+            _data: serde_json::Value,
+        ) -> Result<serde_json::Value, ApiError> {
+            unreachable!("the mint doesn't call other modules")
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_validated_input_spent_earlier_in_epoch() {
+        let (_, mints) = build_mints();
+        let mint = &mints[0];
+        let db: Database = MemDatabase::new().into();
+        let mut dbtx = db.begin_transaction(ModuleDecoderRegistry::default()).await;
+
+        let keypair = KeyPair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+        let note = Note(
+            Nonce(keypair.x_only_public_key().0),
+            tbs::Signature(MessagePoint::generator()),
+        );
+        let input = MintInput(TieredMulti::new(
+            vec![(Amount::from_sats(1), vec![note])]
+                .into_iter()
+                .collect(),
+        ));
+        let cache = mint.build_verification_cache(std::iter::once(&input));
+        let meta = || InputMeta {
+            amount: TransactionItemAmount {
+                amount: Amount::from_sats(1),
+                fee: Amount::ZERO,
+            },
+            puk_keys: vec![*note.spend_key()],
+        };
+
+        // both inputs were valid at the start of the epoch, but the first one spends the note
+        let applied = mint
+            .apply_validated_input(&NoInterconnect, &mut dbtx, &input, &cache, meta())
+            .await
+            .unwrap();
+        assert_eq!(applied.puk_keys, meta().puk_keys);
+        assert!(mint
+            .apply_validated_input(&NoInterconnect, &mut dbtx, &input, &cache, meta())
+            .await
+            .is_err());
+    }
+
     #[test_log::test]
     #[should_panic(expected = "Own key not found among pub keys.")]
     fn test_new_panic_without_own_pub_key() {
@@ -1329,4 +1539,28 @@ mod test {
             },
         });
     }
+
+    #[test_log::test]
+    fn test_proportional_fees() {
+        let fees = FeeConsensus {
+            coin_issuance_abs: Amount::from_msats(10),
+            coin_issuance_ppm: 1_000,
+            coin_spend_abs: Amount::ZERO,
+            coin_spend_ppm: 2_500,
+        };
+
+        assert_eq!(
+            fees.coin_issuance_fee(Amount::from_sats(100), 3),
+            Amount::from_msats(3 * 10 + 100)
+        );
+        assert_eq!(
+            fees.coin_spend_fee(Amount::from_sats(100), 3),
+            Amount::from_msats(250)
+        );
+        // proportional fees are rounded down
+        assert_eq!(
+            fees.coin_spend_fee(Amount::from_msats(399), 1),
+            Amount::ZERO
+        );
+    }
 }
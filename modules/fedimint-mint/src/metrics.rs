@@ -0,0 +1,16 @@
+//! Prometheus metrics of the mint, registered in the default [`prometheus`] registry
+use lazy_static::lazy_static;
+use prometheus::{register_int_gauge, IntGauge};
+
+lazy_static! {
+    pub static ref MINT_ISSUED_MSATS: IntGauge = register_int_gauge!(
+        "fedimint_mint_issued_msats",
+        "Total value of all e-cash notes ever issued"
+    )
+    .unwrap();
+    pub static ref MINT_REDEEMED_MSATS: IntGauge = register_int_gauge!(
+        "fedimint_mint_redeemed_msats",
+        "Total value of all e-cash notes ever redeemed"
+    )
+    .unwrap();
+}
@@ -0,0 +1,78 @@
+use bitcoin_hashes::sha256;
+use bitcoin_hashes::Hash as BitcoinHash;
+use fedimint_api::config::ConfigGenParams;
+use fedimint_api::{Amount, OutPoint, TieredMulti};
+use fedimint_mint::{
+    BlindNonce, Mint, MintConfigGenParams, MintConfigGenerator, MintInput, MintOutput, Nonce, Note,
+};
+use fedimint_testing::FakeFed;
+use secp256k1_zkp::{KeyPair, Secp256k1};
+use tbs::{blind_message, unblind_signature, BlindingKey};
+
+const NOTE_AMOUNT: Amount = Amount::from_sats(1);
+
+fn tiered<T>(items: Vec<T>) -> TieredMulti<T> {
+    TieredMulti::new(vec![(NOTE_AMOUNT, items)].into_iter().collect())
+}
+
+/// Issues a note through a round of consensus
+async fn issue_note(fed: &mut FakeFed<Mint>, seed: u8) -> Note {
+    let keypair = KeyPair::from_seckey_slice(&Secp256k1::new(), &[seed; 32]).unwrap();
+    let nonce = Nonce(keypair.x_only_public_key().0);
+    let blinding_key = BlindingKey::random();
+    let blind_nonce = BlindNonce(blind_message(nonce.to_message(), blinding_key));
+
+    let out_point = OutPoint {
+        txid: sha256::Hash::hash(&[seed]).into(),
+        out_idx: 0,
+    };
+    fed.consensus_round(&[], &[(out_point, MintOutput(tiered(vec![blind_nonce])))])
+        .await;
+    // the signature shares are exchanged in the following round
+    fed.consensus_round(&[], &[]).await;
+
+    let outcome = fed
+        .output_outcome(out_point)
+        .await
+        .unwrap()
+        .0
+        .expect("note was issued");
+    let (_, blind_signature) = outcome.0.iter_items().next().unwrap();
+    Note(nonce, unblind_signature(blinding_key, *blind_signature))
+}
+
+#[test_log::test(tokio::test)]
+async fn test_spent_notes_are_rejected() {
+    let mut fed = FakeFed::<Mint>::new(
+        4,
+        |cfg, _db| async move { Ok(Mint::new(cfg.to_typed()?)) },
+        &ConfigGenParams::new().attach(MintConfigGenParams {
+            mint_amounts: vec![NOTE_AMOUNT],
+        }),
+        &MintConfigGenerator,
+    )
+    .await
+    .unwrap();
+
+    let note = issue_note(&mut fed, 1).await;
+    let other_note = issue_note(&mut fed, 2).await;
+
+    // spending the same note twice within one input must not count its amount twice
+    let duplicate_input = MintInput(tiered(vec![note, note]));
+    assert!(fed.verify_input(&duplicate_input).await.is_err());
+
+    let input = MintInput(tiered(vec![note]));
+    let meta = fed.verify_input(&input).await.unwrap();
+    assert_eq!(meta.amount.amount, NOTE_AMOUNT);
+
+    fed.consensus_round(&[input.clone()], &[]).await;
+    assert!(fed.verify_input(&input).await.is_err());
+
+    // a spent note invalidates the whole input
+    let mixed_input = MintInput(tiered(vec![other_note, note]));
+    assert!(fed.verify_input(&mixed_input).await.is_err());
+    assert!(fed
+        .verify_input(&MintInput(tiered(vec![other_note])))
+        .await
+        .is_ok());
+}
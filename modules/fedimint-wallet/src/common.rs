@@ -10,32 +10,36 @@ use crate::{WalletConsensusItem, WalletInput, WalletOutput, WalletOutputOutcome}
 pub struct WalletModuleDecoder;
 
 impl PluginDecode for WalletModuleDecoder {
-    fn decode_input(mut d: &mut dyn io::Read) -> Result<Input, DecodeError> {
-        Ok(Input::from(WalletInput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
-        )?))
+    fn decode_input(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Input, DecodeError> {
+        Ok(Input::from(WalletInput::consensus_decode(&mut d, modules)?))
     }
-    fn decode_output(mut d: &mut dyn io::Read) -> Result<Output, DecodeError> {
+    fn decode_output(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Output, DecodeError> {
         Ok(Output::from(WalletOutput::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
 
-    fn decode_output_outcome(mut d: &mut dyn io::Read) -> Result<OutputOutcome, DecodeError> {
+    fn decode_output_outcome(
+        mut d: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<OutputOutcome, DecodeError> {
         Ok(OutputOutcome::from(WalletOutputOutcome::consensus_decode(
-            &mut d,
-            &ModuleDecoderRegistry::default(),
+            &mut d, modules,
         )?))
     }
 
     fn decode_consensus_item(
         mut r: &mut dyn io::Read,
+        modules: &ModuleDecoderRegistry,
     ) -> Result<fedimint_api::core::ConsensusItem, DecodeError> {
         Ok(ConsensusItem::from(WalletConsensusItem::consensus_decode(
-            &mut r,
-            &ModuleDecoderRegistry::default(),
+            &mut r, modules,
         )?))
     }
 }
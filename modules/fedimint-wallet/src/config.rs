@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::bail;
 use anyhow::format_err;
@@ -30,12 +30,18 @@ pub struct WalletConfigLocal {
     #[serde(flatten)]
     /// Configuration for connecting to our Bitcoin node
     pub btc_rpc: BitcoindRpcCfg,
+    /// Our public key of the bitcoin multisig if its secret key is held by an external (e.g.
+    /// air-gapped) signer instead of [`WalletConfigPrivate::peg_in_key`]
+    #[serde(default)]
+    pub external_signer_key: Option<CompressedPublicKey>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfigPrivate {
-    /// Secret key for signing bitcoin multisig transactions
-    pub peg_in_key: SecretKey,
+    /// Secret key for signing bitcoin multisig transactions, `None` if peg-outs are signed by an
+    /// external signer
+    #[serde(default)]
+    pub peg_in_key: Option<SecretKey>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -52,6 +58,34 @@ pub struct WalletConfigConsensus {
     pub default_fee: Feerate,
     /// Fees for bitcoin transactions
     pub fee_consensus: FeeConsensus,
+    /// Peers signing peg-outs with an external signer, since that can take a while they are only
+    /// dropped for not contributing their signatures after `external_signer_timeout`
+    #[serde(default)]
+    pub external_signers: BTreeSet<PeerId>,
+    /// Number of blocks external signers may take to sign a peg-out, afterwards they get dropped
+    /// like any other peer that doesn't contribute its signature
+    #[serde(default = "default_external_signer_timeout")]
+    pub external_signer_timeout: u32,
+    /// Multisig the federation used before its guardians changed, its funds are swept to
+    /// `peg_in_descriptor`
+    #[serde(default)]
+    pub previous_peg_in: Option<PreviousPegIn>,
+}
+
+/// About a day of blocks, enough for guardians to get to their air-gapped signers
+pub const DEFAULT_EXTERNAL_SIGNER_TIMEOUT: u32 = 144;
+
+fn default_external_signer_timeout() -> u32 {
+    DEFAULT_EXTERNAL_SIGNER_TIMEOUT
+}
+
+/// Multisig of the federation before resharing its keys among a new set of guardians
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PreviousPegIn {
+    pub peg_in_descriptor: PegInDescriptor,
+    /// Keys of the guardians that can still sign for `peg_in_descriptor`, at least as many as
+    /// its threshold
+    pub peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -70,14 +104,34 @@ impl TypedClientModuleConfig for WalletClientConfig {}
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FeeConsensus {
     pub peg_in_abs: fedimint_api::Amount,
+    /// Parts-per-million of the pegged-in amount charged on top of `peg_in_abs`
+    #[serde(default)]
+    pub peg_in_ppm: u64,
     pub peg_out_abs: fedimint_api::Amount,
+    /// Parts-per-million of the pegged-out amount charged on top of `peg_out_abs`
+    #[serde(default)]
+    pub peg_out_ppm: u64,
+}
+
+impl FeeConsensus {
+    /// Fee for a peg-in of `amount`
+    pub fn peg_in_fee(&self, amount: fedimint_api::Amount) -> fedimint_api::Amount {
+        self.peg_in_abs + amount.mul_ppm(self.peg_in_ppm)
+    }
+
+    /// Fee for a peg-out of `amount` (which includes the bitcoin transaction fees)
+    pub fn peg_out_fee(&self, amount: fedimint_api::Amount) -> fedimint_api::Amount {
+        self.peg_out_abs + amount.mul_ppm(self.peg_out_ppm)
+    }
 }
 
 impl Default for FeeConsensus {
     fn default() -> Self {
         Self {
             peg_in_abs: fedimint_api::Amount::ZERO,
+            peg_in_ppm: 0,
             peg_out_abs: fedimint_api::Amount::ZERO,
+            peg_out_ppm: 0,
         }
     }
 }
@@ -113,18 +167,29 @@ impl TypedServerModuleConfig for WalletConfig {
     }
 
     fn validate_config(&self, identity: &PeerId) -> anyhow::Result<()> {
-        let pubkey = secp256k1::PublicKey::from_secret_key_global(&self.private.peg_in_key);
+        let pubkey = match (&self.private.peg_in_key, &self.local.external_signer_key) {
+            (Some(_), Some(_)) | (None, None) => {
+                bail!("Either the peg-in secret key or the external signer key has to be set")
+            }
+            _ => self.peg_in_public_key(),
+        };
 
         if self
             .consensus
             .peer_peg_in_keys
             .get(identity)
             .ok_or_else(|| format_err!("Secret key doesn't match any public key"))?
-            != &CompressedPublicKey::new(pubkey)
+            != &pubkey
         {
             bail!(" Bitcoin wallet private key doesn't match multisig pubkey");
         }
 
+        if self.local.external_signer_key.is_some()
+            != self.consensus.external_signers.contains(identity)
+        {
+            bail!("Using an external signer requires being listed as external signer in consensus");
+        }
+
         Ok(())
     }
 }
@@ -143,8 +208,13 @@ impl WalletConfig {
         );
 
         Self {
-            local: WalletConfigLocal { btc_rpc },
-            private: WalletConfigPrivate { peg_in_key: sk },
+            local: WalletConfigLocal {
+                btc_rpc,
+                external_signer_key: None,
+            },
+            private: WalletConfigPrivate {
+                peg_in_key: Some(sk),
+            },
             consensus: WalletConfigConsensus {
                 network,
                 peg_in_descriptor,
@@ -152,9 +222,36 @@ impl WalletConfig {
                 finality_delay,
                 default_fee: Feerate { sats_per_kvb: 1000 },
                 fee_consensus: Default::default(),
+                external_signers: BTreeSet::new(),
+                external_signer_timeout: DEFAULT_EXTERNAL_SIGNER_TIMEOUT,
+                previous_peg_in: None,
             },
         }
     }
+
+    /// Our public key of the bitcoin multisig
+    ///
+    /// # Panics
+    /// * If neither the peg-in secret key nor the external signer key is set, which
+    ///   [`TypedServerModuleConfig::validate_config`] rules out
+    pub fn peg_in_public_key(&self) -> CompressedPublicKey {
+        match (&self.private.peg_in_key, &self.local.external_signer_key) {
+            (Some(sk), _) => {
+                CompressedPublicKey::new(secp256k1::PublicKey::from_secret_key_global(sk))
+            }
+            (None, Some(pk)) => *pk,
+            (None, None) => panic!("Wallet config is missing our peg-in key"),
+        }
+    }
+
+    /// Hands signing peg-outs over to an external signer holding our peg-in secret key, which
+    /// is removed from the config and returned. Other peers need to list us in their
+    /// [`WalletConfigConsensus::external_signers`] too.
+    pub fn use_external_signer(&mut self, identity: PeerId) -> Option<SecretKey> {
+        self.local.external_signer_key = Some(self.peg_in_public_key());
+        self.consensus.external_signers.insert(identity);
+        self.private.peg_in_key.take()
+    }
 }
 
 impl WalletClientConfig {
@@ -1,5 +1,5 @@
 use bitcoin::{BlockHash, Txid};
-use fedimint_api::db::DatabaseKeyPrefixConst;
+use fedimint_api::db::{DatabaseKeyPrefixConst, DatabaseVersion};
 use fedimint_api::encoding::{Decodable, Encodable};
 use secp256k1::ecdsa::Signature;
 use serde::Serialize;
@@ -19,8 +19,15 @@ pub enum DbKeyPrefix {
     PendingTransaction = 0x35,
     PegOutTxSigCi = 0x36,
     PegOutBitcoinOutPoint = 0x37,
+    LegacyUtxo = 0x38,
+    PegInHandover = 0x39,
+    LegacyTransaction = 0x3a,
+    ExternalSignerWait = 0x3b,
 }
 
+/// Current version of the wallet module's database schema
+pub const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -143,3 +150,73 @@ impl DatabaseKeyPrefixConst for PegOutBitcoinTransactionPrefix {
     type Key = PegOutBitcoinTransaction;
     type Value = WalletOutputOutcome;
 }
+
+/// UTXO locked to [`PreviousPegIn::peg_in_descriptor`](crate::config::PreviousPegIn) that still
+/// has to be swept to the current descriptor
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct LegacyUTXOKey(pub bitcoin::OutPoint);
+
+impl DatabaseKeyPrefixConst for LegacyUTXOKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::LegacyUtxo as u8;
+    type Key = Self;
+    type Value = SpendableUTXO;
+}
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct LegacyUTXOPrefixKey;
+
+impl DatabaseKeyPrefixConst for LegacyUTXOPrefixKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::LegacyUtxo as u8;
+    type Key = LegacyUTXOKey;
+    type Value = SpendableUTXO;
+}
+
+/// The previous descriptor whose UTXOs were moved to the [`LegacyUTXOKey`] table
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PegInHandoverKey;
+
+impl DatabaseKeyPrefixConst for PegInHandoverKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::PegInHandover as u8;
+    type Key = Self;
+    type Value = String;
+}
+
+/// Unsigned transaction spending UTXOs of the previous descriptor, which is signed by the
+/// guardians holding its keys
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct LegacyTransactionKey(pub Txid);
+
+impl DatabaseKeyPrefixConst for LegacyTransactionKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::LegacyTransaction as u8;
+    type Key = Self;
+    type Value = ();
+}
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct LegacyTransactionPrefixKey;
+
+impl DatabaseKeyPrefixConst for LegacyTransactionPrefixKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::LegacyTransaction as u8;
+    type Key = LegacyTransactionKey;
+    type Value = ();
+}
+
+/// Consensus block height at which we started waiting for external signers to sign a peg-out,
+/// see [`crate::config::WalletConfigConsensus::external_signer_timeout`]
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct ExternalSignerWaitKey(pub Txid);
+
+impl DatabaseKeyPrefixConst for ExternalSignerWaitKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::ExternalSignerWait as u8;
+    type Key = Self;
+    type Value = u32;
+}
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct ExternalSignerWaitPrefixKey;
+
+impl DatabaseKeyPrefixConst for ExternalSignerWaitPrefixKey {
+    const DB_PREFIX: u8 = DbKeyPrefix::ExternalSignerWait as u8;
+    type Key = ExternalSignerWaitKey;
+    type Value = u32;
+}
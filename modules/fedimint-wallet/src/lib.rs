@@ -25,14 +25,17 @@ use fedimint_api::config::{
     ServerModuleConfig, TypedServerModuleConfig,
 };
 use fedimint_api::core::{ModuleKey, MODULE_KEY_WALLET};
-use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::db::{
+    Database, DatabaseTransaction, DatabaseVersion, MigrationMap, ReadDatabaseTransaction,
+};
 use fedimint_api::encoding::{Decodable, Encodable, UnzipConsensus};
 use fedimint_api::module::__reexports::serde_json;
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
 use fedimint_api::module::registry::ModuleDecoderRegistry;
 use fedimint_api::module::{
-    api_endpoint, FederationModuleConfigGen, InputMeta, IntoModuleError, TransactionItemAmount,
+    api_endpoint, FederationModuleConfigGen, InputMeta, IntoModuleError, ModuleReshareParams,
+    TransactionItemAmount,
 };
 use fedimint_api::module::{ApiEndpoint, ModuleError};
 use fedimint_api::net::peers::MuxPeerConnections;
@@ -44,6 +47,7 @@ use fedimint_api::{
 };
 use fedimint_bitcoind::BitcoindRpc;
 use impl_tools::autoimpl;
+use miniscript::descriptor::Wsh;
 use miniscript::psbt::PsbtExt;
 use miniscript::{Descriptor, TranslatePk};
 use rand::rngs::OsRng;
@@ -54,11 +58,12 @@ use thiserror::Error;
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::common::WalletModuleDecoder;
-use crate::config::WalletConfig;
+use crate::config::{PreviousPegIn, WalletConfig, WalletConfigLocal, WalletConfigPrivate};
 use crate::db::{
-    BlockHashKey, PegOutBitcoinTransaction, PegOutTxSignatureCI, PegOutTxSignatureCIPrefix,
+    BlockHashKey, ExternalSignerWaitKey, LegacyTransactionKey, LegacyUTXOKey, LegacyUTXOPrefixKey,
+    PegInHandoverKey, PegOutBitcoinTransaction, PegOutTxSignatureCI, PegOutTxSignatureCIPrefix,
     PendingTransactionKey, PendingTransactionPrefixKey, RoundConsensusKey, UTXOKey, UTXOPrefixKey,
-    UnsignedTransactionKey, UnsignedTransactionPrefixKey,
+    UnsignedTransactionKey, UnsignedTransactionPrefixKey, DATABASE_VERSION,
 };
 use crate::keys::CompressedPublicKey;
 use crate::tweakable::Tweakable;
@@ -68,6 +73,7 @@ pub mod common;
 pub mod config;
 pub mod db;
 pub mod keys;
+mod metrics;
 pub mod tweakable;
 pub mod txoproof;
 
@@ -181,9 +187,50 @@ impl Serialize for UnsignedTransaction {
     }
 }
 
+/// A peg-out PSBT that still lacks our signature, published for external signers
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnsignedPegOut {
+    pub txid: Txid,
+    /// Hex encoded BIP-174 PSBT
+    pub psbt: String,
+}
+
+impl UnsignedPegOut {
+    pub fn new(psbt: &PartiallySignedTransaction) -> Self {
+        UnsignedPegOut {
+            txid: psbt.unsigned_tx.txid(),
+            psbt: bitcoin::consensus::encode::serialize_hex(psbt),
+        }
+    }
+
+    pub fn decode_psbt(&self) -> anyhow::Result<PartiallySignedTransaction> {
+        Ok(bitcoin::consensus::encode::deserialize(&hex::decode(
+            &self.psbt,
+        )?)?)
+    }
+}
+
+/// A peg-out that hasn't been confirmed yet, as reported to guardian operators
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingPegOut {
+    pub txid: Txid,
+    /// Amount leaving the federation, excluding change and fees
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    pub status: PendingPegOutStatus,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingPegOutStatus {
+    /// Waiting for enough guardians to sign, `signatures` lists the ones that already did
+    Signing { signatures: Vec<PeerId> },
+    /// Fully signed and periodically broadcast until it confirms
+    Broadcasting,
+}
+
 struct StatelessWallet<'a> {
     descriptor: &'a Descriptor<CompressedPublicKey>,
-    secret_key: &'a secp256k1::SecretKey,
     secp: &'a secp256k1::Secp256k1<secp256k1::All>,
 }
 
@@ -276,29 +323,11 @@ impl FederationModuleConfigGen for WalletConfigGenerator {
         let secp = secp256k1::Secp256k1::new();
         let (sk, pk) = secp.generate_keypair(&mut OsRng);
         let our_key = CompressedPublicKey { key: pk };
-        let mut peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey> = BTreeMap::new();
-
-        if let Err(Cancelled) = connections
-            .send(peers, MODULE_KEY_WALLET, DkgPeerMsg::PublicKey(our_key.key))
-            .await
-        {
-            return Ok(Err(Cancelled));
-        }
-
-        peer_peg_in_keys.insert(*our_id, our_key);
-        while peer_peg_in_keys.len() < peers.len() {
-            match connections.receive(MODULE_KEY_WALLET).await {
-                Ok((peer, DkgPeerMsg::PublicKey(key))) => {
-                    peer_peg_in_keys.insert(peer, CompressedPublicKey { key });
-                }
-                Ok((peer, msg)) => {
-                    bail!("Invalid message received from: {peer}: {msg:?}");
-                }
-                _ => {
-                    return Ok(Err(Cancelled));
-                }
-            }
-        }
+        let peer_peg_in_keys =
+            match exchange_peg_in_keys(connections, our_id, peers, our_key).await? {
+                Ok(keys) => keys,
+                Err(Cancelled) => return Ok(Err(Cancelled)),
+            };
 
         let wallet_cfg = WalletConfig::new(
             peer_peg_in_keys,
@@ -312,6 +341,94 @@ impl FederationModuleConfigGen for WalletConfigGenerator {
         Ok(Ok(wallet_cfg.to_erased()))
     }
 
+    /// Continuing guardians keep their peg-in keys while joining ones generate new ones. If that
+    /// changes the multisig, the funds of the previous one are swept to the new one once the
+    /// federation runs with the new config.
+    async fn distributed_reshare(
+        &self,
+        connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+        our_id: &PeerId,
+        peers: &[PeerId],
+        reshare: &ModuleReshareParams,
+        params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let params = params
+            .get::<WalletConfigGenParams>()
+            .expect("Invalid wallet params");
+        let old_consensus: WalletConfigConsensus =
+            serde_json::from_value(reshare.old_consensus.clone())?;
+        if old_consensus.previous_peg_in.is_some() {
+            bail!("The federation is still sweeping funds of a previous resharing, remove `previous_peg_in` from the wallet consensus config once that finished");
+        }
+
+        let old_config = match &reshare.old_config {
+            Some(config) if reshare.dealers.contains(our_id) => {
+                Some(config.to_typed::<WalletConfig>()?)
+            }
+            _ => None,
+        };
+        let (peg_in_key, external_signer_key) = match &old_config {
+            Some(config) => (config.private.peg_in_key, config.local.external_signer_key),
+            None => (
+                Some(secp256k1::Secp256k1::new().generate_keypair(&mut OsRng).0),
+                None,
+            ),
+        };
+        let our_key = match (peg_in_key, external_signer_key) {
+            (Some(sk), _) => {
+                CompressedPublicKey::new(secp256k1::PublicKey::from_secret_key_global(&sk))
+            }
+            (None, Some(pk)) => pk,
+            (None, None) => bail!("Wallet config is missing our peg-in key"),
+        };
+        let peer_peg_in_keys =
+            match exchange_peg_in_keys(connections, our_id, peers, our_key).await? {
+                Ok(keys) => keys,
+                Err(Cancelled) => return Ok(Err(Cancelled)),
+            };
+
+        let previous_peg_in = Some(PreviousPegIn {
+            peg_in_descriptor: old_consensus.peg_in_descriptor,
+            peer_peg_in_keys: old_consensus
+                .peer_peg_in_keys
+                .into_iter()
+                .filter(|(peer, _)| reshare.dealers.contains(peer))
+                .collect(),
+        });
+        let peg_in_descriptor = PegInDescriptor::Wsh(Wsh::new_sortedmulti(
+            peers.threshold(),
+            peer_peg_in_keys.values().copied().collect(),
+        )?);
+
+        let wallet_cfg = WalletConfig {
+            local: WalletConfigLocal {
+                btc_rpc: params.bitcoin_rpc,
+                external_signer_key,
+            },
+            private: WalletConfigPrivate { peg_in_key },
+            consensus: WalletConfigConsensus {
+                network: old_consensus.network,
+                // nothing to sweep if the multisig didn't change
+                previous_peg_in: previous_peg_in
+                    .filter(|previous| previous.peg_in_descriptor != peg_in_descriptor),
+                peg_in_descriptor,
+                peer_peg_in_keys,
+                finality_delay: old_consensus.finality_delay,
+                default_fee: old_consensus.default_fee,
+                fee_consensus: old_consensus.fee_consensus,
+                external_signers: old_consensus
+                    .external_signers
+                    .into_iter()
+                    .filter(|peer| reshare.dealers.contains(peer))
+                    .collect(),
+                external_signer_timeout: old_consensus.external_signer_timeout,
+            },
+        };
+
+        Ok(Ok(wallet_cfg.to_erased()))
+    }
+
     fn to_client_config(&self, config: ServerModuleConfig) -> anyhow::Result<ClientModuleConfig> {
         Ok(config
             .to_typed::<WalletConfig>()?
@@ -331,6 +448,40 @@ impl FederationModuleConfigGen for WalletConfigGenerator {
     }
 }
 
+/// Sends our peg-in public key to all `peers` and collects theirs
+async fn exchange_peg_in_keys(
+    connections: &MuxPeerConnections<ModuleKey, DkgPeerMsg>,
+    our_id: &PeerId,
+    peers: &[PeerId],
+    our_key: CompressedPublicKey,
+) -> anyhow::Result<Cancellable<BTreeMap<PeerId, CompressedPublicKey>>> {
+    let mut peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey> = BTreeMap::new();
+
+    if let Err(Cancelled) = connections
+        .send(peers, MODULE_KEY_WALLET, DkgPeerMsg::PublicKey(our_key.key))
+        .await
+    {
+        return Ok(Err(Cancelled));
+    }
+
+    peer_peg_in_keys.insert(*our_id, our_key);
+    while peer_peg_in_keys.len() < peers.len() {
+        match connections.receive(MODULE_KEY_WALLET).await {
+            Ok((peer, DkgPeerMsg::PublicKey(key))) => {
+                peer_peg_in_keys.insert(peer, CompressedPublicKey { key });
+            }
+            Ok((peer, msg)) => {
+                bail!("Invalid message received from: {peer}: {msg:?}");
+            }
+            _ => {
+                return Ok(Err(Cancelled));
+            }
+        }
+    }
+
+    Ok(Ok(peer_peg_in_keys))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletConfigGenParams {
     pub network: bitcoin::network::constants::Network,
@@ -387,6 +538,30 @@ impl ServerModulePlugin for Wallet {
         &WalletModuleDecoder
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        DATABASE_VERSION
+    }
+
+    fn database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    fn consensus_state_prefixes(&self) -> Vec<u8> {
+        // our own peg-out signatures differ between guardians
+        vec![
+            db::DbKeyPrefix::BlockHash as u8,
+            db::DbKeyPrefix::Utxo as u8,
+            db::DbKeyPrefix::RoundConsensus as u8,
+            db::DbKeyPrefix::UnsignedTransaction as u8,
+            db::DbKeyPrefix::PendingTransaction as u8,
+            db::DbKeyPrefix::PegOutBitcoinOutPoint as u8,
+            db::DbKeyPrefix::LegacyUtxo as u8,
+            db::DbKeyPrefix::PegInHandover as u8,
+            db::DbKeyPrefix::LegacyTransaction as u8,
+            db::DbKeyPrefix::ExternalSignerWait as u8,
+        ]
+    }
+
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>) {
         let mut our_target_height = self.target_height().await;
         let last_consensus_height = self.consensus_height(dbtx).await.unwrap_or(0);
@@ -498,6 +673,11 @@ impl ServerModulePlugin for Wallet {
         dbtx.insert_entry(&RoundConsensusKey, &round_consensus)
             .await
             .expect("DB Error");
+
+        if let Some(previous) = &self.cfg.consensus.previous_peg_in {
+            self.process_handover(dbtx, previous, &round_consensus)
+                .await;
+        }
     }
 
     fn build_verification_cache<'a>(
@@ -519,8 +699,7 @@ impl ServerModulePlugin for Wallet {
                 .into_module_error_other();
         }
 
-        input
-            .verify(&self.secp, &self.cfg.consensus.peg_in_descriptor)
+        self.verify_peg_in_descriptor(input)
             .into_module_error_other()?;
 
         if dbtx
@@ -528,14 +707,20 @@ impl ServerModulePlugin for Wallet {
             .await
             .expect("DB error")
             .is_some()
+            || dbtx
+                .get_value(&LegacyUTXOKey(input.outpoint()))
+                .await
+                .expect("DB error")
+                .is_some()
         {
             return Err(WalletError::PegInAlreadyClaimed).into_module_error_other();
         }
 
+        let amount = fedimint_api::Amount::from_sats(input.tx_output().value);
         Ok(InputMeta {
             amount: TransactionItemAmount {
-                amount: fedimint_api::Amount::from_sats(input.tx_output().value),
-                fee: self.cfg.consensus.fee_consensus.peg_in_abs,
+                amount,
+                fee: self.cfg.consensus.fee_consensus.peg_in_fee(amount),
             },
             puk_keys: vec![*input.tweak_contract_key()],
         })
@@ -553,15 +738,20 @@ impl ServerModulePlugin for Wallet {
             .await?;
         debug!(outpoint = %input.outpoint(), amount = %meta.amount.amount, "Claiming peg-in");
 
-        dbtx.insert_new_entry(
-            &UTXOKey(input.outpoint()),
-            &SpendableUTXO {
-                tweak: input.tweak_contract_key().serialize(),
-                amount: bitcoin::Amount::from_sat(input.tx_output().value),
-            },
-        )
-        .await
-        .expect("DB Error");
+        let utxo = SpendableUTXO {
+            tweak: input.tweak_contract_key().serialize(),
+            amount: bitcoin::Amount::from_sat(input.tx_output().value),
+        };
+        // Peg-ins to the previous descriptor, e.g. by clients with an outdated config, get swept
+        if self.verify_peg_in_descriptor(input).expect("validated") {
+            dbtx.insert_new_entry(&LegacyUTXOKey(input.outpoint()), &utxo)
+                .await
+                .expect("DB Error");
+        } else {
+            dbtx.insert_new_entry(&UTXOKey(input.outpoint()), &utxo)
+                .await
+                .expect("DB Error");
+        }
 
         Ok(meta)
     }
@@ -589,9 +779,10 @@ impl ServerModulePlugin for Wallet {
         if self.create_peg_out_tx(dbtx, output).await.is_none() {
             return Err(WalletError::NotEnoughSpendableUTXO).into_module_error_other();
         }
+        let amount = (output.amount + output.fees.amount()).into();
         Ok(TransactionItemAmount {
-            amount: (output.amount + output.fees.amount()).into(),
-            fee: self.cfg.consensus.fee_consensus.peg_out_abs,
+            amount,
+            fee: self.cfg.consensus.fee_consensus.peg_out_fee(amount),
         })
     }
 
@@ -607,42 +798,29 @@ impl ServerModulePlugin for Wallet {
             "Queuing peg-out",
         );
 
-        let mut tx = self
+        let tx = self
             .create_peg_out_tx(dbtx, output)
             .await
             .expect("Should have been validated");
-        self.offline_wallet().sign_psbt(&mut tx.psbt);
         let txid = tx.psbt.unsigned_tx.txid();
-        info!(
-            %txid,
-            "Signing peg out",
-        );
 
-        let sigs = tx
-            .psbt
-            .inputs
-            .iter_mut()
-            .map(|input| {
-                assert_eq!(
-                    input.partial_sigs.len(),
-                    1,
-                    "There was already more than one (our) or no signatures in input"
+        // Without our secret key the signature is submitted later on by our external signer
+        match &self.cfg.private.peg_in_key {
+            Some(secret_key) => {
+                info!(
+                    %txid,
+                    "Signing peg out",
                 );
-
-                // TODO: don't put sig into PSBT in the first place
-                // We actually take out our own signature so everyone finalizes the tx in the
-                // same epoch.
-                let sig = std::mem::take(&mut input.partial_sigs)
-                    .into_values()
-                    .next()
-                    .expect("asserted previously");
-
-                // We drop SIGHASH_ALL, because we always use that and it is only present in the
-                // PSBT for compatibility with other tools.
-                secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
-                    .expect("we serialized it ourselves that way")
-            })
-            .collect::<Vec<_>>();
+                let sig = sign_peg_out(&tx.psbt, secret_key, &self.secp);
+                dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sig.signature)
+                    .await
+                    .expect("DB Error");
+            }
+            None => info!(
+                %txid,
+                "Peg out awaits signature of external signer",
+            ),
+        }
 
         // Delete used UTXOs
         for input in tx.psbt.unsigned_tx.input.iter() {
@@ -654,9 +832,6 @@ impl ServerModulePlugin for Wallet {
         dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
             .await
             .expect("DB Error");
-        dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sigs)
-            .await
-            .expect("DB Error");
         dbtx.insert_new_entry(
             &PegOutBitcoinTransaction(out_point),
             &WalletOutputOutcome(txid),
@@ -688,20 +863,51 @@ impl ServerModulePlugin for Wallet {
                 ..
             } = unsigned;
 
+            // Transactions spending UTXOs of the previous descriptor need signatures of its keys
+            let is_legacy = dbtx
+                .get_value(&LegacyTransactionKey(key.0))
+                .await
+                .expect("DB error")
+                .is_some();
+            let signer_keys = match &self.cfg.consensus.previous_peg_in {
+                Some(previous) if is_legacy => &previous.peer_peg_in_keys,
+                _ => &self.cfg.consensus.peer_peg_in_keys,
+            };
+
             let signers: HashSet<PeerId> = signatures
                 .iter()
-                .filter_map(
-                    |(peer, sig)| match self.sign_peg_out_psbt(&mut psbt, peer, sig) {
+                .filter_map(|(peer, sig)| {
+                    let peer_key = match signer_keys.get(peer) {
+                        Some(peer_key) => peer_key,
+                        None => {
+                            warn!("{} signed {} without holding a key", peer, key.0);
+                            return None;
+                        }
+                    };
+                    match sign_peg_out_psbt(&mut psbt, peer_key, sig, &self.secp) {
                         Ok(_) => Some(*peer),
                         Err(error) => {
                             warn!("Error with {} partial sig {:?}", peer, error);
                             None
                         }
-                    },
-                )
+                    }
+                })
                 .collect();
 
-            for peer in consensus_peers.sub(&signers) {
+            let missing_signers = consensus_peers
+                .sub(&signers)
+                .into_iter()
+                .filter(|peer| signer_keys.contains_key(peer))
+                .collect::<Vec<_>>();
+            let external_signers_timed_out = self
+                .external_signers_timed_out(dbtx, key.0, &missing_signers)
+                .await;
+            for peer in missing_signers {
+                if self.cfg.consensus.external_signers.contains(&peer)
+                    && !external_signers_timed_out
+                {
+                    continue;
+                }
                 error!("Dropping {:?} for not contributing sigs to PSBT", peer);
                 drop_peers.push(peer);
             }
@@ -717,6 +923,12 @@ impl ServerModulePlugin for Wallet {
                     dbtx.remove_entry(&PegOutTxSignatureCI(key.0))
                         .await
                         .expect("DB Error");
+                    dbtx.remove_entry(&LegacyTransactionKey(key.0))
+                        .await
+                        .expect("DB Error");
+                    dbtx.remove_entry(&ExternalSignerWaitKey(key.0))
+                        .await
+                        .expect("DB Error");
                     dbtx.remove_entry(&key).await.expect("DB Error");
                 }
                 Err(e) => {
@@ -724,6 +936,9 @@ impl ServerModulePlugin for Wallet {
                 }
             }
         }
+
+        metrics::WALLET_BALANCE_SATS.set(self.get_wallet_value(dbtx).await.to_sat() as i64);
+
         drop_peers
     }
 
@@ -741,6 +956,11 @@ impl ServerModulePlugin for Wallet {
         audit
             .add_items(dbtx, &UTXOPrefixKey, |_, v| v.amount.to_sat() as i64 * 1000)
             .await;
+        audit
+            .add_items(dbtx, &LegacyUTXOPrefixKey, |_, v| {
+                v.amount.to_sat() as i64 * 1000
+            })
+            .await;
         audit
             .add_items(dbtx, &UnsignedTransactionPrefixKey, |_, v| {
                 v.change.to_sat() as i64 * 1000
@@ -761,19 +981,29 @@ impl ServerModulePlugin for Wallet {
         vec![
             api_endpoint! {
                 "/block_height",
-                async |module: &Wallet, dbtx, _params: ()| -> u32 {
-                    Ok(module.consensus_height(&mut dbtx).await.unwrap_or(0))
+                async |_module: &Wallet, dbtx, _params: ()| -> u32 {
+                    let consensus = dbtx.get_value(&RoundConsensusKey).await.expect("DB error");
+                    Ok(consensus.map_or(0, |consensus| consensus.block_height))
                 }
             },
             api_endpoint! {
                 "/peg_out_fees",
                 async |module: &Wallet, dbtx, params: (Address, u64)| -> Option<PegOutFees> {
                     let (address, sats) = params;
-                    let consensus = module.current_round_consensus(&mut dbtx).await.unwrap();
+                    let consensus = dbtx
+                        .get_value(&RoundConsensusKey)
+                        .await
+                        .expect("DB error")
+                        .unwrap();
+                    let utxos = dbtx
+                        .find_by_prefix(&UTXOPrefixKey)
+                        .await
+                        .collect::<Result<_, _>>()
+                        .expect("DB error");
                     let tx = module.offline_wallet().create_tx(
                         bitcoin::Amount::from_sat(sats),
                         address.script_pubkey(),
-                        module.available_utxos(&mut dbtx).await,
+                        utxos,
                         consensus.fee_rate,
                         &consensus.randomness_beacon
                     );
@@ -871,70 +1101,37 @@ impl Wallet {
         }
     }
 
-    /// Try to attach signatures to a pending peg-out tx.
-    fn sign_peg_out_psbt(
+    /// Whether external signers among `missing_signers` have had more than
+    /// [`WalletConfigConsensus::external_signer_timeout`] blocks to sign the peg-out `txid`. The
+    /// wait starts the first time an external signer is found missing.
+    async fn external_signers_timed_out(
         &self,
-        psbt: &mut PartiallySignedTransaction,
-        peer: &PeerId,
-        signature: &PegOutSignatureItem,
-    ) -> Result<(), ProcessPegOutSigError> {
-        let peer_key = self
-            .cfg
-            .consensus
-            .peer_peg_in_keys
-            .get(peer)
-            .expect("always called with valid peer id");
-
-        if psbt.inputs.len() != signature.signature.len() {
-            return Err(ProcessPegOutSigError::WrongSignatureCount(
-                psbt.inputs.len(),
-                signature.signature.len(),
-            ));
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: Txid,
+        missing_signers: &[PeerId],
+    ) -> bool {
+        if !missing_signers
+            .iter()
+            .any(|peer| self.cfg.consensus.external_signers.contains(peer))
+        {
+            return false;
         }
 
-        let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
-        for (idx, (input, signature)) in psbt
-            .inputs
-            .iter_mut()
-            .zip(signature.signature.iter())
-            .enumerate()
+        let height = self.consensus_height(dbtx).await.unwrap_or(0);
+        let waiting_since = match dbtx
+            .get_value(&ExternalSignerWaitKey(txid))
+            .await
+            .expect("DB error")
         {
-            let tx_hash = tx_hasher
-                .segwit_signature_hash(
-                    idx,
-                    input
-                        .witness_script
-                        .as_ref()
-                        .expect("Missing witness script"),
-                    input.witness_utxo.as_ref().expect("Missing UTXO").value,
-                    EcdsaSighashType::All,
-                )
-                .map_err(|_| ProcessPegOutSigError::SighashError)?;
-
-            let tweak = input
-                .proprietary
-                .get(&proprietary_tweak_key())
-                .expect("we saved it with a tweak");
-
-            let tweaked_peer_key = peer_key.tweak(tweak, &self.secp);
-            self.secp
-                .verify_ecdsa(
-                    &Message::from_slice(&tx_hash[..]).unwrap(),
-                    signature,
-                    &tweaked_peer_key.key,
-                )
-                .map_err(|_| ProcessPegOutSigError::InvalidSignature)?;
-
-            if input
-                .partial_sigs
-                .insert(tweaked_peer_key.into(), EcdsaSig::sighash_all(*signature))
-                .is_some()
-            {
-                // Should never happen since peers only sign a PSBT once
-                return Err(ProcessPegOutSigError::DuplicateSignature);
+            Some(waiting_since) => waiting_since,
+            None => {
+                dbtx.insert_new_entry(&ExternalSignerWaitKey(txid), &height)
+                    .await
+                    .expect("DB Error");
+                height
             }
-        }
-        Ok(())
+        };
+        height.saturating_sub(waiting_since) > self.cfg.consensus.external_signer_timeout
     }
 
     fn finalize_peg_out_psbt(
@@ -1114,20 +1311,30 @@ impl Wallet {
             .peg_in_descriptor
             .tweak(&pending_tx.tweak, &self.secp)
             .script_pubkey();
+        // Peg-outs created before the handover pay change to the previous descriptor
+        let legacy_script_pk = self.cfg.consensus.previous_peg_in.as_ref().map(|previous| {
+            previous
+                .peg_in_descriptor
+                .tweak(&pending_tx.tweak, &self.secp)
+                .script_pubkey()
+        });
         for (idx, output) in pending_tx.tx.output.iter().enumerate() {
+            let outpoint = bitcoin::OutPoint {
+                txid: pending_tx.tx.txid(),
+                vout: idx as u32,
+            };
+            let utxo = SpendableUTXO {
+                tweak: pending_tx.tweak,
+                amount: bitcoin::Amount::from_sat(output.value),
+            };
             if output.script_pubkey == script_pk {
-                dbtx.insert_entry(
-                    &UTXOKey(bitcoin::OutPoint {
-                        txid: pending_tx.tx.txid(),
-                        vout: idx as u32,
-                    }),
-                    &SpendableUTXO {
-                        tweak: pending_tx.tweak,
-                        amount: bitcoin::Amount::from_sat(output.value),
-                    },
-                )
-                .await
-                .expect("DB Error");
+                dbtx.insert_entry(&UTXOKey(outpoint), &utxo)
+                    .await
+                    .expect("DB Error");
+            } else if Some(&output.script_pubkey) == legacy_script_pk.as_ref() {
+                dbtx.insert_entry(&LegacyUTXOKey(outpoint), &utxo)
+                    .await
+                    .expect("DB Error");
             }
         }
     }
@@ -1143,6 +1350,124 @@ impl Wallet {
             .is_some()
     }
 
+    /// Returns whether a peg-in pays to the previous descriptor instead of the current one
+    fn verify_peg_in_descriptor(&self, input: &WalletInput) -> Result<bool, PegInProofError> {
+        match input.verify(&self.secp, &self.cfg.consensus.peg_in_descriptor) {
+            Ok(()) => Ok(false),
+            Err(error) => match &self.cfg.consensus.previous_peg_in {
+                Some(previous)
+                    if input
+                        .verify(&self.secp, &previous.peg_in_descriptor)
+                        .is_ok() =>
+                {
+                    Ok(true)
+                }
+                _ => Err(error),
+            },
+        }
+    }
+
+    /// Hands the funds of the previous descriptor over to the current one after resharing
+    ///
+    /// The first time we run with a new descriptor all UTXOs are moved to the legacy table and
+    /// peg-outs that are still being signed are marked as spending legacy UTXOs. Afterwards, all
+    /// legacy UTXOs are swept to the current descriptor whenever there are any, which is signed
+    /// by the guardians holding keys of the previous descriptor.
+    async fn process_handover(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        previous: &PreviousPegIn,
+        round_consensus: &RoundConsensus,
+    ) {
+        let previous_descriptor = previous.peg_in_descriptor.to_string();
+        if dbtx.get_value(&PegInHandoverKey).await.expect("DB error")
+            != Some(previous_descriptor.clone())
+        {
+            info!(%previous_descriptor, "Handing over UTXOs of previous peg-in descriptor");
+            for (key, utxo) in self.available_utxos(dbtx).await {
+                dbtx.remove_entry(&key).await.expect("DB Error");
+                dbtx.insert_new_entry(&LegacyUTXOKey(key.0), &utxo)
+                    .await
+                    .expect("DB Error");
+            }
+
+            let unsigned_txids = dbtx
+                .find_by_prefix(&UnsignedTransactionPrefixKey)
+                .await
+                .map(|res| res.expect("DB error").0 .0)
+                .collect::<Vec<_>>();
+            for txid in unsigned_txids {
+                dbtx.insert_entry(&LegacyTransactionKey(txid), &())
+                    .await
+                    .expect("DB Error");
+            }
+
+            dbtx.insert_entry(&PegInHandoverKey, &previous_descriptor)
+                .await
+                .expect("DB Error");
+        }
+
+        let legacy_utxos = dbtx
+            .find_by_prefix(&LegacyUTXOPrefixKey)
+            .await
+            .map(|res| {
+                let (key, utxo) = res.expect("DB error");
+                (key.0, utxo)
+            })
+            .collect::<Vec<_>>();
+        if legacy_utxos.is_empty() {
+            return;
+        }
+
+        let previous_wallet = StatelessWallet {
+            descriptor: &previous.peg_in_descriptor,
+            secp: &self.secp,
+        };
+        let tx = match previous_wallet.create_sweep_tx(
+            legacy_utxos,
+            self.offline_wallet()
+                .derive_script(&round_consensus.randomness_beacon),
+            round_consensus.fee_rate,
+            &round_consensus.randomness_beacon,
+        ) {
+            Some(tx) => tx,
+            None => {
+                debug!("Legacy UTXOs don't cover the fees of sweeping them yet");
+                return;
+            }
+        };
+        let txid = tx.psbt.unsigned_tx.txid();
+
+        let our_key = self.cfg.peg_in_public_key();
+        match &self.cfg.private.peg_in_key {
+            Some(secret_key)
+                if previous
+                    .peer_peg_in_keys
+                    .values()
+                    .any(|key| *key == our_key) =>
+            {
+                info!(%txid, "Signing sweep of previous peg-in descriptor");
+                let sig = sign_peg_out(&tx.psbt, secret_key, &self.secp);
+                dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sig.signature)
+                    .await
+                    .expect("DB Error");
+            }
+            _ => info!(%txid, "Sweeping previous peg-in descriptor"),
+        }
+
+        for input in tx.psbt.unsigned_tx.input.iter() {
+            dbtx.remove_entry(&LegacyUTXOKey(input.previous_output))
+                .await
+                .expect("DB Error");
+        }
+        dbtx.insert_new_entry(&LegacyTransactionKey(txid), &())
+            .await
+            .expect("DB Error");
+        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
+            .await
+            .expect("DB Error");
+    }
+
     async fn create_peg_out_tx(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -1185,7 +1510,6 @@ impl Wallet {
     fn offline_wallet(&self) -> StatelessWallet {
         StatelessWallet {
             descriptor: &self.cfg.consensus.peg_in_descriptor,
-            secret_key: &self.cfg.private.peg_in_key,
             secp: &self.secp,
         }
     }
@@ -1297,45 +1621,7 @@ impl<'a> StatelessWallet<'a> {
             unknown: Default::default(),
             inputs: selected_utxos
                 .into_iter()
-                .map(|(_utxo_key, utxo)| {
-                    let script_pubkey = self
-                        .descriptor
-                        .tweak(&utxo.tweak, self.secp)
-                        .script_pubkey();
-                    Input {
-                        non_witness_utxo: None,
-                        witness_utxo: Some(TxOut {
-                            value: utxo.amount.to_sat(),
-                            script_pubkey,
-                        }),
-                        partial_sigs: Default::default(),
-                        sighash_type: None,
-                        redeem_script: None,
-                        witness_script: Some(
-                            self.descriptor
-                                .tweak(&utxo.tweak, self.secp)
-                                .script_code()
-                                .expect("Failed to tweak descriptor"),
-                        ),
-                        bip32_derivation: Default::default(),
-                        final_script_sig: None,
-                        final_script_witness: None,
-                        ripemd160_preimages: Default::default(),
-                        sha256_preimages: Default::default(),
-                        hash160_preimages: Default::default(),
-                        hash256_preimages: Default::default(),
-                        proprietary: vec![(proprietary_tweak_key(), utxo.tweak.to_vec())]
-                            .into_iter()
-                            .collect(),
-                        tap_key_sig: Default::default(),
-                        tap_script_sigs: Default::default(),
-                        tap_scripts: Default::default(),
-                        tap_key_origins: Default::default(),
-                        tap_internal_key: Default::default(),
-                        tap_merkle_root: Default::default(),
-                        unknown: Default::default(),
-                    }
-                })
+                .map(|(_utxo_key, utxo)| self.psbt_input(&utxo))
                 .collect(),
             outputs: vec![Default::default(), change_out],
         };
@@ -1351,60 +1637,132 @@ impl<'a> StatelessWallet<'a> {
         })
     }
 
-    fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) {
-        let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
+    /// Creates a tx sweeping all `utxos` to `destination`, the `tweak` of which is saved in
+    /// the PSBT like the change tweak of peg-outs.
+    /// Returns `None` if the UTXOs don't cover the fees
+    fn create_sweep_tx(
+        &self,
+        utxos: Vec<(bitcoin::OutPoint, SpendableUTXO)>,
+        destination: Script,
+        fee_rate: Feerate,
+        tweak: &[u8],
+    ) -> Option<UnsignedTransaction> {
+        let max_input_weight = (self
+            .descriptor
+            .max_satisfaction_weight()
+            .expect("is satisfyable") +
+            128 + // TxOutHash
+            16 + // TxOutIndex
+            16) as u64; // sequence
+        let total_weight = 16 + // version
+            12 + // up to 2**16-1 inputs
+            12 + // up to 2**16-1 outputs
+            (1 + destination.len() * 4 + 32) as u64 + // single output
+            16 + // lock time
+            max_input_weight * utxos.len() as u64;
+        let fees = fee_rate.calculate_fee(total_weight);
 
-        for (idx, (psbt_input, _tx_input)) in psbt
-            .inputs
-            .iter_mut()
-            .zip(psbt.unsigned_tx.input.iter())
-            .enumerate()
-        {
-            let tweaked_secret = {
-                let tweak_pk_bytes = psbt_input
-                    .proprietary
-                    .get(&proprietary_tweak_key())
-                    .expect("Malformed PSBT: expected tweak");
-                let pub_key = secp256k1::PublicKey::from_secret_key(self.secp, self.secret_key);
-
-                let tweak = {
-                    let mut hasher = HmacEngine::<sha256::Hash>::new(&pub_key.serialize()[..]);
-                    hasher.input(&tweak_pk_bytes[..]);
-                    Hmac::from_engine(hasher).into_inner()
-                };
+        let total_value = utxos
+            .iter()
+            .map(|(_, utxo)| utxo.amount)
+            .fold(bitcoin::Amount::ZERO, |a, b| a + b);
+        if total_value < fees + destination.dust_value() {
+            return None;
+        }
+        let swept = total_value - fees;
 
-                self.secret_key
-                    .add_tweak(&Scalar::from_be_bytes(tweak).expect("can't fail"))
-                    .expect("Tweaking priv key failed") // TODO: why could this happen?
-            };
+        let mut sweep_out = bitcoin::util::psbt::Output::default();
+        sweep_out
+            .proprietary
+            .insert(proprietary_tweak_key(), tweak.to_vec());
 
-            let tx_hash = tx_hasher
-                .segwit_signature_hash(
-                    idx,
-                    psbt_input
-                        .witness_script
-                        .as_ref()
-                        .expect("Missing witness script"),
-                    psbt_input
-                        .witness_utxo
-                        .as_ref()
-                        .expect("Missing UTXO")
-                        .value,
-                    EcdsaSighashType::All,
-                )
-                .expect("Failed to create segwit sighash");
+        let transaction = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: utxos
+                .iter()
+                .map(|(outpoint, _utxo)| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: Default::default(),
+                    sequence: Sequence::MAX,
+                    witness: bitcoin::Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: swept.to_sat(),
+                script_pubkey: destination,
+            }],
+        };
+        info!(
+            txid = %transaction.txid(),
+            inputs = utxos.len(),
+            swept_sats = swept.to_sat(),
+            fees_sats = fees.to_sat(),
+            "Creating sweep tx",
+        );
 
-            let signature = self
-                .secp
-                .sign_ecdsa(&Message::from_slice(&tx_hash[..]).unwrap(), &tweaked_secret);
+        let psbt = PartiallySignedTransaction {
+            unsigned_tx: transaction,
+            version: 0,
+            xpub: Default::default(),
+            proprietary: Default::default(),
+            unknown: Default::default(),
+            inputs: utxos
+                .iter()
+                .map(|(_outpoint, utxo)| self.psbt_input(utxo))
+                .collect(),
+            outputs: vec![sweep_out],
+        };
 
-            psbt_input.partial_sigs.insert(
-                bitcoin::PublicKey {
-                    compressed: true,
-                    inner: secp256k1::PublicKey::from_secret_key(self.secp, &tweaked_secret),
-                },
-                EcdsaSig::sighash_all(signature),
-            );
+        Some(UnsignedTransaction {
+            psbt,
+            signatures: vec![],
+            change: swept,
+            fees: PegOutFees {
+                fee_rate,
+                total_weight,
+            },
+        })
+    }
+
+    /// PSBT input spending one of our UTXOs
+    fn psbt_input(&self, utxo: &SpendableUTXO) -> Input {
+        let script_pubkey = self
+            .descriptor
+            .tweak(&utxo.tweak, self.secp)
+            .script_pubkey();
+        Input {
+            non_witness_utxo: None,
+            witness_utxo: Some(TxOut {
+                value: utxo.amount.to_sat(),
+                script_pubkey,
+            }),
+            partial_sigs: Default::default(),
+            sighash_type: None,
+            redeem_script: None,
+            witness_script: Some(
+                self.descriptor
+                    .tweak(&utxo.tweak, self.secp)
+                    .script_code()
+                    .expect("Failed to tweak descriptor"),
+            ),
+            bip32_derivation: Default::default(),
+            final_script_sig: None,
+            final_script_witness: None,
+            ripemd160_preimages: Default::default(),
+            sha256_preimages: Default::default(),
+            hash160_preimages: Default::default(),
+            hash256_preimages: Default::default(),
+            proprietary: vec![(proprietary_tweak_key(), utxo.tweak.to_vec())]
+                .into_iter()
+                .collect(),
+            tap_key_sig: Default::default(),
+            tap_script_sigs: Default::default(),
+            tap_scripts: Default::default(),
+            tap_key_origins: Default::default(),
+            tap_internal_key: Default::default(),
+            tap_merkle_root: Default::default(),
+            unknown: Default::default(),
         }
     }
 
@@ -1454,6 +1812,284 @@ impl<'a> StatelessWallet<'a> {
 
         descriptor.script_pubkey()
     }
+
+    /// Checks that `peer_key` is one of the keys of our descriptor and `psbt` only spends UTXOs
+    /// derived from it, so a signature with `peer_key` can contribute to spending them
+    fn verify_signer(
+        &self,
+        psbt: &PartiallySignedTransaction,
+        peer_key: &CompressedPublicKey,
+    ) -> Result<(), ProcessPegOutSigError> {
+        struct KeyFinder<'k> {
+            key: &'k CompressedPublicKey,
+            found: bool,
+        }
+
+        impl<'k> miniscript::PkTranslator<CompressedPublicKey, CompressedPublicKey, Infallible>
+            for KeyFinder<'k>
+        {
+            fn pk(&mut self, pk: &CompressedPublicKey) -> Result<CompressedPublicKey, Infallible> {
+                self.found |= pk == self.key;
+                Ok(*pk)
+            }
+
+            fn pkh(
+                &mut self,
+                pkh: &CompressedPublicKey,
+            ) -> Result<CompressedPublicKey, Infallible> {
+                self.pk(pkh)
+            }
+        }
+
+        let mut key_finder = KeyFinder {
+            key: peer_key,
+            found: false,
+        };
+        self.descriptor
+            .translate_pk(&mut key_finder)
+            .expect("can't fail");
+        if !key_finder.found {
+            return Err(ProcessPegOutSigError::NotASigner);
+        }
+
+        for (idx, input) in psbt.inputs.iter().enumerate() {
+            let tweak = input
+                .proprietary
+                .get(&proprietary_tweak_key())
+                .expect("we saved it with a tweak");
+            let spent_script = &input
+                .witness_utxo
+                .as_ref()
+                .expect("Missing UTXO")
+                .script_pubkey;
+            if *spent_script != self.derive_script(tweak) {
+                return Err(ProcessPegOutSigError::WrongDescriptor(idx));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Signs a peg-out PSBT with the peg-in secret key of a peer, which can also be done by an
+/// external signer holding the key, see [`submit_peg_out_signature`]
+pub fn sign_peg_out(
+    psbt: &PartiallySignedTransaction,
+    secret_key: &secp256k1::SecretKey,
+    secp: &Secp256k1<All>,
+) -> PegOutSignatureItem {
+    let mut psbt = psbt.clone();
+    sign_psbt(&mut psbt, secret_key, secp);
+
+    let signature = psbt
+        .inputs
+        .iter_mut()
+        .map(|input| {
+            assert_eq!(
+                input.partial_sigs.len(),
+                1,
+                "There was already more than one (our) or no signatures in input"
+            );
+
+            // TODO: don't put sig into PSBT in the first place
+            // We actually take out our own signature so everyone finalizes the tx in the
+            // same epoch.
+            let sig = std::mem::take(&mut input.partial_sigs)
+                .into_values()
+                .next()
+                .expect("asserted previously");
+
+            // We drop SIGHASH_ALL, because we always use that and it is only present in the
+            // PSBT for compatibility with other tools.
+            secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
+                .expect("we serialized it ourselves that way")
+        })
+        .collect();
+
+    PegOutSignatureItem {
+        txid: psbt.unsigned_tx.txid(),
+        signature,
+    }
+}
+
+fn sign_psbt(
+    psbt: &mut PartiallySignedTransaction,
+    secret_key: &secp256k1::SecretKey,
+    secp: &Secp256k1<All>,
+) {
+    let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
+
+    for (idx, (psbt_input, _tx_input)) in psbt
+        .inputs
+        .iter_mut()
+        .zip(psbt.unsigned_tx.input.iter())
+        .enumerate()
+    {
+        let tweaked_secret = {
+            let tweak_pk_bytes = psbt_input
+                .proprietary
+                .get(&proprietary_tweak_key())
+                .expect("Malformed PSBT: expected tweak");
+            let pub_key = secp256k1::PublicKey::from_secret_key(secp, secret_key);
+
+            let tweak = {
+                let mut hasher = HmacEngine::<sha256::Hash>::new(&pub_key.serialize()[..]);
+                hasher.input(&tweak_pk_bytes[..]);
+                Hmac::from_engine(hasher).into_inner()
+            };
+
+            secret_key
+                .add_tweak(&Scalar::from_be_bytes(tweak).expect("can't fail"))
+                .expect("Tweaking priv key failed") // TODO: why could this happen?
+        };
+
+        let tx_hash = tx_hasher
+            .segwit_signature_hash(
+                idx,
+                psbt_input
+                    .witness_script
+                    .as_ref()
+                    .expect("Missing witness script"),
+                psbt_input
+                    .witness_utxo
+                    .as_ref()
+                    .expect("Missing UTXO")
+                    .value,
+                EcdsaSighashType::All,
+            )
+            .expect("Failed to create segwit sighash");
+
+        let signature =
+            secp.sign_ecdsa(&Message::from_slice(&tx_hash[..]).unwrap(), &tweaked_secret);
+
+        psbt_input.partial_sigs.insert(
+            bitcoin::PublicKey {
+                compressed: true,
+                inner: secp256k1::PublicKey::from_secret_key(secp, &tweaked_secret),
+            },
+            EcdsaSig::sighash_all(signature),
+        );
+    }
+}
+
+/// Try to attach signatures to a pending peg-out tx.
+fn sign_peg_out_psbt<C: Verification>(
+    psbt: &mut PartiallySignedTransaction,
+    peer_key: &CompressedPublicKey,
+    signature: &PegOutSignatureItem,
+    secp: &Secp256k1<C>,
+) -> Result<(), ProcessPegOutSigError> {
+    if psbt.inputs.len() != signature.signature.len() {
+        return Err(ProcessPegOutSigError::WrongSignatureCount(
+            psbt.inputs.len(),
+            signature.signature.len(),
+        ));
+    }
+
+    let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
+    for (idx, (input, signature)) in psbt
+        .inputs
+        .iter_mut()
+        .zip(signature.signature.iter())
+        .enumerate()
+    {
+        let tx_hash = tx_hasher
+            .segwit_signature_hash(
+                idx,
+                input
+                    .witness_script
+                    .as_ref()
+                    .expect("Missing witness script"),
+                input.witness_utxo.as_ref().expect("Missing UTXO").value,
+                EcdsaSighashType::All,
+            )
+            .map_err(|_| ProcessPegOutSigError::SighashError)?;
+
+        let tweak = input
+            .proprietary
+            .get(&proprietary_tweak_key())
+            .expect("we saved it with a tweak");
+
+        let tweaked_peer_key = peer_key.tweak(tweak, secp);
+        secp.verify_ecdsa(
+            &Message::from_slice(&tx_hash[..]).unwrap(),
+            signature,
+            &tweaked_peer_key.key,
+        )
+        .map_err(|_| ProcessPegOutSigError::InvalidSignature)?;
+
+        if input
+            .partial_sigs
+            .insert(tweaked_peer_key.into(), EcdsaSig::sighash_all(*signature))
+            .is_some()
+        {
+            // Should never happen since peers only sign a PSBT once
+            return Err(ProcessPegOutSigError::DuplicateSignature);
+        }
+    }
+    Ok(())
+}
+
+/// Peg-out PSBTs we didn't contribute our signature to yet, to be signed by our external signer
+pub async fn unsigned_peg_outs(dbtx: &mut ReadDatabaseTransaction<'_>) -> Vec<UnsignedPegOut> {
+    let unsigned_txs = dbtx
+        .find_by_prefix(&UnsignedTransactionPrefixKey)
+        .await
+        .map(|res| res.expect("DB error").1)
+        .collect::<Vec<_>>();
+
+    let mut unsigned_peg_outs = vec![];
+    for unsigned in unsigned_txs {
+        let txid = unsigned.psbt.unsigned_tx.txid();
+        if dbtx
+            .get_value(&PegOutTxSignatureCI(txid))
+            .await
+            .expect("DB error")
+            .is_none()
+        {
+            unsigned_peg_outs.push(UnsignedPegOut::new(&unsigned.psbt));
+        }
+    }
+    unsigned_peg_outs
+}
+
+/// Accepts our signature of a peg-out PSBT created by our external signer, it is proposed to the
+/// other peers in the next epoch
+pub async fn submit_peg_out_signature(
+    dbtx: &mut DatabaseTransaction<'_>,
+    cfg: &WalletConfig,
+    signature: PegOutSignatureItem,
+) -> Result<(), ProcessPegOutSigError> {
+    let mut unsigned = dbtx
+        .get_value(&UnsignedTransactionKey(signature.txid))
+        .await
+        .expect("DB error")
+        .ok_or(ProcessPegOutSigError::UnknownTransaction(signature.txid))?;
+
+    // Transactions spending UTXOs of the previous descriptor are signed with our key in it
+    let is_legacy = dbtx
+        .get_value(&LegacyTransactionKey(signature.txid))
+        .await
+        .expect("DB error")
+        .is_some();
+    let descriptor = match &cfg.consensus.previous_peg_in {
+        Some(previous) if is_legacy => &previous.peg_in_descriptor,
+        _ => &cfg.consensus.peg_in_descriptor,
+    };
+
+    let secp = Secp256k1::new();
+    let peer_key = cfg.peg_in_public_key();
+    StatelessWallet {
+        descriptor,
+        secp: &secp,
+    }
+    .verify_signer(&unsigned.psbt, &peer_key)?;
+    sign_peg_out_psbt(&mut unsigned.psbt, &peer_key, &signature, &secp)?;
+
+    info!(txid = %signature.txid, "Received peg out signature of external signer");
+    dbtx.insert_entry(&PegOutTxSignatureCI(signature.txid), &signature.signature)
+        .await
+        .expect("DB Error");
+    Ok(())
 }
 
 fn proprietary_tweak_key() -> ProprietaryKey {
@@ -1508,6 +2144,44 @@ pub async fn broadcast_pending_tx(mut dbtx: DatabaseTransaction<'_>, rpc: &Bitco
     }
 }
 
+/// Lists all peg-outs that are still being signed or waiting for confirmation
+pub async fn pending_peg_outs(dbtx: &mut ReadDatabaseTransaction<'_>) -> Vec<PendingPegOut> {
+    let unsigned = dbtx
+        .find_by_prefix(&UnsignedTransactionPrefixKey)
+        .await
+        .map(|res| {
+            let (_, unsigned) = res.expect("DB error");
+            PendingPegOut {
+                txid: unsigned.psbt.unsigned_tx.txid(),
+                amount: peg_out_amount(&unsigned.psbt.unsigned_tx, unsigned.change),
+                status: PendingPegOutStatus::Signing {
+                    signatures: unsigned.signatures.iter().map(|(peer, _)| *peer).collect(),
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let pending = dbtx
+        .find_by_prefix(&PendingTransactionPrefixKey)
+        .await
+        .map(|res| {
+            let (_, pending) = res.expect("DB error");
+            PendingPegOut {
+                txid: pending.tx.txid(),
+                amount: peg_out_amount(&pending.tx, pending.change),
+                status: PendingPegOutStatus::Broadcasting,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    unsigned.into_iter().chain(pending).collect()
+}
+
+fn peg_out_amount(tx: &Transaction, change: bitcoin::Amount) -> bitcoin::Amount {
+    let total = tx.output.iter().map(|out| out.value).sum::<u64>();
+    bitcoin::Amount::from_sat(total) - change
+}
+
 impl std::hash::Hash for PegOutSignatureItem {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.txid.hash(state);
@@ -1566,6 +2240,10 @@ pub enum ProcessPegOutSigError {
     MalformedSignature(secp256k1::Error),
     #[error("Invalid signature")]
     InvalidSignature,
+    #[error("Our peg-in key can't sign for the descriptor of the spent UTXOs")]
+    NotASigner,
+    #[error("Input {0} doesn't spend a UTXO of the descriptor it is signed for")]
+    WrongDescriptor(usize),
     #[error("Duplicate signature")]
     DuplicateSignature,
     #[error("Missing change tweak")]
@@ -1584,3 +2262,144 @@ impl PartialEq for WalletError {
 
 /// **WARNING**: this is only intended to be used for testing
 impl Eq for WalletError {}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Amount, OutPoint, Script, Txid};
+    use fedimint_api::Feerate;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use crate::keys::CompressedPublicKey;
+    use crate::tweakable::Tweakable;
+    use crate::{
+        proprietary_tweak_key, PegInDescriptor, ProcessPegOutSigError, SpendableUTXO,
+        StatelessWallet,
+    };
+
+    fn descriptor(secp: &Secp256k1<secp256k1::All>) -> PegInDescriptor {
+        let keys = (1..=4u8)
+            .map(|idx| {
+                let sk = SecretKey::from_slice(&[idx; 32]).unwrap();
+                CompressedPublicKey::new(PublicKey::from_secret_key(secp, &sk))
+            })
+            .collect();
+        PegInDescriptor::new_wsh_sortedmulti(3, keys).unwrap()
+    }
+
+    fn utxo(vout: u32, sats: u64) -> (OutPoint, SpendableUTXO) {
+        let outpoint = OutPoint {
+            txid: Txid::all_zeros(),
+            vout,
+        };
+        let utxo = SpendableUTXO {
+            tweak: [vout as u8; 32],
+            amount: Amount::from_sat(sats),
+        };
+        (outpoint, utxo)
+    }
+
+    #[test_log::test]
+    fn sweep_tx_spends_all_utxos_to_destination() {
+        let secp = Secp256k1::new();
+        let descriptor = descriptor(&secp);
+        let wallet = StatelessWallet {
+            descriptor: &descriptor,
+            secp: &secp,
+        };
+        let utxos = vec![utxo(0, 5000), utxo(1, 7000)];
+        let tweak = [42; 32];
+        let destination = wallet.derive_script(&tweak);
+        let fee_rate = Feerate { sats_per_kvb: 1000 };
+
+        let tx = wallet
+            .create_sweep_tx(utxos.clone(), destination.clone(), fee_rate, &tweak)
+            .unwrap();
+        let fees = fee_rate.calculate_fee(tx.fees.total_weight);
+
+        let unsigned_tx = &tx.psbt.unsigned_tx;
+        let outpoints = unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect::<Vec<_>>();
+        assert_eq!(outpoints, vec![utxos[0].0, utxos[1].0]);
+        assert_eq!(unsigned_tx.output.len(), 1);
+        assert_eq!(unsigned_tx.output[0].script_pubkey, destination);
+        assert_eq!(unsigned_tx.output[0].value, 12000 - fees.to_sat());
+        assert_eq!(tx.change, Amount::from_sat(12000) - fees);
+        assert_eq!(
+            tx.psbt.outputs[0].proprietary.get(&proprietary_tweak_key()),
+            Some(&tweak.to_vec())
+        );
+
+        // Inputs are spent from the sweeping wallet's descriptor
+        for ((_, utxo), input) in utxos.iter().zip(tx.psbt.inputs.iter()) {
+            let witness_utxo = input.witness_utxo.as_ref().unwrap();
+            assert_eq!(witness_utxo.value, utxo.amount.to_sat());
+            assert_eq!(
+                witness_utxo.script_pubkey,
+                descriptor.tweak(&utxo.tweak, &secp).script_pubkey()
+            );
+        }
+    }
+
+    #[test_log::test]
+    fn signers_have_to_hold_a_key_of_the_spent_descriptor() {
+        let secp = Secp256k1::new();
+        let descriptor = descriptor(&secp);
+        let wallet = StatelessWallet {
+            descriptor: &descriptor,
+            secp: &secp,
+        };
+        let tx = wallet
+            .create_sweep_tx(
+                vec![utxo(0, 5000), utxo(1, 7000)],
+                wallet.derive_script(&[42; 32]),
+                Feerate { sats_per_kvb: 1000 },
+                &[42; 32],
+            )
+            .unwrap();
+
+        let key = |idx: u8| {
+            let sk = SecretKey::from_slice(&[idx; 32]).unwrap();
+            CompressedPublicKey::new(PublicKey::from_secret_key(&secp, &sk))
+        };
+        assert!(wallet.verify_signer(&tx.psbt, &key(1)).is_ok());
+        assert!(matches!(
+            wallet.verify_signer(&tx.psbt, &key(5)),
+            Err(ProcessPegOutSigError::NotASigner)
+        ));
+
+        // A descriptor sharing our key doesn't spend the UTXOs of the sweep
+        let other_descriptor =
+            PegInDescriptor::new_wsh_sortedmulti(3, (1..=5).map(key).collect()).unwrap();
+        let other_wallet = StatelessWallet {
+            descriptor: &other_descriptor,
+            secp: &secp,
+        };
+        assert!(matches!(
+            other_wallet.verify_signer(&tx.psbt, &key(1)),
+            Err(ProcessPegOutSigError::WrongDescriptor(0))
+        ));
+    }
+
+    #[test_log::test]
+    fn sweep_tx_requires_utxos_to_cover_fees() {
+        let secp = Secp256k1::new();
+        let descriptor = descriptor(&secp);
+        let wallet = StatelessWallet {
+            descriptor: &descriptor,
+            secp: &secp,
+        };
+        let destination: Script = wallet.derive_script(&[42; 32]);
+        let fee_rate = Feerate { sats_per_kvb: 1000 };
+
+        assert!(wallet
+            .create_sweep_tx(vec![utxo(0, 500)], destination.clone(), fee_rate, &[42; 32])
+            .is_none());
+        assert!(wallet
+            .create_sweep_tx(vec![], destination, fee_rate, &[42; 32])
+            .is_none());
+    }
+}
@@ -0,0 +1,11 @@
+//! Prometheus metrics of the wallet, registered in the default [`prometheus`] registry
+use lazy_static::lazy_static;
+use prometheus::{register_int_gauge, IntGauge};
+
+lazy_static! {
+    pub static ref WALLET_BALANCE_SATS: IntGauge = register_int_gauge!(
+        "fedimint_wallet_balance_sats",
+        "Value of all UTXOs the wallet can spend"
+    )
+    .unwrap();
+}